@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use difiew::store::{command::StoreCommand, value::StoreValue, Store};
+
+/// Numbers of keys the store holds before a measured operation
+const SIZES: &[usize] = &[100, 1_000, 10_000];
+
+/// Keys changed between the two versions of a store a repair reconciles
+const CHANGED_KEYS: usize = 10;
+
+fn seeded_store(size: usize) -> Store {
+    let mut store = Store::new();
+    store
+        .bulk_load((0..size).map(|i| (format!("key:{i}"), format!("value:{i}"))))
+        .unwrap();
+    store
+}
+
+fn set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("SET");
+    for &size in SIZES {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            let mut store = seeded_store(size);
+            let mut i = 0usize;
+            b.iter(|| {
+                i += 1;
+                let key = format!("key:{}", i % size);
+                store.execute(StoreCommand::set(key, "new")).unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
+fn get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("GET");
+    for &size in SIZES {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            let mut store = seeded_store(size);
+            let mut i = 0usize;
+            b.iter(|| {
+                i += 1;
+                let key = format!("key:{}", i % size);
+                store.execute(StoreCommand::get(key)).unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
+fn del(c: &mut Criterion) {
+    let mut group = c.benchmark_group("DEL");
+    for &size in SIZES {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter_batched(
+                || seeded_store(size),
+                |mut store| {
+                    store.execute(StoreCommand::del(["key:0"])).unwrap();
+                    store
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn keys(c: &mut Criterion) {
+    let mut group = c.benchmark_group("KEYS");
+    for &size in SIZES {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            let mut store = seeded_store(size);
+            b.iter(|| {
+                store.execute(StoreCommand::keys("key:1*")).unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
+/// A full repair rebuilds the whole tree while a merge only writes the keys
+/// that differ, both bring a store to the same content
+fn repair(c: &mut Criterion) {
+    let mut group = c.benchmark_group("repair");
+    for &size in SIZES {
+        let mut main_store: HashMap<String, StoreValue> =
+            seeded_store(size).get_main_store().unwrap();
+        for i in 0..CHANGED_KEYS.min(size) {
+            main_store.insert(format!("key:{i}"), StoreValue::Str("changed".to_string()));
+        }
+
+        group.bench_with_input(BenchmarkId::new("full", size), &size, |b, &size| {
+            b.iter_batched(
+                || (seeded_store(size), main_store.clone()),
+                |(mut store, main_store)| {
+                    store.update_full_store(main_store).unwrap();
+                    store
+                },
+                BatchSize::LargeInput,
+            );
+        });
+        group.bench_with_input(BenchmarkId::new("incremental", size), &size, |b, &size| {
+            b.iter_batched(
+                || (seeded_store(size), main_store.clone()),
+                |(mut store, main_store)| {
+                    store.merge_matching("*", main_store).unwrap();
+                    store
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, set, get, del, keys, repair);
+criterion_main!(benches);
@@ -0,0 +1,29 @@
+use std::process::Command;
+
+/// Exposes the commit the crate is built from as `DIFIEW_GIT_HASH`, reported
+/// by the `VERSION` command. Builds without the repository, like the Docker
+/// one, can pass it in the environment and fall back to `unknown` otherwise
+fn main() {
+    println!("cargo:rerun-if-env-changed=DIFIEW_GIT_HASH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    let hash = std::env::var("DIFIEW_GIT_HASH")
+        .ok()
+        .filter(|hash| !hash.is_empty())
+        .or_else(git_hash)
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=DIFIEW_GIT_HASH={hash}");
+}
+
+fn git_hash() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let hash = String::from_utf8(output.stdout).ok()?;
+    Some(hash.trim().to_string())
+}
@@ -1,26 +1,50 @@
-use clap::Parser;
 use difiew::{
-    manager::Manager,
-    utils::{bin_args::BinArgs, swarm_builder::build_swarm},
+    manager::{shard_router::ShardRouter, Manager},
+    utils::{
+        bin_args::BinArgs,
+        subscribe::{subscribe_with_retry, SUBSCRIBE_ATTEMPTS, SUBSCRIBE_BACKOFF},
+        swarm_builder::build_swarm,
+    },
     Component,
 };
 use libp2p::{identity, Multiaddr, PeerId};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = BinArgs::parse();
+    let args = BinArgs::parse_with_config()?;
     let key = identity::Keypair::generate_ed25519();
     let peer_id = PeerId::from(key.public());
     println!("Manager peer id: {}", peer_id);
 
     let mut swarm = build_swarm(key, &args)?;
     let topic = libp2p::gossipsub::IdentTopic::new(&args.topic);
-    swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+    subscribe_with_retry(&mut swarm, &topic, SUBSCRIBE_ATTEMPTS, SUBSCRIBE_BACKOFF).await?;
+    if let Some(shards) = args.shards {
+        for shard_topic in ShardRouter::new(&args.topic, shards).topics() {
+            subscribe_with_retry(
+                &mut swarm,
+                shard_topic,
+                SUBSCRIBE_ATTEMPTS,
+                SUBSCRIBE_BACKOFF,
+            )
+            .await?;
+        }
+    }
+    if let Some(notify_topic) = &args.notify_topic {
+        let notify_topic = libp2p::gossipsub::IdentTopic::new(notify_topic);
+        subscribe_with_retry(
+            &mut swarm,
+            &notify_topic,
+            SUBSCRIBE_ATTEMPTS,
+            SUBSCRIBE_BACKOFF,
+        )
+        .await?;
+    }
 
     let tcp_addr: Multiaddr = args.tcp_listen.parse()?;
     swarm.listen_on(tcp_addr)?;
 
-    let mut manager = Manager::new(swarm, peer_id, topic);
+    let mut manager = Manager::new(swarm, peer_id, topic, &args);
     manager.start_event_loop().await;
     Ok(())
 }
@@ -1,26 +1,78 @@
-use clap::Parser;
 use difiew::{
-    node::Node,
-    utils::{bin_args::BinArgs, swarm_builder::build_swarm},
+    node::{acl::Acl, Node, LEAVE_GRACE},
+    store::builder::StoreBuilder,
+    utils::{
+        bin_args::{BinArgs, KeyHashing},
+        preload,
+        subscribe::{subscribe_with_retry, SUBSCRIBE_ATTEMPTS, SUBSCRIBE_BACKOFF},
+        swarm_builder::build_swarm,
+    },
     Component,
 };
 use libp2p::{identity, Multiaddr, PeerId};
+use std::path::Path;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = BinArgs::parse();
+    let args = BinArgs::parse_with_config()?;
+    if args.key_hashing == KeyHashing::Raw {
+        eprintln!("Warning: raw key hashing requires unique keys of at most 32 bytes, longer keys are rejected");
+    }
     let key = identity::Keypair::generate_ed25519();
     let peer_id = PeerId::from(key.public());
     println!("Node peer id: {}", peer_id);
 
     let mut swarm = build_swarm(key, &args)?;
     let topic = libp2p::gossipsub::IdentTopic::new(&args.topic);
-    swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+    subscribe_with_retry(&mut swarm, &topic, SUBSCRIBE_ATTEMPTS, SUBSCRIBE_BACKOFF).await?;
 
     let tcp_addr: Multiaddr = args.tcp_listen.parse()?;
     swarm.listen_on(tcp_addr)?;
 
-    let mut node = Node::new(swarm, peer_id, topic);
-    node.start_event_loop().await;
+    let mut store = StoreBuilder::from_args(&args).build();
+    if let Some(path) = args.snapshot.as_ref().filter(|path| Path::new(path).exists()) {
+        let loaded = store
+            .load_from_path(path)
+            .map_err(|e| format!("Failed to load snapshot {path}: {e}"))?;
+        println!("Loaded {loaded} key(s) from snapshot {path}");
+    }
+    if let Some(path) = &args.preload {
+        let pairs = preload::read_pairs(path)?;
+        let loaded = store
+            .bulk_load(pairs.into_iter())
+            .map_err(|e| format!("Failed to preload {path}: {e:?}"))?;
+        println!("Preloaded {loaded} key(s) from {path}");
+    }
+    let acl = match &args.acl {
+        Some(path) => Some(Acl::read(path).map_err(|e| format!("Failed to read acl {path}: {e}"))?),
+        None => None,
+    };
+    let mut node = Node::with_store(swarm, peer_id, topic, &args, store).with_acl(acl);
+    tokio::select! {
+        _ = node.start_event_loop() => {}
+        _ = shutdown_signal() => println!("Shutting down, leaving the topic"),
+    }
+    node.leave(LEAVE_GRACE).await;
+    if let Some(path) = &args.snapshot {
+        match node.save_snapshot(path, args.snapshot_format).await {
+            Ok(()) => println!("Saved the store to snapshot {path}"),
+            Err(e) => eprintln!("Failed to save snapshot {path}: {e}"),
+        }
+    }
     Ok(())
 }
+
+/// Resolves on Ctrl-C, or on SIGTERM where available
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,227 @@
+use libp2p::{
+    gossipsub::{self, IdentTopic},
+    mdns,
+    swarm::SwarmEvent,
+    PeerId, Swarm,
+};
+use std::time::Duration;
+
+use crate::{
+    utils::timestamp::timestamp_millis, validation_result, Component, ComponentCore, ComponentError,
+};
+
+use crate::node::{MyBehaviour, MyBehaviourEvent};
+use crate::protocol::{metadata::MetaData, ComponentMessage, ManagerMessage, NodeMessage};
+use crate::store::command::StoreCommand;
+use crate::store::result::{ErrorKind, StoreCommandResult};
+use crate::utils::{bin_args::BinArgs, bootstrap::Bootstrap, psk::Psk};
+
+/// How long a command waits for a node to answer unless set by [`Client::with_timeout`]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Programmatic alternative to the manager stdin interface: publishes store
+/// commands like a manager does and returns the first node result answering
+/// each of them
+pub struct Client {
+    core: ComponentCore,
+    next_request_id: u64,
+    timeout: Duration,
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Component(ComponentError),
+    /// no node answered within the timeout
+    Timeout(),
+    /// the node answered with an `ERROR` result
+    Failed(ErrorKind, String),
+    /// the node answered with the result of another command
+    UnexpectedResult(String),
+}
+
+impl From<ComponentError> for ClientError {
+    fn from(err: ComponentError) -> Self {
+        ClientError::Component(err)
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl Client {
+    /// Client publishing on `topic`, the swarm must already be subscribed to it
+    pub fn new(
+        swarm: Swarm<MyBehaviour>,
+        peer_id: PeerId,
+        topic: IdentTopic,
+        args: &BinArgs,
+    ) -> Self {
+        Self {
+            core: ComponentCore::new(swarm, peer_id, topic)
+                .with_psk(args.psk.as_deref().map(Psk::new))
+                .with_peer_scoring(args.enable_peer_scoring)
+                .with_bootstrap(Bootstrap::from_args(args))
+                .with_bincode_config(args.bincode_config)
+                .with_max_transmit_size(args.gossipsub_max_transmit_size),
+            next_request_id: 0,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The string at key, `None` if the key doesn't exist
+    pub async fn get(&mut self, key: &str) -> Result<Option<String>, ClientError> {
+        match self.execute(StoreCommand::get(key)).await? {
+            StoreCommandResult::GET(r) => Ok(r.payload.map(|value| value.into_owned())),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Sets key to value, `true` once it is set
+    pub async fn set(&mut self, key: &str, value: &str) -> Result<bool, ClientError> {
+        match self.execute(StoreCommand::set(key, value)).await? {
+            StoreCommandResult::SET(r) => Ok(r.payload),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Removes the keys and returns how many existed
+    pub async fn del(&mut self, keys: &[&str]) -> Result<usize, ClientError> {
+        match self
+            .execute(StoreCommand::del(keys.iter().copied()))
+            .await?
+        {
+            StoreCommandResult::DEL(r) => Ok(r.payload),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Keys matching the glob pattern, in no particular order
+    pub async fn keys(&mut self, pattern: &str) -> Result<Vec<String>, ClientError> {
+        match self.execute(StoreCommand::keys(pattern)).await? {
+            StoreCommandResult::KEYS(r) => {
+                Ok(r.payload.into_iter().map(|k| k.into_owned()).collect())
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Publishes any store command and waits for the first result answering
+    /// it, an `ERROR` result is returned as [`ClientError::Failed`]
+    pub async fn execute(
+        &mut self,
+        cmd: StoreCommand<'_>,
+    ) -> Result<StoreCommandResult<'static>, ClientError> {
+        self.next_request_id += 1;
+        let request_id = self.next_request_id;
+        let timestamp = timestamp_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata =
+            MetaData::new(self.core.peer_id, timestamp).with_request_id(Some(request_id));
+        let msg = ComponentMessage::ManagerMessage(ManagerMessage::StoreCommand(cmd), metadata);
+        self.publish_message(msg)?;
+
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        loop {
+            let event = tokio::time::timeout_at(deadline, self.core.next_event())
+                .await
+                .map_err(|_| ClientError::Timeout())?;
+            let Some(message) = self.handle_event(event) else {
+                continue;
+            };
+            match self.decode_gossip(&message.data) {
+                Ok(Some(ComponentMessage::NodeMessage(
+                    NodeMessage::StoreCommandResult(result),
+                    metadata,
+                ))) if metadata.request_id == Some(request_id)
+                    && metadata.reply_to == Some(self.core.peer_id.to_string()) =>
+                {
+                    return match result {
+                        StoreCommandResult::ERROR(e) => {
+                            Err(ClientError::Failed(e.kind, e.message.into_owned()))
+                        }
+                        result => Ok(result),
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Keeps the swarm going and hands back the gossip it received, except the
+    /// messages published by this client
+    fn handle_event(&self, event: SwarmEvent<MyBehaviourEvent>) -> Option<gossipsub::Message> {
+        match event {
+            SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
+                let mut swarm = self.core.swarm.borrow_mut();
+                for (peer_id, _multiaddr) in list {
+                    swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                }
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
+                let mut swarm = self.core.swarm.borrow_mut();
+                for (peer_id, _multiaddr) in list {
+                    swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .remove_explicit_peer(&peer_id);
+                }
+            }
+            SwarmEvent::ConnectionEstablished { connection_id, .. }
+            | SwarmEvent::OutgoingConnectionError { connection_id, .. } => {
+                self.core.dial_finished(connection_id);
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed {
+                ..
+            })) => {
+                self.flush_outbox();
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => {
+                let acceptance = validation_result(&self.decode_gossip(&message.data));
+                self.report_validation(&message_id, &propagation_source, acceptance);
+                if !self.is_own_message(&message) {
+                    return Some(message);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+fn unexpected(result: StoreCommandResult) -> ClientError {
+    ClientError::UnexpectedResult(format!("{result:?}"))
+}
+
+#[async_trait::async_trait(?Send)]
+impl Component for Client {
+    fn core(&self) -> &ComponentCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut ComponentCore {
+        &mut self.core
+    }
+
+    /// Keeps the connections and the subscriptions alive between commands,
+    /// the results received meanwhile are dropped
+    async fn start_event_loop<'a>(&'a mut self) {
+        self.core.dial_bootstrap();
+        loop {
+            let event = self.core.next_event().await;
+            self.handle_event(event);
+        }
+    }
+}
@@ -0,0 +1,642 @@
+use libp2p::{
+    connection_limits,
+    gossipsub::{self, IdentTopic, MessageAcceptance, MessageId},
+    mdns,
+    swarm::{ConnectionId, NetworkBehaviour, Swarm, SwarmEvent},
+    PeerId,
+};
+
+use bincode::error::DecodeError;
+use bincode::error::EncodeError;
+use futures::StreamExt;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::protocol::{decode_message, ComponentMessage};
+use crate::store::error::StoreError;
+use crate::utils::{bin_args::BincodeConfig, bootstrap::Bootstrap, psk::Psk};
+
+#[derive(NetworkBehaviour)]
+pub struct MyBehaviour {
+    pub gossipsub: gossipsub::Behaviour,
+    pub mdns: mdns::tokio::Behaviour,
+    pub limits: connection_limits::Behaviour,
+}
+
+pub struct ComponentCore {
+    pub swarm: RefCell<Swarm<MyBehaviour>>,
+    pub peer_id: PeerId,
+    pub topic: IdentTopic,
+    /// see `--bincode-config`
+    pub config: BincodeConfig,
+    pub outbox: RefCell<Outbox>,
+    /// encrypts every published message and decrypts the received ones when set
+    pub psk: Option<Psk>,
+    /// gossipsub holds received messages until they are reported valid
+    pub peer_scoring: bool,
+    pub bootstrap: RefCell<Bootstrap>,
+    /// see `--gossipsub-max-transmit-size`, larger messages are refused before
+    /// they are published
+    pub max_transmit_size: usize,
+}
+
+impl ComponentCore {
+    pub fn new(swarm: Swarm<MyBehaviour>, peer_id: PeerId, topic: IdentTopic) -> Self {
+        Self {
+            swarm: swarm.into(),
+            peer_id,
+            topic,
+            config: BincodeConfig::Standard,
+            outbox: Outbox::new(OUTBOX_CAPACITY).into(),
+            psk: None,
+            peer_scoring: false,
+            bootstrap: Bootstrap::default().into(),
+            max_transmit_size: gossipsub::Config::default().max_transmit_size(),
+        }
+    }
+
+    pub fn with_psk(mut self, psk: Option<Psk>) -> Self {
+        self.psk = psk;
+        self
+    }
+
+    pub fn with_peer_scoring(mut self, peer_scoring: bool) -> Self {
+        self.peer_scoring = peer_scoring;
+        self
+    }
+
+    pub fn with_bincode_config(mut self, config: BincodeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_bootstrap(mut self, bootstrap: Bootstrap) -> Self {
+        self.bootstrap = bootstrap.into();
+        self
+    }
+
+    pub fn with_max_transmit_size(mut self, max_transmit_size: usize) -> Self {
+        self.max_transmit_size = max_transmit_size;
+        self
+    }
+
+    /// Largest data [`Component::publish_to`] hands to gossipsub on `topic`:
+    /// gossipsub limits the whole RPC carrying it, not the data alone
+    pub fn max_data_size(&self, topic: &IdentTopic) -> usize {
+        self.max_transmit_size
+            .saturating_sub(PUBLISH_FRAMING + topic.hash().as_str().len())
+    }
+
+    /// Starts dialing the bootstrap addresses, called when the event loop starts
+    pub fn dial_bootstrap(&self) {
+        let mut swarm = self.swarm.borrow_mut();
+        self.bootstrap.borrow_mut().dial_next(&mut *swarm);
+    }
+
+    /// Dials the next bootstrap address once a connection is established or
+    /// failed, `connection_id` may belong to any connection
+    pub fn dial_finished(&self, connection_id: ConnectionId) {
+        let mut swarm = self.swarm.borrow_mut();
+        self.bootstrap
+            .borrow_mut()
+            .finished(connection_id, &mut *swarm);
+    }
+
+    /// Waits for the next swarm event. The swarm is only borrowed while it is
+    /// polled, never across an await, so the other branches of an event loop
+    /// and the handling of the event itself are free to use it
+    pub async fn next_event(&self) -> SwarmEvent<MyBehaviourEvent> {
+        std::future::poll_fn(|cx| self.swarm.borrow_mut().poll_next_unpin(cx))
+            .await
+            .expect("the swarm stream never ends")
+    }
+}
+
+/// Bytes gossipsub adds around the data of a signed message in its RPC, the
+/// topic aside: source peer id, sequence number, signature, public key and
+/// the protobuf tags and lengths of every field
+pub const PUBLISH_FRAMING: usize = 192;
+
+/// How many unpublished messages a component keeps until a peer subscribes
+pub const OUTBOX_CAPACITY: usize = 128;
+
+/// Messages that couldn't be published yet because no peer was subscribed
+pub struct Outbox {
+    messages: VecDeque<(IdentTopic, Vec<u8>)>,
+    capacity: usize,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Queues a message, dropping the oldest one when the outbox is full
+    pub fn push(&mut self, topic: IdentTopic, data: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+            eprintln!("Outbox is full, dropping the oldest unpublished message");
+        }
+        self.messages.push_back((topic, data));
+    }
+
+    pub fn take(&mut self) -> VecDeque<(IdentTopic, Vec<u8>)> {
+        std::mem::take(&mut self.messages)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// Shared behaviour of nodes and managers.
+///
+/// The trait is object safe, so mixed components can be kept as
+/// `Box<dyn Component>` and driven by [`run_component`]. To keep it that way,
+/// new methods must not be generic, must not return `Self` and must take
+/// `self` by reference; `async fn`s are boxed by `async_trait`.
+#[async_trait::async_trait(?Send)]
+pub trait Component {
+    fn core(&self) -> &ComponentCore;
+    fn core_mut(&mut self) -> &mut ComponentCore;
+
+    async fn start_event_loop(&mut self);
+
+    fn publish_message(&self, msg: ComponentMessage) -> Result<(), ComponentError> {
+        let core = self.core();
+        let data = core.config.encode_to_vec(&msg)?;
+        self.publish_to(core.topic.clone(), data)
+    }
+
+    /// Publishes `data`, keeping it in the outbox if no peer is subscribed yet.
+    /// Data that wouldn't fit the max transmit size once framed is refused with
+    /// `MessageTooLarge` rather than left for gossipsub to fail on
+    fn publish_to(&self, topic: IdentTopic, data: Vec<u8>) -> Result<(), ComponentError> {
+        let data = match &self.core().psk {
+            Some(psk) => psk.encrypt(&data),
+            None => data,
+        };
+        if data.len() > self.core().max_data_size(&topic) {
+            return Err(ComponentError::MessageTooLarge(data.len()));
+        }
+        self.publish_sealed(topic, data)
+    }
+
+    /// Publishes `data` already encrypted by [`Component::publish_to`]
+    fn publish_sealed(&self, topic: IdentTopic, data: Vec<u8>) -> Result<(), ComponentError> {
+        let result = self
+            .core()
+            .swarm
+            .borrow_mut()
+            .behaviour_mut()
+            .gossipsub
+            .publish(topic.clone(), data.clone());
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(gossipsub::PublishError::NoPeersSubscribedToTopic) => {
+                self.core().outbox.borrow_mut().push(topic, data);
+                Ok(())
+            }
+            // the same data was published moments ago and is already in flight,
+            // e.g. a command retried by the operator
+            Err(gossipsub::PublishError::Duplicate) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Publishes the buffered messages again, called when a peer subscribes
+    fn flush_outbox(&self) {
+        let pending = self.core().outbox.borrow_mut().take();
+        for (topic, data) in pending {
+            if let Err(e) = self.publish_sealed(topic, data) {
+                eprintln!("Failed to publish buffered message: {e}");
+            }
+        }
+    }
+
+    /// Decrypts and decodes received data, `Ok(None)` for a message variant
+    /// unknown to this build, see [`decode_message`]
+    fn decode_gossip(
+        &self,
+        data: &[u8],
+    ) -> Result<Option<ComponentMessage<'static, 'static>>, ComponentError> {
+        let core = self.core();
+        match &core.psk {
+            Some(psk) => {
+                let data = psk.decrypt(data).ok_or(ComponentError::Decrypt())?;
+                Ok(decode_message(&data, core.config)?)
+            }
+            None => Ok(decode_message(data, core.config)?),
+        }
+    }
+
+    /// Reports whether a received message could be decoded, rejected messages
+    /// lower the score of the peers that sent them. Does nothing unless peer
+    /// scoring is enabled, gossipsub forwards the messages by itself then
+    fn report_validation(
+        &self,
+        message_id: &MessageId,
+        propagation_source: &PeerId,
+        acceptance: MessageAcceptance,
+    ) {
+        let core = self.core();
+        if !core.peer_scoring {
+            return;
+        }
+        core.swarm
+            .borrow_mut()
+            .behaviour_mut()
+            .gossipsub
+            .report_message_validation_result(message_id, propagation_source, acceptance);
+    }
+
+    /// Whether the message was published by this component, checked on the
+    /// signed source so the message doesn't have to be decoded
+    fn is_own_message(&self, message: &gossipsub::Message) -> bool {
+        message.source == Some(self.core().peer_id)
+    }
+}
+
+/// How a received message is reported to gossipsub: undecodable data is
+/// rejected, a variant unknown to this build is only ignored as it may come
+/// from a newer peer
+pub fn validation_result<T>(decoded: &Result<Option<T>, ComponentError>) -> MessageAcceptance {
+    match decoded {
+        Ok(Some(_)) => MessageAcceptance::Accept,
+        Ok(None) => MessageAcceptance::Ignore,
+        Err(_) => MessageAcceptance::Reject,
+    }
+}
+
+/// Runs the event loop of any component, e.g. one taken from a `Vec<Box<dyn Component>>`
+pub async fn run_component(component: &mut dyn Component) {
+    component.start_event_loop().await;
+}
+
+#[derive(Debug)]
+pub enum ComponentError {
+    Store(StoreError), // only for node
+    Decode(DecodeError),
+    Encode(EncodeError),
+    /// gossipsub refused to publish a message, see [`ComponentError::is_transient`]
+    Network(gossipsub::PublishError),
+    Timestamp(),
+    InvalidInput(), // only for manager
+    Decrypt(),
+    /// encoded size of a message over the max transmit size
+    MessageTooLarge(usize),
+    /// gossipsub refused to subscribe to a topic
+    Subscription(String),
+}
+
+impl ComponentError {
+    /// Whether the same message may be published once the mesh recovers, e.g.
+    /// when the send queues to every peer are full. Any other error comes
+    /// back on a retry
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ComponentError::Network(
+                gossipsub::PublishError::NoPeersSubscribedToTopic
+                    | gossipsub::PublishError::AllQueuesFull(_)
+            )
+        )
+    }
+}
+
+impl From<StoreError> for ComponentError {
+    fn from(err: StoreError) -> Self {
+        ComponentError::Store(err)
+    }
+}
+
+impl From<DecodeError> for ComponentError {
+    fn from(err: DecodeError) -> Self {
+        ComponentError::Decode(err)
+    }
+}
+
+impl From<EncodeError> for ComponentError {
+    fn from(err: EncodeError) -> Self {
+        ComponentError::Encode(err)
+    }
+}
+
+impl From<gossipsub::PublishError> for ComponentError {
+    fn from(err: gossipsub::PublishError) -> Self {
+        ComponentError::Network(err)
+    }
+}
+
+impl std::fmt::Display for ComponentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for ComponentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::Manager;
+    use crate::node::Node;
+    use crate::protocol::{metadata::MetaData, ManagerMessage};
+    use crate::store::command::StoreCommand;
+    use crate::utils::{
+        bin_args::BinArgs,
+        swarm_builder::{build_swarm, build_swarm_with_transport},
+    };
+    use futures::StreamExt;
+    use libp2p::{core::transport::MemoryTransport, identity, swarm::SwarmEvent};
+    use std::time::Duration;
+    use tokio::select;
+
+    fn swarm_parts() -> (Swarm<MyBehaviour>, PeerId, IdentTopic) {
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let swarm = build_swarm(key, &BinArgs::default()).unwrap();
+        (swarm, peer_id, IdentTopic::new("test-topic"))
+    }
+
+    #[tokio::test]
+    async fn heterogeneous_components_in_one_collection() {
+        let args = BinArgs::default();
+        let (swarm, peer_id, topic) = swarm_parts();
+        let node = Node::new(swarm, peer_id, topic, &args);
+        let (swarm, peer_id, topic) = swarm_parts();
+        let manager = Manager::new(swarm, peer_id, topic, &args);
+
+        let components: Vec<Box<dyn Component>> = vec![Box::new(node), Box::new(manager)];
+        let peer_ids: Vec<PeerId> = components.iter().map(|c| c.core().peer_id).collect();
+
+        assert_eq!(peer_ids.len(), 2);
+        assert_ne!(peer_ids[0], peer_ids[1]);
+    }
+
+    #[tokio::test]
+    async fn run_component_drives_boxed_event_loop() {
+        let (swarm, peer_id, topic) = swarm_parts();
+        let mut component: Box<dyn Component> =
+            Box::new(Node::new(swarm, peer_id, topic, &BinArgs::default()));
+
+        // the loop never ends on its own, it is still running when the timeout fires
+        let result =
+            tokio::time::timeout(Duration::from_millis(50), run_component(component.as_mut()))
+                .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_messages_are_refused_before_publishing() {
+        let (swarm, peer_id, topic) = swarm_parts();
+        let args = BinArgs {
+            gossipsub_max_transmit_size: 1024,
+            ..Default::default()
+        };
+        let node = Node::new(swarm, peer_id, topic, &args);
+        let message = |value: &str| {
+            ComponentMessage::ManagerMessage(
+                ManagerMessage::StoreCommand(StoreCommand::set("color", value.to_string())),
+                MetaData::new("manager", 0),
+            )
+        };
+
+        assert!(node.publish_message(message("red")).is_ok());
+        assert!(matches!(
+            node.publish_message(message(&"x".repeat(2048))),
+            Err(ComponentError::MessageTooLarge(size)) if size > 2048
+        ));
+        // only the small message waits in the outbox for a peer
+        assert_eq!(node.core().outbox.borrow().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn data_just_under_the_limit_fits_the_gossipsub_rpc() {
+        let args = BinArgs {
+            gossipsub_max_transmit_size: 1024,
+            ..Default::default()
+        };
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let swarm = build_swarm(key, &args).unwrap();
+        let topic = IdentTopic::new("test-topic");
+        let node = Node::new(swarm, peer_id, topic.clone(), &args);
+        let limit = node.core().max_data_size(&topic);
+
+        // gossipsub accepts the framed message, it waits in the outbox for a peer
+        assert!(node.publish_to(topic.clone(), vec![7; limit]).is_ok());
+        assert_eq!(node.core().outbox.borrow().len(), 1);
+        assert!(matches!(
+            node.publish_to(topic, vec![7; limit + 1]),
+            Err(ComponentError::MessageTooLarge(size)) if size == limit + 1
+        ));
+    }
+
+    #[test]
+    fn each_error_source_maps_to_its_variant() {
+        assert!(matches!(
+            ComponentError::from(StoreError::KeyTooLong(40)),
+            ComponentError::Store(StoreError::KeyTooLong(40))
+        ));
+        assert!(matches!(
+            ComponentError::from(EncodeError::Other("encode")),
+            ComponentError::Encode(_)
+        ));
+        assert!(matches!(
+            ComponentError::from(DecodeError::Other("decode")),
+            ComponentError::Decode(_)
+        ));
+        assert!(matches!(
+            ComponentError::from(gossipsub::PublishError::MessageTooLarge),
+            ComponentError::Network(gossipsub::PublishError::MessageTooLarge)
+        ));
+    }
+
+    #[test]
+    fn only_network_errors_of_a_busy_mesh_are_transient() {
+        assert!(ComponentError::from(gossipsub::PublishError::AllQueuesFull(3)).is_transient());
+        assert!(
+            ComponentError::from(gossipsub::PublishError::NoPeersSubscribedToTopic).is_transient()
+        );
+        assert!(!ComponentError::from(gossipsub::PublishError::MessageTooLarge).is_transient());
+        assert!(!ComponentError::from(StoreError::TimestampError).is_transient());
+        assert!(!ComponentError::Timestamp().is_transient());
+    }
+
+    #[tokio::test]
+    async fn truncated_gossip_is_a_decode_error() {
+        let (swarm, peer_id, topic) = swarm_parts();
+        let node = Node::new(swarm, peer_id, topic, &BinArgs::default());
+
+        assert!(matches!(
+            node.decode_gossip(&[]),
+            Err(ComponentError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn outbox_drops_oldest_when_full() {
+        let topic = IdentTopic::new("test-topic");
+        let mut outbox = Outbox::new(2);
+        for data in [b"a", b"b", b"c"] {
+            outbox.push(topic.clone(), data.to_vec());
+        }
+
+        let data: Vec<Vec<u8>> = outbox.take().into_iter().map(|(_, data)| data).collect();
+        assert_eq!(data, vec![b"b".to_vec(), b"c".to_vec()]);
+        assert!(outbox.is_empty());
+    }
+
+    #[tokio::test]
+    async fn psk_messages_only_decode_with_the_same_key() {
+        let node_with_psk = |psk: Option<&str>| {
+            let (swarm, peer_id, topic) = swarm_parts();
+            let args = BinArgs {
+                psk: psk.map(str::to_string),
+                ..Default::default()
+            };
+            Node::new(swarm, peer_id, topic, &args)
+        };
+
+        let sender = node_with_psk(Some("secret"));
+        let msg = ComponentMessage::ManagerMessage(
+            ManagerMessage::StoreCommand(StoreCommand::get("color")),
+            MetaData::new("manager", 0),
+        );
+        let data = sender.core().config.encode_to_vec(&msg).unwrap();
+        let sealed = sender.core().psk.as_ref().unwrap().encrypt(&data);
+
+        assert!(matches!(
+            node_with_psk(Some("secret")).decode_gossip(&sealed),
+            Ok(Some(ComponentMessage::ManagerMessage(..)))
+        ));
+        assert!(matches!(
+            node_with_psk(Some("other")).decode_gossip(&sealed),
+            Err(ComponentError::Decrypt())
+        ));
+        assert!(!matches!(
+            node_with_psk(None).decode_gossip(&sealed),
+            Ok(Some(_))
+        ));
+    }
+
+    fn memory_swarm() -> (Swarm<MyBehaviour>, PeerId, IdentTopic) {
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let transport = MemoryTransport::default();
+        let mut swarm = build_swarm_with_transport(key, &BinArgs::default(), transport).unwrap();
+        let topic = IdentTopic::new("test-topic");
+        swarm.behaviour_mut().gossipsub.subscribe(&topic).unwrap();
+        (swarm, peer_id, topic)
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn duplicate_publish_is_not_an_error() {
+        let (swarm, peer_id, topic) = memory_swarm();
+        let node = Node::new(swarm, peer_id, topic.clone(), &BinArgs::default());
+
+        let (mut peer, _, _) = memory_swarm();
+        peer.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = peer.select_next_some().await {
+                break address;
+            }
+        };
+        node.core().swarm.borrow_mut().dial(address).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut swarm = node.core().swarm.borrow_mut();
+            loop {
+                select! {
+                    event = swarm.select_next_some() => {
+                        if let SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                            gossipsub::Event::Subscribed { .. },
+                        )) = event
+                        {
+                            return;
+                        }
+                    }
+                    _ = peer.select_next_some() => {}
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert!(node.publish_to(topic.clone(), b"twice".to_vec()).is_ok());
+        let duplicate = node
+            .core()
+            .swarm
+            .borrow_mut()
+            .behaviour_mut()
+            .gossipsub
+            .publish(topic.clone(), b"twice".to_vec());
+        assert!(matches!(duplicate, Err(gossipsub::PublishError::Duplicate)));
+        assert!(node.publish_to(topic, b"twice".to_vec()).is_ok());
+        assert!(node.core().outbox.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn buffered_message_is_delivered_once_peer_joins() {
+        let (swarm, peer_id, topic) = memory_swarm();
+        let node = Node::new(swarm, peer_id, topic.clone(), &BinArgs::default());
+
+        // nobody is subscribed yet, the message waits in the outbox
+        assert!(node.publish_to(topic, b"early".to_vec()).is_ok());
+        assert_eq!(node.core().outbox.borrow().len(), 1);
+
+        let (mut peer, _, _) = memory_swarm();
+        peer.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = peer.select_next_some().await {
+                break address;
+            }
+        };
+        node.core().swarm.borrow_mut().dial(address).unwrap();
+
+        let delivered = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let node_event = {
+                    let mut swarm = node.core().swarm.borrow_mut();
+                    select! {
+                        event = swarm.select_next_some() => Some(event),
+                        event = peer.select_next_some() => {
+                            if let SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                                gossipsub::Event::Message { message, .. },
+                            )) = event
+                            {
+                                return message.data;
+                            }
+                            None
+                        }
+                    }
+                };
+                if let Some(SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                    gossipsub::Event::Subscribed { .. },
+                ))) = node_event
+                {
+                    node.flush_outbox();
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(delivered, b"early".to_vec());
+        assert!(node.core().outbox.borrow().is_empty());
+    }
+}
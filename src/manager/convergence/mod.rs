@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Nodes that reported the same root
+#[derive(Debug, PartialEq)]
+pub struct RootGroup {
+    /// the monotree root, `None` for an empty store
+    pub root: Option<[u8; 32]>,
+    /// every node holding this root with its key count, sorted by peer id
+    pub nodes: Vec<(String, usize)>,
+}
+
+/// Roots reported by the nodes answering a VERIFY request
+#[derive(Debug, PartialEq)]
+pub struct ConvergenceReport {
+    /// sorted by decreasing number of nodes, the most common root first
+    pub groups: Vec<RootGroup>,
+}
+
+impl ConvergenceReport {
+    pub fn aggregate(snapshots: &HashMap<String, (Option<[u8; 32]>, usize)>) -> Self {
+        let mut by_root: HashMap<Option<[u8; 32]>, Vec<(String, usize)>> = HashMap::new();
+        for (peer_id, &(root, keys)) in snapshots {
+            by_root
+                .entry(root)
+                .or_default()
+                .push((peer_id.clone(), keys));
+        }
+
+        let mut groups: Vec<RootGroup> = by_root
+            .into_iter()
+            .map(|(root, mut nodes)| {
+                nodes.sort();
+                RootGroup { root, nodes }
+            })
+            .collect();
+        // ties are broken by root so the report is stable
+        groups.sort_by(|a, b| {
+            b.nodes
+                .len()
+                .cmp(&a.nodes.len())
+                .then_with(|| a.root.cmp(&b.root))
+        });
+
+        Self { groups }
+    }
+
+    /// Whether every answering node holds the same root
+    pub fn is_converged(&self) -> bool {
+        self.groups.len() <= 1
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.groups.iter().map(|group| group.nodes.len()).sum()
+    }
+}
+
+struct PendingVerify {
+    deadline: Instant,
+    snapshots: HashMap<String, (Option<[u8; 32]>, usize)>,
+}
+
+/// Collects the root and key count of every node answering a VERIFY request
+/// until its window closes
+pub struct ConvergenceTracker {
+    pending: HashMap<u64, PendingVerify>,
+}
+
+impl ConvergenceTracker {
+    pub fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn track(&mut self, request_id: u64, deadline: Instant) {
+        self.pending.insert(
+            request_id,
+            PendingVerify {
+                deadline,
+                snapshots: HashMap::new(),
+            },
+        );
+    }
+
+    /// Records a node snapshot, returns `false` if the request isn't collected
+    pub fn record(
+        &mut self,
+        request_id: u64,
+        peer_id: String,
+        root: Option<[u8; 32]>,
+        keys: usize,
+    ) -> bool {
+        match self.pending.get_mut(&request_id) {
+            Some(verify) => {
+                verify.snapshots.insert(peer_id, (root, keys));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the request and returns its report, whether its window has
+    /// closed or not
+    pub fn take(&mut self, request_id: u64) -> Option<ConvergenceReport> {
+        self.pending
+            .remove(&request_id)
+            .map(|verify| ConvergenceReport::aggregate(&verify.snapshots))
+    }
+
+    /// Removes the requests whose window has closed and returns their reports
+    pub fn take_expired(&mut self, now: Instant) -> Vec<ConvergenceReport> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, verify)| verify.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .map(|verify| ConvergenceReport::aggregate(&verify.snapshots))
+            .collect()
+    }
+}
+
+impl Default for ConvergenceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn snapshots(
+        entries: &[(&str, Option<u8>, usize)],
+    ) -> HashMap<String, (Option<[u8; 32]>, usize)> {
+        entries
+            .iter()
+            .map(|&(peer_id, root, keys)| (peer_id.to_string(), (root.map(|r| [r; 32]), keys)))
+            .collect()
+    }
+
+    #[test]
+    fn test_aggregate_groups_mixed_roots() {
+        let report = ConvergenceReport::aggregate(&snapshots(&[
+            ("p1", Some(1), 5),
+            ("p2", Some(2), 4),
+            ("p3", Some(1), 5),
+            ("p4", None, 0),
+            ("p5", Some(1), 5),
+        ]));
+
+        assert!(!report.is_converged());
+        assert_eq!(report.node_count(), 5);
+        assert_eq!(
+            report.groups[0],
+            RootGroup {
+                root: Some([1; 32]),
+                nodes: vec![
+                    ("p1".to_string(), 5),
+                    ("p3".to_string(), 5),
+                    ("p5".to_string(), 5)
+                ],
+            }
+        );
+        // single node groups are ordered by root, an empty store first
+        assert_eq!(report.groups[1].root, None);
+        assert_eq!(report.groups[2].nodes, vec![("p2".to_string(), 4)]);
+    }
+
+    #[test]
+    fn test_aggregate_single_root_is_converged() {
+        let report =
+            ConvergenceReport::aggregate(&snapshots(&[("p1", Some(1), 3), ("p2", Some(1), 3)]));
+        assert!(report.is_converged());
+        assert_eq!(report.groups.len(), 1);
+
+        let empty = ConvergenceReport::aggregate(&HashMap::new());
+        assert!(empty.is_converged());
+        assert_eq!(empty.node_count(), 0);
+    }
+
+    #[test]
+    fn test_take_expired_collects_snapshots() {
+        let mut tracker = ConvergenceTracker::new();
+        let now = Instant::now();
+        tracker.track(1, now);
+        tracker.track(2, now + Duration::from_secs(60));
+
+        assert!(tracker.record(1, "p1".to_string(), Some([1; 32]), 2));
+        assert!(tracker.record(1, "p2".to_string(), Some([2; 32]), 2));
+        assert!(!tracker.record(3, "p1".to_string(), None, 0));
+
+        let reports = tracker.take_expired(now);
+        assert_eq!(reports.len(), 1);
+        assert!(!reports[0].is_converged());
+        assert!(tracker.take_expired(now).is_empty());
+    }
+
+    #[test]
+    fn test_take_before_the_window_closes() {
+        let mut tracker = ConvergenceTracker::new();
+        tracker.track(1, Instant::now() + Duration::from_secs(60));
+        assert!(tracker.record(1, "p1".to_string(), Some([1; 32]), 2));
+
+        let report = tracker.take(1).unwrap();
+        assert_eq!(report.node_count(), 1);
+        assert!(tracker.take(1).is_none());
+        assert!(!tracker.record(1, "p2".to_string(), Some([1; 32]), 2));
+    }
+}
@@ -0,0 +1,215 @@
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    validate::Validator, Context, Editor, Helper,
+};
+use tokio::{io, io::AsyncBufReadExt, sync::mpsc};
+
+use crate::store::command::{CmdArgs, COMMAND_NAMES};
+
+/// Commands handled by the manager itself rather than sent as store commands
+pub const MANAGER_COMMANDS: &[&str] = &[
+    "QGET",
+    "DIFF",
+    "VERIFY",
+    "WATCH",
+    "SIGNAL",
+    "BAN",
+    "UNBAN",
+    "SWITCHTOPIC",
+];
+
+/// Why an input line holds no command
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// the line is blank, the readers skip it
+    Empty,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "the line holds no command"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Splits an input line into the command, its first word, and the arguments,
+/// the rest of the line with the whitespace around it removed
+pub fn parse_line(line: &str) -> Result<CmdArgs, ParseError> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    let (cmd_type, cmd_arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    Ok(CmdArgs {
+        cmd_type: cmd_type.to_string(),
+        cmd_arg: cmd_arg.trim_start().to_string(),
+    })
+}
+
+/// Sends the commands read line by line from stdin until it is closed
+pub fn spawn_stdin(tx: mpsc::Sender<CmdArgs>) {
+    tokio::spawn(async move {
+        let stdin = io::stdin();
+        let mut reader = io::BufReader::new(stdin).lines();
+
+        loop {
+            match reader.next_line().await {
+                Ok(Some(line)) => {
+                    if let Ok(args) = parse_line(&line)
+                        && tx.send(args).await.is_err()
+                    {
+                        break;
+                    }
+                }
+                Ok(None) => break, // EOF
+                Err(e) => {
+                    eprintln!("Input error: {e}");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Sends the commands read through a line editor with history, arrow-key
+/// editing and completion of the command names, until Ctrl-D. The editor
+/// blocks, so it gets a thread of its own
+pub fn spawn_editor(tx: mpsc::Sender<CmdArgs>) {
+    std::thread::spawn(move || {
+        let mut editor = match Editor::new() {
+            Ok(editor) => editor,
+            Err(e) => {
+                eprintln!("Input error: {e}");
+                return;
+            }
+        };
+        editor.set_helper(Some(CommandHelper));
+
+        loop {
+            match editor.readline("> ") {
+                Ok(line) => {
+                    let Ok(args) = parse_line(&line) else {
+                        continue;
+                    };
+                    let _ = editor.add_history_entry(line.trim());
+                    if tx.blocking_send(args).is_err() {
+                        break;
+                    }
+                }
+                // Ctrl-C only drops the line being edited
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => {
+                    eprintln!("Input error: {e}");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Known command names starting with `prefix`, whatever its case
+pub fn completions(prefix: &str) -> Vec<&'static str> {
+    let prefix = prefix.to_uppercase();
+    let mut names: Vec<&'static str> = COMMAND_NAMES
+        .iter()
+        .chain(MANAGER_COMMANDS)
+        .copied()
+        .filter(|name| name.starts_with(&prefix))
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+/// Completes the command name, the first word of the line
+struct CommandHelper;
+
+impl Completer for CommandHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let word = &line[..pos];
+        if word.contains(char::is_whitespace) {
+            return Ok((pos, Vec::new()));
+        }
+        let names = completions(word).into_iter().map(String::from).collect();
+        Ok((0, names))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustyline::history::MemHistory;
+
+    #[test]
+    fn known_commands_are_completed() {
+        assert_eq!(
+            completions("h"),
+            ["HDEL", "HGET", "HGETALL", "HINCRBY", "HSET"]
+        );
+        assert_eq!(completions("qg"), ["QGET"]);
+        assert!(completions("").contains(&"WATCH"));
+        assert!(completions("nope").is_empty());
+
+        let history = MemHistory::new();
+        let ctx = Context::new(&history);
+        let (start, names) = CommandHelper.complete("rena", 4, &ctx).unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(names, ["RENAME", "RENAMENX"]);
+
+        // the arguments are not completed
+        let (_, names) = CommandHelper.complete("GET h", 5, &ctx).unwrap();
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn parse_line_splits_the_command() {
+        let args = parse_line("  SET color red  ").unwrap();
+        assert_eq!(args.cmd_type, "SET");
+        assert_eq!(args.cmd_arg, "color red");
+    }
+
+    #[test]
+    fn parse_line_rejects_blank_lines() {
+        assert_eq!(parse_line("").unwrap_err(), ParseError::Empty);
+        assert_eq!(parse_line(" \t ").unwrap_err(), ParseError::Empty);
+    }
+
+    #[test]
+    fn parse_line_accepts_a_command_alone() {
+        let args = parse_line("ttls").unwrap();
+        assert_eq!(args.cmd_type, "ttls");
+        assert_eq!(args.cmd_arg, "");
+
+        let args = parse_line(" VERIFY\t").unwrap();
+        assert_eq!(args.cmd_type, "VERIFY");
+        assert_eq!(args.cmd_arg, "");
+    }
+
+    #[test]
+    fn parse_line_keeps_multi_word_arguments_whole() {
+        let args = parse_line("SET\tgreeting   hello  big world").unwrap();
+        assert_eq!(args.cmd_type, "SET");
+        // only the whitespace after the command goes, the value keeps its own
+        assert_eq!(args.cmd_arg, "greeting   hello  big world");
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Difference between the key sets of two nodes
+#[derive(Debug, PartialEq)]
+pub struct KeyDiff {
+    pub left: String,
+    pub right: String,
+    /// keys present only on the left node
+    pub only_left: Vec<String>,
+    /// keys present only on the right node
+    pub only_right: Vec<String>,
+    /// keys present on both nodes with different values
+    pub changed: Vec<String>,
+}
+
+impl KeyDiff {
+    pub fn compute(
+        left: String,
+        right: String,
+        left_hashes: &HashMap<String, [u8; 32]>,
+        right_hashes: &HashMap<String, [u8; 32]>,
+    ) -> Self {
+        let mut only_left = Vec::new();
+        let mut changed = Vec::new();
+        for (key, hash) in left_hashes {
+            match right_hashes.get(key) {
+                None => only_left.push(key.clone()),
+                Some(other) if other != hash => changed.push(key.clone()),
+                Some(_) => {}
+            }
+        }
+        let mut only_right: Vec<String> = right_hashes
+            .keys()
+            .filter(|key| !left_hashes.contains_key(*key))
+            .cloned()
+            .collect();
+
+        only_left.sort();
+        only_right.sort();
+        changed.sort();
+
+        Self {
+            left,
+            right,
+            only_left,
+            only_right,
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.only_left.is_empty() && self.only_right.is_empty() && self.changed.is_empty()
+    }
+}
+
+struct PendingDiff {
+    left: String,
+    right: String,
+    deadline: Instant,
+    hashes: HashMap<String, HashMap<String, [u8; 32]>>,
+}
+
+/// Collects the key hashes of the two nodes of every DIFF request
+pub struct DiffTracker {
+    pending: HashMap<u64, PendingDiff>,
+}
+
+impl DiffTracker {
+    pub fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn track(&mut self, request_id: u64, left: String, right: String, deadline: Instant) {
+        self.pending.insert(
+            request_id,
+            PendingDiff {
+                left,
+                right,
+                deadline,
+                hashes: HashMap::new(),
+            },
+        );
+    }
+
+    /// Records the key hashes of a node, returns the diff once both nodes answered
+    pub fn record(
+        &mut self,
+        request_id: u64,
+        peer_id: String,
+        hashes: HashMap<String, [u8; 32]>,
+    ) -> Option<KeyDiff> {
+        let diff = self.pending.get_mut(&request_id)?;
+        if peer_id != diff.left && peer_id != diff.right {
+            return None;
+        }
+        diff.hashes.insert(peer_id, hashes);
+
+        let (Some(left_hashes), Some(right_hashes)) =
+            (diff.hashes.get(&diff.left), diff.hashes.get(&diff.right))
+        else {
+            return None;
+        };
+        let diff = KeyDiff::compute(
+            diff.left.clone(),
+            diff.right.clone(),
+            left_hashes,
+            right_hashes,
+        );
+        self.pending.remove(&request_id);
+        Some(diff)
+    }
+
+    /// Removes the requests whose window has closed, returning the nodes that never answered
+    pub fn take_expired(&mut self, now: Instant) -> Vec<Vec<String>> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, diff)| diff.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .map(|diff| {
+                [diff.left, diff.right]
+                    .into_iter()
+                    .filter(|peer_id| !diff.hashes.contains_key(peer_id))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl Default for DiffTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn hashes(entries: &[(&str, u8)]) -> HashMap<String, [u8; 32]> {
+        entries
+            .iter()
+            .map(|&(key, hash)| (key.to_string(), [hash; 32]))
+            .collect()
+    }
+
+    #[test]
+    fn test_compute_detects_added_removed_and_changed_keys() {
+        let diff = KeyDiff::compute(
+            "p1".to_string(),
+            "p2".to_string(),
+            &hashes(&[("a", 1), ("b", 2), ("c", 3)]),
+            &hashes(&[("b", 2), ("c", 4), ("d", 5)]),
+        );
+
+        assert_eq!(diff.only_left, vec!["a"]);
+        assert_eq!(diff.only_right, vec!["d"]);
+        assert_eq!(diff.changed, vec!["c"]);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_compute_identical_maps() {
+        let map = hashes(&[("a", 1), ("b", 2)]);
+        let diff = KeyDiff::compute("p1".to_string(), "p2".to_string(), &map, &map);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_record_waits_for_both_nodes() {
+        let mut tracker = DiffTracker::new();
+        tracker.track(1, "p1".to_string(), "p2".to_string(), Instant::now());
+
+        assert!(tracker
+            .record(1, "p1".to_string(), hashes(&[("a", 1)]))
+            .is_none());
+        assert!(tracker.record(1, "p3".to_string(), hashes(&[])).is_none());
+
+        let diff = tracker.record(1, "p2".to_string(), hashes(&[])).unwrap();
+        assert_eq!(diff.only_left, vec!["a"]);
+        assert!(tracker.record(1, "p2".to_string(), hashes(&[])).is_none());
+    }
+
+    #[test]
+    fn test_take_expired_reports_missing_nodes() {
+        let mut tracker = DiffTracker::new();
+        let now = Instant::now();
+        tracker.track(1, "p1".to_string(), "p2".to_string(), now);
+        tracker.track(
+            2,
+            "p1".to_string(),
+            "p2".to_string(),
+            now + Duration::from_secs(60),
+        );
+        tracker.record(1, "p2".to_string(), hashes(&[]));
+
+        assert_eq!(tracker.take_expired(now), vec![vec!["p1".to_string()]]);
+        assert!(tracker.take_expired(now).is_empty());
+    }
+}
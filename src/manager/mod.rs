@@ -1,50 +1,691 @@
-use bincode::config;
-use futures::stream::StreamExt;
 use libp2p::{
-    gossipsub::{self, IdentTopic},
+    gossipsub::{self, IdentTopic, MessageAcceptance},
     mdns,
     swarm::SwarmEvent,
     PeerId, Swarm,
 };
-use tokio::{io, io::AsyncBufReadExt, select};
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+use tokio::{select, sync::mpsc};
 
-use crate::{utils::timestamp::timestamp_millis, Component, ComponentCore, ComponentError};
+use crate::{
+    utils::timestamp::timestamp_millis, validation_result, Component, ComponentCore, ComponentError,
+};
 
 use crate::node::{MyBehaviour, MyBehaviourEvent};
-use crate::protocol::{metadata::MetaData, ComponentMessage, ManagerMessage, NodeMessage};
-use crate::store::command::{handle_cmd_input, CmdArgs};
+use crate::protocol::{
+    metadata::MetaData, BanParams, ComponentMessage, KeyChangedParams, ManagerMessage,
+    NodeMessage, SignalParams, SwitchTopicParams, TargetedStoreCommandParams,
+};
+use crate::store::command::{handle_cmd_input, CmdArgs, GETParams, StoreCommand};
+use crate::store::result::StoreCommandResult;
+use crate::utils::{
+    bin_args::{BinArgs, OutputFormat},
+    bootstrap::Bootstrap,
+    hex::to_hex,
+    psk::Psk,
+};
+
+pub mod convergence;
+pub mod input;
+pub mod key_diff;
+pub mod response_collector;
+pub mod session_cache;
+pub mod shard_router;
+
+use convergence::{ConvergenceReport, ConvergenceTracker};
+use key_diff::{DiffTracker, KeyDiff};
+use response_collector::{QuorumOutcome, ResponseCollector};
+use session_cache::SessionCache;
+use shard_router::ShardRouter;
 
 pub struct Manager {
     core: ComponentCore,
+    next_request_id: u64,
+    collector: ResponseCollector,
+    diffs: DiffTracker,
+    verifications: ConvergenceTracker,
+    quorum_window: Duration,
+    read_repair: bool,
+    dry_run: bool,
+    observer: bool,
+    /// read the commands through a line editor when stdin is a terminal
+    interactive: bool,
+    output: OutputFormat,
+    /// keys whose change notifications are printed
+    watched: HashSet<String>,
+    /// own recent writes, only kept with `--read-your-writes`
+    session: Option<SessionCache>,
+    /// the shard of every key, only with `--shards`
+    router: Option<ShardRouter>,
 }
 
 #[allow(dead_code)]
 impl Manager {
-    pub fn new(swarm: Swarm<MyBehaviour>, peer_id: PeerId, topic: IdentTopic) -> Self {
+    pub fn new(
+        swarm: Swarm<MyBehaviour>,
+        peer_id: PeerId,
+        topic: IdentTopic,
+        args: &BinArgs,
+    ) -> Self {
         Self {
-            core: ComponentCore {
-                swarm: swarm.into(),
-                peer_id,
-                topic,
-                config: config::standard(),
-            },
+            core: ComponentCore::new(swarm, peer_id, topic)
+                .with_psk(args.psk.as_deref().map(Psk::new))
+                .with_peer_scoring(args.enable_peer_scoring)
+                .with_bootstrap(Bootstrap::from_args(args))
+                .with_bincode_config(args.bincode_config)
+                .with_max_transmit_size(args.gossipsub_max_transmit_size),
+            next_request_id: 0,
+            collector: ResponseCollector::new(),
+            diffs: DiffTracker::new(),
+            verifications: ConvergenceTracker::new(),
+            quorum_window: Duration::from_millis(args.quorum_window_ms),
+            read_repair: args.read_repair,
+            dry_run: args.dry_run,
+            observer: args.observer,
+            interactive: args.interactive,
+            output: args.output,
+            watched: HashSet::new(),
+            session: args.read_your_writes.then(|| {
+                SessionCache::new(Duration::from_millis(args.read_your_writes_window_ms))
+            }),
+            router: args.shards.map(|shards| ShardRouter::new(&args.topic, shards)),
         }
     }
 
     pub fn execute_user_input(&mut self, args: CmdArgs) -> Result<(), ComponentError> {
+        if args.cmd_type.eq_ignore_ascii_case("QGET") {
+            return self.quorum_get(&args.cmd_arg);
+        }
+        if args.cmd_type.eq_ignore_ascii_case("DIFF") {
+            return self.diff(&args.cmd_arg);
+        }
+        if args.cmd_type.eq_ignore_ascii_case("VERIFY") {
+            return self.verify(&args.cmd_arg);
+        }
+        if args.cmd_type.eq_ignore_ascii_case("WATCH") {
+            return self.watch(&args.cmd_arg);
+        }
+        if args.cmd_type.eq_ignore_ascii_case("SIGNAL") {
+            return self.signal(&args.cmd_arg);
+        }
+        if args.cmd_type.eq_ignore_ascii_case("BAN") {
+            return self.ban(&args.cmd_arg, true);
+        }
+        if args.cmd_type.eq_ignore_ascii_case("UNBAN") {
+            return self.ban(&args.cmd_arg, false);
+        }
+        if args.cmd_type.eq_ignore_ascii_case("SWITCHTOPIC") {
+            return self.switch_topic(&args.cmd_arg);
+        }
+
         let store_cmd = handle_cmd_input(&args).ok_or(ComponentError::InvalidInput())?;
+        if let Some(session) = &mut self.session
+            && let StoreCommand::GET(GETParams { key }) = &store_cmd
+            && let Some(value) = session.lookup(key, Instant::now())
+        {
+            Self::print_own_write(self.output, key, value);
+            return Ok(());
+        }
+        let request_id = self.next_request_id();
+        // a dry run sends nothing, so no node confirms the write
+        if !self.dry_run
+            && let Some(session) = &mut self.session
+        {
+            session.track(request_id, &store_cmd, Instant::now());
+        }
+
+        let published = self.publish_command(ManagerMessage::StoreCommand(store_cmd), request_id);
+        if published.is_err()
+            && let Some(session) = &mut self.session
+        {
+            session.untrack(request_id);
+        }
+        published
+    }
+
+    fn next_request_id(&mut self) -> u64 {
+        self.next_request_id += 1;
+        self.next_request_id
+    }
 
+    fn publish_command(&self, msg: ManagerMessage, request_id: u64) -> Result<(), ComponentError> {
+        let topics = self.route(&msg)?;
         let timestamp = timestamp_millis().ok_or(ComponentError::Timestamp())?;
 
-        let metadata = MetaData::new(self.core.peer_id, timestamp);
+        let metadata =
+            MetaData::new(self.core.peer_id, timestamp).with_request_id(Some(request_id));
 
-        let msg =
-            ComponentMessage::ManagerMessage(ManagerMessage::StoreCommand(store_cmd), metadata);
+        let msg = ComponentMessage::ManagerMessage(msg, metadata);
 
         println!("Compmsg: {:?}", msg);
 
-        self.publish_message(msg)
-            .map_err(|e| ComponentError::Publish(e.to_string()))
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let data = self.core.config.encode_to_vec(&msg)?;
+        for topic in topics {
+            self.publish_to(topic, data.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Topics a message is published to: the manager topic, or with `--shards`
+    /// the shard of the command keys and every shard for the other messages
+    fn route(&self, msg: &ManagerMessage) -> Result<Vec<IdentTopic>, ComponentError> {
+        let Some(router) = &self.router else {
+            return Ok(vec![self.core.topic.clone()]);
+        };
+        let keys = match msg {
+            ManagerMessage::StoreCommand(cmd) => cmd.key_args(),
+            _ => Vec::new(),
+        };
+        if keys.is_empty() {
+            return Ok(router.topics().to_vec());
+        }
+        match router.common_shard(keys) {
+            Some(shard) => Ok(vec![router.topic(shard).clone()]),
+            None => {
+                eprintln!("Error: the keys of the command belong to different shards");
+                Err(ComponentError::InvalidInput())
+            }
+        }
+    }
+
+    /// Sends a GET to every node and collects the answers for the quorum window
+    fn quorum_get(&mut self, cmd_arg: &str) -> Result<(), ComponentError> {
+        let mut keys = cmd_arg.split_whitespace();
+        let key = match (keys.next(), keys.next()) {
+            (Some(key), None) => key,
+            _ => {
+                eprintln!("Error: 'QGET' requires exactly one key");
+                return Err(ComponentError::InvalidInput());
+            }
+        };
+
+        let request_id = self.next_request_id();
+        if !self.dry_run {
+            self.collector.track_get(
+                request_id,
+                key.to_string(),
+                Instant::now() + self.quorum_window,
+            );
+        }
+
+        self.publish_command(
+            ManagerMessage::StoreCommand(StoreCommand::get(key)),
+            request_id,
+        )
+    }
+
+    /// Asks two nodes for their key hashes and prints how their key sets differ
+    fn diff(&mut self, cmd_arg: &str) -> Result<(), ComponentError> {
+        let mut peers = cmd_arg.split_whitespace();
+        let (left, right) = match (peers.next(), peers.next(), peers.next()) {
+            (Some(left), Some(right), None) if left != right => (left, right),
+            _ => {
+                eprintln!("Error: 'DIFF' requires two different peer ids");
+                return Err(ComponentError::InvalidInput());
+            }
+        };
+
+        let request_id = self.next_request_id();
+        if !self.dry_run {
+            self.diffs.track(
+                request_id,
+                left.to_string(),
+                right.to_string(),
+                Instant::now() + self.quorum_window,
+            );
+        }
+
+        let params = TargetedStoreCommandParams::new(
+            vec![left.to_string(), right.to_string()],
+            StoreCommand::keyhashes(),
+        );
+        self.publish_command(ManagerMessage::TargetedStoreCommand(params), request_id)
+    }
+
+    /// Makes the listed nodes, or every node without arguments, share their
+    /// signature right away so a divergence is noticed without waiting
+    fn signal(&mut self, cmd_arg: &str) -> Result<(), ComponentError> {
+        let dst_ids = cmd_arg.split_whitespace().map(str::to_string).collect();
+        let request_id = self.next_request_id();
+        self.publish_command(
+            ManagerMessage::Signal(SignalParams::new(dst_ids)),
+            request_id,
+        )
+    }
+
+    /// Prints a GET answered with a value this manager wrote itself
+    fn print_own_write(output: OutputFormat, key: &str, value: Option<&str>) {
+        if output == OutputFormat::Json {
+            // the version is only known to the nodes
+            let result = serde_json::json!({"type": "GET", "payload": value});
+            println!("{}", serde_json::json!({"session": true, "result": result}));
+        } else {
+            println!("GET {key}: {value:?} (written by this manager)");
+        }
+    }
+
+    /// Makes every node drop the messages of a peer, or accept them again
+    fn ban(&mut self, cmd_arg: &str, banned: bool) -> Result<(), ComponentError> {
+        let name = if banned { "BAN" } else { "UNBAN" };
+        let mut parts = cmd_arg.split_whitespace();
+        let (Some(peer_id), None) = (parts.next(), parts.next()) else {
+            eprintln!("Error: '{name}' requires exactly one peer id");
+            return Err(ComponentError::InvalidInput());
+        };
+        if peer_id.parse::<PeerId>().is_err() {
+            eprintln!("Error: '{peer_id}' is not a valid peer id");
+            return Err(ComponentError::InvalidInput());
+        }
+
+        let params = BanParams::new(peer_id.to_string());
+        let msg = match banned {
+            true => ManagerMessage::Ban(params),
+            false => ManagerMessage::Unban(params),
+        };
+        let request_id = self.next_request_id();
+        self.publish_command(msg, request_id)
+    }
+
+    /// Moves the nodes to another topic, asked on the current one, and follows
+    /// them: the manager publishes and listens on the new topic from now on
+    fn switch_topic(&mut self, cmd_arg: &str) -> Result<(), ComponentError> {
+        let mut parts = cmd_arg.split_whitespace();
+        let (Some(topic), None) = (parts.next(), parts.next()) else {
+            eprintln!("Error: 'SWITCHTOPIC' requires exactly one topic");
+            return Err(ComponentError::InvalidInput());
+        };
+
+        let request_id = self.next_request_id();
+        let params = SwitchTopicParams::new(topic.to_string());
+        self.publish_command(ManagerMessage::SwitchTopic(params), request_id)?;
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let topic = IdentTopic::new(topic);
+        let mut swarm = self.core.swarm.borrow_mut();
+        let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+        gossipsub
+            .subscribe(&topic)
+            .map_err(|e| ComponentError::Subscription(e.to_string()))?;
+        gossipsub.unsubscribe(&self.core.topic);
+        drop(swarm);
+        self.core.topic = topic;
+        Ok(())
+    }
+
+    /// Prints the change notifications about the key from now on, they are only
+    /// received with `--notify-topic`
+    fn watch(&mut self, cmd_arg: &str) -> Result<(), ComponentError> {
+        let mut parts = cmd_arg.split_whitespace();
+        let (Some(key), None) = (parts.next(), parts.next()) else {
+            eprintln!("Error: 'WATCH' requires exactly one key");
+            return Err(ComponentError::InvalidInput());
+        };
+        self.watched.insert(key.to_string());
+        Ok(())
+    }
+
+    /// The line printed for a change notification, `None` if the key isn't watched
+    fn key_changed_line(&self, change: &KeyChangedParams, peer_id: &str) -> Option<String> {
+        if !self.watched.contains(&change.key) {
+            return None;
+        }
+        let hash = |hash: Option<[u8; 32]>| hash.map_or_else(|| "none".to_string(), |h| to_hex(&h));
+        Some(format!(
+            "CHANGED {} on {peer_id}: value hash {}, root {}",
+            change.key,
+            hash(change.new_value_hash),
+            hash(change.root)
+        ))
+    }
+
+    /// Asks every node for its root and key count, the convergence report is
+    /// printed once the quorum window closes
+    fn verify(&mut self, cmd_arg: &str) -> Result<(), ComponentError> {
+        if !cmd_arg.trim().is_empty() {
+            eprintln!("Error: 'VERIFY' takes no arguments");
+            return Err(ComponentError::InvalidInput());
+        }
+
+        let request_id = self.next_request_id();
+        if !self.dry_run {
+            self.verifications
+                .track(request_id, Instant::now() + self.quorum_window);
+        }
+
+        self.publish_command(
+            ManagerMessage::StoreCommand(StoreCommand::treeinfo()),
+            request_id,
+        )
+    }
+
+    fn print_convergence(report: &ConvergenceReport) {
+        if report.node_count() == 0 {
+            println!("VERIFY: no node answered");
+            return;
+        }
+        if report.is_converged() {
+            println!("VERIFY: converged, {} node(s) agree", report.node_count());
+        } else {
+            println!(
+                "VERIFY: DIVERGED, {} node(s) hold {} different roots",
+                report.node_count(),
+                report.groups.len()
+            );
+        }
+        for group in &report.groups {
+            let root = group
+                .root
+                .map_or_else(|| "none".to_string(), |root| to_hex(&root));
+            println!(
+                "  root {root}: {} node(s) {:?}",
+                group.nodes.len(),
+                group.nodes
+            );
+        }
+    }
+
+    fn print_diff(diff: &KeyDiff) {
+        if diff.is_empty() {
+            println!("DIFF {} {}: key sets are identical", diff.left, diff.right);
+            return;
+        }
+        println!(
+            "DIFF {} {}: only on {}: {:?}, only on {}: {:?}, changed: {:?}",
+            diff.left,
+            diff.right,
+            diff.left,
+            diff.only_left,
+            diff.right,
+            diff.only_right,
+            diff.changed
+        );
+    }
+
+    /// Reads commands from stdin, through a line editor with `--interactive`.
+    /// An observer never sends any so it gets no input at all
+    fn spawn_input(&self) -> Option<mpsc::Receiver<CmdArgs>> {
+        if self.observer {
+            return None;
+        }
+
+        let (tx, rx) = mpsc::channel(32);
+        if self.interactive && std::io::stdin().is_terminal() {
+            input::spawn_editor(tx);
+        } else {
+            input::spawn_stdin(tx);
+        }
+
+        Some(rx)
+    }
+
+    /// Handles a received message and returns how it should be reported to gossipsub
+    fn handle_gossip_message(&mut self, message: gossipsub::Message) -> MessageAcceptance {
+        if self.is_own_message(&message) {
+            return MessageAcceptance::Ignore;
+        }
+        let decoded = self.decode_gossip(&message.data);
+        let acceptance = validation_result(&decoded);
+        let Ok(Some(decoded)) = decoded else {
+            return acceptance;
+        };
+        match decoded {
+            ComponentMessage::NodeMessage(msg, metadata) if self.observer => {
+                println!("observed from {}: {:?}", metadata.peer_id_str, msg);
+            }
+            ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(_), metadata)
+                if !self.issued(&metadata) => {}
+            ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), metadata) => {
+                self.handle_command_result(result, metadata)
+            }
+            ComponentMessage::NodeMessage(NodeMessage::KeyChanged(change), metadata) => {
+                if let Some(line) = self.key_changed_line(&change, &metadata.peer_id_str) {
+                    println!("{line}");
+                }
+            }
+            ComponentMessage::NodeMessage(NodeMessage::KeyExpired(expired), metadata)
+                if self.watched.contains(&expired.key) =>
+            {
+                println!("EXPIRED {} on {}", expired.key, metadata.peer_id_str);
+            }
+            _ => {}
+        }
+        acceptance
+    }
+
+    /// Whether a result answers a command of this manager rather than one of
+    /// another manager, a result addressed to no manager is kept
+    fn issued(&self, metadata: &MetaData) -> bool {
+        metadata
+            .reply_to
+            .as_ref()
+            .is_none_or(|manager_id| *manager_id == self.core.peer_id.to_string())
+    }
+
+    fn handle_command_result(&mut self, result: StoreCommandResult, metadata: MetaData) {
+        if let (Some(request_id), Some(session)) = (metadata.request_id, &mut self.session) {
+            session.record(request_id, &result, Instant::now());
+        }
+        if let (Some(request_id), StoreCommandResult::GET(get)) = (metadata.request_id, &result) {
+            let value = get.payload.as_ref().map(|v| v.to_string());
+            if self
+                .collector
+                .record_get(request_id, metadata.peer_id_str.clone(), value)
+            {
+                return;
+            }
+        }
+        if let (Some(request_id), StoreCommandResult::KEYHASHES(hashes)) =
+            (metadata.request_id, &result)
+        {
+            if let Some(diff) =
+                self.diffs
+                    .record(request_id, metadata.peer_id_str, hashes.payload.clone())
+            {
+                Self::print_diff(&diff);
+            }
+            return;
+        }
+        if let (Some(request_id), StoreCommandResult::TREEINFO(info)) =
+            (metadata.request_id, &result)
+            && self.verifications.record(
+                request_id,
+                metadata.peer_id_str.clone(),
+                info.root,
+                info.keys,
+            )
+        {
+            return;
+        }
+        if self.output == OutputFormat::Json {
+            println!("{}", json_line(&result, &metadata));
+            return;
+        }
+        if let StoreCommandResult::ERROR(error) = &result {
+            eprintln!(
+                "ERROR {}: {} (exit code {}): {}",
+                metadata.peer_id_str,
+                error.kind.name(),
+                error.kind.exit_code(),
+                error.message
+            );
+            return;
+        }
+        if let StoreCommandResult::TREEINFO(info) = &result {
+            let root = info
+                .root
+                .map_or_else(|| "none".to_string(), |root| to_hex(&root));
+            println!(
+                "TREEINFO {}: root {root}, {} key(s), depth {}",
+                metadata.peer_id_str, info.keys, info.depth
+            );
+            return;
+        }
+        if let StoreCommandResult::VERSION(version) = &result {
+            println!(
+                "VERSION {}: difiew {} ({}), protocol {}",
+                metadata.peer_id_str,
+                version.crate_version,
+                version.git_hash,
+                version.protocol_version
+            );
+            return;
+        }
+        if let StoreCommandResult::REBUILD(rebuild) = &result {
+            let root = rebuild
+                .root
+                .map_or_else(|| "none".to_string(), |root| to_hex(&root));
+            println!("REBUILD {}: root {root}", metadata.peer_id_str);
+            return;
+        }
+        if let StoreCommandResult::PEERS(peers) = &result {
+            println!(
+                "PEERS {}: agree {:?}, disagree {:?}",
+                metadata.peer_id_str, peers.agree, peers.disagree
+            );
+            return;
+        }
+        if let StoreCommandResult::TRACKER(tracker) = &result {
+            println!("TRACKER {}:", metadata.peer_id_str);
+            for peer in &tracker.peers {
+                let root = peer.root.map_or_else(|| "none".to_string(), |root| to_hex(&root));
+                println!(
+                    "  {}: root {root}, timestamp {}, weight {}",
+                    peer.peer_id, peer.local_timestamp, peer.weight
+                );
+            }
+            return;
+        }
+        if let StoreCommandResult::METRICS(metrics) = &result {
+            println!(
+                "METRICS {}: key sizes {}; value sizes {}",
+                metadata.peer_id_str, metrics.key_sizes, metrics.value_sizes
+            );
+            return;
+        }
+        if let StoreCommandResult::DUMPJSON(dump) = &result {
+            println!("DUMPJSON {}:\n{}", metadata.peer_id_str, dump.payload);
+            return;
+        }
+        println!("manager got {:?}", result)
+    }
+
+    fn finish_quorum_queries(&mut self) {
+        for outcome in self.collector.take_expired(Instant::now()) {
+            println!(
+                "QGET {}: {:?} from {} node(s), divergent: {:?}",
+                outcome.key,
+                outcome.value,
+                outcome.agreeing.len(),
+                outcome.divergent
+            );
+            if self.read_repair
+                && let Err(e) = self.read_repair(&outcome)
+            {
+                eprintln!("Failed to read-repair '{}': {e}", outcome.key);
+            }
+        }
+        for missing in self.diffs.take_expired(Instant::now()) {
+            eprintln!("DIFF timed out, no key hashes from {missing:?}");
+        }
+        for report in self.verifications.take_expired(Instant::now()) {
+            Self::print_convergence(&report);
+        }
+    }
+
+    /// Sends the majority value only to the nodes that answered with a stale one
+    fn read_repair(&mut self, outcome: &QuorumOutcome) -> Result<(), ComponentError> {
+        let Some(cmd) = outcome.repair_command() else {
+            return Ok(());
+        };
+        let params = TargetedStoreCommandParams::new(outcome.divergent.clone(), cmd);
+        let request_id = self.next_request_id();
+
+        self.publish_command(ManagerMessage::TargetedStoreCommand(params), request_id)
+    }
+
+    /// Asks the nodes for their roots every quorum window, as `VERIFY` does,
+    /// until every answering node holds the same root or `timeout` elapses, and
+    /// returns whether they converged. A round nobody answers doesn't count.
+    /// It drives the swarm itself, so it is meant for tests and deployment
+    /// gates rather than for a manager running its event loop
+    pub async fn await_convergence(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            let request_id = self.next_request_id();
+            let window_end = (Instant::now() + self.quorum_window).min(deadline);
+            self.verifications.track(request_id, window_end);
+            let treeinfo = ManagerMessage::StoreCommand(StoreCommand::treeinfo());
+            if let Err(e) = self.publish_command(treeinfo, request_id) {
+                eprintln!("Failed to ask the nodes for their roots: {e}");
+            }
+
+            let window = tokio::time::sleep_until(window_end.into());
+            tokio::pin!(window);
+            loop {
+                select! {
+                    event = self.core.next_event() => self.handle_swarm_event(event),
+                    _ = &mut window => break,
+                }
+            }
+
+            if let Some(report) = self.verifications.take(request_id)
+                && report.node_count() > 0
+                && report.is_converged()
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Handles an event of the swarm, the event loop and `await_convergence`
+    /// both drive it
+    fn handle_swarm_event(&mut self, event: SwarmEvent<MyBehaviourEvent>) {
+        match event {
+            SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
+                let mut swarm = self.core.swarm.borrow_mut();
+                for (peer_id, _multiaddr) in list {
+                    println!("mDNS discovered a new peer: {peer_id}");
+                    swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                }
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
+                let mut swarm = self.core.swarm.borrow_mut();
+                for (peer_id, _multiaddr) in list {
+                    println!("mDNS discover peer has expired: {peer_id}");
+                    swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+                }
+            }
+            SwarmEvent::NewListenAddr { address, .. } => {
+                println!("Local node is listening on {address}");
+            }
+            SwarmEvent::ConnectionEstablished { connection_id, .. }
+            | SwarmEvent::OutgoingConnectionError { connection_id, .. } => {
+                self.core.dial_finished(connection_id);
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                gossipsub::Event::Subscribed { .. },
+            )) => {
+                self.flush_outbox();
+            }
+            SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => {
+                let acceptance = self.handle_gossip_message(message);
+                self.report_validation(&message_id, &propagation_source, acceptance);
+            }
+            _ => {}
+        }
     }
 }
 
@@ -58,109 +699,390 @@ impl Component for Manager {
     }
 
     async fn start_event_loop<'a>(&'a mut self) {
-        let (tx, mut rx) = tokio::sync::mpsc::channel(32);
-
-        let input_handle = tokio::spawn({
-            let tx = tx.clone();
-            async move {
-                let stdin = io::stdin();
-                let mut reader = io::BufReader::new(stdin).lines();
-
-                loop {
-                    match reader.next_line().await {
-                        Ok(Some(line)) => {
-                            let line = line.trim();
-                            if line.is_empty() {
-                                continue;
-                            }
-
-                            let parts: Vec<&str> = line.splitn(2, ' ').collect();
-                            match parts.as_slice() {
-                                [cmd_type, cmd_arg] => {
-                                    let args = CmdArgs {
-                                        cmd_type: cmd_type.to_string(),
-                                        cmd_arg: cmd_arg.to_string(),
-                                    };
-                                    if tx.send(args).await.is_err() {
-                                        break;
-                                    }
-                                }
-                                [cmd_type] => {
-                                    println!(
-                                        "Invalid command: missing argument for '{}'",
-                                        cmd_type
-                                    );
-                                }
-                                [] => {
-                                    continue;
-                                }
-                                &[_, _, _, ..] => println!("unhandled case"),
-                            }
-                        }
-                        Ok(None) => break, // EOF
-                        Err(e) => {
-                            eprintln!("Input error: {e}");
-                            break;
-                        }
-                    }
-                }
-            }
-        });
+        let mut rx = self.spawn_input();
+        let mut quorum_tick = tokio::time::interval(Duration::from_millis(100));
+        self.core.dial_bootstrap();
 
         loop {
-            let mut swarm_guard = self.core.swarm.borrow_mut();
             select! {
-                event = swarm_guard.select_next_some() => match event {
-                    SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
-                                                drop(swarm_guard);
-
-                        for (peer_id, _multiaddr) in list {
-                            println!("mDNS discovered a new peer: {peer_id}");
-                            self.core.swarm.borrow_mut().behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
-                        }
-                    },
-                    SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
-                                                drop(swarm_guard);
-
-                        for (peer_id, _multiaddr) in list {
-                            println!("mDNS discover peer has expired: {peer_id}");
-                            self.core.swarm.borrow_mut().behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
-                        }
-                    },
-                    SwarmEvent::NewListenAddr { address, .. } => {
-                        println!("Local node is listening on {address}");
+                event = self.core.next_event() => self.handle_swarm_event(event),
+
+                _ = quorum_tick.tick() => {
+                    self.finish_quorum_queries();
+                }
+
+                args = next_input(&mut rx) => {
+                    match args {
+                        Some(args) => _ = self.execute_user_input(args),
+                        // stdin is closed, keep printing what the nodes send
+                        None => rx = None,
                     }
+                }
+            }
+        }
+    }
+}
 
-                    SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
-                        propagation_source: _peer_id,
-                        message_id: _id,
-                        message,
-                    })) => {
-                        if let Ok((decoded, _len)) = bincode::decode_from_slice(&message.data[..], self.core.config) {
-                            drop(swarm_guard);
+/// A received result as a single line of JSON, printed with `--output json`
+fn json_line(result: &StoreCommandResult, metadata: &MetaData) -> String {
+    serde_json::json!({
+        "peer_id": metadata.peer_id_str,
+        "request_id": metadata.request_id,
+        "result": result.to_json(),
+    })
+    .to_string()
+}
 
+async fn next_input(rx: &mut Option<mpsc::Receiver<CmdArgs>>) -> Option<CmdArgs> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
 
-                                if let ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), _) = decoded {
-                                println!("manager got {:?}", result)}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::decode_message;
+    use crate::utils::swarm_builder::build_swarm;
+    use bincode::config;
+    use libp2p::identity;
 
+    fn test_manager(args: &BinArgs) -> Manager {
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let swarm = build_swarm(key, args).unwrap();
+        Manager::new(swarm, peer_id, IdentTopic::new("test-topic"), args)
+    }
 
-                            }
-                    }
+    fn set_args() -> CmdArgs {
+        CmdArgs {
+            cmd_type: "set".to_string(),
+            cmd_arg: "color red".to_string(),
+        }
+    }
 
-                    _ => {}
-                },
+    #[tokio::test]
+    async fn get_after_set_reads_the_own_write() {
+        let args = BinArgs {
+            read_your_writes: true,
+            ..Default::default()
+        };
+        let mut manager = test_manager(&args);
+        let get_args = || CmdArgs {
+            cmd_type: "get".to_string(),
+            cmd_arg: "color".to_string(),
+        };
 
-                args = rx.recv() => {
-                    if let Some(args) = args {
-                        drop(swarm_guard);
-                        _ = self.execute_user_input(args);
-                    } else {
-                        break;
-                    }
-                }
-            }
+        let set_result = |request_id| {
+            let metadata = MetaData::new("node", 0).with_request_id(Some(request_id));
+            (StoreCommandResult::set(true, None), metadata)
+        };
+
+        // until a node confirms the SET the GET is sent
+        manager.execute_user_input(set_args()).unwrap();
+        manager.execute_user_input(get_args()).unwrap();
+        assert_eq!(manager.core.outbox.borrow().len(), 2);
+
+        // once confirmed, whatever node would answer, the GET isn't sent at all
+        let (result, metadata) = set_result(1);
+        manager.handle_command_result(result, metadata);
+        manager.execute_user_input(get_args()).unwrap();
+        assert_eq!(manager.core.outbox.borrow().len(), 2);
+        let session = manager.session.as_mut().unwrap();
+        assert_eq!(session.lookup("color", Instant::now()), Some(Some("red")));
+
+        // other keys are still read from the nodes
+        let args = CmdArgs {
+            cmd_type: "get".to_string(),
+            cmd_arg: "size".to_string(),
+        };
+        manager.execute_user_input(args).unwrap();
+        assert_eq!(manager.core.outbox.borrow().len(), 3);
+
+        // a dry run confirms nothing
+        let args = BinArgs {
+            read_your_writes: true,
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut manager = test_manager(&args);
+        manager.execute_user_input(set_args()).unwrap();
+        let (result, metadata) = set_result(1);
+        manager.handle_command_result(result, metadata);
+        let session = manager.session.as_mut().unwrap();
+        assert_eq!(session.lookup("color", Instant::now()), None);
+
+        let mut manager = test_manager(&BinArgs::default());
+        manager.execute_user_input(set_args()).unwrap();
+        manager.execute_user_input(get_args()).unwrap();
+        assert_eq!(manager.core.outbox.borrow().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn publish_without_peers_is_buffered() {
+        let mut manager = test_manager(&BinArgs::default());
+        assert!(manager.execute_user_input(set_args()).is_ok());
+        assert_eq!(manager.core.outbox.borrow().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sharded_commands_are_published_to_the_shard_of_their_keys() {
+        let args = BinArgs {
+            shards: Some(4),
+            ..Default::default()
+        };
+        let mut manager = test_manager(&args);
+        let router = ShardRouter::new("test-topic", 4);
+        let input = |cmd_type: &str, cmd_arg: &str| CmdArgs {
+            cmd_type: cmd_type.to_string(),
+            cmd_arg: cmd_arg.to_string(),
+        };
+
+        for _ in 0..2 {
+            manager.execute_user_input(set_args()).unwrap();
+            manager.execute_user_input(input("GET", "color")).unwrap();
+        }
+        let published = manager.core.outbox.borrow_mut().take();
+        assert_eq!(published.len(), 4);
+        let shard_topic = router.topic(router.shard("color")).hash();
+        assert!(published.iter().all(|(topic, _)| topic.hash() == shard_topic));
+
+        manager.execute_user_input(input("KEYS", "*")).unwrap();
+        let published = manager.core.outbox.borrow_mut().take();
+        let topics: Vec<_> = published.iter().map(|(topic, _)| topic.hash()).collect();
+        let all: Vec<_> = router.topics().iter().map(IdentTopic::hash).collect();
+        assert_eq!(topics, all);
+
+        let other = (0..100)
+            .map(|i| format!("key:{i}"))
+            .find(|key| router.shard(key) != router.shard("color"))
+            .unwrap();
+        let del = input("DEL", &format!("color {other}"));
+        assert!(manager.execute_user_input(del).is_err());
+    }
+
+    #[tokio::test]
+    async fn switch_topic_is_asked_on_the_old_topic_then_followed() {
+        let mut manager = test_manager(&BinArgs::default());
+        let switch = CmdArgs {
+            cmd_type: "switchtopic".to_string(),
+            cmd_arg: "new-topic".to_string(),
+        };
+        manager.execute_user_input(switch).unwrap();
+        manager.execute_user_input(set_args()).unwrap();
+
+        let published = manager.core.outbox.borrow_mut().take();
+        assert_eq!(published[0].0.hash(), IdentTopic::new("test-topic").hash());
+        assert!(matches!(
+            decode_message(&published[0].1, manager.core.config).unwrap(),
+            Some(ComponentMessage::ManagerMessage(ManagerMessage::SwitchTopic(params), _))
+                if params.topic == "new-topic"
+        ));
+        assert_eq!(published[1].0.hash(), IdentTopic::new("new-topic").hash());
+    }
+
+    #[tokio::test]
+    async fn ban_requires_a_valid_peer_id() {
+        let mut manager = test_manager(&BinArgs::default());
+        let two_peers = format!("{} {}", PeerId::random(), PeerId::random());
+        for cmd_arg in ["", "not-a-peer", two_peers.as_str()] {
+            let args = CmdArgs {
+                cmd_type: "ban".to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(manager.execute_user_input(args).is_err());
+        }
+
+        let args = CmdArgs {
+            cmd_type: "unban".to_string(),
+            cmd_arg: PeerId::random().to_string(),
+        };
+        assert!(manager.execute_user_input(args).is_ok());
+        let published = manager.core.outbox.borrow_mut().take();
+        assert!(matches!(
+            decode_message(&published[0].1, manager.core.config).unwrap(),
+            Some(ComponentMessage::ManagerMessage(ManagerMessage::Unban(_), _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dry_run_skips_publish() {
+        let args = BinArgs {
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut manager = test_manager(&args);
+
+        // without peers a real publish would end up in the outbox
+        assert!(manager.execute_user_input(set_args()).is_ok());
+        assert!(manager.core.outbox.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dry_run_does_not_collect_quorum_responses() {
+        let args = BinArgs {
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut manager = test_manager(&args);
+
+        let qget = CmdArgs {
+            cmd_type: "qget".to_string(),
+            cmd_arg: "color".to_string(),
+        };
+        assert!(manager.execute_user_input(qget).is_ok());
+        assert!(!manager.collector.record_get(1, "p1".to_string(), None));
+    }
+
+    #[tokio::test]
+    async fn observer_creates_no_input_channel() {
+        let args = BinArgs {
+            observer: true,
+            ..Default::default()
+        };
+        let manager = test_manager(&args);
+        assert!(manager.spawn_input().is_none());
+    }
+
+    fn get_result_message(
+        source: PeerId,
+        request_id: u64,
+        reply_to: Option<PeerId>,
+    ) -> gossipsub::Message {
+        let mut metadata = MetaData::new(source, 0).with_request_id(Some(request_id));
+        metadata.reply_to = reply_to.map(|manager_id| manager_id.to_string());
+        let result = StoreCommandResult::get(Some("red"), 1);
+        let msg = ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), metadata);
+        gossipsub::Message {
+            source: Some(source),
+            data: bincode::encode_to_vec(&msg, config::standard()).unwrap(),
+            sequence_number: None,
+            topic: IdentTopic::new("test-topic").hash(),
+        }
+    }
+
+    #[tokio::test]
+    async fn watch_filters_key_changes() {
+        let mut manager = test_manager(&BinArgs::default());
+        let change = |key: &str| KeyChangedParams::new(key.to_string(), Some([0xab; 32]), None);
+        assert_eq!(manager.key_changed_line(&change("color"), "node"), None);
+
+        manager.execute_user_input(watch_args("color")).unwrap();
+        assert!(manager.execute_user_input(watch_args("")).is_err());
+        assert!(manager.execute_user_input(watch_args("a b")).is_err());
+
+        assert_eq!(
+            manager.key_changed_line(&change("color"), "node"),
+            Some(format!(
+                "CHANGED color on node: value hash {}, root none",
+                "ab".repeat(32)
+            ))
+        );
+        assert_eq!(manager.key_changed_line(&change("size"), "node"), None);
+    }
+
+    fn watch_args(key: &str) -> CmdArgs {
+        CmdArgs {
+            cmd_type: "WATCH".to_string(),
+            cmd_arg: key.to_string(),
         }
+    }
+
+    #[test]
+    fn json_output_is_a_valid_json_line() {
+        let metadata = MetaData::new("node", 0).with_request_id(Some(7));
+        let line = json_line(&StoreCommandResult::get(Some("red"), 2), &metadata);
+        assert!(!line.contains('\n'));
 
-        let _ = input_handle.await;
+        let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed["peer_id"], "node");
+        assert_eq!(parsed["request_id"], 7);
+        assert_eq!(
+            parsed["result"],
+            serde_json::json!({"type": "GET", "payload": "red", "version": 2})
+        );
+    }
+
+    #[tokio::test]
+    async fn own_messages_are_ignored() {
+        let mut manager = test_manager(&BinArgs::default());
+        manager
+            .collector
+            .track_get(1, "color".to_string(), Instant::now());
+
+        let own_id = manager.core.peer_id;
+        manager.handle_gossip_message(get_result_message(own_id, 1, None));
+        let other_id = PeerId::random();
+        manager.handle_gossip_message(get_result_message(other_id, 1, None));
+
+        let outcome = manager.collector.take_expired(Instant::now()).remove(0);
+        assert_eq!(outcome.agreeing, vec![other_id.to_string()]);
+        assert!(outcome.divergent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn managers_only_collect_results_of_their_own_commands() {
+        let mut managers = [
+            test_manager(&BinArgs::default()),
+            test_manager(&BinArgs::default()),
+        ];
+        // both managers numbered their first command 1
+        for manager in &mut managers {
+            manager.execute_user_input(qget_args("color")).unwrap();
+        }
+        let ids = managers.each_ref().map(|manager| manager.core.peer_id);
+
+        let node = PeerId::random();
+        for manager in &mut managers {
+            manager.handle_gossip_message(get_result_message(node, 1, Some(ids[0])));
+        }
+
+        let deadline = Instant::now() + managers[0].quorum_window;
+        let first = managers[0].collector.take_expired(deadline);
+        assert_eq!(first[0].agreeing, vec![node.to_string()]);
+        let second = managers[1].collector.take_expired(deadline);
+        assert!(second[0].agreeing.is_empty());
+    }
+
+    fn qget_args(key: &str) -> CmdArgs {
+        CmdArgs {
+            cmd_type: "QGET".to_string(),
+            cmd_arg: key.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_collects_treeinfo_results() {
+        let mut manager = test_manager(&BinArgs::default());
+        let verify = CmdArgs {
+            cmd_type: "verify".to_string(),
+            cmd_arg: String::new(),
+        };
+        assert!(manager.execute_user_input(verify).is_ok());
+
+        let metadata = MetaData::new(PeerId::random(), 0).with_request_id(Some(1));
+        manager.handle_command_result(StoreCommandResult::treeinfo(Some([1; 32]), 2, 1), metadata);
+
+        let report = manager
+            .verifications
+            .take_expired(Instant::now() + manager.quorum_window);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].node_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn diff_requires_two_peers() {
+        let mut manager = test_manager(&BinArgs::default());
+        for cmd_arg in ["p1", "p1 p1", "p1 p2 p3"] {
+            let diff = CmdArgs {
+                cmd_type: "diff".to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(matches!(
+                manager.execute_user_input(diff),
+                Err(ComponentError::InvalidInput())
+            ));
+        }
     }
 }
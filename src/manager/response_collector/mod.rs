@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::store::command::StoreCommand;
+
+struct QuorumGet {
+    key: String,
+    deadline: Instant,
+    responses: HashMap<String, Option<String>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QuorumOutcome {
+    pub key: String,
+    /// the value returned by most nodes, `None` if most nodes don't have the key
+    pub value: Option<String>,
+    pub agreeing: Vec<String>,
+    pub divergent: Vec<String>,
+}
+
+impl QuorumOutcome {
+    /// Command that brings the divergent nodes to the majority value
+    pub fn repair_command(&self) -> Option<StoreCommand<'_>> {
+        if self.divergent.is_empty() || self.agreeing.is_empty() {
+            return None;
+        }
+        match &self.value {
+            Some(value) => Some(StoreCommand::set(self.key.as_str(), value.as_str())),
+            None => Some(StoreCommand::del([self.key.as_str()])),
+        }
+    }
+}
+
+/// Collects node responses to quorum commands until their window closes
+pub struct ResponseCollector {
+    pending: HashMap<u64, QuorumGet>,
+}
+
+impl ResponseCollector {
+    pub fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn track_get(&mut self, request_id: u64, key: String, deadline: Instant) {
+        self.pending.insert(
+            request_id,
+            QuorumGet {
+                key,
+                deadline,
+                responses: HashMap::new(),
+            },
+        );
+    }
+
+    /// Records a GET response, returns `false` if the request isn't collected
+    pub fn record_get(&mut self, request_id: u64, peer_id: String, value: Option<String>) -> bool {
+        match self.pending.get_mut(&request_id) {
+            Some(query) => {
+                query.responses.insert(peer_id, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the requests whose window has closed and returns their outcomes
+    pub fn take_expired(&mut self, now: Instant) -> Vec<QuorumOutcome> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, query)| query.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .map(Self::outcome)
+            .collect()
+    }
+
+    fn outcome(query: QuorumGet) -> QuorumOutcome {
+        let mut freqs: HashMap<&Option<String>, usize> = HashMap::new();
+        for value in query.responses.values() {
+            *freqs.entry(value).or_insert(0) += 1;
+        }
+        // ties are broken by value so every manager picks the same one
+        let value = freqs
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)))
+            .and_then(|(value, _)| value.clone());
+
+        let mut agreeing = Vec::new();
+        let mut divergent = Vec::new();
+        for (peer_id, v) in &query.responses {
+            if *v == value {
+                agreeing.push(peer_id.clone());
+            } else {
+                divergent.push(peer_id.clone());
+            }
+        }
+        agreeing.sort();
+        divergent.sort();
+
+        QuorumOutcome {
+            key: query.key,
+            value,
+            agreeing,
+            divergent,
+        }
+    }
+}
+
+impl Default for ResponseCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn collector_with_responses(responses: &[(&str, Option<&str>)]) -> ResponseCollector {
+        let mut collector = ResponseCollector::new();
+        collector.track_get(1, "color".to_string(), Instant::now());
+        for (peer_id, value) in responses {
+            collector.record_get(1, peer_id.to_string(), value.map(str::to_string));
+        }
+        collector
+    }
+
+    #[test]
+    fn test_record_get_ignores_untracked_request() {
+        let mut collector = ResponseCollector::new();
+        assert!(!collector.record_get(1, "p1".to_string(), None));
+    }
+
+    #[test]
+    fn test_take_expired_keeps_open_requests() {
+        let mut collector = ResponseCollector::new();
+        let now = Instant::now();
+        collector.track_get(1, "a".to_string(), now);
+        collector.track_get(2, "b".to_string(), now + Duration::from_secs(60));
+
+        let outcomes = collector.take_expired(now);
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].key, "a");
+        assert!(collector.record_get(2, "p1".to_string(), None));
+    }
+
+    #[test]
+    fn test_outcome_detects_divergent_peers() {
+        let mut collector = collector_with_responses(&[
+            ("p1", Some("red")),
+            ("p2", Some("red")),
+            ("p3", Some("blue")),
+        ]);
+
+        let outcome = collector.take_expired(Instant::now()).remove(0);
+        assert_eq!(outcome.value, Some("red".to_string()));
+        assert_eq!(outcome.agreeing, vec!["p1", "p2"]);
+        assert_eq!(outcome.divergent, vec!["p3"]);
+    }
+
+    #[test]
+    fn test_repair_command_sets_majority_value() {
+        let mut collector =
+            collector_with_responses(&[("p1", Some("red")), ("p2", Some("red")), ("p3", None)]);
+
+        let outcome = collector.take_expired(Instant::now()).remove(0);
+        match outcome.repair_command() {
+            Some(StoreCommand::SET(params)) => {
+                assert_eq!(params.key, "color");
+                assert_eq!(params.value, "red");
+            }
+            other => panic!("Expected SET repair, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_repair_command_deletes_when_majority_lacks_key() {
+        let mut collector =
+            collector_with_responses(&[("p1", None), ("p2", None), ("p3", Some("red"))]);
+
+        let outcome = collector.take_expired(Instant::now()).remove(0);
+        assert!(matches!(
+            outcome.repair_command(),
+            Some(StoreCommand::DEL(_))
+        ));
+    }
+
+    #[test]
+    fn test_no_repair_when_converged() {
+        let mut collector = collector_with_responses(&[("p1", Some("red")), ("p2", Some("red"))]);
+
+        let outcome = collector.take_expired(Instant::now()).remove(0);
+        assert!(outcome.divergent.is_empty());
+        assert!(outcome.repair_command().is_none());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::protocol::audit::AuditMutation;
+use crate::store::command::{DELParams, EXPIREParams, SETParams, StoreCommand};
+use crate::store::result::StoreCommandResult;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A write sent by this manager that no node confirmed yet
+struct PendingWrite {
+    keys: Vec<String>,
+    /// the value set at the key, `None` for a delete
+    value: Option<String>,
+    sent: Instant,
+}
+
+/// Values this manager wrote recently, a GET of one of them is answered from
+/// here so it cannot observe a node that hasn't applied the write yet
+pub struct SessionCache {
+    window: Duration,
+    /// the value written at each key, `None` once deleted, with the write time
+    writes: HashMap<String, (Option<String>, Instant)>,
+    /// writes kept until a node confirms them, by request id
+    pending: HashMap<u64, PendingWrite>,
+}
+
+impl SessionCache {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            writes: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Notes a command this manager sends. Its keys are read from the nodes
+    /// again until a node confirms a `SET` or `DEL`, see `record`, a write
+    /// whose outcome can't be known without the store is never confirmed
+    pub fn track(&mut self, request_id: u64, cmd: &StoreCommand, now: Instant) {
+        let (keys, value) = match cmd {
+            StoreCommand::SET(SETParams {
+                key,
+                value,
+                return_old: false,
+            }) => (vec![key.to_string()], Some(Some(value.to_string()))),
+            StoreCommand::DEL(DELParams { keys }) => {
+                (keys.iter().map(|key| key.to_string()).collect(), Some(None))
+            }
+            StoreCommand::EXPIRE(EXPIREParams { key, .. }) => (vec![key.to_string()], None),
+            cmd => (
+                AuditMutation::from_command(cmd)
+                    .iter()
+                    .flat_map(|m| m.keys())
+                    .cloned()
+                    .collect(),
+                None,
+            ),
+        };
+
+        // an earlier write confirmed later must not shadow this one
+        for key in &keys {
+            self.writes.remove(key);
+        }
+        self.pending
+            .retain(|_, write| !write.keys.iter().any(|key| keys.contains(key)));
+        if let Some(value) = value {
+            let write = PendingWrite {
+                keys,
+                value,
+                sent: now,
+            };
+            self.pending.insert(request_id, write);
+        }
+    }
+
+    /// Drops a tracked write that was never sent
+    pub fn untrack(&mut self, request_id: u64) {
+        self.pending.remove(&request_id);
+    }
+
+    /// Takes the result a node sent for `request_id`, a successful `SET` or
+    /// `DEL` makes its value served from here
+    pub fn record(&mut self, request_id: u64, result: &StoreCommandResult, now: Instant) {
+        let confirmed = match (self.pending.get(&request_id), result) {
+            (Some(write), StoreCommandResult::SET(set)) => write.value.is_some() && set.payload,
+            (Some(write), StoreCommandResult::DEL(_)) => write.value.is_none(),
+            _ => false,
+        };
+        if !confirmed {
+            return;
+        }
+        let Some(write) = self.pending.remove(&request_id) else {
+            return;
+        };
+        for key in write.keys {
+            self.writes.insert(key, (write.value.clone(), now));
+        }
+    }
+
+    /// The value written at key within the window, `Some(None)` if the key was
+    /// deleted and `None` if it has to be read from the nodes
+    pub fn lookup(&mut self, key: &str, now: Instant) -> Option<Option<&str>> {
+        let window = self.window;
+        self.writes
+            .retain(|_, (_, written)| now.duration_since(*written) < window);
+        self.pending
+            .retain(|_, write| now.duration_since(write.sent) < window);
+        self.writes.get(key).map(|(value, _)| value.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::result::ErrorKind;
+
+    /// Tracks `cmd` as request `request_id` and confirms it right away
+    fn confirmed(
+        cache: &mut SessionCache,
+        request_id: u64,
+        cmd: &StoreCommand,
+        result: &StoreCommandResult,
+        now: Instant,
+    ) {
+        cache.track(request_id, cmd, now);
+        cache.record(request_id, result, now);
+    }
+
+    #[test]
+    fn serves_writes_within_the_window() {
+        let mut cache = SessionCache::new(Duration::from_secs(5));
+        let start = Instant::now();
+        let set = StoreCommandResult::set(true, Some([1; 32]));
+        confirmed(
+            &mut cache,
+            1,
+            &StoreCommand::set("color", "red"),
+            &set,
+            start,
+        );
+        let del = StoreCommandResult::del(1, None);
+        confirmed(&mut cache, 2, &StoreCommand::del(["size"]), &del, start);
+
+        let soon = start + Duration::from_secs(1);
+        assert_eq!(cache.lookup("color", soon), Some(Some("red")));
+        assert_eq!(cache.lookup("size", soon), Some(None));
+        assert_eq!(cache.lookup("other", soon), None);
+
+        let later = start + Duration::from_secs(5);
+        assert_eq!(cache.lookup("color", later), None);
+    }
+
+    #[test]
+    fn only_confirmed_writes_are_served() {
+        let mut cache = SessionCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        cache.track(1, &StoreCommand::set("color", "red"), now);
+        assert_eq!(cache.lookup("color", now), None);
+
+        // an error or the result of another request confirms nothing
+        let error = StoreCommandResult::error(ErrorKind::Internal, "failed");
+        cache.record(1, &error, now);
+        cache.record(2, &StoreCommandResult::set(true, None), now);
+        assert_eq!(cache.lookup("color", now), None);
+
+        cache.record(1, &StoreCommandResult::set(true, None), now);
+        assert_eq!(cache.lookup("color", now), Some(Some("red")));
+
+        // a write never sent is never confirmed
+        cache.track(3, &StoreCommand::set("size", "xl"), now);
+        cache.untrack(3);
+        cache.record(3, &StoreCommandResult::set(true, None), now);
+        assert_eq!(cache.lookup("size", now), None);
+    }
+
+    #[test]
+    fn other_writes_forget_the_key() {
+        let mut cache = SessionCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        let set = StoreCommandResult::set(true, None);
+        for cmd in [
+            StoreCommand::rpush("color", ["red"]),
+            StoreCommand::expire("color", 10),
+            StoreCommand::rename("color", "shade"),
+        ] {
+            confirmed(&mut cache, 1, &StoreCommand::set("color", "red"), &set, now);
+            confirmed(
+                &mut cache,
+                2,
+                &StoreCommand::set("shade", "blue"),
+                &set,
+                now,
+            );
+            cache.track(3, &cmd, now);
+            assert_eq!(cache.lookup("color", now), None);
+        }
+        assert_eq!(cache.lookup("shade", now), None);
+
+        // a write sent later wins over the confirmation of an earlier one
+        cache.track(4, &StoreCommand::set("color", "red"), now);
+        cache.track(5, &StoreCommand::set("color", "blue"), now);
+        cache.record(4, &set, now);
+        assert_eq!(cache.lookup("color", now), None);
+
+        // reads change nothing
+        confirmed(&mut cache, 6, &StoreCommand::set("color", "red"), &set, now);
+        cache.track(7, &StoreCommand::get("color"), now);
+        assert_eq!(cache.lookup("color", now), Some(Some("red")));
+    }
+}
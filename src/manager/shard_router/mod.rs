@@ -0,0 +1,137 @@
+use libp2p::gossipsub::IdentTopic;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Points every shard takes on the ring, more points spread the keys more evenly
+const POINTS_PER_SHARD: usize = 128;
+
+/// Maps keys to shards by consistent hashing. Every shard takes a number of
+/// points on a ring of hashes and a key belongs to the shard of the first point
+/// at or after the hash of the key, so adding a shard only moves the keys that
+/// now fall on its points
+pub struct ShardRouter {
+    /// the shard owning each point
+    ring: BTreeMap<u64, usize>,
+    topics: Vec<IdentTopic>,
+}
+
+impl ShardRouter {
+    /// Router over `shards` shards, published to on `<topic>-0` to
+    /// `<topic>-<shards - 1>`
+    pub fn new(topic: &str, shards: usize) -> Self {
+        assert!(shards > 0, "a router needs at least one shard");
+        let ring = (0..shards)
+            .flat_map(|shard| {
+                (0..POINTS_PER_SHARD).map(move |point| {
+                    (
+                        ring_hash(format!("shard-{shard}#{point}").as_bytes()),
+                        shard,
+                    )
+                })
+            })
+            .collect();
+        let topics = (0..shards)
+            .map(|shard| IdentTopic::new(format!("{topic}-{shard}")))
+            .collect();
+        Self { ring, topics }
+    }
+
+    /// The shard holding key
+    pub fn shard(&self, key: &str) -> usize {
+        let hash = ring_hash(key.as_bytes());
+        let (_, &shard) = self
+            .ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.first_key_value())
+            .expect("the ring has points of every shard");
+        shard
+    }
+
+    /// The shard holding every one of the keys, `None` without keys or when
+    /// they belong to different shards
+    pub fn common_shard<'k>(&self, keys: impl IntoIterator<Item = &'k str>) -> Option<usize> {
+        let mut shards = keys.into_iter().map(|key| self.shard(key));
+        let first = shards.next()?;
+        shards.all(|shard| shard == first).then_some(first)
+    }
+
+    pub fn topic(&self, shard: usize) -> &IdentTopic {
+        &self.topics[shard]
+    }
+
+    /// Topics of every shard, in shard order
+    pub fn topics(&self) -> &[IdentTopic] {
+        &self.topics
+    }
+}
+
+/// Position on the ring, the first 8 bytes of the SHA-256 hash
+fn ring_hash(bytes: &[u8]) -> u64 {
+    let digest = Sha256::digest(bytes);
+    u64::from_be_bytes(digest[..8].try_into().expect("a digest has 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(count: usize) -> impl Iterator<Item = String> {
+        (0..count).map(|i| format!("key:{i}"))
+    }
+
+    #[test]
+    fn a_key_always_maps_to_the_same_shard() {
+        let router = ShardRouter::new("test-topic", 4);
+        let again = ShardRouter::new("test-topic", 4);
+        for key in keys(1000) {
+            let shard = router.shard(&key);
+            assert_eq!(router.shard(&key), shard);
+            assert_eq!(again.shard(&key), shard);
+        }
+        assert_eq!(
+            router.topic(2).hash(),
+            IdentTopic::new("test-topic-2").hash()
+        );
+        assert_eq!(router.topics().len(), 4);
+    }
+
+    #[test]
+    fn keys_are_spread_over_every_shard() {
+        let router = ShardRouter::new("test-topic", 4);
+        let mut counts = [0; 4];
+        for key in keys(10_000) {
+            counts[router.shard(&key)] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 1500), "{counts:?}");
+    }
+
+    #[test]
+    fn adding_a_shard_moves_a_bounded_fraction_of_keys() {
+        let before = ShardRouter::new("test-topic", 4);
+        let after = ShardRouter::new("test-topic", 5);
+        let mut moved = 0;
+        for key in keys(10_000) {
+            let shard = after.shard(&key);
+            if shard != before.shard(&key) {
+                // keys only ever move to the new shard
+                assert_eq!(shard, 4);
+                moved += 1;
+            }
+        }
+        // a fifth of the keys is expected to move
+        assert!((1000..3000).contains(&moved), "{moved} keys moved");
+    }
+
+    #[test]
+    fn common_shard_of_keys() {
+        let router = ShardRouter::new("test-topic", 8);
+        let key = "color";
+        let other = keys(100)
+            .find(|other| router.shard(other) != router.shard(key))
+            .unwrap();
+        assert_eq!(router.common_shard([key, key]), Some(router.shard(key)));
+        assert_eq!(router.common_shard([key, other.as_str()]), None);
+        assert_eq!(router.common_shard([]), None);
+    }
+}
@@ -0,0 +1,186 @@
+use regex::Regex;
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+
+use crate::protocol::{audit::AuditMutation, ManagerMessage};
+use crate::store::command::StoreCommand;
+
+/// Key prefixes the managers matching `peer` may use
+#[derive(Debug, Clone)]
+struct Rule {
+    peer: Regex,
+    write: Vec<String>,
+    /// reads are not restricted when `None`
+    read: Option<Vec<String>>,
+}
+
+/// Restricts the keys a manager may write, and optionally read, by the key
+/// prefixes granted to its peer id, see `--acl`. The first rule whose peer
+/// pattern matches applies, a manager matching none may read but not write.
+/// A command without key arguments, e.g. `FLUSH` or `KEYS`, needs the empty
+/// prefix as it may touch every key, and so do the manager messages other
+/// than commands
+#[derive(Debug, Clone)]
+pub struct Acl {
+    rules: Vec<Rule>,
+}
+
+impl Acl {
+    /// Reads an `--acl` file, a JSON array of rules such as
+    /// `{"peer": "12D3KooW*", "write": ["tenant-a:"], "read": ["tenant-a:"]}`
+    /// where `*` in the peer pattern matches anything and `read` may be left out
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub(crate) fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let Value::Array(entries) = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?
+        else {
+            return Err(invalid("the acl must be a JSON array of rules".to_string()));
+        };
+
+        let prefixes = |value: Option<&Value>, field: &str| -> io::Result<Option<Vec<String>>> {
+            let Some(value) = value else {
+                return Ok(None);
+            };
+            value
+                .as_array()
+                .and_then(|prefixes| {
+                    prefixes
+                        .iter()
+                        .map(|prefix| prefix.as_str().map(str::to_string))
+                        .collect()
+                })
+                .map(Some)
+                .ok_or_else(|| invalid(format!("{field} must be an array of key prefixes")))
+        };
+
+        let mut rules = Vec::new();
+        for entry in &entries {
+            let peer = entry
+                .get("peer")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid("every rule needs a peer pattern".to_string()))?;
+            let peer = Regex::new(&format!("^{}$", regex::escape(peer).replace(r"\*", ".*")))
+                .map_err(|e| invalid(e.to_string()))?;
+            rules.push(Rule {
+                peer,
+                write: prefixes(entry.get("write"), "write")?.unwrap_or_default(),
+                read: prefixes(entry.get("read"), "read")?,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Whether the manager `peer_id` may send `msg`. Switching the topic and
+    /// banning a peer count as writes, a signal as a read
+    pub fn allows_message(&self, peer_id: &str, msg: &ManagerMessage) -> bool {
+        match msg {
+            ManagerMessage::StoreCommand(cmd) => self.allows(peer_id, cmd),
+            ManagerMessage::TargetedStoreCommand(params) => self.allows(peer_id, &params.cmd),
+            ManagerMessage::Signal(_) => self.allows_keys(peer_id, false, &[]),
+            ManagerMessage::Ban(_) | ManagerMessage::Unban(_) | ManagerMessage::SwitchTopic(_) => {
+                self.allows_keys(peer_id, true, &[])
+            }
+        }
+    }
+
+    /// Whether the manager `peer_id` may run `cmd`
+    pub fn allows(&self, peer_id: &str, cmd: &StoreCommand) -> bool {
+        self.allows_keys(peer_id, is_write(cmd), &cmd.key_args())
+    }
+
+    fn allows_keys(&self, peer_id: &str, write: bool, keys: &[&str]) -> bool {
+        let Some(rule) = self.rules.iter().find(|rule| rule.peer.is_match(peer_id)) else {
+            return !write;
+        };
+        let prefixes = match (write, &rule.read) {
+            (true, _) => &rule.write,
+            (false, Some(read)) => read,
+            (false, None) => return true,
+        };
+
+        if keys.is_empty() {
+            return prefixes.iter().any(String::is_empty);
+        }
+        keys.iter()
+            .all(|key| prefixes.iter().any(|prefix| key.starts_with(prefix.as_str())))
+    }
+}
+
+/// Whether the command changes the content of the store
+fn is_write(cmd: &StoreCommand) -> bool {
+    AuditMutation::from_command(cmd).is_some()
+        || matches!(cmd, StoreCommand::EXPIRE(_) | StoreCommand::FLUSH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{BanParams, SignalParams, SwitchTopicParams};
+
+    fn acl() -> Acl {
+        Acl::parse(
+            r#"[
+                {"peer": "tenant-a-*", "write": ["a:"], "read": ["a:", "shared:"]},
+                {"peer": "admin", "write": [""]}
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn writes_are_limited_to_the_granted_prefixes() {
+        let acl = acl();
+        assert!(acl.allows("tenant-a-1", &StoreCommand::set("a:color", "red")));
+        assert!(!acl.allows("tenant-a-1", &StoreCommand::set("b:color", "red")));
+        assert!(!acl.allows("tenant-a-1", &StoreCommand::del(["a:color", "b:color"])));
+        assert!(!acl.allows("tenant-a-1", &StoreCommand::rename("a:color", "b:color")));
+        assert!(!acl.allows("tenant-a-1", &StoreCommand::flush()));
+        assert!(acl.allows("admin", &StoreCommand::flush()));
+    }
+
+    #[test]
+    fn reads_are_only_limited_when_granted() {
+        let acl = acl();
+        assert!(acl.allows("tenant-a-1", &StoreCommand::get("shared:motd")));
+        assert!(!acl.allows("tenant-a-1", &StoreCommand::get("b:color")));
+        assert!(!acl.allows("tenant-a-1", &StoreCommand::keys("*")));
+        assert!(acl.allows("admin", &StoreCommand::keys("*")));
+    }
+
+    #[test]
+    fn unknown_managers_may_only_read() {
+        let acl = acl();
+        assert!(acl.allows("stranger", &StoreCommand::get("a:color")));
+        assert!(!acl.allows("stranger", &StoreCommand::set("a:color", "red")));
+        assert!(!acl.allows("stranger", &StoreCommand::expire("a:color", 10)));
+    }
+
+    #[test]
+    fn cluster_messages_need_every_key() {
+        let acl = acl();
+        let ban = ManagerMessage::Ban(BanParams::new("peer".to_string()));
+        let switch = ManagerMessage::SwitchTopic(SwitchTopicParams::new("other".to_string()));
+        let signal = ManagerMessage::Signal(SignalParams::new(Vec::new()));
+        for msg in [&ban, &switch] {
+            assert!(acl.allows_message("admin", msg));
+            assert!(!acl.allows_message("tenant-a-1", msg));
+            assert!(!acl.allows_message("stranger", msg));
+        }
+        assert!(acl.allows_message("stranger", &signal));
+        assert!(!acl.allows_message("tenant-a-1", &signal));
+        let peers = ManagerMessage::StoreCommand(StoreCommand::peers());
+        assert!(!acl.allows_message("tenant-a-1", &peers));
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        let err = Acl::parse(r#"[{"write": ["a:"]}]"#).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = Acl::parse(r#"[{"peer": "admin", "write": "a:"}]"#).unwrap_err();
+        assert!(err.to_string().contains("write"));
+    }
+}
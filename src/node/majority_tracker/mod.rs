@@ -1,46 +1,119 @@
-use bincode::{Decode, Encode};
+use std::cell::Cell;
 use std::collections::*;
 
-#[derive(Decode, Encode, Debug, Clone, PartialEq)]
-pub struct Signature {
-    pub root: Option<[u8; 32]>,
-    pub local_timestamp: u128,
-}
+pub use crate::protocol::Signature;
 
 pub struct MajorityTracker {
     history: HashMap<String, Signature>,
+    /// result of `most_common_root` while no peer changed its root or weight,
+    /// signatures that only move the timestamp forward keep it
+    most_common: Cell<Option<Option<[u8; 32]>>>,
 }
 
 impl MajorityTracker {
     pub fn new() -> Self {
         Self {
             history: HashMap::new(),
+            most_common: Cell::new(None),
         }
     }
 
     pub fn update_signature(&mut self, peer_id: String, new_signature: Signature) {
         if let Some(old) = self.history.get(&peer_id) {
             if old.local_timestamp < new_signature.local_timestamp {
+                if (old.root, old.weight) != (new_signature.root, new_signature.weight) {
+                    self.most_common.set(None);
+                }
                 self.history.insert(peer_id, new_signature);
             }
         } else {
+            self.most_common.set(None);
             self.history.insert(peer_id, new_signature);
         }
     }
 
+    /// Signature of `peer_id` a keepalive confirms, `None` if the one shared at
+    /// `since` was missed or superseded
+    pub fn keepalive(&self, peer_id: &str, since: u128) -> Option<&Signature> {
+        self.history
+            .get(peer_id)
+            .filter(|signature| signature.local_timestamp == since)
+    }
+
+    /// Peers whose last signature has `local_root` and the ones whose root
+    /// differs, both sorted
+    pub fn partition_by_local_root(
+        &self,
+        local_root: Option<[u8; 32]>,
+    ) -> (Vec<String>, Vec<String>) {
+        let mut agree = Vec::new();
+        let mut disagree = Vec::new();
+        for (peer_id, signature) in self.history.iter() {
+            match signature.root == local_root {
+                true => agree.push(peer_id.to_string()),
+                false => disagree.push(peer_id.to_string()),
+            }
+        }
+        agree.sort();
+        disagree.sort();
+        (agree, disagree)
+    }
+
+    /// The root held by the largest total weight of peers
     fn most_common_root(&self) -> Option<[u8; 32]> {
-        let mut freqs = HashMap::new();
+        if let Some(root) = self.most_common.get() {
+            return root;
+        }
+        let root = self.count_most_common_root();
+        self.most_common.set(Some(root));
+        root
+    }
+
+    fn count_most_common_root(&self) -> Option<[u8; 32]> {
+        let mut weights = HashMap::new();
         for signature in self.history.values() {
             if let Some(root) = signature.root {
-                *freqs.entry(root).or_insert(0) += 1;
+                *weights.entry(root).or_insert(0u64) += u64::from(signature.weight);
             }
         }
-        freqs
+        weights
             .into_iter()
-            .max_by_key(|&(_, count)| count)
+            .max_by_key(|&(_, weight)| weight)
             .map(|(root, _)| root)
     }
 
+    /// Total weight of the peers whose last signature has `root`
+    pub fn root_weight(&self, root: Option<[u8; 32]>) -> u64 {
+        self.history
+            .values()
+            .filter(|signature| signature.root == root)
+            .map(|signature| u64::from(signature.weight))
+            .sum()
+    }
+
+    /// Copy of the last signature of every peer, sorted by peer id
+    pub fn snapshot(&self) -> Vec<(String, Signature)> {
+        let mut snapshot: Vec<(String, Signature)> = self
+            .history
+            .iter()
+            .map(|(peer_id, signature)| (peer_id.clone(), signature.clone()))
+            .collect();
+        snapshot.sort_by(|(a, _), (b, _)| a.cmp(b));
+        snapshot
+    }
+
+    /// Every peer that shared a signature, sorted
+    pub fn peers(&self) -> Vec<String> {
+        let mut peers: Vec<String> = self.history.keys().cloned().collect();
+        peers.sort();
+        peers
+    }
+
+    /// Root of the truthful majority, see [`MajorityTracker::truthful_majority`]
+    pub fn majority_root(&self) -> Option<[u8; 32]> {
+        self.most_common_root()
+    }
+
     pub fn truthful_majority(&self) -> Option<Vec<String>> {
         if let Some(mc_root) = self.most_common_root() {
             let mut result = Vec::new();
@@ -66,9 +139,15 @@ mod tests {
     use super::*;
 
     fn sig(root: Option<[u8; 32]>, ts: u128) -> Signature {
+        weighted_sig(root, ts, 1)
+    }
+
+    fn weighted_sig(root: Option<[u8; 32]>, ts: u128, weight: u32) -> Signature {
         Signature {
             root,
             local_timestamp: ts,
+            fingerprint: 0,
+            weight,
         }
     }
 
@@ -107,6 +186,94 @@ mod tests {
         assert_eq!(tracker.history["p1"].root, Some([1; 32]));
     }
 
+    #[test]
+    fn test_keepalive_confirms_only_the_tracked_signature() {
+        let mut tracker = MajorityTracker::new();
+        assert_eq!(tracker.keepalive("p1", 100), None);
+
+        tracker.update_signature("p1".to_string(), sig(Some([1; 32]), 100));
+        assert_eq!(tracker.keepalive("p1", 100), Some(&sig(Some([1; 32]), 100)));
+        // refers to a signature that never arrived
+        assert_eq!(tracker.keepalive("p1", 50), None);
+        assert_eq!(tracker.keepalive("p2", 100), None);
+    }
+
+    #[test]
+    fn test_root_weight_and_peers() {
+        let mut tracker = MajorityTracker::new();
+        tracker.update_signature("p2".to_string(), weighted_sig(Some([1; 32]), 100, 3));
+        tracker.update_signature("p1".to_string(), sig(Some([1; 32]), 100));
+        tracker.update_signature("p3".to_string(), sig(None, 100));
+
+        assert_eq!(tracker.root_weight(Some([1; 32])), 4);
+        assert_eq!(tracker.root_weight(None), 1);
+        assert_eq!(tracker.root_weight(Some([2; 32])), 0);
+        assert_eq!(tracker.peers(), ["p1", "p2", "p3"]);
+    }
+
+    #[test]
+    fn test_snapshot_is_sorted_by_peer_id() {
+        let mut tracker = MajorityTracker::new();
+        tracker.update_signature("p3".to_string(), sig(Some([3; 32]), 300));
+        tracker.update_signature("p1".to_string(), sig(Some([1; 32]), 100));
+        tracker.update_signature("p2".to_string(), sig(None, 200));
+
+        assert_eq!(
+            tracker.snapshot(),
+            [
+                ("p1".to_string(), sig(Some([1; 32]), 100)),
+                ("p2".to_string(), sig(None, 200)),
+                ("p3".to_string(), sig(Some([3; 32]), 300)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partition_by_local_root() {
+        let mut t = MajorityTracker::new();
+        assert_eq!(t.partition_by_local_root(None), (vec![], vec![]));
+
+        t.update_signature("p3".to_string(), sig(Some([1; 32]), 1));
+        t.update_signature("p1".to_string(), sig(Some([1; 32]), 1));
+        t.update_signature("p2".to_string(), sig(Some([2; 32]), 1));
+        t.update_signature("p4".to_string(), sig(None, 1));
+
+        assert_eq!(
+            t.partition_by_local_root(Some([1; 32])),
+            (
+                vec!["p1".to_string(), "p3".to_string()],
+                vec!["p2".to_string(), "p4".to_string()]
+            )
+        );
+        // an empty store agrees with the peers whose store is empty too
+        assert_eq!(
+            t.partition_by_local_root(None),
+            (
+                vec!["p4".to_string()],
+                vec!["p1".to_string(), "p2".to_string(), "p3".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn test_most_common_root_is_cached_until_a_root_changes() {
+        let mut tracker = MajorityTracker::new();
+        tracker.update_signature("p1".to_string(), sig(Some([1; 32]), 100));
+        tracker.update_signature("p2".to_string(), sig(Some([1; 32]), 100));
+        assert_eq!(tracker.most_common.get(), None);
+        assert_eq!(tracker.most_common_root(), Some([1; 32]));
+
+        // a newer signature of the same state keeps the cached root
+        tracker.update_signature("p1".to_string(), sig(Some([1; 32]), 200));
+        assert_eq!(tracker.most_common.get(), Some(Some([1; 32])));
+
+        tracker.update_signature("p1".to_string(), sig(Some([2; 32]), 300));
+        assert_eq!(tracker.most_common.get(), None);
+        tracker.update_signature("p2".to_string(), sig(Some([2; 32]), 300));
+        assert_eq!(tracker.most_common_root(), Some([2; 32]));
+        assert_eq!(tracker.most_common.get(), Some(Some([2; 32])));
+    }
+
     #[test]
     fn test_most_common_root_empty() {
         let tracker = MajorityTracker::new();
@@ -134,6 +301,26 @@ mod tests {
         assert_eq!(t.most_common_root(), Some(a));
     }
 
+    #[test]
+    fn test_heavy_minority_determines_majority() {
+        let mut t = MajorityTracker::new();
+        let seed = [1; 32];
+        let light = [2; 32];
+
+        t.update_signature("seed".to_string(), weighted_sig(Some(seed), 1, 5));
+        for peer in ["p1", "p2", "p3", "p4"] {
+            t.update_signature(peer.to_string(), weighted_sig(Some(light), 1, 1));
+        }
+        assert_eq!(t.most_common_root(), Some(seed));
+        assert_eq!(t.truthful_majority(), Some(vec!["seed".to_string()]));
+
+        // enough light nodes outweigh the seed again
+        for peer in ["p5", "p6"] {
+            t.update_signature(peer.to_string(), weighted_sig(Some(light), 1, 1));
+        }
+        assert_eq!(t.most_common_root(), Some(light));
+    }
+
     #[test]
     fn test_most_common_root_tie_returns_one_of_max() {
         let mut t = MajorityTracker::new();
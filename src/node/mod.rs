@@ -1,141 +1,977 @@
-use bincode::config;
-use futures::stream::StreamExt;
+use futures::future::LocalBoxFuture;
 use libp2p::{
-    gossipsub::{self, IdentTopic},
+    gossipsub::{self, IdentTopic, MessageAcceptance, MessageId, TopicHash},
     mdns,
     swarm::SwarmEvent,
     PeerId, Swarm,
 };
-use std::cell::RefCell;
-use std::time::Duration;
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{ready, Poll};
+use std::time::{Duration, Instant};
 use tokio::select;
 
 pub use crate::MyBehaviour;
 pub use crate::MyBehaviourEvent;
 
 use crate::{
-    node::majority_tracker::{MajorityTracker, Signature},
+    node::{
+        acl::Acl,
+        majority_tracker::{MajorityTracker, Signature},
+        repair_tracker::{PushGuard, RepairAction, RepairTracker},
+    },
     protocol::{
-        metadata::MetaData, ComponentMessage, ManagerMessage, NodeMessage, RepairRequestParams,
-        RepairResponseParams, ShareSignatureParams,
+        audit::{AuditMutation, AuditRecord},
+        metadata::MetaData,
+        AntiEntropyParams, ComponentMessage, KeepaliveParams, KeyChangedParams, KeyExpiredParams,
+        ManagerMessage, NodeMessage, PartialRepairRequestParams, PartialRepairResponseParams,
+        RepairRequestParams, RepairResponseParams, ShareSignatureParams,
+    },
+    store::{
+        builder::StoreBuilder,
+        command::StoreCommand,
+        error::StoreError,
+        result::{ErrorKind, StoreCommandResult, TrackedPeer},
+        *,
     },
-    store::*,
-    utils::timestamp::timestamp_millis,
-    Component, ComponentCore, ComponentError,
+    utils::{
+        bin_args::{BinArgs, BincodeConfig, ExpiryStrategy, RepairMode, SnapshotFormat},
+        bootstrap::Bootstrap,
+        clock::Clock,
+        hex::to_hex,
+        psk::Psk,
+    },
+    validation_result, Component, ComponentCore, ComponentError,
 };
 
+pub mod acl;
 pub mod majority_tracker;
+pub mod repair_tracker;
 
 #[allow(dead_code)]
 pub struct Node {
     core: ComponentCore,
-    storage: RefCell<Store>,
+    /// shared with the blocking pool running the store commands
+    storage: Arc<Mutex<Store>>,
+    /// store work for the blocking pool, run one job at a time in order so
+    /// the event loop never waits on the store lock
+    store_jobs: RefCell<VecDeque<StoreJob>>,
+    /// read once, the store may be held by the blocking pool later on
+    expiry_strategy: ExpiryStrategy,
     tracker: MajorityTracker,
+    audit_topic: Option<IdentTopic>,
+    notify_topic: Option<IdentTopic>,
+    /// see `--log-mutations`
+    log_mutations: bool,
+    /// the clock of the store, see `Store::clock`
+    clock: Arc<dyn Clock>,
+    repair: RepairTracker,
+    repair_mode: RepairMode,
+    /// unsolicited repairs sent in push mode or by anti-entropy
+    pushes: PushGuard,
+    /// see `--anti-entropy-interval-secs`, `None` when disabled
+    anti_entropy_interval: Option<Duration>,
+    /// number of anti-entropy exchanges started, picks the next peer
+    anti_entropy_turn: usize,
+    command_timeout: Duration,
+    /// shared in the signature, see `--node-weight`
+    weight: u32,
+    /// last full signature shared, keepalives are sent while it still holds
+    last_signature: RefCell<Option<Signature>>,
+    /// timestamp of the last signature generated, see `signature_timestamp`
+    last_signature_millis: Cell<u128>,
+    /// peers whose messages are dropped, see `ManagerMessage::Ban`
+    banned: RefCell<HashSet<PeerId>>,
+    /// the last connection closed, the node announces itself again once a
+    /// peer reconnects
+    isolated: bool,
+    /// topic left by `switch_topic`, still listened to until the deadline
+    previous_topic: Option<(IdentTopic, Instant)>,
+    /// see `--acl`, every manager may run any command when `None`
+    acl: Option<Acl>,
+}
+
+/// How long a leaving node keeps its swarm running so peers receive the unsubscribe
+pub const LEAVE_GRACE: Duration = Duration::from_millis(500);
+
+/// How long a node still handles the messages of the topic it switched from,
+/// so the ones published before every component moved aren't lost
+pub const TOPIC_SWITCH_GRACE: Duration = Duration::from_secs(5);
+
+/// How long keepalives may stand in for the full signature, so that peers
+/// which missed it get it again
+pub const SIGNATURE_REFRESH: Duration = Duration::from_secs(30);
+
+/// What a store command run on the blocking pool hands back to the event loop
+struct ExecutedCommand {
+    /// the encoded `StoreCommandResult` message, ready to be published
+    message: Vec<u8>,
+    applied: bool,
+    /// store root before the command ran
+    root_before: Option<[u8; 32]>,
+    root: Option<[u8; 32]>,
+    /// notifications for the keys the command changed
+    changes: Vec<KeyChangedParams>,
+    /// keys lazy expiry removed before the command ran
+    expired: Vec<String>,
 }
 
+/// Store work queued by the event loop, see `Node::next_store_job`
+type StoreJob = LocalBoxFuture<'static, StoreJobDone>;
+
+/// What a store job hands back to the event loop once done
+enum StoreJobDone {
+    Command(Box<CommandDone>),
+    /// a repair built or applied, with the message to publish if any
+    Repair(Result<Option<Vec<u8>>, ComponentError>),
+}
+
+/// A manager command run by a store job
+struct CommandDone {
+    run: Result<CommandRun, ComponentError>,
+    mutation: Option<AuditMutation>,
+    /// of the result, answering the request
+    metadata: MetaData,
+    timestamp: u128,
+    /// completed after timing out, the manager was already answered
+    late: bool,
+}
+
+/// Outcome of `execute_with_timeout`
+enum CommandRun {
+    Done(ExecutedCommand),
+    /// the command still runs in the background, the task completes once it
+    /// is applied
+    TimedOut(CommandTask),
+}
+
+type CommandTask = tokio::task::JoinHandle<Result<ExecutedCommand, ComponentError>>;
+
 #[allow(dead_code)]
 impl Node {
-    pub fn new(swarm: Swarm<MyBehaviour>, peer_id: PeerId, topic: IdentTopic) -> Self {
+    pub fn new(
+        swarm: Swarm<MyBehaviour>,
+        peer_id: PeerId,
+        topic: IdentTopic,
+        args: &BinArgs,
+    ) -> Self {
+        let store = StoreBuilder::from_args(args).build();
+        Self::with_store(swarm, peer_id, topic, args, store)
+    }
+
+    /// Same as [`Node::new`] but serving a prebuilt store, the store options in
+    /// `args` are ignored
+    pub fn with_store(
+        swarm: Swarm<MyBehaviour>,
+        peer_id: PeerId,
+        topic: IdentTopic,
+        args: &BinArgs,
+        store: Store,
+    ) -> Self {
         Self {
-            core: ComponentCore {
-                swarm: swarm.into(),
-                peer_id,
-                topic,
-                config: config::standard(),
-            },
-            storage: Store::new().into(),
+            core: ComponentCore::new(swarm, peer_id, topic)
+                .with_psk(args.psk.as_deref().map(Psk::new))
+                .with_peer_scoring(args.enable_peer_scoring)
+                .with_bootstrap(Bootstrap::from_args(args))
+                .with_bincode_config(args.bincode_config)
+                .with_max_transmit_size(args.gossipsub_max_transmit_size),
+            clock: store.clock(),
+            expiry_strategy: store.expiry_strategy(),
+            storage: Arc::new(Mutex::new(store)),
+            store_jobs: RefCell::new(VecDeque::new()),
             tracker: MajorityTracker::new(),
+            audit_topic: args.audit_topic.as_deref().map(IdentTopic::new),
+            notify_topic: args.notify_topic.as_deref().map(IdentTopic::new),
+            log_mutations: args.log_mutations,
+            repair: RepairTracker::new(
+                Duration::from_millis(args.repair_timeout_ms),
+                args.repair_retries,
+            ),
+            repair_mode: args.repair_mode,
+            pushes: PushGuard::new(Duration::from_millis(args.repair_timeout_ms)),
+            anti_entropy_interval: (args.anti_entropy_interval_secs > 0)
+                .then(|| Duration::from_secs(args.anti_entropy_interval_secs)),
+            anti_entropy_turn: 0,
+            command_timeout: Duration::from_millis(args.command_timeout_ms),
+            weight: args.node_weight,
+            last_signature: RefCell::new(None),
+            last_signature_millis: Cell::new(0),
+            banned: RefCell::new(HashSet::new()),
+            isolated: false,
+            previous_topic: None,
+            acl: None,
         }
     }
 
-    fn generate_signature(&self) -> Result<Signature, ComponentError> {
-        let root = self.storage.borrow().reveal_root();
-        let local_timestamp = timestamp_millis().ok_or(ComponentError::Timestamp())?;
+    pub fn with_acl(mut self, acl: Option<Acl>) -> Self {
+        self.acl = acl;
+        self
+    }
 
-        Ok(Signature {
-            root,
-            local_timestamp,
+    /// Writes the store to `path` on the blocking pool, see `--snapshot`
+    pub async fn save_snapshot(
+        &self,
+        path: &str,
+        format: SnapshotFormat,
+    ) -> Result<(), StoreError> {
+        let storage = self.storage.clone();
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || lock_store(&storage).save_to_path(&path, format))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+
+    /// Waits for the store, the event loop goes through `spawn_store_job` instead
+    #[cfg(test)]
+    fn store(&self) -> MutexGuard<'_, Store> {
+        lock_store(&self.storage)
+    }
+
+    /// Unsubscribes from the topic so peers drop this node from their mesh
+    /// right away, then drives the swarm for `grace` to send the unsubscribe.
+    /// Called on shutdown once the event loop has stopped
+    pub async fn leave(&self, grace: Duration) {
+        self.core
+            .swarm
+            .borrow_mut()
+            .behaviour_mut()
+            .gossipsub
+            .unsubscribe(&self.core.topic);
+        let _ = tokio::time::timeout(grace, async {
+            loop {
+                self.core.next_event().await;
+            }
         })
+        .await;
     }
 
-    fn share_signature(&mut self) -> Result<(), ComponentError> {
-        let signature = self.generate_signature()?;
-        let timestamp = timestamp_millis().ok_or(ComponentError::Timestamp())?;
+    /// The store unless a command still running on the blocking pool holds it
+    fn try_store(&self) -> Option<MutexGuard<'_, Store>> {
+        self.storage.try_lock().ok()
+    }
+
+    /// Queues `job` for the blocking pool, the message it returns is published
+    /// once it is done, see `finish_store_job`
+    fn spawn_store_job<F>(&self, job: F)
+    where
+        F: FnOnce(&mut Store) -> Result<Option<Vec<u8>>, ComponentError> + Send + 'static,
+    {
+        let storage = self.storage.clone();
+        // only spawned once the jobs queued before it are done
+        let job = async move {
+            let task = tokio::task::spawn_blocking(move || job(&mut lock_store(&storage)));
+            match task.await {
+                Ok(done) => StoreJobDone::Repair(done),
+                Err(e) => std::panic::resume_unwind(e.into_panic()),
+            }
+        };
+        self.store_jobs.borrow_mut().push_back(Box::pin(job));
+    }
+
+    /// Waits for the oldest store job, the next one only starts after it.
+    /// Never resolves while no job is queued
+    async fn next_store_job(&self) -> StoreJobDone {
+        std::future::poll_fn(|cx| {
+            let mut jobs = self.store_jobs.borrow_mut();
+            let Some(job) = jobs.front_mut() else {
+                return Poll::Pending;
+            };
+            let done = ready!(job.as_mut().poll(cx));
+            jobs.pop_front();
+            Poll::Ready(done)
+        })
+        .await
+    }
+
+    fn finish_store_job(&self, done: StoreJobDone) -> Result<(), ComponentError> {
+        match done {
+            StoreJobDone::Command(done) => self.finish_command(*done),
+            StoreJobDone::Repair(message) => match message? {
+                Some(data) => self.publish_to(self.core.topic.clone(), data),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Signature of the current store, `None` while a slow command holds it
+    fn generate_signature(&self) -> Result<Option<Signature>, ComponentError> {
+        let Some(storage) = self.try_store() else {
+            return Ok(None);
+        };
+        let local_timestamp = self.signature_timestamp()?;
+
+        Ok(Some(Signature {
+            root: storage.reveal_root(),
+            local_timestamp,
+            fingerprint: storage.fingerprint(),
+            weight: self.weight,
+        }))
+    }
+
+    /// Timestamp of a new signature, strictly after the previous one so peers
+    /// keep the latest even while the clock of the store stands still
+    fn signature_timestamp(&self) -> Result<u128, ComponentError> {
+        let now = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let timestamp = now.max(self.last_signature_millis.get() + 1);
+        self.last_signature_millis.set(timestamp);
+        Ok(timestamp)
+    }
+
+    /// Shares the signature, or only a keepalive while the store state is the
+    /// one of the last full signature and that one is recent enough
+    fn share_signature(&self) -> Result<(), ComponentError> {
+        let Some(signature) = self.generate_signature()? else {
+            return Ok(());
+        };
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let src_id = self.core.peer_id.to_string();
+
+        let last = self.last_signature.borrow().clone();
+        let body = match last {
+            Some(last)
+                if last.same_state(&signature)
+                    && signature.local_timestamp.saturating_sub(last.local_timestamp)
+                        < SIGNATURE_REFRESH.as_millis() =>
+            {
+                let params =
+                    KeepaliveParams::new(src_id, last.local_timestamp, signature.local_timestamp);
+                NodeMessage::Keepalive(params)
+            }
+            _ => {
+                *self.last_signature.borrow_mut() = Some(signature.clone());
+                NodeMessage::ShareSignature(ShareSignatureParams::new(src_id, signature))
+            }
+        };
 
         let metadata = MetaData::new(self.core.peer_id, timestamp);
-        let params = ShareSignatureParams::new(self.core.peer_id.to_string(), signature);
-        let msg = ComponentMessage::NodeMessage(NodeMessage::ShareSignature(params), metadata);
+        self.publish_message(ComponentMessage::NodeMessage(body, metadata))
+    }
 
-        self.publish_message(msg)
+    /// Makes the next signature shared a full one, e.g. for a peer that just joined
+    fn reset_signature(&self) {
+        self.last_signature.take();
+    }
+
+    /// Whether the messages of `topic` are handled: the ones of the node topic,
+    /// and the ones of the previous topic for a while after a switch
+    fn listens_to(&self, topic: &TopicHash, now: Instant) -> bool {
+        *topic == self.core.topic.hash()
+            || self
+                .previous_topic
+                .as_ref()
+                .is_some_and(|(previous, until)| *topic == previous.hash() && now < *until)
+    }
+
+    /// Moves the node to `topic`: it publishes there from now on and only
+    /// leaves the old topic after `TOPIC_SWITCH_GRACE`, see `leave_previous_topic`
+    fn switch_topic(&mut self, topic: &str, now: Instant) -> Result<(), ComponentError> {
+        let topic = IdentTopic::new(topic);
+        if topic.hash() == self.core.topic.hash() {
+            return Ok(());
+        }
+        self.core
+            .swarm
+            .borrow_mut()
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(&topic)
+            .map_err(|e| ComponentError::Subscription(e.to_string()))?;
+        println!("Switching from topic {} to {topic}", self.core.topic);
+        // the topic before an earlier switch still within its grace is left now,
+        // unless the node comes back to it
+        if let Some((older, _)) = self.previous_topic.take()
+            && older.hash() != topic.hash()
+        {
+            self.leave_topic(&older);
+        }
+        let previous = std::mem::replace(&mut self.core.topic, topic);
+        self.previous_topic = Some((previous, now + TOPIC_SWITCH_GRACE));
+        self.reset_signature();
+        Ok(())
     }
 
+    /// Leaves the topic switched from once its grace is over at `now`
+    fn leave_previous_topic(&mut self, now: Instant) {
+        if let Some((previous, until)) = self.previous_topic.take() {
+            match now < until {
+                true => self.previous_topic = Some((previous, until)),
+                false => self.leave_topic(&previous),
+            }
+        }
+    }
+
+    fn leave_topic(&self, topic: &IdentTopic) {
+        let mut swarm = self.core.swarm.borrow_mut();
+        swarm.behaviour_mut().gossipsub.unsubscribe(topic);
+        println!("Left topic {topic}");
+    }
+
+    /// Notes that a connection closed, the node is isolated once none is left
+    fn connection_closed(&mut self) {
+        if self.isolated || self.core.swarm.borrow().connected_peers().next().is_some() {
+            return;
+        }
+        println!("Lost every peer, waiting for one to reconnect");
+        self.isolated = true;
+    }
+
+    /// Rejoins the topic and shares a full signature when the first peer comes
+    /// back after an isolation, peers may have moved on in the meantime. The
+    /// signature waits in the outbox until the peer subscribes
+    fn connection_established(&mut self) -> Result<(), ComponentError> {
+        if !self.isolated {
+            return Ok(());
+        }
+        println!("A peer reconnected, announcing the node again");
+        self.isolated = false;
+        {
+            let mut swarm = self.core.swarm.borrow_mut();
+            let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+            gossipsub.unsubscribe(&self.core.topic);
+            gossipsub
+                .subscribe(&self.core.topic)
+                .map_err(|e| ComponentError::Subscription(e.to_string()))?;
+        }
+        self.reset_signature();
+        self.share_signature()
+    }
+
+    /// Answers a manager message, store commands are queued for the blocking
+    /// pool and answered by `finish_command`. The acl is checked before any
+    /// message is handled
     fn handle_manager_message_and_publish(
-        &self,
-        msg: ManagerMessage,
+        &mut self,
+        msg: ManagerMessage<'static>,
+        request: MetaData,
     ) -> Result<(), ComponentError> {
         println!("handling ManagerMessage: {:?}", msg);
-        let mut binding = self.storage.borrow_mut();
-        let message = match msg {
-            ManagerMessage::StoreCommand(cmd) => {
-                let cmd_result = binding.execute(cmd)?;
+        if let Some(acl) = &self.acl
+            && !acl.allows_message(&request.peer_id_str, &msg)
+        {
+            println!("The acl forbids this message to {}", request.peer_id_str);
+            let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+            let metadata = MetaData::new(self.core.peer_id, timestamp).in_reply_to(&request);
+            let result = StoreCommandResult::error(ErrorKind::Forbidden, "forbidden");
+            let message =
+                ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), metadata);
+            return self.publish_message(message);
+        }
 
-                let timestamp = timestamp_millis().ok_or(ComponentError::Timestamp())?;
-                let metadata = MetaData::new(self.core.peer_id, timestamp);
+        let cmd = match msg {
+            ManagerMessage::StoreCommand(cmd) => cmd,
+            ManagerMessage::TargetedStoreCommand(params) => {
+                if !params.dst_ids.contains(&self.core.peer_id.to_string()) {
+                    return Ok(());
+                }
+                params.cmd
+            }
+            ManagerMessage::Signal(params) => {
+                if params.targets(&self.core.peer_id.to_string()) {
+                    self.reset_signature();
+                    self.share_signature()?;
+                }
+                return Ok(());
+            }
+            ManagerMessage::Ban(params) => return self.set_banned(&params.peer_id, true),
+            ManagerMessage::Unban(params) => return self.set_banned(&params.peer_id, false),
+            ManagerMessage::SwitchTopic(params) => {
+                return self.switch_topic(&params.topic, Instant::now());
+            }
+        };
+        match cmd {
+            StoreCommand::PEERS => return self.publish_peers(request),
+            StoreCommand::TRACKER => return self.publish_tracker(request),
+            _ => {}
+        }
+
+        let mutation = AuditMutation::from_command(&cmd);
+        let changed_keys = match (&self.notify_topic, &mutation) {
+            (Some(_), Some(mutation)) => mutation.keys().to_vec(),
+            _ => Vec::new(),
+        };
+
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp).in_reply_to(&request);
+
+        let run = execute_with_timeout(
+            self.storage.clone(),
+            cmd,
+            changed_keys,
+            metadata.clone(),
+            self.core.config,
+            self.command_timeout,
+        );
+        let job = async move {
+            StoreJobDone::Command(Box::new(CommandDone {
+                run: run.await,
+                mutation,
+                metadata,
+                timestamp,
+                late: false,
+            }))
+        };
+        self.store_jobs.borrow_mut().push_back(Box::pin(job));
+        Ok(())
+    }
+
+    /// Publishes the result of a command run by `handle_manager_message_and_publish`
+    /// with its audit record and key notifications. A command that timed out is
+    /// answered right away, the rest is published once it is applied
+    fn finish_command(&self, done: CommandDone) -> Result<(), ComponentError> {
+        let CommandDone {
+            run,
+            mutation,
+            metadata,
+            timestamp,
+            late,
+        } = done;
+        let executed = match run? {
+            CommandRun::Done(executed) => executed,
+            CommandRun::TimedOut(task) => {
+                eprintln!(
+                    "store command timed out after {}ms",
+                    self.command_timeout.as_millis()
+                );
+                // ahead of the queued jobs, they wait for the store it holds anyway
+                let answered = metadata.clone();
+                let job = async move {
+                    StoreJobDone::Command(Box::new(CommandDone {
+                        run: join_command(task).await.map(CommandRun::Done),
+                        mutation,
+                        metadata,
+                        timestamp,
+                        late: true,
+                    }))
+                };
+                self.store_jobs.borrow_mut().push_front(Box::pin(job));
+                let result = StoreCommandResult::error(
+                    ErrorKind::Internal,
+                    "command timed out, it may still be applied",
+                );
+                let message = ComponentMessage::NodeMessage(
+                    NodeMessage::StoreCommandResult(result),
+                    answered,
+                );
+                return self.publish_message(message);
+            }
+        };
+
+        let published = match late {
+            true => Ok(()),
+            false => self.publish_to(self.core.topic.clone(), executed.message),
+        };
 
-                ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(cmd_result), metadata)
+        if let (Some(mutation), true) = (mutation, executed.applied) {
+            if self.log_mutations {
+                println!(
+                    "{}",
+                    mutation_log_line(&mutation, executed.root_before, executed.root)
+                );
+            }
+            self.publish_audit(mutation, executed.root, timestamp)?;
+        }
+        self.publish_key_changes(executed.changes, metadata)?;
+        if !executed.expired.is_empty() {
+            println!("removed {} expired key(s)", executed.expired.len());
+            // same rule as `purge_expired_keys`, on the root before the expiry
+            if self.reports_expiries(executed.root_before) {
+                self.publish_key_expiries(executed.expired)?;
+            }
+        }
+        published
+    }
+
+    /// Blacklists the peer in gossipsub, which drops the messages it publishes
+    /// or relays, or lifts the ban. A node never bans itself
+    fn set_banned(&self, peer_id: &str, banned: bool) -> Result<(), ComponentError> {
+        let peer_id: PeerId = peer_id.parse().map_err(|_| ComponentError::InvalidInput())?;
+        if peer_id == self.core.peer_id {
+            return Ok(());
+        }
+        let mut swarm = self.core.swarm.borrow_mut();
+        let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+        if banned {
+            gossipsub.blacklist_peer(&peer_id);
+            self.banned.borrow_mut().insert(peer_id);
+            println!("Banned peer {peer_id}");
+        } else {
+            gossipsub.remove_blacklisted_peer(&peer_id);
+            self.banned.borrow_mut().remove(&peer_id);
+            println!("Lifted the ban of peer {peer_id}");
+        }
+        Ok(())
+    }
+
+    fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.banned.borrow().contains(peer_id)
+    }
+
+    /// Answers `PEERS` with the view of the tracker on the current root
+    fn publish_peers(&self, request: MetaData) -> Result<(), ComponentError> {
+        let result = match self.try_store() {
+            Some(storage) => {
+                let (agree, disagree) = self.tracker.partition_by_local_root(storage.reveal_root());
+                StoreCommandResult::peers(agree, disagree)
+            }
+            None => {
+                StoreCommandResult::error(ErrorKind::Internal, "store is busy with a slow command")
             }
         };
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp).in_reply_to(&request);
+        let msg = ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), metadata);
+        self.publish_message(msg)
+    }
 
-        self.publish_message(message)?;
+    /// Answers `TRACKER` with the last signature held for every peer
+    fn publish_tracker(&self, request: MetaData) -> Result<(), ComponentError> {
+        let peers = self
+            .tracker
+            .snapshot()
+            .into_iter()
+            .map(|(peer_id, signature)| TrackedPeer {
+                peer_id,
+                root: signature.root,
+                local_timestamp: signature.local_timestamp,
+                weight: signature.weight,
+            })
+            .collect();
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp).in_reply_to(&request);
+        let result = StoreCommandResult::tracker(peers);
+        let msg = ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), metadata);
+        self.publish_message(msg)
+    }
+
+    fn publish_key_changes(
+        &self,
+        changes: Vec<KeyChangedParams>,
+        metadata: MetaData,
+    ) -> Result<(), ComponentError> {
+        let Some(topic) = &self.notify_topic else {
+            return Ok(());
+        };
+        for change in changes {
+            let msg =
+                ComponentMessage::NodeMessage(NodeMessage::KeyChanged(change), metadata.clone());
+            let data = self.core.config.encode_to_vec(&msg)?;
+            self.publish_to(topic.clone(), data)?;
+        }
         Ok(())
     }
 
+    fn publish_audit(
+        &self,
+        mutation: AuditMutation,
+        root: Option<[u8; 32]>,
+        timestamp: u128,
+    ) -> Result<(), ComponentError> {
+        let Some(topic) = &self.audit_topic else {
+            return Ok(());
+        };
+        let record = AuditRecord::new(self.core.peer_id, timestamp, mutation, root);
+        let data = self.core.config.encode_to_vec(&record)?;
+        self.publish_to(topic.clone(), data)
+    }
+
+    fn send_repair_request(&self, dst_id: String) -> Result<(), ComponentError> {
+        let body = RepairRequestParams::new(self.core.peer_id.to_string(), dst_id);
+
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp);
+
+        let msg = ComponentMessage::NodeMessage(NodeMessage::RepairRequest(body), metadata);
+        self.publish_message(msg)
+    }
+
+    /// Asks `dst_id` for the keys matching the glob `pattern` only, the other
+    /// keys are kept when the response is merged
+    pub fn send_partial_repair_request(
+        &self,
+        dst_id: String,
+        pattern: String,
+    ) -> Result<(), ComponentError> {
+        let body = PartialRepairRequestParams::new(self.core.peer_id.to_string(), dst_id, pattern);
+
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp);
+
+        let msg = ComponentMessage::NodeMessage(NodeMessage::PartialRepairRequest(body), metadata);
+        self.publish_message(msg)
+    }
+
+    /// Moves a timed out repair request to another majority peer
+    fn check_repair_timeout(&mut self) -> Result<(), ComponentError> {
+        let majority = self.tracker.truthful_majority().unwrap_or_default();
+        match self.repair.poll_timeout(&majority, Instant::now()) {
+            RepairAction::Wait => Ok(()),
+            RepairAction::Retry(peer_id) => {
+                println!("repair request timed out, retrying with peer {peer_id}");
+                self.send_repair_request(peer_id)
+            }
+            RepairAction::GiveUp => {
+                eprintln!("repair request timed out on every attempt, giving up");
+                Ok(())
+            }
+        }
+    }
+
+    fn purge_expired_keys(&self) -> Result<(), ComponentError> {
+        // a slow command holds the store, the keys are purged on a later tick
+        let Some(mut storage) = self.try_store() else {
+            return Ok(());
+        };
+        // elected on the root the peers last saw, the expiry is about to change it
+        let reports = self.reports_expiries(storage.reveal_root());
+        let removed = storage.reap_expired()?;
+        drop(storage);
+        if removed.is_empty() {
+            return Ok(());
+        }
+        println!("removed {} expired key(s)", removed.len());
+        if reports {
+            self.publish_key_expiries(removed)?;
+        }
+        Ok(())
+    }
+
+    /// Whether this node publishes the expiries every node performs: the
+    /// smallest peer id of the majority, this node included if it holds
+    /// `root`, the majority root. Without a majority every peer is a candidate
+    fn reports_expiries(&self, root: Option<[u8; 32]>) -> bool {
+        let own_id = self.core.peer_id.to_string();
+        let majority_root = self.tracker.majority_root();
+        let mut candidates = match self.tracker.truthful_majority() {
+            Some(majority) => majority,
+            None => self.tracker.peers(),
+        };
+        if majority_root.is_none() || majority_root == root {
+            candidates.push(own_id.clone());
+        }
+        candidates.iter().min() == Some(&own_id)
+    }
+
+    fn publish_key_expiries(&self, keys: Vec<String>) -> Result<(), ComponentError> {
+        let Some(topic) = &self.notify_topic else {
+            return Ok(());
+        };
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp);
+        for key in keys {
+            let expired = NodeMessage::KeyExpired(KeyExpiredParams::new(key));
+            let msg = ComponentMessage::NodeMessage(expired, metadata.clone());
+            let data = self.core.config.encode_to_vec(&msg)?;
+            self.publish_to(topic.clone(), data)?;
+        }
+        Ok(())
+    }
+
+    /// Repairs whichever side is stale if `signature` of `peer_id` differs from
+    /// our state: in pull mode asks the majority for its data, in push mode
+    /// sends ours to the peer if we hold the majority root
+    fn repair_if_diverged(
+        &mut self,
+        peer_id: &str,
+        signature: &Signature,
+    ) -> Result<(), ComponentError> {
+        let Some(own) = self.generate_signature()? else {
+            return Ok(());
+        };
+        if own.same_state(signature) {
+            return Ok(());
+        }
+        match self.repair_mode {
+            RepairMode::Pull => {
+                if let Some(majority) = self.tracker.truthful_majority()
+                    && let Some(peer_id) = self.repair.start(&majority, Instant::now())
+                {
+                    self.send_repair_request(peer_id)?;
+                }
+                Ok(())
+            }
+            RepairMode::Push => self.push_repair(peer_id, &own),
+        }
+    }
+
+    /// Sends our data to a peer outside the majority. Among the majority only
+    /// the lowest peer id pushes, so the peer gets a single copy
+    fn push_repair(&mut self, peer_id: &str, own: &Signature) -> Result<(), ComponentError> {
+        let own_id = self.core.peer_id.to_string();
+        let Some(majority) = self.tracker.truthful_majority() else {
+            return Ok(());
+        };
+        if own.root.is_none()
+            || self.tracker.majority_root() != own.root
+            || majority.iter().any(|id| id == peer_id || *id < own_id)
+            || !self.pushes.allow(peer_id, Instant::now())
+        {
+            return Ok(());
+        }
+        println!("pushing a repair to stale peer {peer_id}");
+        self.send_repair_response(peer_id.to_string())
+    }
+
+    /// Sends our signature to the next known peer in turn, which repairs
+    /// whichever side is stale if the states differ
+    fn start_anti_entropy(&mut self) -> Result<(), ComponentError> {
+        let peers = self.tracker.peers();
+        if peers.is_empty() {
+            return Ok(());
+        }
+        let Some(signature) = self.generate_signature()? else {
+            return Ok(());
+        };
+        let dst_id = peers[self.anti_entropy_turn % peers.len()].clone();
+        self.anti_entropy_turn = self.anti_entropy_turn.wrapping_add(1);
+
+        let body = AntiEntropyParams::new(self.core.peer_id.to_string(), dst_id, signature);
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp);
+
+        let msg = ComponentMessage::NodeMessage(NodeMessage::AntiEntropy(body), metadata);
+        self.publish_message(msg)
+    }
+
+    /// Answers an anti-entropy exchange started by `peer_id`: if the states
+    /// differ, our data is pushed to the peer when it wins and requested from
+    /// the peer otherwise
+    fn reconcile(&mut self, peer_id: &str, theirs: &Signature) -> Result<(), ComponentError> {
+        let Some(own) = self.generate_signature()? else {
+            return Ok(());
+        };
+        if own.same_state(theirs) {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if self.wins_over(&own, peer_id, theirs) {
+            if self.pushes.allow(peer_id, now) {
+                println!("anti-entropy: pushing our data to peer {peer_id}");
+                self.send_repair_response(peer_id.to_string())?;
+            }
+        } else if let Some(dst_id) = self.repair.start(&[peer_id.to_string()], now) {
+            println!("anti-entropy: requesting the data of peer {dst_id}");
+            self.send_repair_request(dst_id)?;
+        }
+        Ok(())
+    }
+
+    /// Whether our state wins over the one of `peer_id`: the root held by more
+    /// weight wins, counting ours, then data wins over an empty store and the
+    /// last resort is the lower peer id
+    fn wins_over(&self, own: &Signature, peer_id: &str, theirs: &Signature) -> bool {
+        let own_weight = self.tracker.root_weight(own.root) + u64::from(self.weight);
+        let their_weight = self.tracker.root_weight(theirs.root);
+        let own_id = self.core.peer_id.to_string();
+        (own_weight, own.root.is_some(), Reverse(own_id.as_str()))
+            > (their_weight, theirs.root.is_some(), Reverse(peer_id))
+    }
+
+    /// Sends the whole store to `dst_id`, requested or pushed
+    fn send_repair_response(&self, dst_id: String) -> Result<(), ComponentError> {
+        let src_id = self.core.peer_id.to_string();
+        let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+        let metadata = MetaData::new(self.core.peer_id, timestamp);
+        let config = self.core.config;
+
+        self.spawn_store_job(move |store| {
+            let data = store.get_main_store()?;
+            let versions = store.versions_of("*")?;
+            let body = RepairResponseParams::new(src_id, dst_id, data, versions);
+            let msg = ComponentMessage::NodeMessage(NodeMessage::RepairResponse(body), metadata);
+            Ok(Some(config.encode_to_vec(&msg)?))
+        });
+        Ok(())
+    }
+
+    /// Decodes and applies a gossipsub message. Only the messages of the node
+    /// topic are handled, the same bytes published on another topic must not
+    /// reach the store of this one
+    fn handle_gossip(
+        &mut self,
+        propagation_source: &PeerId,
+        message_id: &MessageId,
+        message: &gossipsub::Message,
+    ) {
+        if self.is_own_message(message) {
+            return;
+        }
+        // gossipsub already drops them once blacklisted, the ones it delivered
+        // before the ban are still refused
+        if self.is_banned(propagation_source) || message.source.is_some_and(|s| self.is_banned(&s))
+        {
+            return;
+        }
+        if !self.listens_to(&message.topic, Instant::now()) {
+            println!("Ignoring message on topic {}", message.topic);
+            self.report_validation(message_id, propagation_source, MessageAcceptance::Ignore);
+            return;
+        }
+
+        let decoded = self.decode_gossip(&message.data);
+        let acceptance = validation_result(&decoded);
+        self.report_validation(message_id, propagation_source, acceptance);
+        let decoded = match decoded {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                println!("Ignoring message with an unknown variant");
+                return;
+            }
+            Err(e) => {
+                eprintln!("Failed to decode message: {e}");
+                return;
+            }
+        };
+
+        match decoded {
+            // the acl trusts the peer id of the metadata, it must be the signer's
+            ComponentMessage::ManagerMessage(_, metadata)
+                if self.acl.is_some()
+                    && message.source.map(|source| source.to_string())
+                        != Some(metadata.peer_id_str.clone()) =>
+            {
+                eprintln!("Ignoring manager message not signed by {}", metadata.peer_id_str);
+            }
+            ComponentMessage::ManagerMessage(mng_msg, metadata) => {
+                if let Err(e) = self.handle_manager_message_and_publish(mng_msg, metadata) {
+                    eprintln!("Failed to handle manager message: {e}");
+                }
+            }
+            ComponentMessage::NodeMessage(nd_msg, _) => {
+                if let Err(e) = self.handle_node_message(nd_msg) {
+                    eprintln!("Failed to handle node message: {e}");
+                }
+            }
+        }
+    }
+
     fn handle_node_message(&mut self, msg: NodeMessage) -> Result<(), ComponentError> {
         match msg {
             NodeMessage::ShareSignature(params) => {
                 let signature = params.sgn;
-                let src_id = params.src_id;
                 self.tracker
-                    .update_signature(src_id.clone(), signature.clone());
-
-                if self.generate_signature()?.root != signature.root 
-                    && let Some(majority) = self.tracker.truthful_majority() {
-                        for peer_id in majority {
-                            let body = RepairRequestParams::new(
-                                self.core.peer_id.to_string(),
-                                peer_id.to_string(),
-                            );
-
-                            let timestamp = timestamp_millis().ok_or(ComponentError::Timestamp())?;
-                            let metadata = MetaData::new(self.core.peer_id, timestamp);
-
-                            let msg = ComponentMessage::NodeMessage(
-                                NodeMessage::RepairRequest(body.clone()),
-                                metadata,
-                            );
-                            self.publish_message(msg)?;
-                        }
-                    }
-                Ok(())
+                    .update_signature(params.src_id.clone(), signature.clone());
+                self.repair_if_diverged(&params.src_id, &signature)
+            }
+            NodeMessage::Keepalive(params) => {
+                match self.tracker.keepalive(&params.src_id, params.since).cloned() {
+                    Some(signature) => self.repair_if_diverged(&params.src_id, &signature),
+                    // the full signature comes again within `SIGNATURE_REFRESH`
+                    None => Ok(()),
+                }
+            }
+            NodeMessage::AntiEntropy(params) => {
+                if params.dst_id != self.core.peer_id.to_string() {
+                    return Ok(());
+                }
+                self.tracker
+                    .update_signature(params.src_id.clone(), params.sgn.clone());
+                self.reconcile(&params.src_id, &params.sgn)
             }
             NodeMessage::RepairRequest(params) => {
-                let dst = params.dst_id;
-                let src = params.src_id;
-                if dst == self.core.peer_id.to_string() {
-                    let body = RepairResponseParams::new(
-                        dst.clone(),
-                        src,
-                        self.storage.borrow().get_main_store(),
-                    );
-
-                    let timestamp = timestamp_millis().ok_or(ComponentError::Timestamp())?;
-                    let metadata = MetaData::new(self.core.peer_id, timestamp);
-
-                    let msg = ComponentMessage::NodeMessage(
-                        NodeMessage::RepairResponse(body.clone()),
-                        metadata,
-                    );
-                    self.publish_message(msg)?;
+                if params.dst_id == self.core.peer_id.to_string() {
+                    self.send_repair_response(params.src_id)?;
                 }
                 Ok(())
             }
@@ -144,9 +980,67 @@ impl Node {
                 let dst = params.dst_id;
                 let data = params.repaired_data;
                 if dst == self.core.peer_id.to_string() {
-                    let _ = self.storage.borrow_mut().update_full_store(data);
-                    println!("peer {dst} received a response from peer {src} and replaced the data with new ones");
+                    self.repair.complete(&src);
+                    let versions = params.versions;
+                    self.spawn_store_job(move |store| {
+                        match store.update_full_store(data) {
+                            Ok(summary) => {
+                                store.adopt_versions("*", versions)?;
+                                println!(
+                                    "peer {dst} replaced its data with the one of peer {src}: \
+                                     {} added, {} removed, {} changed",
+                                    summary.added, summary.removed, summary.changed
+                                );
+                            }
+                            Err(e) => {
+                                eprintln!(
+                                    "peer {dst} failed to apply the repair of peer {src}: {e:?}"
+                                )
+                            }
+                        }
+                        Ok(None)
+                    });
+                }
+                Ok(())
+            }
+            NodeMessage::PartialRepairRequest(params) => {
+                if params.dst_id != self.core.peer_id.to_string() {
+                    return Ok(());
+                }
+                let timestamp = self.clock.now_millis().ok_or(ComponentError::Timestamp())?;
+                let metadata = MetaData::new(self.core.peer_id, timestamp);
+                let config = self.core.config;
+                self.spawn_store_job(move |store| {
+                    let data = store.get_matching(&params.pattern)?;
+                    let versions = store.versions_of(&params.pattern)?;
+                    let body = PartialRepairResponseParams::new(
+                        params.dst_id,
+                        params.src_id,
+                        params.pattern,
+                        data,
+                        versions,
+                    );
+                    let msg = ComponentMessage::NodeMessage(
+                        NodeMessage::PartialRepairResponse(body),
+                        metadata,
+                    );
+                    Ok(Some(config.encode_to_vec(&msg)?))
+                });
+                Ok(())
+            }
+            NodeMessage::PartialRepairResponse(params) => {
+                if params.dst_id != self.core.peer_id.to_string() {
+                    return Ok(());
                 }
+                self.spawn_store_job(move |store| {
+                    store.merge_matching(&params.pattern, params.repaired_data)?;
+                    store.adopt_versions(&params.pattern, params.versions)?;
+                    println!(
+                        "peer {} replaced the keys matching '{}' with the ones of peer {}",
+                        params.dst_id, params.pattern, params.src_id
+                    );
+                    Ok(None)
+                });
                 Ok(())
             }
             _ => Ok(()),
@@ -154,6 +1048,93 @@ impl Node {
     }
 }
 
+fn lock_store(storage: &Mutex<Store>) -> MutexGuard<'_, Store> {
+    storage.lock().expect("a store command panicked")
+}
+
+/// The line `--log-mutations` prints for a mutation applied by the node
+fn mutation_log_line(
+    mutation: &AuditMutation,
+    root_before: Option<[u8; 32]>,
+    root_after: Option<[u8; 32]>,
+) -> String {
+    let root = |root: Option<[u8; 32]>| root.map_or_else(|| "none".to_string(), |r| to_hex(&r));
+    let (command, keys) = match mutation {
+        AuditMutation::SET(key) => ("SET", vec![key.as_str()]),
+        AuditMutation::DEL(keys) => ("DEL", keys.iter().map(String::as_str).collect()),
+        AuditMutation::RENAME([key, newkey]) => ("RENAME", vec![key.as_str(), newkey.as_str()]),
+        AuditMutation::MSETEX(keys) => ("MSETEX", keys.iter().map(String::as_str).collect()),
+    };
+    format!(
+        "mutation {command} {}: root {} -> {}",
+        keys.join(" "),
+        root(root_before),
+        root(root_after)
+    )
+}
+
+/// Runs the command on the blocking pool so that a slow one, like a `KEYS` over
+/// a huge store, cannot stall the event loop. Returns the running task once
+/// `timeout` elapses: the command cannot be cancelled and still completes in
+/// the background, keeping the store locked until it does. A notification is
+/// prepared for each of `changed_keys` if the command changed the store
+async fn execute_with_timeout(
+    storage: Arc<Mutex<Store>>,
+    cmd: StoreCommand<'static>,
+    changed_keys: Vec<String>,
+    metadata: MetaData,
+    config: BincodeConfig,
+    timeout: Duration,
+) -> Result<CommandRun, ComponentError> {
+    let applied_by = AuditMutation::applied_by(&cmd);
+    let mut task: CommandTask = tokio::task::spawn_blocking(move || {
+        let mut storage = lock_store(&storage);
+        let root_before = storage.reveal_root();
+        // a failed command is answered too, so the manager learns about it
+        let result = storage
+            .execute(cmd)
+            .unwrap_or_else(StoreCommandResult::from);
+        let applied = applied_by(&result);
+        let message =
+            ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), metadata);
+        let message = config.encode_to_vec(&message)?;
+        let expired = storage.take_expired();
+        let root = storage.reveal_root();
+        let changes = match applied {
+            true => changed_keys
+                .into_iter()
+                .map(|key| {
+                    let hash = storage.value_hash(&key)?;
+                    Ok(KeyChangedParams::new(key, hash, root))
+                })
+                .collect::<Result<_, ComponentError>>()?,
+            false => Vec::new(),
+        };
+        Ok(ExecutedCommand {
+            message,
+            applied,
+            root_before,
+            root,
+            changes,
+            expired,
+        })
+    });
+
+    match tokio::time::timeout(timeout, &mut task).await {
+        Ok(Ok(executed)) => executed.map(CommandRun::Done),
+        Ok(Err(e)) => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Ok(CommandRun::TimedOut(task)),
+    }
+}
+
+/// Waits for a command that timed out in `execute_with_timeout`
+async fn join_command(task: CommandTask) -> Result<ExecutedCommand, ComponentError> {
+    match task.await {
+        Ok(executed) => executed,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
 #[async_trait::async_trait(?Send)]
 impl Component for Node {
     fn core(&self) -> &ComponentCore {
@@ -166,68 +1147,1242 @@ impl Component for Node {
 
     async fn start_event_loop<'a>(&'a mut self) {
         let mut share_signature_stream = tokio::time::interval(Duration::from_secs(1));
+        let mut repair_timeout_stream = tokio::time::interval(Duration::from_millis(100));
+        let mut expiry_stream = tokio::time::interval(Duration::from_millis(100));
+        let active_expiry = self.expiry_strategy == ExpiryStrategy::Active;
+        let anti_entropy = self.anti_entropy_interval;
+        let mut anti_entropy_stream =
+            tokio::time::interval(anti_entropy.unwrap_or(Duration::from_secs(1)));
+        self.core.dial_bootstrap();
 
         loop {
-            let mut swarm_guard = self.core.swarm.borrow_mut();
             select! {
-                        event = swarm_guard.select_next_some() => {
-                            match event {
-                                SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
-                                    for (peer_id, _multiaddr) in list {
-                                        println!("mDNS discovered a new peer: {peer_id}");
-                                        swarm_guard.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
-                                    }
-                                }
-                                SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
-                                    for (peer_id, _multiaddr) in list {
-                                        println!("mDNS discover peer has expired: {peer_id}");
-                                        swarm_guard.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
-                                    }
-                                }
-                                SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
-                                    propagation_source: _peer_id,
-                                    message_id: _id,
-                                    message,
-                                })) => {
-                                    drop(swarm_guard);
-
-                                    let (decoded, _len): (ComponentMessage, usize) = match bincode::decode_from_slice(&message.data[..], self.core.config) {
-                                        Ok(v) => v,
-                                        Err(e) => {
-                                            eprintln!("Failed to decode message: {e}");
-                                            continue;
-                                        }
-                                    };
-
-                                    match decoded {
-                                        ComponentMessage::ManagerMessage(mng_msg, _) => {
-                                    let _ = self.handle_manager_message_and_publish(mng_msg);
-
+                event = self.core.next_event() => match event {
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
+                        let mut swarm = self.core.swarm.borrow_mut();
+                        for (peer_id, _multiaddr) in list {
+                            println!("mDNS discovered a new peer: {peer_id}");
+                            swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                        }
+                    }
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
+                        let mut swarm = self.core.swarm.borrow_mut();
+                        for (peer_id, _multiaddr) in list {
+                            println!("mDNS discover peer has expired: {peer_id}");
+                            swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+                        }
+                    }
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                        propagation_source,
+                        message_id,
+                        message,
+                    })) => {
+                        self.handle_gossip(&propagation_source, &message_id, &message);
+                    }
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed { .. })) => {
+                        self.reset_signature();
+                        self.flush_outbox();
+                    }
+                    SwarmEvent::NewListenAddr { address, .. } => {
+                        println!("Local node is listening on {address}");
+                    }
+                    SwarmEvent::ConnectionEstablished { connection_id, .. } => {
+                        self.core.dial_finished(connection_id);
+                        if let Err(e) = self.connection_established() {
+                            eprintln!("Failed to announce the node again: {e}");
+                        }
+                    }
+                    SwarmEvent::OutgoingConnectionError { connection_id, .. } => {
+                        self.core.dial_finished(connection_id);
+                    }
+                    SwarmEvent::ConnectionClosed { .. } => self.connection_closed(),
+                    _ => {}
+                },
+                done = self.next_store_job(), if !self.store_jobs.borrow().is_empty() => {
+                    if let Err(e) = self.finish_store_job(done) {
+                        eprintln!("Failed to finish a store job: {e}");
+                    }
+                }
+                _ = share_signature_stream.tick() => {
+                    if let Err(e) = self.share_signature() {
+                        eprintln!("Failed to share signature: {e}");
+                    }
+                    self.leave_previous_topic(Instant::now());
+                }
+                _ = repair_timeout_stream.tick() => {
+                    if let Err(e) = self.check_repair_timeout() {
+                        eprintln!("Failed to retry repair: {e}");
+                    }
+                }
+                _ = expiry_stream.tick(), if active_expiry => {
+                    if let Err(e) = self.purge_expired_keys() {
+                        eprintln!("Failed to remove expired keys: {e}");
+                    }
+                }
+                _ = anti_entropy_stream.tick(), if anti_entropy.is_some() => {
+                    if let Err(e) = self.start_anti_entropy() {
+                        eprintln!("Failed to start anti-entropy: {e}");
+                    }
+                }
             }
-            ComponentMessage::NodeMessage(nd_msg, _) => {
-                if let Err(e) = self.handle_node_message(nd_msg) {
-                    eprintln!("Failed to handle node message: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        decode_message, BanParams, PartialRepairRequestParams, SignalParams, SwitchTopicParams,
+        TargetedStoreCommandParams,
+    };
+    use crate::store::value::StoreValue;
+    use crate::utils::bin_args::KeyHashing;
+    use crate::utils::clock::MockClock;
+    use crate::utils::swarm_builder::{build_swarm, build_swarm_with_transport};
+    use futures::StreamExt;
+    use libp2p::{core::transport::MemoryTransport, identity};
+    use sha2::{Digest, Sha256};
+
+    fn test_node() -> Node {
+        test_node_with(&BinArgs::default())
+    }
+
+    fn test_node_with(args: &BinArgs) -> Node {
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let swarm = build_swarm(key, args).unwrap();
+        Node::new(swarm, peer_id, IdentTopic::new("test-topic"), args)
+    }
+
+    fn memory_swarm(topic: &IdentTopic) -> (Swarm<MyBehaviour>, PeerId) {
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let transport = MemoryTransport::default();
+        let mut swarm = build_swarm_with_transport(key, &BinArgs::default(), transport).unwrap();
+        swarm.behaviour_mut().gossipsub.subscribe(topic).unwrap();
+        (swarm, peer_id)
+    }
+
+    #[tokio::test]
+    async fn swarm_and_store_are_usable_while_waiting_for_an_event() {
+        let topic = IdentTopic::new("test-topic");
+        let (swarm, peer_id) = memory_swarm(&topic);
+        let node = Node::new(swarm, peer_id, topic, &BinArgs::default());
+
+        let wait_for_address = async {
+            loop {
+                if let SwarmEvent::NewListenAddr { address, .. } = node.core.next_event().await {
+                    return address;
                 }
             }
-                            }
+        };
+        // runs while the loop above is parked on the swarm, holding its
+        // borrow across the await would make `borrow_mut` panic here
+        let use_node = async {
+            tokio::task::yield_now().await;
+            node.store()
+                .execute(StoreCommand::set("color", "red"))
+                .unwrap();
+            node.share_signature().unwrap();
+            node.core
+                .swarm
+                .borrow_mut()
+                .listen_on("/memory/0".parse().unwrap())
+                .unwrap();
+        };
 
-                                }
-                                SwarmEvent::NewListenAddr { address, .. } => {
-                                    println!("Local node is listening on {address}");
+        let (address, ()) = tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::join!(wait_for_address, use_node)
+        })
+        .await
+        .unwrap();
+        assert!(address.to_string().starts_with("/memory/"));
+        assert!(node.store().get_main_store().unwrap().contains_key("color"));
+    }
 
-                                }
-                                _ => {
+    #[tokio::test]
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn peer_observes_unsubscribe_when_node_leaves() {
+        let topic = IdentTopic::new("test-topic");
+        let (swarm, peer_id) = memory_swarm(&topic);
+        let node = Node::new(swarm, peer_id, topic.clone(), &BinArgs::default());
 
-                        }
-                            }
-                        }
-                        _ = share_signature_stream.tick() => {
-                            drop(swarm_guard);
-                            if let Err(e) = self.share_signature() {
-                                eprintln!("Failed to share signature: {e}");
-                            }
+        let (mut peer, _) = memory_swarm(&topic);
+        peer.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = peer.select_next_some().await {
+                break address;
+            }
+        };
+        node.core.swarm.borrow_mut().dial(address).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut swarm = node.core.swarm.borrow_mut();
+            loop {
+                select! {
+                    _ = swarm.select_next_some() => {}
+                    event = peer.select_next_some() => {
+                        if let SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                            gossipsub::Event::Subscribed { peer_id: subscriber, .. },
+                        )) = event
+                            && subscriber == peer_id
+                        {
+                            return;
                         }
                     }
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        let observed = async {
+            loop {
+                if let SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                    gossipsub::Event::Unsubscribed { peer_id, .. },
+                )) = peer.select_next_some().await
+                {
+                    return peer_id;
+                }
+            }
+        };
+        let (_, observed) = tokio::join!(
+            node.leave(Duration::from_secs(1)),
+            tokio::time::timeout(Duration::from_secs(5), observed)
+        );
+
+        assert_eq!(observed.unwrap(), peer_id);
+        assert_eq!(
+            node.core.swarm.borrow().behaviour().gossipsub.topics().count(),
+            0
+        );
+    }
+
+    fn targeted_set(dst_ids: Vec<String>) -> ManagerMessage<'static> {
+        ManagerMessage::TargetedStoreCommand(TargetedStoreCommandParams::new(
+            dst_ids,
+            StoreCommand::set("color", "red"),
+        ))
+    }
+
+    fn set_node_keys(node: &Node, pairs: &[(&str, &str)]) {
+        for &(key, value) in pairs {
+            node.store().execute(StoreCommand::set(key, value)).unwrap();
+        }
+    }
+
+    /// Runs the queued store jobs one after the other, as the event loop does
+    async fn run_store_jobs(node: &Node) {
+        while !node.store_jobs.borrow().is_empty() {
+            let done = node.next_store_job().await;
+            node.finish_store_job(done).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn partial_repair_updates_only_matching_keys() {
+        let mut stale = test_node();
+        set_node_keys(
+            &stale,
+            &[("user:1", "old"), ("user:2", "removed"), ("color", "blue")],
+        );
+        let mut fresh = test_node();
+        set_node_keys(
+            &fresh,
+            &[("user:1", "new"), ("user:3", "added"), ("color", "red")],
+        );
+
+        let request = PartialRepairRequestParams::new(
+            stale.core.peer_id.to_string(),
+            fresh.core.peer_id.to_string(),
+            "user:*".to_string(),
+        );
+        fresh
+            .handle_node_message(NodeMessage::PartialRepairRequest(request))
+            .unwrap();
+        run_store_jobs(&fresh).await;
+        let published = fresh.core.outbox.borrow_mut().take();
+        let Some(ComponentMessage::NodeMessage(response, _)) =
+            decode_message(&published[0].1, fresh.core.config).unwrap()
+        else {
+            panic!("Expected a node message");
+        };
+        assert!(matches!(
+            &response,
+            NodeMessage::PartialRepairResponse(params) if params.repaired_data.len() == 2
+        ));
+
+        stale.handle_node_message(response).unwrap();
+        run_store_jobs(&stale).await;
+        let repaired = stale.store().get_main_store().unwrap();
+        assert_eq!(repaired.len(), 3);
+        assert_eq!(repaired.get("user:1"), Some(&StoreValue::from("new")));
+        assert_eq!(repaired.get("user:3"), Some(&StoreValue::from("added")));
+        assert_eq!(repaired.get("color"), Some(&StoreValue::from("blue")));
+    }
+
+    #[tokio::test]
+    async fn only_the_smallest_majority_peer_reports_an_expiry() {
+        let args = BinArgs {
+            notify_topic: Some("test-notify".to_string()),
+            ..Default::default()
+        };
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let mut nodes: Vec<Node> = (0..3)
+            .map(|_| {
+                let key = identity::Keypair::generate_ed25519();
+                let peer_id = PeerId::from(key.public());
+                let swarm = build_swarm(key, &args).unwrap();
+                let store = StoreBuilder::new().clock(clock.clone()).build();
+                Node::with_store(swarm, peer_id, IdentTopic::new("test-topic"), &args, store)
+            })
+            .collect();
+        for node in &nodes {
+            set_node_keys(node, &[("session", "a"), ("token", "b"), ("color", "red")]);
+            for key in ["session", "token"] {
+                node.store().execute(StoreCommand::expire(key, 10)).unwrap();
+            }
+        }
+        let signatures: Vec<(String, Signature)> = nodes
+            .iter()
+            .map(|node| {
+                let signature = node.generate_signature().unwrap().unwrap();
+                (node.core.peer_id.to_string(), signature)
+            })
+            .collect();
+        for node in &mut nodes {
+            for (peer_id, signature) in &signatures {
+                if *peer_id != node.core.peer_id.to_string() {
+                    node.tracker
+                        .update_signature(peer_id.clone(), signature.clone());
+                }
+            }
+        }
+
+        clock.advance(Duration::from_secs(10));
+        let mut expired = Vec::new();
+        for node in &nodes {
+            node.purge_expired_keys().unwrap();
+            assert_eq!(node.store().get_main_store().unwrap().len(), 1);
+            for (topic, data) in node.core.outbox.borrow_mut().take() {
+                assert_eq!(topic.hash(), IdentTopic::new("test-notify").hash());
+                let Some(ComponentMessage::NodeMessage(NodeMessage::KeyExpired(params), metadata)) =
+                    decode_message(&data, node.core.config).unwrap()
+                else {
+                    panic!("Expected a key expiry");
+                };
+                expired.push((params.key, metadata.peer_id_str));
+            }
+        }
+
+        expired.sort();
+        let reporter = signatures.iter().map(|(peer_id, _)| peer_id).min().unwrap();
+        assert_eq!(
+            expired,
+            [
+                ("session".to_string(), reporter.clone()),
+                ("token".to_string(), reporter.clone())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn lazily_expired_keys_are_reported() {
+        let args = BinArgs {
+            notify_topic: Some("test-notify".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(args.expiry_strategy, ExpiryStrategy::Lazy);
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let swarm = build_swarm(key, &args).unwrap();
+        let store = StoreBuilder::from_args(&args).clock(clock.clone()).build();
+        let mut node = Node::with_store(swarm, peer_id, IdentTopic::new("test-topic"), &args, store);
+        set_node_keys(&node, &[("session", "a"), ("color", "red")]);
+        node.store()
+            .execute(StoreCommand::expire("session", 10))
+            .unwrap();
+
+        clock.advance(Duration::from_secs(10));
+        node.handle_manager_message_and_publish(
+            ManagerMessage::StoreCommand(StoreCommand::get("session")),
+            MetaData::new("manager", 0),
+        )
+        .unwrap();
+        run_store_jobs(&node).await;
+
+        let expired: Vec<String> = node
+            .core
+            .outbox
+            .borrow_mut()
+            .take()
+            .into_iter()
+            .filter(|(topic, _)| topic.hash() == IdentTopic::new("test-notify").hash())
+            .map(|(_, data)| match decode_message(&data, node.core.config).unwrap() {
+                Some(ComponentMessage::NodeMessage(NodeMessage::KeyExpired(params), _)) => {
+                    params.key
+                }
+                _ => panic!("Expected a key expiry"),
+            })
+            .collect();
+        assert_eq!(expired, ["session"]);
+        assert_eq!(node.store().get_main_store().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn repair_carries_structured_values() {
+        let mut stale = test_node();
+        set_node_keys(&stale, &[("queue", "not a list")]);
+        let mut fresh = test_node();
+        for cmd in [
+            StoreCommand::rpush("queue", ["a", "b"]),
+            StoreCommand::hset("user:1", "name", "ada"),
+        ] {
+            fresh.store().execute(cmd).unwrap();
+        }
+
+        let request = RepairRequestParams::new(
+            stale.core.peer_id.to_string(),
+            fresh.core.peer_id.to_string(),
+        );
+        fresh
+            .handle_node_message(NodeMessage::RepairRequest(request))
+            .unwrap();
+        run_store_jobs(&fresh).await;
+        let published = fresh.core.outbox.borrow_mut().take();
+        let Some(ComponentMessage::NodeMessage(response, _)) =
+            decode_message(&published[0].1, fresh.core.config).unwrap()
+        else {
+            panic!("Expected a node message");
+        };
+        stale.handle_node_message(response).unwrap();
+        run_store_jobs(&stale).await;
+
+        let repaired = stale.store().get_main_store().unwrap();
+        assert_eq!(
+            repaired.get("queue"),
+            Some(&StoreValue::List(vec!["a".to_string(), "b".to_string()]))
+        );
+        assert_eq!(repaired.get("user:1").map(StoreValue::type_name), Some("hash"));
+        assert_eq!(stale.store().reveal_root(), fresh.store().reveal_root());
+    }
+
+    #[tokio::test]
+    async fn busy_store_does_not_stall_the_handlers() {
+        let mut node = test_node();
+        let busy = {
+            let storage = node.storage.clone();
+            std::thread::spawn(move || {
+                let _storage = lock_store(&storage);
+                std::thread::sleep(Duration::from_millis(300));
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let started = Instant::now();
+        let request = RepairRequestParams::new("other".to_string(), node.core.peer_id.to_string());
+        node.handle_node_message(NodeMessage::RepairRequest(request))
+            .unwrap();
+        node.handle_manager_message_and_publish(
+            ManagerMessage::StoreCommand(StoreCommand::set("color", "red")),
+            MetaData::new("manager", 0),
+        )
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+        assert_eq!(node.store_jobs.borrow().len(), 2);
+
+        run_store_jobs(&node).await;
+        busy.join().unwrap();
+        // answered in the order the messages came in
+        let published = node.core.outbox.borrow_mut().take();
+        let decoded: Vec<_> = published
+            .iter()
+            .map(|(_, data)| decode_message(data, node.core.config).unwrap())
+            .collect();
+        assert!(matches!(
+            decoded.as_slice(),
+            [
+                Some(ComponentMessage::NodeMessage(NodeMessage::RepairResponse(_), _)),
+                Some(ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(_), _)),
+            ]
+        ));
+    }
+
+    /// Audit records the node published, the other messages are dropped
+    fn take_audit_records(node: &Node) -> Vec<AuditRecord> {
+        let audit = node.audit_topic.as_ref().unwrap().hash();
+        node.core
+            .outbox
+            .borrow_mut()
+            .take()
+            .into_iter()
+            .filter(|(topic, _)| topic.hash() == audit)
+            .map(|(_, data)| node.core.config.decode_from_slice(&data).unwrap().0)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn set_get_is_audited() {
+        let args = BinArgs {
+            audit_topic: Some("test-audit".to_string()),
+            ..Default::default()
+        };
+        let mut node = test_node_with(&args);
+
+        node.handle_manager_message_and_publish(
+            ManagerMessage::StoreCommand(StoreCommand::set_get("color", "red")),
+            MetaData::new("manager", 0),
+        )
+        .unwrap();
+        run_store_jobs(&node).await;
+
+        let records = take_audit_records(&node);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].mutation, AuditMutation::SET("color".to_string()));
+        assert_eq!(records[0].root, node.store().reveal_root());
+    }
+
+    #[tokio::test]
+    async fn timed_out_write_is_audited_once_applied() {
+        let args = BinArgs {
+            audit_topic: Some("test-audit".to_string()),
+            command_timeout_ms: 50,
+            ..Default::default()
+        };
+        let mut node = test_node_with(&args);
+        let busy = {
+            let storage = node.storage.clone();
+            std::thread::spawn(move || {
+                let _storage = lock_store(&storage);
+                std::thread::sleep(Duration::from_millis(300));
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        node.handle_manager_message_and_publish(
+            ManagerMessage::StoreCommand(StoreCommand::set("color", "red")),
+            MetaData::new("manager", 0),
+        )
+        .unwrap();
+        let done = node.next_store_job().await;
+        node.finish_store_job(done).unwrap();
+        let published = node.core.outbox.borrow_mut().take();
+        assert!(matches!(
+            decode_message(&published[0].1, node.core.config).unwrap(),
+            Some(ComponentMessage::NodeMessage(
+                NodeMessage::StoreCommandResult(StoreCommandResult::ERROR(error)),
+                _
+            )) if error.kind == ErrorKind::Internal
+        ));
+        assert_eq!(published.len(), 1);
+
+        // the write lands once the store is free, the audit record follows
+        run_store_jobs(&node).await;
+        busy.join().unwrap();
+        let records = take_audit_records(&node);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].mutation, AuditMutation::SET("color".to_string()));
+        assert_eq!(records[0].root, node.store().reveal_root());
+    }
+
+    #[tokio::test]
+    async fn push_mode_repairs_a_stale_peer_without_a_request() {
+        let args = BinArgs {
+            repair_mode: RepairMode::Push,
+            ..Default::default()
+        };
+        let mut fresh = test_node_with(&args);
+        set_node_keys(&fresh, &[("color", "red"), ("size", "XL")]);
+        let mut stale = test_node_with(&args);
+        set_node_keys(&stale, &[("color", "blue")]);
+        let share = |node: &Node, src_id: &str| {
+            let sgn = node.generate_signature().unwrap().unwrap();
+            NodeMessage::ShareSignature(ShareSignatureParams::new(src_id.to_string(), sgn))
+        };
+
+        // two more peers hold the root of the fresh node, their ids sort after it
+        for peer in ["peer-a", "peer-b"] {
+            fresh.handle_node_message(share(&fresh, peer)).unwrap();
+        }
+        let stale_id = stale.core.peer_id.to_string();
+        fresh.handle_node_message(share(&stale, &stale_id)).unwrap();
+        run_store_jobs(&fresh).await;
+        let published = fresh.core.outbox.borrow_mut().take();
+        assert_eq!(published.len(), 1);
+        let Some(ComponentMessage::NodeMessage(response, _)) =
+            decode_message(&published[0].1, fresh.core.config).unwrap()
+        else {
+            panic!("Expected a node message");
+        };
+        assert!(matches!(
+            &response,
+            NodeMessage::RepairResponse(params) if params.dst_id == stale_id
+        ));
+
+        // the stale peer is not pushed to again right away
+        fresh.handle_node_message(share(&stale, &stale_id)).unwrap();
+        run_store_jobs(&fresh).await;
+        assert!(fresh.core.outbox.borrow().is_empty());
+
+        // nor does the stale node ask for a repair in push mode
+        let fresh_id = fresh.core.peer_id.to_string();
+        stale.handle_node_message(share(&fresh, &fresh_id)).unwrap();
+        assert!(stale.core.outbox.borrow().is_empty());
+
+        stale.handle_node_message(response).unwrap();
+        run_store_jobs(&stale).await;
+        assert_eq!(stale.store().reveal_root(), fresh.store().reveal_root());
+    }
+
+    #[tokio::test]
+    async fn signal_shares_signature_immediately() {
+        let mut node = test_node();
+        let signal = |dst_ids: Vec<String>| ManagerMessage::Signal(SignalParams::new(dst_ids));
+
+        node.handle_manager_message_and_publish(
+            signal(vec!["other".to_string()]),
+            MetaData::new("manager", 0),
+        )
+        .unwrap();
+        assert!(node.core.outbox.borrow().is_empty());
+
+        // no tick of the signature interval is involved, the event loop isn't running
+        node.handle_manager_message_and_publish(signal(Vec::new()), MetaData::new("manager", 0))
+            .unwrap();
+        let published = node.core.outbox.borrow_mut().take();
+        assert_eq!(published.len(), 1);
+        assert!(matches!(
+            decode_message(&published[0].1, node.core.config).unwrap(),
+            Some(ComponentMessage::NodeMessage(NodeMessage::ShareSignature(params), _))
+                if params.src_id == node.core.peer_id.to_string()
+        ));
+    }
+
+    #[tokio::test]
+    async fn node_announces_itself_again_after_an_isolation() {
+        let mut node = test_node();
+        node.share_signature().unwrap();
+        node.core.outbox.borrow_mut().take();
+
+        // a connection of a node that never lost its peers changes nothing
+        node.connection_established().unwrap();
+        assert!(node.core.outbox.borrow().is_empty());
+
+        node.connection_closed();
+        assert!(node.isolated);
+        node.connection_established().unwrap();
+        assert!(!node.isolated);
+        let published = node.core.outbox.borrow_mut().take();
+        assert_eq!(published.len(), 1);
+        // a full signature rather than a keepalive of the one shared before
+        assert!(matches!(
+            decode_message(&published[0].1, node.core.config).unwrap(),
+            Some(ComponentMessage::NodeMessage(NodeMessage::ShareSignature(_), _))
+        ));
+        let swarm = node.core.swarm.borrow();
+        let mut topics = swarm.behaviour().gossipsub.topics();
+        assert_eq!(topics.next(), Some(&node.core.topic.hash()));
+    }
+
+    #[tokio::test]
+    async fn unchanged_node_shares_keepalives() {
+        let node = test_node();
+        let shared = |node: &Node| {
+            node.share_signature().unwrap();
+            let published = node.core.outbox.borrow_mut().take();
+            match decode_message(&published[0].1, node.core.config).unwrap() {
+                Some(ComponentMessage::NodeMessage(msg, _)) => msg,
+                _ => panic!("Expected a node message"),
+            }
+        };
+
+        let NodeMessage::ShareSignature(first) = shared(&node) else {
+            panic!("Expected a full signature first");
+        };
+        for _ in 0..2 {
+            assert!(matches!(
+                shared(&node),
+                NodeMessage::Keepalive(params)
+                    if params.since == first.sgn.local_timestamp
+                        && params.local_timestamp > params.since
+            ));
+        }
+
+        // the root changed
+        set_node_keys(&node, &[("color", "red")]);
+        assert!(matches!(
+            shared(&node),
+            NodeMessage::ShareSignature(params) if params.sgn.root.is_some()
+        ));
+        assert!(matches!(shared(&node), NodeMessage::Keepalive(_)));
+
+        // e.g. a peer joined
+        node.reset_signature();
+        assert!(matches!(shared(&node), NodeMessage::ShareSignature(_)));
+    }
+
+    #[tokio::test]
+    async fn signatures_are_ordered_while_the_clock_stands_still() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let args = BinArgs::default();
+        let key = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(key.public());
+        let swarm = build_swarm(key, &args).unwrap();
+        let store = StoreBuilder::new().clock(clock.clone()).build();
+        let node = Node::with_store(swarm, peer_id, IdentTopic::new("test-topic"), &args, store);
+
+        let first = node.generate_signature().unwrap().unwrap();
+        let second = node.generate_signature().unwrap().unwrap();
+        assert_eq!(first.local_timestamp, 1_000);
+        assert!(second.local_timestamp > first.local_timestamp);
+        // only the signatures move, the store keeps the time of its clock
+        assert_eq!(clock.now_millis(), Some(1_000));
+    }
+
+    #[tokio::test]
+    async fn peers_lists_who_agrees_with_the_local_root() {
+        let mut node = test_node();
+        set_node_keys(&node, &[("color", "red")]);
+        let own = node.generate_signature().unwrap().unwrap();
+        node.tracker.update_signature("same".to_string(), own.clone());
+        node.tracker.update_signature(
+            "other".to_string(),
+            Signature {
+                root: None,
+                ..own
+            },
+        );
+
+        let metadata = MetaData::new("manager", 0).with_request_id(Some(7));
+        node.handle_manager_message_and_publish(
+            ManagerMessage::StoreCommand(StoreCommand::peers()),
+            metadata,
+        )
+        .unwrap();
+        let published = node.core.outbox.borrow_mut().take();
+        assert!(matches!(
+            decode_message(&published[0].1, node.core.config).unwrap(),
+            Some(ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), metadata))
+                if result == StoreCommandResult::peers(
+                    vec!["same".to_string()],
+                    vec!["other".to_string()]
+                ) && metadata.request_id == Some(7)
+                    && metadata.reply_to.as_deref() == Some("manager")
+        ));
+    }
+
+    #[tokio::test]
+    async fn acl_refuses_writes_outside_the_granted_prefixes() {
+        let acl = Acl::parse(r#"[{"peer": "manager", "write": ["tenant-a:"]}]"#).unwrap();
+        let mut node = test_node().with_acl(Some(acl));
+        let set = |key: &str| {
+            ManagerMessage::StoreCommand(StoreCommand::set(key.to_string(), "red".to_string()))
+        };
+
+        node.handle_manager_message_and_publish(set("tenant-a:color"), MetaData::new("manager", 0))
+            .unwrap();
+        node.handle_manager_message_and_publish(set("tenant-b:color"), MetaData::new("manager", 0))
+            .unwrap();
+        run_store_jobs(&node).await;
+
+        // the refusal is answered at once, the allowed write once its job ran
+        let published = node.core.outbox.borrow_mut().take();
+        assert!(matches!(
+            decode_message(&published[0].1, node.core.config).unwrap(),
+            Some(ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), _))
+                if result == StoreCommandResult::error(ErrorKind::Forbidden, "forbidden")
+        ));
+        let store = node.store().get_main_store().unwrap();
+        assert!(store.contains_key("tenant-a:color"));
+        assert!(!store.contains_key("tenant-b:color"));
+    }
+
+    #[tokio::test]
+    async fn acl_is_checked_before_any_manager_message() {
+        let acl = Acl::parse(
+            r#"[{"peer": "admin", "write": [""]}, {"peer": "*", "read": ["tenant-a:"]}]"#,
+        )
+        .unwrap();
+        let mut node = test_node().with_acl(Some(acl));
+        let config = node.core.config;
+        let signer = identity::Keypair::generate_ed25519();
+        let manager = PeerId::from(signer.public());
+        let gossip = |msg: ManagerMessage<'static>| {
+            let msg = ComponentMessage::ManagerMessage(msg, MetaData::new(manager, 0));
+            gossipsub::Message {
+                source: Some(manager),
+                data: config.encode_to_vec(&msg).unwrap(),
+                sequence_number: Some(1),
+                topic: IdentTopic::new("test-topic").hash(),
+            }
+        };
+        let message_id = MessageId::from("id");
+
+        let switch = SwitchTopicParams::new("new-topic".to_string());
+        let ban = BanParams::new(PeerId::random().to_string());
+        for msg in [
+            ManagerMessage::SwitchTopic(switch),
+            ManagerMessage::Ban(ban),
+            ManagerMessage::StoreCommand(StoreCommand::peers()),
+        ] {
+            node.handle_gossip(&manager, &message_id, &gossip(msg));
+        }
+
+        assert_eq!(node.core.topic.hash(), IdentTopic::new("test-topic").hash());
+        assert!(node.banned.borrow().is_empty());
+        let published = node.core.outbox.borrow_mut().take();
+        assert_eq!(published.len(), 3);
+        for (_, data) in published {
+            assert!(matches!(
+                decode_message(&data, config).unwrap(),
+                Some(ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), _))
+                    if result == StoreCommandResult::error(ErrorKind::Forbidden, "forbidden")
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn tracker_lists_the_last_signature_of_every_peer() {
+        let mut node = test_node();
+        let signature = Signature {
+            root: Some([1; 32]),
+            local_timestamp: 42,
+            fingerprint: 0,
+            weight: 2,
+        };
+        node.tracker.update_signature("p1".to_string(), signature);
+
+        let metadata = MetaData::new("manager", 0).with_request_id(Some(7));
+        node.handle_manager_message_and_publish(
+            ManagerMessage::StoreCommand(StoreCommand::tracker()),
+            metadata,
+        )
+        .unwrap();
+        let published = node.core.outbox.borrow_mut().take();
+        let expected = TrackedPeer {
+            peer_id: "p1".to_string(),
+            root: Some([1; 32]),
+            local_timestamp: 42,
+            weight: 2,
+        };
+        assert!(matches!(
+            decode_message(&published[0].1, node.core.config).unwrap(),
+            Some(ComponentMessage::NodeMessage(NodeMessage::StoreCommandResult(result), _))
+                if result == StoreCommandResult::tracker(vec![expected])
+        ));
+    }
+
+    #[tokio::test]
+    async fn logged_set_carries_the_roots_around_it() {
+        let storage = Arc::new(Mutex::new(Store::new()));
+        lock_store(&storage)
+            .execute(StoreCommand::set("size", "L"))
+            .unwrap();
+        let before = lock_store(&storage).reveal_root();
+
+        let executed = execute_with_timeout(
+            storage.clone(),
+            StoreCommand::set("color", "red"),
+            Vec::new(),
+            MetaData::new("node", 0),
+            BincodeConfig::Standard,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        let CommandRun::Done(executed) = executed else {
+            panic!("Expected the command to complete");
+        };
+        let after = lock_store(&storage).reveal_root();
+        assert_eq!((executed.root_before, executed.root), (before, after));
+
+        let mutation = AuditMutation::from_command(&StoreCommand::set("color", "red")).unwrap();
+        assert_eq!(
+            mutation_log_line(&mutation, executed.root_before, executed.root),
+            format!(
+                "mutation SET color: root {} -> {}",
+                to_hex(&before.unwrap()),
+                to_hex(&after.unwrap())
+            )
+        );
+        assert_eq!(
+            mutation_log_line(&AuditMutation::DEL(vec!["a".into(), "b".into()]), after, None),
+            format!("mutation DEL a b: root {} -> none", to_hex(&after.unwrap()))
+        );
+    }
+
+    #[tokio::test]
+    async fn targeted_command_is_applied_by_listed_node() {
+        let mut node = test_node();
+        let metadata = MetaData::new("manager", 0).with_request_id(Some(1));
+        let own_id = node.core.peer_id.to_string();
+
+        node.handle_manager_message_and_publish(targeted_set(vec![own_id]), metadata)
+            .unwrap();
+        run_store_jobs(&node).await;
+
+        assert_eq!(
+            node.store().get_main_store().unwrap().get("color"),
+            Some(&StoreValue::from("red"))
+        );
+    }
+
+    #[tokio::test]
+    async fn targeted_command_is_ignored_by_other_nodes() {
+        let mut node = test_node();
+        let metadata = MetaData::new("manager", 0).with_request_id(Some(1));
+
+        let result = node
+            .handle_manager_message_and_publish(targeted_set(vec!["other".to_string()]), metadata);
+
+        assert!(result.is_ok());
+        assert!(node.store_jobs.borrow().is_empty());
+        assert!(node.store().get_main_store().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn messages_of_another_topic_are_not_applied() {
+        let mut node = test_node();
+        let message = ComponentMessage::ManagerMessage(
+            ManagerMessage::StoreCommand(StoreCommand::set("color", "red")),
+            MetaData::new("manager", 0).with_request_id(Some(1)),
+        );
+        let data = node.core.config.encode_to_vec(&message).unwrap();
+        let gossip = |topic: &str| gossipsub::Message {
+            source: Some(PeerId::random()),
+            data: data.clone(),
+            sequence_number: Some(1),
+            topic: IdentTopic::new(topic).hash(),
+        };
+        let source = PeerId::random();
+        let message_id = MessageId::from("id");
+
+        node.handle_gossip(&source, &message_id, &gossip("other-topic"));
+        run_store_jobs(&node).await;
+        assert!(node.store().get_main_store().unwrap().is_empty());
+
+        node.handle_gossip(&source, &message_id, &gossip("test-topic"));
+        run_store_jobs(&node).await;
+        assert!(node
+            .store()
+            .get_main_store()
+            .unwrap()
+            .contains_key("color"));
+    }
+
+    #[tokio::test]
+    async fn switched_node_handles_the_commands_of_the_new_topic() {
+        let mut node = test_node();
+        let config = node.core.config;
+        let gossip = |topic: &str, msg: ManagerMessage<'static>| {
+            let msg = ComponentMessage::ManagerMessage(msg, MetaData::new("manager", 0));
+            gossipsub::Message {
+                source: Some(PeerId::random()),
+                data: config.encode_to_vec(&msg).unwrap(),
+                sequence_number: Some(1),
+                topic: IdentTopic::new(topic).hash(),
+            }
+        };
+        let set = |key| ManagerMessage::StoreCommand(StoreCommand::set(key, "value"));
+        let source = PeerId::random();
+        let message_id = MessageId::from("id");
+
+        let switch = SwitchTopicParams::new("new-topic".to_string());
+        let switch = gossip("test-topic", ManagerMessage::SwitchTopic(switch));
+        node.handle_gossip(&source, &message_id, &switch);
+        let new_topic = IdentTopic::new("new-topic").hash();
+        assert_eq!(node.core.topic.hash(), new_topic);
+
+        node.handle_gossip(&source, &message_id, &gossip("new-topic", set("new")));
+        // published on the old topic before the manager moved
+        node.handle_gossip(&source, &message_id, &gossip("test-topic", set("late")));
+        node.leave_previous_topic(Instant::now() + TOPIC_SWITCH_GRACE);
+        node.handle_gossip(&source, &message_id, &gossip("test-topic", set("old")));
+        run_store_jobs(&node).await;
+
+        let main = node.store().get_main_store().unwrap();
+        assert!(main.contains_key("new") && main.contains_key("late"));
+        assert!(!main.contains_key("old"));
+        let published = node.core.outbox.borrow_mut().take();
+        assert!(published.iter().all(|(topic, _)| topic.hash() == new_topic));
+        let swarm = node.core.swarm.borrow();
+        let topics: Vec<_> = swarm.behaviour().gossipsub.topics().collect();
+        assert_eq!(topics, [&new_topic]);
+    }
+
+    /// Runs the node and the peer swarms until `done` holds or `duration`
+    /// elapses, the gossip received by the node is handed to it
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn run_with_peer(
+        node: &mut Node,
+        peer: &mut Swarm<MyBehaviour>,
+        duration: Duration,
+        done: impl Fn(&Node, &Swarm<MyBehaviour>) -> bool,
+    ) -> bool {
+        let deadline = tokio::time::Instant::now() + duration;
+        while !done(node, peer) {
+            let event = {
+                let mut swarm = node.core.swarm.borrow_mut();
+                select! {
+                    event = swarm.select_next_some() => Some(event),
+                    _ = peer.select_next_some() => None,
+                    _ = tokio::time::sleep_until(deadline) => return false,
+                }
+            };
+            if let Some(SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                },
+            ))) = event
+            {
+                node.handle_gossip(&propagation_source, &message_id, &message);
+                run_store_jobs(node).await;
+            }
+        }
+        true
+    }
+
+    /// Runs two nodes until `done` holds or `duration` elapses, each is handed
+    /// the gossip it receives
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn run_pair(
+        a: &mut Node,
+        b: &mut Node,
+        duration: Duration,
+        done: impl Fn(&Node, &Node) -> bool,
+    ) -> bool {
+        let deadline = tokio::time::Instant::now() + duration;
+        while !done(a, b) {
+            let (event, to_a) = {
+                let mut swarm_a = a.core.swarm.borrow_mut();
+                let mut swarm_b = b.core.swarm.borrow_mut();
+                select! {
+                    event = swarm_a.select_next_some() => (event, true),
+                    event = swarm_b.select_next_some() => (event, false),
+                    _ = tokio::time::sleep_until(deadline) => return false,
+                }
+            };
+            if let SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                },
+            )) = event
+            {
+                let node = if to_a { &mut *a } else { &mut *b };
+                node.handle_gossip(&propagation_source, &message_id, &message);
+                run_store_jobs(node).await;
+            }
+        }
+        true
+    }
+
+    #[tokio::test]
+    async fn anti_entropy_converges_diverged_nodes_without_writes() {
+        let topic = IdentTopic::new("test-topic");
+        let args = BinArgs {
+            anti_entropy_interval_secs: 1,
+            ..Default::default()
+        };
+        let (swarm, peer_id) = memory_swarm(&topic);
+        let mut a = Node::new(swarm, peer_id, topic.clone(), &args);
+        let (swarm, peer_id) = memory_swarm(&topic);
+        let mut b = Node::new(swarm, peer_id, topic, &args);
+        set_node_keys(&a, &[("color", "red")]);
+        set_node_keys(&b, &[("size", "XL")]);
+
+        b.core
+            .swarm
+            .borrow_mut()
+            .listen_on("/memory/0".parse().unwrap())
+            .unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = b.core.next_event().await {
+                break address;
+            }
+        };
+        a.core.swarm.borrow_mut().dial(address).unwrap();
+        let subscribed = |node: &Node| {
+            let swarm = node.core.swarm.borrow();
+            let mut peers = swarm.behaviour().gossipsub.all_peers();
+            peers.any(|(_, topics)| !topics.is_empty())
+        };
+        let timeout = Duration::from_secs(10);
+        assert!(run_pair(&mut a, &mut b, timeout, |a, b| subscribed(a) && subscribed(b)).await);
+
+        // the broadcast missed the divergence, `a` believes `b` holds its state
+        let stale_view = a.generate_signature().unwrap().unwrap();
+        a.tracker
+            .update_signature(b.core.peer_id.to_string(), stale_view);
+        assert_eq!(a.anti_entropy_interval, Some(Duration::from_secs(1)));
+        a.start_anti_entropy().unwrap();
+
+        let converged = |a: &Node, b: &Node| a.store().reveal_root() == b.store().reveal_root();
+        assert!(run_pair(&mut a, &mut b, timeout, converged).await);
+        // the data of one side replaced the other
+        assert_eq!(a.store().get_main_store().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn messages_of_a_banned_peer_are_dropped() {
+        let topic = IdentTopic::new("test-topic");
+        let (swarm, peer_id) = memory_swarm(&topic);
+        let mut node = Node::new(swarm, peer_id, topic.clone(), &BinArgs::default());
+
+        let (mut peer, banned_id) = memory_swarm(&topic);
+        peer.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = peer.select_next_some().await {
+                break address;
+            }
+        };
+        node.core.swarm.borrow_mut().dial(address).unwrap();
+
+        let subscribed = |swarm: &Swarm<MyBehaviour>| {
+            swarm.behaviour().gossipsub.all_peers().any(|(_, topics)| !topics.is_empty())
+        };
+        assert!(
+            run_with_peer(&mut node, &mut peer, Duration::from_secs(10), |node, peer| {
+                subscribed(&node.core.swarm.borrow()) && subscribed(peer)
+            })
+            .await
+        );
+
+        let ban = |banned| {
+            let params = BanParams::new(banned_id.to_string());
+            match banned {
+                true => ManagerMessage::Ban(params),
+                false => ManagerMessage::Unban(params),
+            }
+        };
+        let config = node.core.config;
+        let set = |key| {
+            let message = ComponentMessage::ManagerMessage(
+                ManagerMessage::StoreCommand(StoreCommand::set(key, "value")),
+                MetaData::new("manager", 0),
+            );
+            config.encode_to_vec(&message).unwrap()
+        };
+        let holds = |key: &'static str| {
+            move |node: &Node, _: &Swarm<MyBehaviour>| {
+                node.store().get_main_store().unwrap().contains_key(key)
+            }
+        };
+
+        node.handle_manager_message_and_publish(ban(true), MetaData::new("manager", 0))
+            .unwrap();
+        peer.behaviour_mut()
+            .gossipsub
+            .publish(topic.clone(), set("banned"))
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        assert!(!run_with_peer(&mut node, &mut peer, timeout, holds("banned")).await);
+
+        node.handle_manager_message_and_publish(ban(false), MetaData::new("manager", 0))
+            .unwrap();
+        peer.behaviour_mut()
+            .gossipsub
+            .publish(topic, set("allowed"))
+            .unwrap();
+        let timeout = Duration::from_secs(10);
+        assert!(run_with_peer(&mut node, &mut peer, timeout, holds("allowed")).await);
+        assert!(!node.store().get_main_store().unwrap().contains_key("banned"));
+    }
+
+    #[tokio::test]
+    async fn failed_command_is_answered_with_its_error_kind() {
+        let store = StoreBuilder::new().key_hashing(KeyHashing::Raw).build();
+        let storage = Arc::new(Mutex::new(store));
+        let config = BincodeConfig::Standard;
+        let long_key = "k".repeat(40);
+
+        let executed = execute_with_timeout(
+            storage,
+            StoreCommand::set(long_key, "value"),
+            Vec::new(),
+            MetaData::new("node", 0),
+            config,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        let CommandRun::Done(executed) = executed else {
+            panic!("Expected the command to complete");
+        };
+        assert!(!executed.applied);
+        assert!(matches!(
+            decode_message(&executed.message, config).unwrap(),
+            Some(ComponentMessage::NodeMessage(
+                NodeMessage::StoreCommandResult(StoreCommandResult::ERROR(error)),
+                _
+            )) if error.kind == ErrorKind::BadRequest
+        ));
+    }
+
+    #[tokio::test]
+    async fn slow_command_times_out() {
+        let storage = Arc::new(Mutex::new(Store::new()));
+        let metadata = MetaData::new("node", 0);
+        let config = BincodeConfig::Standard;
+
+        // an artificially slow command keeps the store busy for a while
+        let busy = {
+            let storage = storage.clone();
+            std::thread::spawn(move || {
+                let _storage = lock_store(&storage);
+                std::thread::sleep(Duration::from_millis(300));
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let executed = execute_with_timeout(
+            storage.clone(),
+            StoreCommand::set("color", "red"),
+            Vec::new(),
+            metadata.clone(),
+            config,
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(executed, CommandRun::TimedOut(_)));
+        busy.join().unwrap();
+
+        let executed = execute_with_timeout(
+            storage.clone(),
+            StoreCommand::set("size", "XL"),
+            vec!["size".to_string()],
+            metadata,
+            config,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        let CommandRun::Done(executed) = executed else {
+            panic!("Expected the command to complete");
+        };
+        assert!(executed.applied);
+        assert_eq!(executed.changes[0].key, "size");
+        let hash = Sha256::digest(StoreValue::from("XL").canonical_encode());
+        assert_eq!(executed.changes[0].new_value_hash, Some(hash.into()));
+        assert!(matches!(
+            decode_message(&executed.message, config).unwrap(),
+            Some(ComponentMessage::NodeMessage(
+                NodeMessage::StoreCommandResult(StoreCommandResult::SET(_)),
+                _
+            ))
+        ));
+
+        // the timed out command still completes in the background
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while lock_store(&storage).get_main_store().unwrap().len() < 2 {
+            assert!(Instant::now() < deadline);
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
     }
 }
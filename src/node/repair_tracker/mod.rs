@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum RepairAction {
+    /// nothing to do, no repair is outstanding or it hasn't timed out yet
+    Wait,
+    /// the outstanding request timed out, ask this peer instead
+    Retry(String),
+    /// every attempt timed out, the repair is abandoned
+    GiveUp,
+}
+
+struct PendingRepair {
+    dst_id: String,
+    deadline: Instant,
+    tried: HashSet<String>,
+    failures: u32,
+}
+
+/// Keeps at most one outstanding repair request and moves it to another
+/// majority peer when the chosen one doesn't answer in time
+pub struct RepairTracker {
+    timeout: Duration,
+    retries: u32,
+    pending: Option<PendingRepair>,
+}
+
+impl RepairTracker {
+    pub fn new(timeout: Duration, retries: u32) -> Self {
+        Self {
+            timeout,
+            retries,
+            pending: None,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Picks the peer to send a repair request to, `None` if a repair is
+    /// already outstanding or there is no candidate
+    pub fn start(&mut self, candidates: &[String], now: Instant) -> Option<String> {
+        if self.pending.is_some() {
+            return None;
+        }
+        let dst_id = Self::pick(candidates, &HashSet::new())?;
+        self.pending = Some(PendingRepair {
+            dst_id: dst_id.clone(),
+            deadline: now + self.timeout,
+            tried: HashSet::from([dst_id.clone()]),
+            failures: 0,
+        });
+        Some(dst_id)
+    }
+
+    /// Marks the repair as done if the response comes from the awaited peer
+    pub fn complete(&mut self, src_id: &str) -> bool {
+        match &self.pending {
+            Some(pending) if pending.dst_id == src_id => {
+                self.pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn poll_timeout(&mut self, candidates: &[String], now: Instant) -> RepairAction {
+        let Some(pending) = &mut self.pending else {
+            return RepairAction::Wait;
+        };
+        if pending.deadline > now {
+            return RepairAction::Wait;
+        }
+
+        pending.failures += 1;
+        if pending.failures > self.retries {
+            self.pending = None;
+            return RepairAction::GiveUp;
+        }
+        match Self::pick(candidates, &pending.tried) {
+            Some(dst_id) => {
+                pending.tried.insert(dst_id.clone());
+                pending.dst_id = dst_id.clone();
+                pending.deadline = now + self.timeout;
+                RepairAction::Retry(dst_id)
+            }
+            None => {
+                self.pending = None;
+                RepairAction::GiveUp
+            }
+        }
+    }
+
+    fn pick(candidates: &[String], tried: &HashSet<String>) -> Option<String> {
+        candidates
+            .iter()
+            .filter(|peer_id| !tried.contains(*peer_id))
+            .min()
+            .cloned()
+    }
+}
+
+/// Limits the unsolicited repairs pushed to a peer to one per cooldown, the
+/// peer keeps looking stale until its next signature
+pub struct PushGuard {
+    cooldown: Duration,
+    pushed: HashMap<String, Instant>,
+}
+
+impl PushGuard {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            pushed: HashMap::new(),
+        }
+    }
+
+    /// Whether a repair may be pushed to `peer_id` now, recording it if so
+    pub fn allow(&mut self, peer_id: &str, now: Instant) -> bool {
+        let cooldown = self.cooldown;
+        self.pushed
+            .retain(|_, pushed_at| now < *pushed_at + cooldown);
+        if self.pushed.contains_key(peer_id) {
+            return false;
+        }
+        self.pushed.insert(peer_id.to_string(), now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_start_picks_single_peer() {
+        let mut tracker = RepairTracker::new(Duration::from_millis(100), 2);
+        let now = Instant::now();
+
+        assert_eq!(
+            tracker.start(&peers(&["p2", "p1"]), now),
+            Some("p1".to_string())
+        );
+        assert!(tracker.is_pending());
+        assert_eq!(tracker.start(&peers(&["p2", "p1"]), now), None);
+    }
+
+    #[test]
+    fn test_push_guard_allows_one_push_per_cooldown() {
+        let mut guard = PushGuard::new(Duration::from_millis(100));
+        let now = Instant::now();
+
+        assert!(guard.allow("p1", now));
+        assert!(!guard.allow("p1", now + Duration::from_millis(50)));
+        assert!(guard.allow("p2", now + Duration::from_millis(50)));
+        assert!(guard.allow("p1", now + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_start_without_candidates() {
+        let mut tracker = RepairTracker::new(Duration::from_millis(100), 2);
+        assert_eq!(tracker.start(&[], Instant::now()), None);
+        assert!(!tracker.is_pending());
+    }
+
+    #[test]
+    fn test_complete_only_from_awaited_peer() {
+        let mut tracker = RepairTracker::new(Duration::from_millis(100), 2);
+        tracker.start(&peers(&["p1", "p2"]), Instant::now());
+
+        assert!(!tracker.complete("p2"));
+        assert!(tracker.complete("p1"));
+        assert!(!tracker.is_pending());
+    }
+
+    #[test]
+    fn test_unresponsive_peer_is_replaced() {
+        let mut tracker = RepairTracker::new(Duration::from_millis(100), 2);
+        let now = Instant::now();
+        let candidates = peers(&["p1", "p2"]);
+        tracker.start(&candidates, now);
+
+        assert_eq!(
+            tracker.poll_timeout(&candidates, now + Duration::from_millis(50)),
+            RepairAction::Wait
+        );
+        assert_eq!(
+            tracker.poll_timeout(&candidates, now + Duration::from_millis(100)),
+            RepairAction::Retry("p2".to_string())
+        );
+        assert!(tracker.complete("p2"));
+    }
+
+    #[test]
+    fn test_gives_up_after_retries() {
+        let mut tracker = RepairTracker::new(Duration::from_millis(100), 1);
+        let mut now = Instant::now();
+        let candidates = peers(&["p1", "p2", "p3"]);
+        tracker.start(&candidates, now);
+
+        now += Duration::from_millis(100);
+        assert_eq!(
+            tracker.poll_timeout(&candidates, now),
+            RepairAction::Retry("p2".to_string())
+        );
+        now += Duration::from_millis(100);
+        assert_eq!(tracker.poll_timeout(&candidates, now), RepairAction::GiveUp);
+        assert!(!tracker.is_pending());
+    }
+
+    #[test]
+    fn test_gives_up_when_candidates_exhausted() {
+        let mut tracker = RepairTracker::new(Duration::from_millis(100), 5);
+        let now = Instant::now();
+        let candidates = peers(&["p1"]);
+        tracker.start(&candidates, now);
+
+        assert_eq!(
+            tracker.poll_timeout(&candidates, now + Duration::from_millis(100)),
+            RepairAction::GiveUp
+        );
+    }
+}
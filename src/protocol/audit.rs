@@ -0,0 +1,208 @@
+use bincode::{Decode, Encode};
+
+use crate::store::command::{
+    CASVParams, DECRBYParams, DELParams, HDELParams, HINCRBYParams, HSETParams, INCRBYParams,
+    LPUSHParams, MSETEXParams, RENAMEParams, RPUSHParams, SADDParams, SETParams, SREMParams,
+    StoreCommand,
+};
+use crate::store::result::StoreCommandResult;
+
+/// Immutable record of a mutation, published to the audit topic
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub peer_id_str: String,
+    pub local_time: u128,
+    pub mutation: AuditMutation,
+    /// store root after the mutation
+    pub root: Option<[u8; 32]>,
+}
+
+impl AuditRecord {
+    pub fn new(
+        peer_id: impl ToString,
+        local_time: u128,
+        mutation: AuditMutation,
+        root: Option<[u8; 32]>,
+    ) -> Self {
+        Self {
+            peer_id_str: peer_id.to_string(),
+            local_time,
+            mutation,
+            root,
+        }
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub enum AuditMutation {
+    SET(String),
+    DEL(Vec<String>),
+    /// key and the newkey it was moved to
+    RENAME([String; 2]),
+    /// keys set together by a single command
+    MSETEX(Vec<String>),
+}
+
+impl AuditMutation {
+    /// The mutation a command would perform, `None` for read-only commands
+    pub fn from_command(cmd: &StoreCommand) -> Option<Self> {
+        match cmd {
+            StoreCommand::SET(SETParams { key, .. })
+            | StoreCommand::CASV(CASVParams { key, .. })
+            | StoreCommand::INCRBY(INCRBYParams { key, .. })
+            | StoreCommand::DECRBY(DECRBYParams { key, .. })
+            | StoreCommand::LPUSH(LPUSHParams { key, .. })
+            | StoreCommand::RPUSH(RPUSHParams { key, .. })
+            | StoreCommand::HSET(HSETParams { key, .. })
+            | StoreCommand::HINCRBY(HINCRBYParams { key, .. })
+            | StoreCommand::HDEL(HDELParams { key, .. })
+            | StoreCommand::SADD(SADDParams { key, .. })
+            | StoreCommand::SREM(SREMParams { key, .. }) => {
+                Some(AuditMutation::SET(key.to_string()))
+            }
+            StoreCommand::DEL(DELParams { keys }) => Some(AuditMutation::DEL(
+                keys.iter().map(|k| k.to_string()).collect(),
+            )),
+            StoreCommand::RENAME(RENAMEParams { key, newkey })
+            | StoreCommand::RENAMENX(RENAMEParams { key, newkey }) => {
+                Some(AuditMutation::RENAME([key.to_string(), newkey.to_string()]))
+            }
+            StoreCommand::MSETEX(MSETEXParams { pairs, .. }) => Some(AuditMutation::MSETEX(
+                pairs.iter().map(|(k, _)| k.to_string()).collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// The keys the mutation writes or removes
+    pub fn keys(&self) -> &[String] {
+        match self {
+            AuditMutation::SET(key) => std::slice::from_ref(key),
+            AuditMutation::DEL(keys) => keys,
+            AuditMutation::RENAME(keys) => keys,
+            AuditMutation::MSETEX(keys) => keys,
+        }
+    }
+
+    /// The check telling from its result whether `cmd` actually changed the
+    /// store, picked before running it as the store consumes the command
+    pub fn applied_by(cmd: &StoreCommand) -> fn(&StoreCommandResult) -> bool {
+        match cmd {
+            // SET with GET replies with the old value once the new one is set
+            StoreCommand::SET(SETParams {
+                return_old: true, ..
+            }) => |result| matches!(result, StoreCommandResult::GET(_)),
+            // they reply with the number of members added or removed, the
+            // store is left alone when there is none
+            StoreCommand::HDEL(_) | StoreCommand::SADD(_) | StoreCommand::SREM(_) => {
+                |result| matches!(result, StoreCommandResult::INT(n) if n.payload > 0)
+            }
+            _ => Self::applied,
+        }
+    }
+
+    /// Whether the result shows that the store was actually changed
+    pub fn applied(result: &StoreCommandResult) -> bool {
+        match result {
+            StoreCommandResult::SET(r) => r.payload,
+            StoreCommandResult::DEL(r) => r.payload > 0,
+            StoreCommandResult::CASV(r) => r.payload,
+            // among mutations only the counters and the list, hash and set
+            // updates reply with an integer, see `applied_by` for the members
+            StoreCommandResult::INT(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::result::ErrorKind;
+
+    #[test]
+    fn test_bincode_serialization_roundtrip() {
+        let original = AuditRecord::new(
+            "12D3KooWHuHkD4ySUmGACxMcd7xUsZo92C5wpadjrFNHb8AYQvX1",
+            1234,
+            AuditMutation::DEL(vec!["first".to_string(), "second".to_string()]),
+            Some([7; 32]),
+        );
+
+        let encoded: Vec<u8> =
+            bincode::encode_to_vec(&original, bincode::config::standard()).unwrap();
+        let (decoded, _): (AuditRecord, _) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+
+        assert_eq!(decoded, original);
+    }
+
+    #[test]
+    fn test_from_command_maps_mutations() {
+        assert_eq!(
+            AuditMutation::from_command(&StoreCommand::set("color", "red")),
+            Some(AuditMutation::SET("color".to_string()))
+        );
+        assert_eq!(
+            AuditMutation::from_command(&StoreCommand::casv("color", 1, "red")),
+            Some(AuditMutation::SET("color".to_string()))
+        );
+        assert_eq!(
+            AuditMutation::from_command(&StoreCommand::del(["a", "b"])),
+            Some(AuditMutation::DEL(vec!["a".to_string(), "b".to_string()]))
+        );
+        assert_eq!(
+            AuditMutation::from_command(&StoreCommand::renamenx("a", "b")),
+            Some(AuditMutation::RENAME(["a".to_string(), "b".to_string()]))
+        );
+        assert_eq!(
+            AuditMutation::from_command(&StoreCommand::msetex(10, [("a", "1"), ("b", "2")])),
+            Some(AuditMutation::MSETEX(vec![
+                "a".to_string(),
+                "b".to_string()
+            ]))
+        );
+        assert_eq!(
+            AuditMutation::from_command(&StoreCommand::get("color")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_applied_ignores_noop_results() {
+        assert!(AuditMutation::applied(&StoreCommandResult::set(
+            true,
+            Some([1; 32])
+        )));
+        assert!(AuditMutation::applied(&StoreCommandResult::del(2, None)));
+        assert!(!AuditMutation::applied(&StoreCommandResult::del(
+            0,
+            Some([1; 32])
+        )));
+        assert!(!AuditMutation::applied(&StoreCommandResult::casv(false, 3)));
+    }
+
+    #[test]
+    fn test_set_get_is_applied_when_answered_with_the_old_value() {
+        let applied = AuditMutation::applied_by(&StoreCommand::set_get("color", "red"));
+        assert!(applied(&StoreCommandResult::get(Some("blue"), 2)));
+        assert!(applied(&StoreCommandResult::get::<&str>(None, 1)));
+        assert!(!applied(&StoreCommandResult::error(
+            ErrorKind::Conflict,
+            "WRONGTYPE"
+        )));
+    }
+
+    #[test]
+    fn test_membership_change_is_applied_only_when_a_member_moved() {
+        for cmd in [
+            StoreCommand::hdel("user", ["name"]),
+            StoreCommand::sadd("tags", ["red"]),
+            StoreCommand::srem("tags", ["red"]),
+        ] {
+            let applied = AuditMutation::applied_by(&cmd);
+            assert!(applied(&StoreCommandResult::int(1)));
+            assert!(!applied(&StoreCommandResult::int(0)));
+        }
+    }
+}
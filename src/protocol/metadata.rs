@@ -1,22 +1,40 @@
 use bincode::{Decode, Encode};
-use libp2p::PeerId;
 
 #[derive(Decode, Encode, Debug, Clone, PartialEq)]
 pub struct MetaData {
     pub peer_id_str: String,
     pub local_time: u128,
+    /// correlates a node result with the manager command it answers
+    pub request_id: Option<u64>,
+    /// peer id of the manager whose command a node result answers, other
+    /// managers ignore the result
+    pub reply_to: Option<String>,
 }
 
 impl MetaData {
-    pub fn new(peer_id: PeerId, local_time: u128) -> Self {
+    pub fn new(peer_id: impl ToString, local_time: u128) -> Self {
         Self {
             peer_id_str: peer_id.to_string(),
             local_time,
+            request_id: None,
+            reply_to: None,
         }
     }
+
+    pub fn with_request_id(mut self, request_id: Option<u64>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Addresses the message to the sender of `request` under its request id
+    pub fn in_reply_to(mut self, request: &MetaData) -> Self {
+        self.request_id = request.request_id;
+        self.reply_to = Some(request.peer_id_str.clone());
+        self
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "networking"))]
 mod tests {
     use super::*;
     use libp2p::PeerId;
@@ -68,6 +86,28 @@ mod tests {
         assert!(debug_str.contains(&meta.local_time.to_string()));
     }
 
+    #[test]
+    fn test_with_request_id_roundtrip() {
+        let peer_id =
+            PeerId::from_str("12D3KooWHuHkD4ySUmGACxMcd7xUsZo92C5wpadjrFNHb8AYQvX1").unwrap();
+        let meta = MetaData::new(peer_id, 100).with_request_id(Some(7));
+
+        let encoded: Vec<u8> = bincode::encode_to_vec(&meta, bincode::config::standard()).unwrap();
+        let (decoded, _): (MetaData, _) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        assert_eq!(decoded.request_id, Some(7));
+    }
+
+    #[test]
+    fn test_in_reply_to_addresses_the_requesting_manager() {
+        let request = MetaData::new("manager", 100).with_request_id(Some(7));
+        let reply = MetaData::new("node", 200).in_reply_to(&request);
+
+        assert_eq!(reply.peer_id_str, "node");
+        assert_eq!(reply.request_id, Some(7));
+        assert_eq!(reply.reply_to.as_deref(), Some("manager"));
+    }
+
     #[test]
     fn test_different_peer_ids_produce_different_strings() {
         let peer1 =
@@ -1,12 +1,24 @@
-use crate::node::majority_tracker::*;
 use crate::store::command::*;
 use crate::store::result::*;
+use crate::store::value::StoreValue;
+use crate::utils::bin_args::BincodeConfig;
+use bincode::error::{DecodeError, EncodeError};
 use bincode::{Decode, Encode};
 use std::collections::HashMap;
 
+pub mod audit;
 pub mod metadata;
 use metadata::*;
 
+/// Version of the wire protocol, bumped whenever a change breaks the
+/// compatibility contract below
+pub const PROTOCOL_VERSION: u32 = 5;
+
+/// Wire compatibility contract: variants of [`ComponentMessage`], [`ManagerMessage`] and
+/// [`NodeMessage`] are only ever appended at the end, never reordered or removed, so the
+/// tags of existing variants stay stable. A peer running an older build that receives a
+/// variant it does not know skips that message instead of treating it as corrupted,
+/// see [`decode_message`].
 #[derive(Decode, Encode, Debug)]
 pub enum ComponentMessage<'m, 'n> {
     ManagerMessage(ManagerMessage<'m>, MetaData),
@@ -16,6 +28,21 @@ pub enum ComponentMessage<'m, 'n> {
 #[derive(Decode, Encode, Debug)]
 pub enum ManagerMessage<'a> {
     StoreCommand(StoreCommand<'a>),
+
+    /// Store command executed only by the listed nodes, e.g. a read-repair
+    TargetedStoreCommand(TargetedStoreCommandParams<'a>),
+
+    /// Asks the nodes to share their signature now instead of on the next tick
+    Signal(SignalParams),
+
+    /// Makes the nodes drop every message published or relayed by a peer
+    Ban(BanParams),
+
+    /// Lifts a `Ban`
+    Unban(BanParams),
+
+    /// Moves the nodes to another topic, see `Node::switch_topic`
+    SwitchTopic(SwitchTopicParams),
 }
 
 #[derive(Decode, Encode, Debug)]
@@ -28,6 +55,92 @@ pub enum NodeMessage<'a> {
 
     // WARN: for the first time all data will be sent, without batching and etc
     RepairResponse(RepairResponseParams),
+
+    /// Published to the notifications topic for every key a mutation changed
+    KeyChanged(KeyChangedParams),
+
+    /// Repair of the keys matching a glob pattern only
+    PartialRepairRequest(PartialRepairRequestParams),
+
+    PartialRepairResponse(PartialRepairResponseParams),
+
+    /// Shared instead of `ShareSignature` while the store state is unchanged
+    Keepalive(KeepaliveParams),
+
+    /// Periodic comparison of the signatures of two nodes, see
+    /// `--anti-entropy-interval-secs`
+    AntiEntropy(AntiEntropyParams),
+
+    /// Published to the notifications topic for every key the reaper removed,
+    /// by a single node of the majority, see `Node::reports_expiries`
+    KeyExpired(KeyExpiredParams),
+}
+
+#[derive(Decode, Encode, Debug)]
+pub struct TargetedStoreCommandParams<'a> {
+    pub dst_ids: Vec<String>,
+    pub cmd: StoreCommand<'a>,
+}
+
+impl<'a> TargetedStoreCommandParams<'a> {
+    pub fn new(dst_ids: Vec<String>, cmd: StoreCommand<'a>) -> Self {
+        Self { dst_ids, cmd }
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct BanParams {
+    pub peer_id: String,
+}
+
+impl BanParams {
+    pub fn new(peer_id: String) -> Self {
+        Self { peer_id }
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct SwitchTopicParams {
+    pub topic: String,
+}
+
+impl SwitchTopicParams {
+    pub fn new(topic: String) -> Self {
+        Self { topic }
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct SignalParams {
+    /// the nodes that should share their signature, every node if empty
+    pub dst_ids: Vec<String>,
+}
+
+impl SignalParams {
+    pub fn new(dst_ids: Vec<String>) -> Self {
+        Self { dst_ids }
+    }
+
+    pub fn targets(&self, peer_id: &str) -> bool {
+        self.dst_ids.is_empty() || self.dst_ids.iter().any(|id| id == peer_id)
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub root: Option<[u8; 32]>,
+    pub local_timestamp: u128,
+    pub fingerprint: u64,
+    /// how much the node counts when the majority root is chosen, see `--node-weight`
+    pub weight: u32,
+}
+
+impl Signature {
+    /// Whether both signatures describe the same store state, the fingerprints
+    /// are compared first so most divergent stores skip the root comparison
+    pub fn same_state(&self, other: &Signature) -> bool {
+        self.fingerprint == other.fingerprint && self.root == other.root
+    }
 }
 
 #[derive(Decode, Encode, Debug, Clone)]
@@ -42,6 +155,44 @@ impl ShareSignatureParams {
     }
 }
 
+/// "Still alive, state unchanged since the signature shared at `since`"
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct KeepaliveParams {
+    pub src_id: String,
+    /// `local_timestamp` of the last full signature, which still holds
+    pub since: u128,
+    pub local_timestamp: u128,
+}
+
+impl KeepaliveParams {
+    pub fn new(src_id: String, since: u128, local_timestamp: u128) -> Self {
+        Self {
+            src_id,
+            since,
+            local_timestamp,
+        }
+    }
+}
+
+/// Signature of `src_id` sent to `dst_id` only, which repairs whichever side
+/// is stale
+#[derive(Decode, Encode, Debug, Clone)]
+pub struct AntiEntropyParams {
+    pub src_id: String,
+    pub dst_id: String,
+    pub sgn: Signature,
+}
+
+impl AntiEntropyParams {
+    pub fn new(src_id: String, dst_id: String, sgn: Signature) -> Self {
+        Self {
+            src_id,
+            dst_id,
+            sgn,
+        }
+    }
+}
+
 #[derive(Decode, Encode, Debug, Clone)]
 pub struct RepairRequestParams {
     pub src_id: String,
@@ -58,15 +209,262 @@ impl RepairRequestParams {
 pub struct RepairResponseParams {
     pub src_id: String,
     pub dst_id: String,
-    pub repaired_data: HashMap<String, String>,
+    pub repaired_data: HashMap<String, StoreValue>,
+    /// version of every key of `repaired_data` and of the deleted ones, see `CASV`
+    pub versions: HashMap<String, u64>,
 }
 
 impl RepairResponseParams {
-    pub fn new(src_id: String, dst_id: String, repaired_data: HashMap<String, String>) -> Self {
+    pub fn new(
+        src_id: String,
+        dst_id: String,
+        repaired_data: HashMap<String, StoreValue>,
+        versions: HashMap<String, u64>,
+    ) -> Self {
         Self {
             src_id,
             dst_id,
             repaired_data,
+            versions,
         }
     }
 }
+
+#[derive(Decode, Encode, Debug, Clone)]
+pub struct PartialRepairRequestParams {
+    pub src_id: String,
+    pub dst_id: String,
+    /// glob pattern of the keys to repair, like the `KEYS` one
+    pub pattern: String,
+}
+
+impl PartialRepairRequestParams {
+    pub fn new(src_id: String, dst_id: String, pattern: String) -> Self {
+        Self {
+            src_id,
+            dst_id,
+            pattern,
+        }
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone)]
+pub struct PartialRepairResponseParams {
+    pub src_id: String,
+    pub dst_id: String,
+    pub pattern: String,
+    /// every key of the responding node matching the pattern
+    pub repaired_data: HashMap<String, StoreValue>,
+    /// version of every key of `repaired_data` and of the deleted ones, see `CASV`
+    pub versions: HashMap<String, u64>,
+}
+
+impl PartialRepairResponseParams {
+    pub fn new(
+        src_id: String,
+        dst_id: String,
+        pattern: String,
+        repaired_data: HashMap<String, StoreValue>,
+        versions: HashMap<String, u64>,
+    ) -> Self {
+        Self {
+            src_id,
+            dst_id,
+            pattern,
+            repaired_data,
+            versions,
+        }
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct KeyChangedParams {
+    pub key: String,
+    /// SHA-256 of the new value, `None` once the key was removed
+    pub new_value_hash: Option<[u8; 32]>,
+    /// store root after the mutation
+    pub root: Option<[u8; 32]>,
+}
+
+impl KeyChangedParams {
+    pub fn new(key: String, new_value_hash: Option<[u8; 32]>, root: Option<[u8; 32]>) -> Self {
+        Self {
+            key,
+            new_value_hash,
+            root,
+        }
+    }
+}
+
+#[derive(Decode, Encode, Debug, Clone, PartialEq)]
+pub struct KeyExpiredParams {
+    pub key: String,
+}
+
+impl KeyExpiredParams {
+    pub fn new(key: String) -> Self {
+        Self { key }
+    }
+}
+
+impl BincodeConfig {
+    pub fn encode_to_vec<E: Encode>(self, value: E) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            BincodeConfig::Standard => bincode::encode_to_vec(value, bincode::config::standard()),
+            BincodeConfig::Fixint => {
+                bincode::encode_to_vec(value, bincode::config::standard().with_fixed_int_encoding())
+            }
+        }
+    }
+
+    pub fn decode_from_slice<D: Decode<()>>(self, data: &[u8]) -> Result<(D, usize), DecodeError> {
+        match self {
+            BincodeConfig::Standard => {
+                bincode::decode_from_slice(data, bincode::config::standard())
+            }
+            BincodeConfig::Fixint => bincode::decode_from_slice(
+                data,
+                bincode::config::standard().with_fixed_int_encoding(),
+            ),
+        }
+    }
+
+    /// Gossipsub protocol id prefix announced with this encoding, `None` for
+    /// the default one. Components whose encodings differ don't negotiate
+    /// gossipsub with each other instead of misreading each other's messages
+    pub fn gossipsub_protocol_prefix(self) -> Option<String> {
+        match self {
+            BincodeConfig::Standard => None,
+            BincodeConfig::Fixint => Some(format!("/difiew/{PROTOCOL_VERSION}/fixint/meshsub")),
+        }
+    }
+}
+
+/// Decodes a message received from the topic.
+///
+/// Returns `Ok(None)` when the message carries a variant tag unknown to this build
+/// (i.e. it was sent by a newer peer), so callers can ignore it cleanly. Any other
+/// malformed payload is still reported as an error.
+pub fn decode_message(
+    data: &[u8],
+    config: BincodeConfig,
+) -> Result<Option<ComponentMessage<'static, 'static>>, DecodeError> {
+    match config.decode_from_slice(data) {
+        Ok((msg, _len)) => Ok(Some(msg)),
+        Err(DecodeError::UnexpectedVariant { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> MetaData {
+        MetaData::new("12D3KooWHuHkD4ySUmGACxMcd7xUsZo92C5wpadjrFNHb8AYQvX1", 42)
+    }
+
+    #[test]
+    fn key_changed_roundtrip() {
+        let msg = ComponentMessage::NodeMessage(
+            NodeMessage::KeyChanged(KeyChangedParams::new(
+                "color".to_string(),
+                None,
+                Some([3; 32]),
+            )),
+            metadata(),
+        );
+        let encoded = bincode::encode_to_vec(&msg, bincode::config::standard()).unwrap();
+        let decoded = decode_message(&encoded, BincodeConfig::Standard).unwrap();
+        assert!(matches!(
+            decoded,
+            Some(ComponentMessage::NodeMessage(NodeMessage::KeyChanged(params), _))
+                if params == KeyChangedParams::new("color".to_string(), None, Some([3; 32]))
+        ));
+    }
+
+    #[test]
+    fn message_roundtrip_under_each_config() {
+        let msg = || {
+            ComponentMessage::NodeMessage(
+                NodeMessage::StoreCommandResult(StoreCommandResult::del(300, Some([3; 32]))),
+                metadata().with_request_id(Some(70_000)),
+            )
+        };
+        let standard = BincodeConfig::Standard.encode_to_vec(msg()).unwrap();
+        let fixint = BincodeConfig::Fixint.encode_to_vec(msg()).unwrap();
+        assert_eq!(
+            standard,
+            bincode::encode_to_vec(msg(), bincode::config::standard()).unwrap()
+        );
+        assert!(fixint.len() > standard.len());
+
+        for (config, data) in [
+            (BincodeConfig::Standard, standard),
+            (BincodeConfig::Fixint, fixint),
+        ] {
+            assert!(matches!(
+                decode_message(&data, config).unwrap(),
+                Some(ComponentMessage::NodeMessage(
+                    NodeMessage::StoreCommandResult(result),
+                    metadata,
+                )) if result == StoreCommandResult::del(300, Some([3; 32]))
+                    && metadata.request_id == Some(70_000)
+            ));
+        }
+    }
+
+    #[test]
+    fn only_the_default_config_uses_the_default_gossipsub_protocol() {
+        assert_eq!(BincodeConfig::Standard.gossipsub_protocol_prefix(), None);
+        let prefix = BincodeConfig::Fixint.gossipsub_protocol_prefix().unwrap();
+        assert!(prefix.contains(&PROTOCOL_VERSION.to_string()));
+    }
+
+    #[test]
+    fn decode_message_known_variant() {
+        let msg = ComponentMessage::ManagerMessage(
+            ManagerMessage::StoreCommand(StoreCommand::get("key")),
+            metadata(),
+        );
+        let data = bincode::encode_to_vec(&msg, bincode::config::standard()).unwrap();
+
+        let decoded = decode_message(&data, BincodeConfig::Standard).unwrap();
+        assert!(matches!(
+            decoded,
+            Some(ComponentMessage::ManagerMessage(
+                ManagerMessage::StoreCommand(StoreCommand::GET(_)),
+                _
+            ))
+        ));
+    }
+
+    #[test]
+    fn decode_message_ignores_unknown_node_message_variant() {
+        // `ComponentMessage::NodeMessage` tag followed by a `NodeMessage` tag from a newer build
+        let mut data = vec![1, 200];
+        data.extend(bincode::encode_to_vec(metadata(), bincode::config::standard()).unwrap());
+
+        let decoded = decode_message(&data, BincodeConfig::Standard).unwrap();
+        assert!(decoded.is_none());
+    }
+
+    #[test]
+    fn decode_message_ignores_unknown_component_message_variant() {
+        let data = vec![7, 0, 0];
+        let decoded = decode_message(&data, BincodeConfig::Standard).unwrap();
+        assert!(decoded.is_none());
+    }
+
+    #[test]
+    fn decode_message_reports_truncated_payload() {
+        let msg = ComponentMessage::ManagerMessage(
+            ManagerMessage::StoreCommand(StoreCommand::set("key", "value")),
+            metadata(),
+        );
+        let data = bincode::encode_to_vec(&msg, bincode::config::standard()).unwrap();
+
+        let result = decode_message(&data[..data.len() / 2], BincodeConfig::Standard);
+        assert!(result.is_err());
+    }
+}
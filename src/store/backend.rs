@@ -0,0 +1,332 @@
+use super::{error::StoreError, value::StoreValue};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+
+/// Where a [`Store`](super::Store) keeps its key-value pairs. The monotree and
+/// the key metadata (timeouts, versions, access times) stay in memory whatever
+/// the backend
+pub trait KvBackend {
+    /// The value at key, borrowed when the backend holds it in memory
+    fn get(&self, key: &str) -> StdResult<Option<Cow<'_, StoreValue>>, StoreError>;
+
+    /// Stores value at key and returns the value it replaced
+    fn set(&mut self, key: &str, value: StoreValue) -> StdResult<Option<StoreValue>, StoreError>;
+
+    /// Removes key and returns the value it held
+    fn del(&mut self, key: &str) -> StdResult<Option<StoreValue>, StoreError>;
+
+    /// Every pair, in no particular order
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = StdResult<(Cow<'_, str>, Cow<'_, StoreValue>), StoreError>>;
+
+    /// Removes every pair
+    fn clear(&mut self) -> StdResult<(), StoreError>;
+
+    /// Number of keys
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains_key(&self, key: &str) -> StdResult<bool, StoreError> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Every key, in no particular order
+    fn keys(&self) -> impl Iterator<Item = StdResult<Cow<'_, str>, StoreError>> {
+        self.iter().map(|entry| entry.map(|(key, _)| key))
+    }
+
+    /// Writes the pending changes to disk and returns once they are synced,
+    /// with the number of bytes written. Nothing to do for a backend in memory
+    fn flush(&self) -> StdResult<usize, StoreError> {
+        Ok(0)
+    }
+}
+
+/// Pairs held in a `HashMap`, the default backend
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    entries: HashMap<String, Entry>,
+    /// values whose encoding takes at least this many bytes are kept compressed
+    compression_threshold: Option<usize>,
+}
+
+/// A value as the memory backend holds it
+#[derive(Debug, Clone)]
+enum Entry {
+    Plain(StoreValue),
+    /// lz4 compressed bincode encoding of the value
+    Compressed(Vec<u8>),
+}
+
+impl MemoryBackend {
+    /// Backend compressing the values whose encoding takes at least `threshold`
+    /// bytes, they are decompressed on every read
+    pub fn with_compression(threshold: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            compression_threshold: Some(threshold),
+        }
+    }
+
+    fn pack(&self, value: StoreValue) -> StdResult<Entry, StoreError> {
+        let Some(threshold) = self.compression_threshold else {
+            return Ok(Entry::Plain(value));
+        };
+        let encoded = bincode::encode_to_vec(&value, bincode::config::standard())
+            .map_err(|e| StoreError::BackendError(e.to_string()))?;
+        match encoded.len() >= threshold {
+            true => Ok(Entry::Compressed(lz4_flex::compress_prepend_size(&encoded))),
+            false => Ok(Entry::Plain(value)),
+        }
+    }
+
+    fn unpack(entry: &Entry) -> StdResult<Cow<'_, StoreValue>, StoreError> {
+        match entry {
+            Entry::Plain(value) => Ok(Cow::Borrowed(value)),
+            Entry::Compressed(bytes) => Ok(Cow::Owned(Self::decompress(bytes)?)),
+        }
+    }
+
+    fn into_value(entry: Entry) -> StdResult<StoreValue, StoreError> {
+        match entry {
+            Entry::Plain(value) => Ok(value),
+            Entry::Compressed(bytes) => Self::decompress(&bytes),
+        }
+    }
+
+    fn decompress(bytes: &[u8]) -> StdResult<StoreValue, StoreError> {
+        let encoded = lz4_flex::decompress_size_prepended(bytes)
+            .map_err(|e| StoreError::BackendError(e.to_string()))?;
+        bincode::decode_from_slice(&encoded, bincode::config::standard())
+            .map(|(value, _)| value)
+            .map_err(|e| StoreError::BackendError(e.to_string()))
+    }
+}
+
+impl KvBackend for MemoryBackend {
+    fn get(&self, key: &str) -> StdResult<Option<Cow<'_, StoreValue>>, StoreError> {
+        self.entries.get(key).map(Self::unpack).transpose()
+    }
+
+    fn set(&mut self, key: &str, value: StoreValue) -> StdResult<Option<StoreValue>, StoreError> {
+        let entry = self.pack(value)?;
+        self.entries
+            .insert(key.to_string(), entry)
+            .map(Self::into_value)
+            .transpose()
+    }
+
+    fn del(&mut self, key: &str) -> StdResult<Option<StoreValue>, StoreError> {
+        self.entries.remove(key).map(Self::into_value).transpose()
+    }
+
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = StdResult<(Cow<'_, str>, Cow<'_, StoreValue>), StoreError>> {
+        self.entries
+            .iter()
+            .map(|(key, entry)| Ok((Cow::Borrowed(key.as_str()), Self::unpack(entry)?)))
+    }
+
+    fn clear(&mut self) -> StdResult<(), StoreError> {
+        self.entries.clear();
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn contains_key(&self, key: &str) -> StdResult<bool, StoreError> {
+        Ok(self.entries.contains_key(key))
+    }
+
+    fn keys(&self) -> impl Iterator<Item = StdResult<Cow<'_, str>, StoreError>> {
+        self.entries
+            .keys()
+            .map(|key| Ok(Cow::Borrowed(key.as_str())))
+    }
+}
+
+/// Pairs held in a sled database, so the values can outgrow the memory and
+/// survive a restart. Values are stored bincode encoded
+#[cfg(feature = "sled")]
+#[derive(Debug, Clone)]
+pub struct SledBackend {
+    db: sled::Db,
+    /// number of keys, counting them in sled means a full scan
+    len: usize,
+}
+
+#[cfg(feature = "sled")]
+impl SledBackend {
+    /// Backend over an opened database, keeping the pairs it already holds
+    pub fn new(db: sled::Db) -> Self {
+        let len = db.len();
+        Self { db, len }
+    }
+
+    /// Opens (or creates) the database at `path`
+    pub fn open(path: impl AsRef<std::path::Path>) -> StdResult<Self, StoreError> {
+        Ok(Self::new(sled::open(path)?))
+    }
+
+    fn encode(value: &StoreValue) -> StdResult<Vec<u8>, StoreError> {
+        bincode::encode_to_vec(value, bincode::config::standard())
+            .map_err(|e| StoreError::BackendError(e.to_string()))
+    }
+
+    fn decode(bytes: &[u8]) -> StdResult<StoreValue, StoreError> {
+        bincode::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(value, _)| value)
+            .map_err(|e| StoreError::BackendError(e.to_string()))
+    }
+
+    fn decode_key(bytes: &[u8]) -> StdResult<String, StoreError> {
+        String::from_utf8(bytes.to_vec()).map_err(|e| StoreError::BackendError(e.to_string()))
+    }
+}
+
+#[cfg(feature = "sled")]
+impl KvBackend for SledBackend {
+    fn get(&self, key: &str) -> StdResult<Option<Cow<'_, StoreValue>>, StoreError> {
+        match self.db.get(key)? {
+            Some(bytes) => Ok(Some(Cow::Owned(Self::decode(&bytes)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: StoreValue) -> StdResult<Option<StoreValue>, StoreError> {
+        match self.db.insert(key, Self::encode(&value)?)? {
+            Some(old) => Ok(Some(Self::decode(&old)?)),
+            None => {
+                self.len += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    fn del(&mut self, key: &str) -> StdResult<Option<StoreValue>, StoreError> {
+        match self.db.remove(key)? {
+            Some(old) => {
+                self.len -= 1;
+                Ok(Some(Self::decode(&old)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = StdResult<(Cow<'_, str>, Cow<'_, StoreValue>), StoreError>> {
+        self.db.iter().map(|entry| {
+            let (key, value) = entry?;
+            Ok((
+                Cow::Owned(Self::decode_key(&key)?),
+                Cow::Owned(Self::decode(&value)?),
+            ))
+        })
+    }
+
+    fn clear(&mut self) -> StdResult<(), StoreError> {
+        self.db.clear()?;
+        self.len = 0;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn contains_key(&self, key: &str) -> StdResult<bool, StoreError> {
+        Ok(self.db.contains_key(key)?)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = StdResult<Cow<'_, str>, StoreError>> {
+        self.db
+            .iter()
+            .keys()
+            .map(|key| Ok(Cow::Owned(Self::decode_key(&key?)?)))
+    }
+
+    /// sled also flushes by itself every few hundred milliseconds
+    fn flush(&self) -> StdResult<usize, StoreError> {
+        Ok(self.db.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Behaviour every backend must share
+    fn check_backend(mut backend: impl KvBackend) -> StdResult<(), StoreError> {
+        assert!(backend.is_empty());
+        assert_eq!(backend.set("color", StoreValue::from("red"))?, None);
+        assert_eq!(
+            backend.set("color", StoreValue::from("blue"))?,
+            Some(StoreValue::from("red"))
+        );
+        backend.set("list", StoreValue::List(vec!["a".to_string()]))?;
+
+        assert_eq!(backend.len(), 2);
+        assert!(backend.contains_key("list")?);
+        assert_eq!(
+            backend.get("color")?.as_deref(),
+            Some(&StoreValue::from("blue"))
+        );
+        let mut keys: Vec<String> = backend
+            .keys()
+            .map(|key| key.map(Cow::into_owned))
+            .collect::<StdResult<_, _>>()?;
+        keys.sort();
+        assert_eq!(keys, ["color", "list"]);
+        assert_eq!(backend.iter().count(), 2);
+
+        assert_eq!(backend.del("color")?, Some(StoreValue::from("blue")));
+        assert_eq!(backend.del("color")?, None);
+        assert_eq!(backend.get("color")?, None);
+        assert_eq!(backend.len(), 1);
+
+        backend.clear()?;
+        assert!(backend.is_empty());
+        assert_eq!(backend.iter().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_backend() -> StdResult<(), StoreError> {
+        check_backend(MemoryBackend::default())
+    }
+
+    #[test]
+    fn compressing_memory_backend() -> StdResult<(), StoreError> {
+        check_backend(MemoryBackend::with_compression(0))?;
+
+        let mut backend = MemoryBackend::with_compression(64);
+        let large = StoreValue::from("x".repeat(1000).as_str());
+        backend.set("small", StoreValue::from("red"))?;
+        backend.set("large", large.clone())?;
+
+        assert!(matches!(backend.entries["small"], Entry::Plain(_)));
+        assert!(matches!(
+            &backend.entries["large"],
+            Entry::Compressed(bytes) if bytes.len() < 100
+        ));
+        assert_eq!(backend.get("large")?.as_deref(), Some(&large));
+        assert_eq!(backend.del("large")?, Some(large));
+        Ok(())
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_backend() -> StdResult<(), StoreError> {
+        let db = sled::Config::new().temporary(true).open()?;
+        check_backend(SledBackend::new(db))
+    }
+}
@@ -0,0 +1,222 @@
+use super::{
+    backend::{KvBackend, MemoryBackend},
+    error::StoreError,
+    histogram::SizeHistogram,
+    Store,
+};
+use crate::utils::{
+    bin_args::{BinArgs, ExpiryStrategy, KeyHashing},
+    clock::{Clock, SystemClock},
+};
+use monotree::Monotree;
+use std::collections::{BTreeMap, HashMap};
+use std::result::Result as StdResult;
+use std::sync::Arc;
+
+/// Configures a [`Store`], `Store::new` is the same as `StoreBuilder::new().build()`
+#[derive(Debug, Clone)]
+pub struct StoreBuilder {
+    key_hashing: KeyHashing,
+    expiry_strategy: ExpiryStrategy,
+    max_keys: Option<usize>,
+    max_value_size: Option<usize>,
+    max_key_bytes: Option<usize>,
+    value_compression: Option<usize>,
+    reaper_batch_size: usize,
+    clock: Arc<dyn Clock>,
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        Self {
+            key_hashing: KeyHashing::Sha256,
+            expiry_strategy: ExpiryStrategy::Lazy,
+            max_keys: None,
+            max_value_size: None,
+            max_key_bytes: None,
+            value_compression: None,
+            reaper_batch_size: 1000,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Builder with the store options given on the command line
+    pub fn from_args(args: &BinArgs) -> Self {
+        let mut builder = Self::new()
+            .key_hashing(args.key_hashing)
+            .expiry_strategy(args.expiry_strategy)
+            .reaper_batch_size(args.reaper_batch_size);
+        if let Some(max_key_bytes) = args.max_key_bytes {
+            builder = builder.max_key_bytes(max_key_bytes);
+        }
+        if args.value_compression {
+            builder = builder.value_compression(args.value_compression_threshold);
+        }
+        builder
+    }
+
+    pub fn key_hashing(mut self, key_hashing: KeyHashing) -> Self {
+        self.key_hashing = key_hashing;
+        self
+    }
+
+    pub fn expiry_strategy(mut self, expiry_strategy: ExpiryStrategy) -> Self {
+        self.expiry_strategy = expiry_strategy;
+        self
+    }
+
+    /// Rejects writes creating a key once the store holds `max_keys` keys
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+
+    /// Rejects writes of values longer than `max_value_size` bytes
+    pub fn max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = Some(max_value_size);
+        self
+    }
+
+    /// Rejects writes to keys longer than `max_key_bytes` bytes
+    pub fn max_key_bytes(mut self, max_key_bytes: usize) -> Self {
+        self.max_key_bytes = Some(max_key_bytes);
+        self
+    }
+
+    /// Keeps the values of at least `threshold` bytes compressed, the monotree
+    /// still hashes them uncompressed so the root doesn't depend on it. Only
+    /// applies to the memory backend of [`StoreBuilder::build`]
+    pub fn value_compression(mut self, threshold: usize) -> Self {
+        self.value_compression = Some(threshold);
+        self
+    }
+
+    /// Number of volatile keys the active expiry checks per step, 1000 by default
+    pub fn reaper_batch_size(mut self, reaper_batch_size: usize) -> Self {
+        self.reaper_batch_size = reaper_batch_size;
+        self
+    }
+
+    /// Time source for the key expiry, the system clock by default
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn build(self) -> Store {
+        let data = match self.value_compression {
+            Some(threshold) => MemoryBackend::with_compression(threshold),
+            None => MemoryBackend::default(),
+        };
+        self.store(data)
+    }
+
+    /// Store keeping its pairs in `data`. The pairs a persistent backend
+    /// already holds are hashed into a new monotree, their timeouts are lost
+    pub fn build_with_backend<B: KvBackend>(self, data: B) -> StdResult<Store<B>, StoreError> {
+        let mut store = self.store(data);
+        store.index_backend()?;
+        Ok(store)
+    }
+
+    fn store<B: KvBackend>(self, data: B) -> Store<B> {
+        Store {
+            monotree: Monotree::default(),
+            tree_writes: 0,
+            root: None,
+            data,
+            expires: BTreeMap::new(),
+            reaper_cursor: None,
+            reaper_batch_size: self.reaper_batch_size,
+            touched_expired: Vec::new(),
+            versions: HashMap::new(),
+            created: HashMap::new(),
+            accessed: HashMap::new(),
+            fingerprint: 0,
+            key_sizes: SizeHistogram::new(),
+            value_sizes: SizeHistogram::new(),
+            key_hashing: self.key_hashing,
+            expiry_strategy: self.expiry_strategy,
+            max_keys: self.max_keys,
+            max_value_size: self.max_value_size,
+            max_key_bytes: self.max_key_bytes,
+            clock: self.clock,
+        }
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{command::StoreCommand, error::StoreError};
+
+    #[test]
+    fn test_default_builder_matches_new() {
+        let store = StoreBuilder::new().build();
+        assert_eq!(store.key_hashing, KeyHashing::Sha256);
+        assert_eq!(store.expiry_strategy(), ExpiryStrategy::Lazy);
+        assert_eq!(store.max_keys, None);
+        assert_eq!(store.max_value_size, None);
+        assert_eq!(store.max_key_bytes, None);
+    }
+
+    #[test]
+    fn test_builder_with_several_options() -> Result<(), StoreError> {
+        let mut store = StoreBuilder::new()
+            .key_hashing(KeyHashing::Raw)
+            .expiry_strategy(ExpiryStrategy::Active)
+            .max_keys(2)
+            .max_value_size(8)
+            .build();
+        assert_eq!(store.expiry_strategy(), ExpiryStrategy::Active);
+
+        store.execute(StoreCommand::set("a", "1"))?;
+        store.execute(StoreCommand::set("b", "2"))?;
+        // overwriting an existing key is still allowed at the limit
+        store.execute(StoreCommand::set("a", "3"))?;
+
+        assert_eq!(
+            store.execute(StoreCommand::set("c", "3")),
+            Err(StoreError::KeyLimitReached(2))
+        );
+        assert_eq!(
+            store.execute(StoreCommand::set("a", "too long value")),
+            Err(StoreError::ValueTooLarge(14))
+        );
+        assert_eq!(
+            store.execute(StoreCommand::set("a".repeat(33).as_str(), "1")),
+            Err(StoreError::KeyTooLong(33))
+        );
+        assert_eq!(store.get("a")?.as_deref(), Some("3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_from_args() {
+        let args = BinArgs {
+            key_hashing: KeyHashing::Raw,
+            expiry_strategy: ExpiryStrategy::Active,
+            max_key_bytes: Some(16),
+            value_compression: true,
+            value_compression_threshold: 64,
+            reaper_batch_size: 250,
+            ..Default::default()
+        };
+        let mut store = StoreBuilder::from_args(&args).build();
+        store.set("large", "x".repeat(100).as_str()).unwrap();
+        assert_eq!(
+            store.get("large").unwrap().as_deref(),
+            Some("x".repeat(100).as_str())
+        );
+        assert_eq!(store.key_hashing, KeyHashing::Raw);
+        assert_eq!(store.expiry_strategy(), ExpiryStrategy::Active);
+        assert_eq!(store.max_key_bytes, Some(16));
+        assert_eq!(store.reaper_batch_size, 250);
+    }
+}
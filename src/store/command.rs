@@ -4,7 +4,8 @@ use std::borrow::Cow;
 
 #[derive(Encode, Decode, Debug)]
 pub enum StoreCommand<'a> {
-    /// Removes the specified keys. A key is ignored if it does not exist
+    /// Removes the specified keys. A key is ignored if it does not exist,
+    /// a key given several times is removed and counted once
     DEL(DELParams<'a>),
 
     /// Checks keys existance
@@ -18,6 +19,121 @@ pub enum StoreCommand<'a> {
 
     /// Set key to hold the string value. If key already holds a value, it is overwritten
     SET(SETParams<'a>),
+
+    /// Set a timeout on key, after which the key is deleted
+    EXPIRE(EXPIREParams<'a>),
+
+    /// List all volatile keys with their remaining time to live
+    TTLS,
+
+    /// Set key to value only if the key version matches the expected one
+    CASV(CASVParams<'a>),
+
+    /// Inspect the internals of the value stored at key
+    OBJECT(OBJECTParams<'a>),
+
+    /// Describe the monotree: root hash, number of keys and depth
+    TREEINFO,
+
+    /// Increment the integer value of a key by the given amount
+    INCRBY(INCRBYParams<'a>),
+
+    /// Decrement the integer value of a key by the given amount
+    DECRBY(DECRBYParams<'a>),
+
+    /// Dump the whole store and its root as human-readable JSON
+    DUMPJSON,
+
+    /// List every key with the SHA-256 hash of its value
+    KEYHASHES,
+
+    /// Insert the values at the head of the list stored at key
+    LPUSH(LPUSHParams<'a>),
+
+    /// Append the values at the tail of the list stored at key
+    RPUSH(RPUSHParams<'a>),
+
+    /// Elements of the list stored at key between two indexes, both inclusive
+    LRANGE(LRANGEParams<'a>),
+
+    /// Length of the list stored at key
+    LLEN(LLENParams<'a>),
+
+    /// Type of the value stored at key
+    TYPE(TYPEParams<'a>),
+
+    /// Set field in the hash stored at key to value
+    HSET(HSETParams<'a>),
+
+    /// Get the value of a field in the hash stored at key
+    HGET(HGETParams<'a>),
+
+    /// Get every field and value of the hash stored at key
+    HGETALL(HGETALLParams<'a>),
+
+    /// Removes the specified fields from the hash stored at key, the key is
+    /// removed with its last field
+    HDEL(HDELParams<'a>),
+
+    /// Add the members to the set stored at key
+    SADD(SADDParams<'a>),
+
+    /// Remove the members from the set stored at key, the key is removed with
+    /// its last member
+    SREM(SREMParams<'a>),
+
+    /// Every member of the set stored at key
+    SMEMBERS(SMEMBERSParams<'a>),
+
+    /// Check whether member belongs to the set stored at key
+    SISMEMBER(SISMEMBERParams<'a>),
+
+    /// Histograms of the key and value sizes held by the store
+    METRICS,
+
+    /// Build and protocol version of the node
+    VERSION,
+
+    /// Rename key to newkey, overwriting newkey. The timeout of key moves with it
+    RENAME(RENAMEParams<'a>),
+
+    /// Rename key to newkey only if newkey does not exist yet
+    RENAMENX(RENAMEParams<'a>),
+
+    /// Peers whose last signature shares the root of the node and the ones
+    /// that differ, answered by the node rather than its store
+    PEERS,
+
+    /// Rebuild the monotree from scratch out of the stored values
+    REBUILD,
+
+    /// Whether every one of the keys exists
+    EXISTSALL(EXISTSParams<'a>),
+
+    /// Whether at least one of the keys exists
+    EXISTSANY(EXISTSParams<'a>),
+
+    /// Approximate number of bytes the keys and values take in memory
+    MEMUSAGE,
+
+    /// Write the pending changes of the backend to disk, answered once they
+    /// are synced
+    FLUSH,
+
+    /// `KEYHASHES` of the keys matching pattern only, typed `KEYHASHES pattern`
+    KEYHASHESMATCHING(KEYSParams<'a>),
+
+    /// Last signature the node holds for every peer, answered by the node
+    /// rather than its store
+    TRACKER,
+
+    /// Increment the integer in a field of the hash at key, a missing field counts as 0
+    HINCRBY(HINCRBYParams<'a>),
+
+    /// Set every key to its value with the same timeout, typed
+    /// `MSETEX seconds key value [key value ...]`. The pairs are all set or,
+    /// if one is rejected, none of them
+    MSETEX(MSETEXParams<'a>),
 }
 
 impl<'a> StoreCommand<'a> {
@@ -41,6 +157,26 @@ impl<'a> StoreCommand<'a> {
         StoreCommand::EXISTS(EXISTSParams { keys })
     }
 
+    pub fn existsall<K, I>(keys: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = K>,
+        I::IntoIter: 'a,
+    {
+        let keys = keys.into_iter().map(|k| k.into()).collect();
+        StoreCommand::EXISTSALL(EXISTSParams { keys })
+    }
+
+    pub fn existsany<K, I>(keys: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = K>,
+        I::IntoIter: 'a,
+    {
+        let keys = keys.into_iter().map(|k| k.into()).collect();
+        StoreCommand::EXISTSANY(EXISTSParams { keys })
+    }
+
     pub fn get<K>(key: K) -> Self
     where
         K: Into<Cow<'a, str>>,
@@ -65,8 +201,364 @@ impl<'a> StoreCommand<'a> {
         StoreCommand::SET(SETParams {
             key: key.into(),
             value: value.into(),
+            return_old: false,
+        })
+    }
+
+    /// `SET key value GET`: sets like `set` and answers with the value it replaced
+    pub fn set_get<K, V>(key: K, value: V) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommand::SET(SETParams {
+            key: key.into(),
+            value: value.into(),
+            return_old: true,
+        })
+    }
+
+    pub fn expire<K>(key: K, seconds: u64) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::EXPIRE(EXPIREParams {
+            key: key.into(),
+            seconds,
+        })
+    }
+
+    pub fn ttls() -> Self {
+        StoreCommand::TTLS
+    }
+
+    pub fn casv<K, V>(key: K, expected_version: u64, value: V) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommand::CASV(CASVParams {
+            key: key.into(),
+            expected_version,
+            value: value.into(),
+        })
+    }
+
+    pub fn object<K>(subcommand: ObjectSubcommand, key: K) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::OBJECT(OBJECTParams {
+            subcommand,
+            key: key.into(),
+        })
+    }
+
+    pub fn treeinfo() -> Self {
+        StoreCommand::TREEINFO
+    }
+
+    pub fn incrby<K>(key: K, amount: i64) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::INCRBY(INCRBYParams {
+            key: key.into(),
+            amount,
+        })
+    }
+
+    pub fn dumpjson() -> Self {
+        StoreCommand::DUMPJSON
+    }
+
+    pub fn keyhashes() -> Self {
+        StoreCommand::KEYHASHES
+    }
+
+    pub fn keyhashes_matching<P>(pattern: P) -> Self
+    where
+        P: Into<Cow<'a, str>>,
+    {
+        StoreCommand::KEYHASHESMATCHING(KEYSParams {
+            pattern: pattern.into(),
+        })
+    }
+
+    pub fn metrics() -> Self {
+        StoreCommand::METRICS
+    }
+
+    pub fn version() -> Self {
+        StoreCommand::VERSION
+    }
+
+    pub fn memusage() -> Self {
+        StoreCommand::MEMUSAGE
+    }
+
+    pub fn flush() -> Self {
+        StoreCommand::FLUSH
+    }
+
+    pub fn peers() -> Self {
+        StoreCommand::PEERS
+    }
+
+    pub fn tracker() -> Self {
+        StoreCommand::TRACKER
+    }
+
+    pub fn rebuild() -> Self {
+        StoreCommand::REBUILD
+    }
+
+    pub fn decrby<K>(key: K, amount: i64) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::DECRBY(DECRBYParams {
+            key: key.into(),
+            amount,
+        })
+    }
+
+    pub fn lpush<K, V, I>(key: K, values: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = V>,
+    {
+        StoreCommand::LPUSH(LPUSHParams {
+            key: key.into(),
+            values: values.into_iter().map(|v| v.into()).collect(),
+        })
+    }
+
+    pub fn rpush<K, V, I>(key: K, values: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = V>,
+    {
+        StoreCommand::RPUSH(RPUSHParams {
+            key: key.into(),
+            values: values.into_iter().map(|v| v.into()).collect(),
+        })
+    }
+
+    pub fn lrange<K>(key: K, start: i64, stop: i64) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::LRANGE(LRANGEParams {
+            key: key.into(),
+            start,
+            stop,
+        })
+    }
+
+    pub fn llen<K>(key: K) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::LLEN(LLENParams { key: key.into() })
+    }
+
+    pub fn key_type<K>(key: K) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::TYPE(TYPEParams { key: key.into() })
+    }
+
+    pub fn hset<K, F, V>(key: K, field: F, value: V) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        F: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommand::HSET(HSETParams {
+            key: key.into(),
+            field: field.into(),
+            value: value.into(),
+        })
+    }
+
+    pub fn hincrby<K, F>(key: K, field: F, amount: i64) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        F: Into<Cow<'a, str>>,
+    {
+        StoreCommand::HINCRBY(HINCRBYParams {
+            key: key.into(),
+            field: field.into(),
+            amount,
+        })
+    }
+
+    pub fn msetex<K, V, I>(seconds: u64, pairs: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        StoreCommand::MSETEX(MSETEXParams {
+            seconds,
+            pairs: pairs
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        })
+    }
+
+    pub fn hget<K, F>(key: K, field: F) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        F: Into<Cow<'a, str>>,
+    {
+        StoreCommand::HGET(HGETParams {
+            key: key.into(),
+            field: field.into(),
+        })
+    }
+
+    pub fn hgetall<K>(key: K) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::HGETALL(HGETALLParams { key: key.into() })
+    }
+
+    pub fn hdel<K, F, I>(key: K, fields: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        F: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = F>,
+    {
+        StoreCommand::HDEL(HDELParams {
+            key: key.into(),
+            fields: fields.into_iter().map(|f| f.into()).collect(),
+        })
+    }
+
+    pub fn sadd<K, M, I>(key: K, members: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        M: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = M>,
+    {
+        StoreCommand::SADD(SADDParams {
+            key: key.into(),
+            members: members.into_iter().map(|m| m.into()).collect(),
+        })
+    }
+
+    pub fn srem<K, M, I>(key: K, members: I) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        M: Into<Cow<'a, str>>,
+        I: IntoIterator<Item = M>,
+    {
+        StoreCommand::SREM(SREMParams {
+            key: key.into(),
+            members: members.into_iter().map(|m| m.into()).collect(),
+        })
+    }
+
+    pub fn smembers<K>(key: K) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+    {
+        StoreCommand::SMEMBERS(SMEMBERSParams { key: key.into() })
+    }
+
+    pub fn rename<K, N>(key: K, newkey: N) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        N: Into<Cow<'a, str>>,
+    {
+        StoreCommand::RENAME(RENAMEParams {
+            key: key.into(),
+            newkey: newkey.into(),
+        })
+    }
+
+    pub fn renamenx<K, N>(key: K, newkey: N) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        N: Into<Cow<'a, str>>,
+    {
+        StoreCommand::RENAMENX(RENAMEParams {
+            key: key.into(),
+            newkey: newkey.into(),
+        })
+    }
+
+    pub fn sismember<K, M>(key: K, member: M) -> Self
+    where
+        K: Into<Cow<'a, str>>,
+        M: Into<Cow<'a, str>>,
+    {
+        StoreCommand::SISMEMBER(SISMEMBERParams {
+            key: key.into(),
+            member: member.into(),
         })
     }
+
+    /// Keys the command reads or writes, empty for the commands about the
+    /// whole store
+    pub fn key_args(&self) -> Vec<&str> {
+        match self {
+            StoreCommand::DEL(DELParams { keys })
+            | StoreCommand::EXISTS(EXISTSParams { keys })
+            | StoreCommand::EXISTSALL(EXISTSParams { keys })
+            | StoreCommand::EXISTSANY(EXISTSParams { keys }) => {
+                keys.iter().map(|key| key.as_ref()).collect()
+            }
+            StoreCommand::MSETEX(MSETEXParams { pairs, .. }) => {
+                pairs.iter().map(|(key, _)| key.as_ref()).collect()
+            }
+            StoreCommand::GET(GETParams { key })
+            | StoreCommand::SET(SETParams { key, .. })
+            | StoreCommand::EXPIRE(EXPIREParams { key, .. })
+            | StoreCommand::CASV(CASVParams { key, .. })
+            | StoreCommand::OBJECT(OBJECTParams { key, .. })
+            | StoreCommand::INCRBY(INCRBYParams { key, .. })
+            | StoreCommand::DECRBY(DECRBYParams { key, .. })
+            | StoreCommand::LPUSH(LPUSHParams { key, .. })
+            | StoreCommand::RPUSH(RPUSHParams { key, .. })
+            | StoreCommand::LRANGE(LRANGEParams { key, .. })
+            | StoreCommand::LLEN(LLENParams { key })
+            | StoreCommand::TYPE(TYPEParams { key })
+            | StoreCommand::HSET(HSETParams { key, .. })
+            | StoreCommand::HINCRBY(HINCRBYParams { key, .. })
+            | StoreCommand::HGET(HGETParams { key, .. })
+            | StoreCommand::HGETALL(HGETALLParams { key })
+            | StoreCommand::HDEL(HDELParams { key, .. })
+            | StoreCommand::SADD(SADDParams { key, .. })
+            | StoreCommand::SREM(SREMParams { key, .. })
+            | StoreCommand::SMEMBERS(SMEMBERSParams { key })
+            | StoreCommand::SISMEMBER(SISMEMBERParams { key, .. }) => vec![key.as_ref()],
+            StoreCommand::RENAME(RENAMEParams { key, newkey })
+            | StoreCommand::RENAMENX(RENAMEParams { key, newkey }) => {
+                vec![key.as_ref(), newkey.as_ref()]
+            }
+            StoreCommand::KEYS(_)
+            | StoreCommand::TTLS
+            | StoreCommand::TREEINFO
+            | StoreCommand::DUMPJSON
+            | StoreCommand::KEYHASHES
+            | StoreCommand::METRICS
+            | StoreCommand::VERSION
+            | StoreCommand::PEERS
+            | StoreCommand::REBUILD
+            | StoreCommand::MEMUSAGE
+            | StoreCommand::FLUSH
+            | StoreCommand::KEYHASHESMATCHING(_)
+            | StoreCommand::TRACKER => Vec::new(),
+        }
+    }
 }
 
 #[derive(Encode, Decode, Debug)]
@@ -93,6 +585,145 @@ pub struct KEYSParams<'a> {
 pub struct SETParams<'a> {
     pub key: Cow<'a, str>,
     pub value: Cow<'a, str>,
+    /// answer with a `GET` result holding the previous value instead of a `SET` result
+    pub return_old: bool,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct EXPIREParams<'a> {
+    pub key: Cow<'a, str>,
+    pub seconds: u64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct CASVParams<'a> {
+    pub key: Cow<'a, str>,
+    pub expected_version: u64,
+    pub value: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct INCRBYParams<'a> {
+    pub key: Cow<'a, str>,
+    pub amount: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct DECRBYParams<'a> {
+    pub key: Cow<'a, str>,
+    pub amount: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct LPUSHParams<'a> {
+    pub key: Cow<'a, str>,
+    pub values: Vec<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct RPUSHParams<'a> {
+    pub key: Cow<'a, str>,
+    pub values: Vec<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct LRANGEParams<'a> {
+    pub key: Cow<'a, str>,
+    /// first index, negative values count from the end of the list
+    pub start: i64,
+    /// last index, negative values count from the end of the list
+    pub stop: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct LLENParams<'a> {
+    pub key: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct TYPEParams<'a> {
+    pub key: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct HSETParams<'a> {
+    pub key: Cow<'a, str>,
+    pub field: Cow<'a, str>,
+    pub value: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct HINCRBYParams<'a> {
+    pub key: Cow<'a, str>,
+    pub field: Cow<'a, str>,
+    pub amount: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct MSETEXParams<'a> {
+    pub seconds: u64,
+    pub pairs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct HGETParams<'a> {
+    pub key: Cow<'a, str>,
+    pub field: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct HGETALLParams<'a> {
+    pub key: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct HDELParams<'a> {
+    pub key: Cow<'a, str>,
+    pub fields: Vec<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct SADDParams<'a> {
+    pub key: Cow<'a, str>,
+    pub members: Vec<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct SREMParams<'a> {
+    pub key: Cow<'a, str>,
+    pub members: Vec<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct SMEMBERSParams<'a> {
+    pub key: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct SISMEMBERParams<'a> {
+    pub key: Cow<'a, str>,
+    pub member: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct RENAMEParams<'a> {
+    pub key: Cow<'a, str>,
+    pub newkey: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct OBJECTParams<'a> {
+    pub subcommand: ObjectSubcommand,
+    pub key: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq)]
+pub enum ObjectSubcommand {
+    /// Seconds since the key was first set
+    AGE,
+
+    /// Seconds since the key was last written or read
+    IDLETIME,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -102,63 +733,463 @@ pub struct CmdArgs {
     pub cmd_arg: String,
 }
 
+/// Names of the commands `handle_cmd_input` parses
+pub const COMMAND_NAMES: &[&str] = &[
+    "DEL",
+    "EXISTS",
+    "GET",
+    "KEYS",
+    "SET",
+    "EXPIRE",
+    "TTLS",
+    "CASV",
+    "OBJECT",
+    "TREEINFO",
+    "INCRBY",
+    "DECRBY",
+    "DUMPJSON",
+    "KEYHASHES",
+    "LPUSH",
+    "RPUSH",
+    "LRANGE",
+    "LLEN",
+    "TYPE",
+    "HSET",
+    "HGET",
+    "HGETALL",
+    "HDEL",
+    "SADD",
+    "SREM",
+    "SMEMBERS",
+    "SISMEMBER",
+    "METRICS",
+    "VERSION",
+    "RENAME",
+    "RENAMENX",
+    "PEERS",
+    "REBUILD",
+    "EXISTSALL",
+    "EXISTSANY",
+    "MEMUSAGE",
+    "FLUSH",
+    "TRACKER",
+    "HINCRBY",
+    "MSETEX",
+];
+
 pub fn handle_cmd_input<'a>(args: &'a CmdArgs) -> Option<StoreCommand<'a>> {
     let cmd = args.cmd_type.to_uppercase();
     let mut cmd_args = args.cmd_arg.split_whitespace();
 
     match cmd.as_str() {
-        "DEL" | "EXISTS" => {
+        "DEL" | "EXISTS" | "EXISTSALL" | "EXISTSANY" => {
             let keys: Vec<&str> = cmd_args.collect();
             if keys.is_empty() {
                 eprintln!("Error: '{cmd}' requires at least one key");
                 return None;
             }
-            if cmd == "DEL" {
-                Some(StoreCommand::del(keys))
+            match cmd.as_str() {
+                "DEL" => Some(StoreCommand::del(keys)),
+                "EXISTSALL" => Some(StoreCommand::existsall(keys)),
+                "EXISTSANY" => Some(StoreCommand::existsany(keys)),
+                _ => Some(StoreCommand::exists(keys)),
+            }
+        }
+
+        "GET" | "KEYS" | "LLEN" | "TYPE" | "HGETALL" | "SMEMBERS" => {
+            let first = match cmd_args.next() {
+                Some(arg) => arg,
+                None => {
+                    eprintln!("Error: '{cmd}' requires exactly one argument");
+                    return None;
+                }
+            };
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes exactly one argument");
+                return None;
+            }
+            match cmd.as_str() {
+                "GET" => Some(StoreCommand::get(first)),
+                "KEYS" => Some(StoreCommand::keys(first)),
+                "LLEN" => Some(StoreCommand::llen(first)),
+                "HGETALL" => Some(StoreCommand::hgetall(first)),
+                "SMEMBERS" => Some(StoreCommand::smembers(first)),
+                _ => Some(StoreCommand::key_type(first)),
+            }
+        }
+
+        "SET" => {
+            let key = match cmd_args.next() {
+                Some(k) => k,
+                None => {
+                    eprintln!("Error: '{cmd}' requires key and value");
+                    return None;
+                }
+            };
+            let value = match cmd_args.next() {
+                Some(v) => v,
+                None => {
+                    eprintln!("Error: '{cmd}' requires value after key");
+                    return None;
+                }
+            };
+            match (cmd_args.next(), cmd_args.next()) {
+                (None, None) => Some(StoreCommand::set(key, value)),
+                (Some(option), None) if option.eq_ignore_ascii_case("GET") => {
+                    Some(StoreCommand::set_get(key, value))
+                }
+                _ => {
+                    eprintln!("Error: '{cmd}' takes a key, a value and an optional GET");
+                    None
+                }
+            }
+        }
+
+        "EXPIRE" => {
+            let key = match cmd_args.next() {
+                Some(k) => k,
+                None => {
+                    eprintln!("Error: '{cmd}' requires key and seconds");
+                    return None;
+                }
+            };
+            let seconds = match cmd_args.next().map(str::parse::<u64>) {
+                Some(Ok(s)) => s,
+                Some(Err(_)) => {
+                    eprintln!("Error: '{cmd}' seconds must be a non-negative integer");
+                    return None;
+                }
+                None => {
+                    eprintln!("Error: '{cmd}' requires seconds after key");
+                    return None;
+                }
+            };
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes exactly two arguments: key and seconds");
+                return None;
+            }
+            Some(StoreCommand::expire(key, seconds))
+        }
+
+        "TTLS" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::ttls())
+        }
+
+        "CASV" => {
+            let (key, version, value) = match (cmd_args.next(), cmd_args.next(), cmd_args.next()) {
+                (Some(k), Some(ver), Some(v)) => (k, ver, v),
+                _ => {
+                    eprintln!("Error: '{cmd}' requires key, expected version and value");
+                    return None;
+                }
+            };
+            let expected_version = match version.parse::<u64>() {
+                Ok(ver) => ver,
+                Err(_) => {
+                    eprintln!("Error: '{cmd}' expected version must be a non-negative integer");
+                    return None;
+                }
+            };
+            if cmd_args.next().is_some() {
+                eprintln!(
+                    "Error: '{cmd}' takes exactly three arguments: key, expected version and value"
+                );
+                return None;
+            }
+            Some(StoreCommand::casv(key, expected_version, value))
+        }
+
+        "OBJECT" => {
+            let (subcommand, key) = match (cmd_args.next(), cmd_args.next(), cmd_args.next()) {
+                (Some(sub), Some(key), None) => (sub.to_uppercase(), key),
+                _ => {
+                    eprintln!("Error: '{cmd}' takes exactly two arguments: subcommand and key");
+                    return None;
+                }
+            };
+            let subcommand = match subcommand.as_str() {
+                "AGE" => ObjectSubcommand::AGE,
+                "IDLETIME" => ObjectSubcommand::IDLETIME,
+                _ => {
+                    eprintln!("Error: unknown '{cmd}' subcommand '{subcommand}'");
+                    return None;
+                }
+            };
+            Some(StoreCommand::object(subcommand, key))
+        }
+
+        "TREEINFO" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::treeinfo())
+        }
+
+        "DUMPJSON" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::dumpjson())
+        }
+
+        "KEYHASHES" => match (cmd_args.next(), cmd_args.next()) {
+            (None, _) => Some(StoreCommand::keyhashes()),
+            (Some(pattern), None) => Some(StoreCommand::keyhashes_matching(pattern)),
+            _ => {
+                eprintln!("Error: '{cmd}' takes at most one argument: pattern");
+                None
+            }
+        },
+
+        "METRICS" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::metrics())
+        }
+
+        "VERSION" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::version())
+        }
+
+        "PEERS" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::peers())
+        }
+
+        "TRACKER" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::tracker())
+        }
+
+        "REBUILD" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::rebuild())
+        }
+
+        "MEMUSAGE" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::memusage())
+        }
+
+        "FLUSH" => {
+            if cmd_args.next().is_some() {
+                eprintln!("Error: '{cmd}' takes no arguments");
+                return None;
+            }
+            Some(StoreCommand::flush())
+        }
+
+        "RENAME" | "RENAMENX" => {
+            let (key, newkey) = match (cmd_args.next(), cmd_args.next(), cmd_args.next()) {
+                (Some(key), Some(newkey), None) => (key, newkey),
+                _ => {
+                    eprintln!("Error: '{cmd}' takes exactly two arguments: key and newkey");
+                    return None;
+                }
+            };
+            if cmd == "RENAME" {
+                Some(StoreCommand::rename(key, newkey))
+            } else {
+                Some(StoreCommand::renamenx(key, newkey))
+            }
+        }
+
+        "INCRBY" | "DECRBY" => {
+            let (key, amount) = match (cmd_args.next(), cmd_args.next(), cmd_args.next()) {
+                (Some(key), Some(amount), None) => (key, amount),
+                _ => {
+                    eprintln!("Error: '{cmd}' takes exactly two arguments: key and amount");
+                    return None;
+                }
+            };
+            let amount = parse_amount(&cmd, amount)?;
+            if cmd == "INCRBY" {
+                Some(StoreCommand::incrby(key, amount))
+            } else {
+                Some(StoreCommand::decrby(key, amount))
+            }
+        }
+
+        "LPUSH" | "RPUSH" => {
+            let key = match cmd_args.next() {
+                Some(k) => k,
+                None => {
+                    eprintln!("Error: '{cmd}' requires key and at least one value");
+                    return None;
+                }
+            };
+            let values: Vec<&str> = cmd_args.collect();
+            if values.is_empty() {
+                eprintln!("Error: '{cmd}' requires at least one value after key");
+                return None;
+            }
+            if cmd == "LPUSH" {
+                Some(StoreCommand::lpush(key, values))
             } else {
-                Some(StoreCommand::exists(keys))
+                Some(StoreCommand::rpush(key, values))
             }
         }
 
-        "GET" | "KEYS" => {
-            let first = match cmd_args.next() {
-                Some(arg) => arg,
+        "HSET" => {
+            let (key, field, value) = match (
+                cmd_args.next(),
+                cmd_args.next(),
+                cmd_args.next(),
+                cmd_args.next(),
+            ) {
+                (Some(key), Some(field), Some(value), None) => (key, field, value),
+                _ => {
+                    eprintln!("Error: '{cmd}' takes exactly three arguments: key, field and value");
+                    return None;
+                }
+            };
+            Some(StoreCommand::hset(key, field, value))
+        }
+
+        "HINCRBY" => {
+            let (key, field, amount) = match (
+                cmd_args.next(),
+                cmd_args.next(),
+                cmd_args.next(),
+                cmd_args.next(),
+            ) {
+                (Some(key), Some(field), Some(amount), None) => (key, field, amount),
+                _ => {
+                    eprintln!(
+                        "Error: '{cmd}' takes exactly three arguments: key, field and amount"
+                    );
+                    return None;
+                }
+            };
+            let amount = parse_amount(&cmd, amount)?;
+            Some(StoreCommand::hincrby(key, field, amount))
+        }
+
+        "MSETEX" => {
+            let seconds = match cmd_args.next().map(str::parse::<u64>) {
+                Some(Ok(s)) => s,
+                Some(Err(_)) => {
+                    eprintln!("Error: '{cmd}' seconds must be a non-negative integer");
+                    return None;
+                }
                 None => {
-                    eprintln!("Error: '{cmd}' requires exactly one argument");
+                    eprintln!("Error: '{cmd}' requires seconds and at least one key and value");
                     return None;
                 }
             };
-            if cmd_args.next().is_some() {
-                eprintln!("Error: '{cmd}' takes exactly one argument");
+            let rest: Vec<&str> = cmd_args.collect();
+            if rest.is_empty() || !rest.len().is_multiple_of(2) {
+                eprintln!("Error: '{cmd}' requires pairs of key and value after seconds");
                 return None;
             }
-            if cmd == "GET" {
-                Some(StoreCommand::get(first))
-            } else {
-                Some(StoreCommand::keys(first))
-            }
+            Some(StoreCommand::msetex(
+                seconds,
+                rest.chunks(2).map(|pair| (pair[0], pair[1])),
+            ))
         }
 
-        "SET" => {
+        "HGET" => {
+            let (key, field) = match (cmd_args.next(), cmd_args.next(), cmd_args.next()) {
+                (Some(key), Some(field), None) => (key, field),
+                _ => {
+                    eprintln!("Error: '{cmd}' takes exactly two arguments: key and field");
+                    return None;
+                }
+            };
+            Some(StoreCommand::hget(key, field))
+        }
+
+        "SISMEMBER" => {
+            let (key, member) = match (cmd_args.next(), cmd_args.next(), cmd_args.next()) {
+                (Some(key), Some(member), None) => (key, member),
+                _ => {
+                    eprintln!("Error: '{cmd}' takes exactly two arguments: key and member");
+                    return None;
+                }
+            };
+            Some(StoreCommand::sismember(key, member))
+        }
+
+        "SADD" | "SREM" => {
             let key = match cmd_args.next() {
                 Some(k) => k,
                 None => {
-                    eprintln!("Error: '{cmd}' requires key and value");
+                    eprintln!("Error: '{cmd}' requires key and at least one member");
                     return None;
                 }
             };
-            let value = match cmd_args.next() {
-                Some(v) => v,
+            let members: Vec<&str> = cmd_args.collect();
+            if members.is_empty() {
+                eprintln!("Error: '{cmd}' requires at least one member after key");
+                return None;
+            }
+            if cmd == "SADD" {
+                Some(StoreCommand::sadd(key, members))
+            } else {
+                Some(StoreCommand::srem(key, members))
+            }
+        }
+
+        "HDEL" => {
+            let key = match cmd_args.next() {
+                Some(k) => k,
                 None => {
-                    eprintln!("Error: '{cmd}' requires value after key");
+                    eprintln!("Error: '{cmd}' requires key and at least one field");
                     return None;
                 }
             };
-            if cmd_args.next().is_some() {
-                eprintln!("Error: '{cmd}' takes exactly two arguments: key and value");
+            let fields: Vec<&str> = cmd_args.collect();
+            if fields.is_empty() {
+                eprintln!("Error: '{cmd}' requires at least one field after key");
                 return None;
             }
-            Some(StoreCommand::set(key, value))
+            Some(StoreCommand::hdel(key, fields))
+        }
+
+        "LRANGE" => {
+            let (key, start, stop) = match (
+                cmd_args.next(),
+                cmd_args.next(),
+                cmd_args.next(),
+                cmd_args.next(),
+            ) {
+                (Some(key), Some(start), Some(stop), None) => (key, start, stop),
+                _ => {
+                    eprintln!("Error: '{cmd}' takes exactly three arguments: key, start and stop");
+                    return None;
+                }
+            };
+            match (start.parse::<i64>(), stop.parse::<i64>()) {
+                (Ok(start), Ok(stop)) => Some(StoreCommand::lrange(key, start, stop)),
+                _ => {
+                    eprintln!("Error: '{cmd}' start and stop must be integers");
+                    None
+                }
+            }
         }
 
         _ => {
@@ -168,6 +1199,17 @@ pub fn handle_cmd_input<'a>(args: &'a CmdArgs) -> Option<StoreCommand<'a>> {
     }
 }
 
+/// The amount of `INCRBY`, `DECRBY` and `HINCRBY`
+fn parse_amount(cmd: &str, amount: &str) -> Option<i64> {
+    match amount.parse::<i64>() {
+        Ok(amount) => Some(amount),
+        Err(_) => {
+            eprintln!("Error: '{cmd}' amount must be an integer");
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,6 +1236,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn key_args_lists_the_keys_of_a_command() {
+        assert_eq!(StoreCommand::set("color", "red").key_args(), ["color"]);
+        assert_eq!(StoreCommand::del(["a", "b"]).key_args(), ["a", "b"]);
+        assert_eq!(StoreCommand::rename("a", "b").key_args(), ["a", "b"]);
+        assert!(StoreCommand::keys("*").key_args().is_empty());
+    }
+
     #[test]
     fn store_command_get() {
         let cmd = StoreCommand::get("user:123");
@@ -219,6 +1269,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn store_command_expire() {
+        let cmd = StoreCommand::expire("session", 30);
+        assert!(matches!(cmd, StoreCommand::EXPIRE(_)));
+        if let StoreCommand::EXPIRE(params) = cmd {
+            assert_eq!(params.key, "session");
+            assert_eq!(params.seconds, 30);
+        }
+    }
+
     #[test]
     fn handle_cmd_input_del() {
         let args = CmdArgs {
@@ -269,6 +1329,39 @@ mod tests {
         assert!(matches!(cmd, StoreCommand::SET(_)));
     }
 
+    #[test]
+    fn handle_cmd_input_set_get_option() {
+        let args = CmdArgs {
+            cmd_type: "set".to_string(),
+            cmd_arg: "color red get".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::SET(SETParams {
+                return_old: true,
+                ..
+            }))
+        ));
+
+        let args = CmdArgs {
+            cmd_type: "set".to_string(),
+            cmd_arg: "color red".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::SET(SETParams {
+                return_old: false,
+                ..
+            }))
+        ));
+
+        let args = CmdArgs {
+            cmd_type: "set".to_string(),
+            cmd_arg: "color red nx".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
     #[test]
     fn handle_cmd_input_set_missing_value() {
         let args = CmdArgs {
@@ -295,4 +1388,407 @@ mod tests {
         };
         assert!(handle_cmd_input(&args).is_some());
     }
+
+    #[test]
+    fn handle_cmd_input_expire_valid() {
+        let args = CmdArgs {
+            cmd_type: "expire".to_string(),
+            cmd_arg: "session 10".to_string(),
+        };
+        let cmd = handle_cmd_input(&args).unwrap();
+        assert!(matches!(cmd, StoreCommand::EXPIRE(_)));
+    }
+
+    #[test]
+    fn handle_cmd_input_expire_invalid_seconds() {
+        let args = CmdArgs {
+            cmd_type: "expire".to_string(),
+            cmd_arg: "session soon".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_ttls() {
+        let args = CmdArgs {
+            cmd_type: "ttls".to_string(),
+            cmd_arg: "".to_string(),
+        };
+        let cmd = handle_cmd_input(&args).unwrap();
+        assert!(matches!(cmd, StoreCommand::TTLS));
+    }
+
+    #[test]
+    fn handle_cmd_input_ttls_rejects_arguments() {
+        let args = CmdArgs {
+            cmd_type: "ttls".to_string(),
+            cmd_arg: "extra".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_casv_valid() {
+        let args = CmdArgs {
+            cmd_type: "casv".to_string(),
+            cmd_arg: "color 2 blue".to_string(),
+        };
+        let cmd = handle_cmd_input(&args).unwrap();
+        assert!(matches!(cmd, StoreCommand::CASV(_)));
+        if let StoreCommand::CASV(params) = cmd {
+            assert_eq!(params.key, "color");
+            assert_eq!(params.expected_version, 2);
+            assert_eq!(params.value, "blue");
+        }
+    }
+
+    #[test]
+    fn handle_cmd_input_casv_invalid_version() {
+        let args = CmdArgs {
+            cmd_type: "casv".to_string(),
+            cmd_arg: "color two blue".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_object_age() {
+        let args = CmdArgs {
+            cmd_type: "object".to_string(),
+            cmd_arg: "age color".to_string(),
+        };
+        let cmd = handle_cmd_input(&args).unwrap();
+        assert!(matches!(
+            cmd,
+            StoreCommand::OBJECT(OBJECTParams {
+                subcommand: ObjectSubcommand::AGE,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn handle_cmd_input_object_unknown_subcommand() {
+        let args = CmdArgs {
+            cmd_type: "object".to_string(),
+            cmd_arg: "encoding color".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_treeinfo() {
+        let args = CmdArgs {
+            cmd_type: "treeinfo".to_string(),
+            cmd_arg: "".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::TREEINFO)
+        ));
+
+        let args = CmdArgs {
+            cmd_type: "treeinfo".to_string(),
+            cmd_arg: "extra".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_peers() {
+        let args = CmdArgs {
+            cmd_type: "peers".to_string(),
+            cmd_arg: "".to_string(),
+        };
+        assert!(matches!(handle_cmd_input(&args), Some(StoreCommand::PEERS)));
+
+        let args = CmdArgs {
+            cmd_type: "peers".to_string(),
+            cmd_arg: "extra".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_tracker() {
+        let args = CmdArgs {
+            cmd_type: "tracker".to_string(),
+            cmd_arg: "".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::TRACKER)
+        ));
+
+        let args = CmdArgs {
+            cmd_type: "tracker".to_string(),
+            cmd_arg: "extra".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_rebuild() {
+        let args = CmdArgs {
+            cmd_type: "rebuild".to_string(),
+            cmd_arg: "".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::REBUILD)
+        ));
+    }
+
+    #[test]
+    fn handle_cmd_input_existsall_existsany() {
+        let args = CmdArgs {
+            cmd_type: "existsall".to_string(),
+            cmd_arg: "a b".to_string(),
+        };
+        match handle_cmd_input(&args) {
+            Some(StoreCommand::EXISTSALL(params)) => assert_eq!(params.keys, vec!["a", "b"]),
+            other => panic!("unexpected command {other:?}"),
+        }
+
+        let args = CmdArgs {
+            cmd_type: "existsany".to_string(),
+            cmd_arg: "a".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::EXISTSANY(_))
+        ));
+
+        let args = CmdArgs {
+            cmd_type: "existsany".to_string(),
+            cmd_arg: "".to_string(),
+        };
+        assert!(handle_cmd_input(&args).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_incrby_valid() {
+        let args = CmdArgs {
+            cmd_type: "incrby".to_string(),
+            cmd_arg: "counter -5".to_string(),
+        };
+        let cmd = handle_cmd_input(&args).unwrap();
+        assert!(matches!(
+            cmd,
+            StoreCommand::INCRBY(INCRBYParams { amount: -5, .. })
+        ));
+
+        let args = CmdArgs {
+            cmd_type: "DECRBY".to_string(),
+            cmd_arg: "counter 3".to_string(),
+        };
+        let cmd = handle_cmd_input(&args).unwrap();
+        assert!(matches!(
+            cmd,
+            StoreCommand::DECRBY(DECRBYParams { amount: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn handle_cmd_input_list_commands() {
+        let args = CmdArgs {
+            cmd_type: "lpush".to_string(),
+            cmd_arg: "queue a b".to_string(),
+        };
+        let Some(StoreCommand::LPUSH(params)) = handle_cmd_input(&args) else {
+            panic!("Expected LPUSH command");
+        };
+        assert_eq!(params.key, "queue");
+        assert_eq!(params.values, vec!["a", "b"]);
+
+        let args = CmdArgs {
+            cmd_type: "lrange".to_string(),
+            cmd_arg: "queue 0 -1".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::LRANGE(LRANGEParams {
+                start: 0,
+                stop: -1,
+                ..
+            }))
+        ));
+
+        for (cmd_type, cmd_arg) in [
+            ("rpush", "queue"),
+            ("lrange", "queue 0"),
+            ("lrange", "queue 0 last"),
+            ("llen", ""),
+            ("type", "a b"),
+        ] {
+            let args = CmdArgs {
+                cmd_type: cmd_type.to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(handle_cmd_input(&args).is_none(), "{cmd_type} {cmd_arg}");
+        }
+    }
+
+    #[test]
+    fn handle_cmd_input_hash_commands() {
+        let args = CmdArgs {
+            cmd_type: "hset".to_string(),
+            cmd_arg: "user:1 name alice".to_string(),
+        };
+        let Some(StoreCommand::HSET(params)) = handle_cmd_input(&args) else {
+            panic!("Expected HSET command");
+        };
+        assert_eq!(params.key, "user:1");
+        assert_eq!(params.field, "name");
+        assert_eq!(params.value, "alice");
+
+        let args = CmdArgs {
+            cmd_type: "hdel".to_string(),
+            cmd_arg: "user:1 name age".to_string(),
+        };
+        let Some(StoreCommand::HDEL(params)) = handle_cmd_input(&args) else {
+            panic!("Expected HDEL command");
+        };
+        assert_eq!(params.fields, vec!["name", "age"]);
+
+        let args = CmdArgs {
+            cmd_type: "hincrby".to_string(),
+            cmd_arg: "user:1 visits -3".to_string(),
+        };
+        let Some(StoreCommand::HINCRBY(params)) = handle_cmd_input(&args) else {
+            panic!("Expected HINCRBY command");
+        };
+        assert_eq!(params.key, "user:1");
+        assert_eq!(params.field, "visits");
+        assert_eq!(params.amount, -3);
+
+        for (cmd_type, cmd_arg) in [
+            ("hset", "user:1 name"),
+            ("hincrby", "user:1 visits"),
+            ("hincrby", "user:1 visits many"),
+            ("hget", "user:1"),
+            ("hget", "user:1 name age"),
+            ("hdel", "user:1"),
+            ("hgetall", ""),
+        ] {
+            let args = CmdArgs {
+                cmd_type: cmd_type.to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(handle_cmd_input(&args).is_none(), "{cmd_type} {cmd_arg}");
+        }
+    }
+
+    #[test]
+    fn handle_cmd_input_set_commands() {
+        let args = CmdArgs {
+            cmd_type: "sadd".to_string(),
+            cmd_arg: "tags red blue".to_string(),
+        };
+        let Some(StoreCommand::SADD(params)) = handle_cmd_input(&args) else {
+            panic!("Expected SADD command");
+        };
+        assert_eq!(params.key, "tags");
+        assert_eq!(params.members, vec!["red", "blue"]);
+
+        let args = CmdArgs {
+            cmd_type: "sismember".to_string(),
+            cmd_arg: "tags red".to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args),
+            Some(StoreCommand::SISMEMBER(_))
+        ));
+
+        for (cmd_type, cmd_arg) in [
+            ("sadd", "tags"),
+            ("srem", "tags"),
+            ("smembers", "tags red"),
+            ("sismember", "tags"),
+        ] {
+            let args = CmdArgs {
+                cmd_type: cmd_type.to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(handle_cmd_input(&args).is_none(), "{cmd_type} {cmd_arg}");
+        }
+    }
+
+    #[test]
+    fn handle_cmd_input_msetex() {
+        let args = CmdArgs {
+            cmd_type: "msetex".to_string(),
+            cmd_arg: "60 color red size xl".to_string(),
+        };
+        let Some(StoreCommand::MSETEX(params)) = handle_cmd_input(&args) else {
+            panic!("Expected MSETEX command");
+        };
+        assert_eq!(params.seconds, 60);
+        assert_eq!(
+            params.pairs,
+            vec![
+                (Cow::Borrowed("color"), Cow::Borrowed("red")),
+                (Cow::Borrowed("size"), Cow::Borrowed("xl")),
+            ]
+        );
+
+        // a key without its value fails the whole command
+        for cmd_arg in ["60 color red size", "60", "", "soon color red", "-1 color red"] {
+            let args = CmdArgs {
+                cmd_type: "msetex".to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(handle_cmd_input(&args).is_none(), "msetex {cmd_arg}");
+        }
+    }
+
+    #[test]
+    fn handle_cmd_input_keyhashes_pattern_is_optional() {
+        let args = |cmd_arg: &str| CmdArgs {
+            cmd_type: "keyhashes".to_string(),
+            cmd_arg: cmd_arg.to_string(),
+        };
+        assert!(matches!(
+            handle_cmd_input(&args("")),
+            Some(StoreCommand::KEYHASHES)
+        ));
+        assert!(matches!(
+            handle_cmd_input(&args("user:*")),
+            Some(StoreCommand::KEYHASHESMATCHING(KEYSParams { pattern })) if pattern == "user:*"
+        ));
+        assert!(handle_cmd_input(&args("a b")).is_none());
+    }
+
+    #[test]
+    fn handle_cmd_input_rename() {
+        let args = CmdArgs {
+            cmd_type: "renamenx".to_string(),
+            cmd_arg: "old new".to_string(),
+        };
+        let cmd = handle_cmd_input(&args).unwrap();
+        assert!(matches!(
+            cmd,
+            StoreCommand::RENAMENX(RENAMEParams { ref key, ref newkey })
+                if key == "old" && newkey == "new"
+        ));
+
+        for cmd_arg in ["old", "old new extra", ""] {
+            let args = CmdArgs {
+                cmd_type: "rename".to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(handle_cmd_input(&args).is_none(), "{cmd_arg}");
+        }
+    }
+
+    #[test]
+    fn handle_cmd_input_incrby_invalid_amount() {
+        for cmd_arg in ["counter five", "counter 1.5", "counter", "counter 1 2"] {
+            let args = CmdArgs {
+                cmd_type: "incrby".to_string(),
+                cmd_arg: cmd_arg.to_string(),
+            };
+            assert!(handle_cmd_input(&args).is_none(), "{cmd_arg}");
+        }
+    }
 }
@@ -1,9 +1,56 @@
+use super::result::ErrorKind;
+use std::fmt;
+
 #[derive(Debug, PartialEq)]
 pub enum StoreError {
     MonotreeError(String),
     RegexError(String),
+    TimestampError,
+    /// the key is longer than the 32 bytes allowed by raw key hashing
+    KeyTooLong(usize),
+    /// the value is longer than the configured maximum value size
+    ValueTooLarge(usize),
+    /// the store already holds the configured maximum number of keys
+    KeyLimitReached(usize),
+    /// the backend holding the values failed to read or write them
+    BackendError(String),
+    /// a snapshot couldn't be written, read or understood
+    SnapshotError(String),
+}
+
+impl StoreError {
+    /// Whether the error is due to the command or to the node running it
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            StoreError::RegexError(_)
+            | StoreError::KeyTooLong(_)
+            | StoreError::ValueTooLarge(_) => ErrorKind::BadRequest,
+            StoreError::KeyLimitReached(_) => ErrorKind::Conflict,
+            StoreError::MonotreeError(_)
+            | StoreError::TimestampError
+            | StoreError::BackendError(_)
+            | StoreError::SnapshotError(_) => ErrorKind::Internal,
+        }
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::MonotreeError(e) => write!(f, "monotree error: {e}"),
+            StoreError::RegexError(e) => write!(f, "invalid pattern: {e}"),
+            StoreError::TimestampError => write!(f, "the system time is unavailable"),
+            StoreError::KeyTooLong(len) => write!(f, "key of {len} bytes is too long"),
+            StoreError::ValueTooLarge(len) => write!(f, "value of {len} bytes is too large"),
+            StoreError::KeyLimitReached(max) => write!(f, "store already holds {max} keys"),
+            StoreError::BackendError(e) => write!(f, "backend error: {e}"),
+            StoreError::SnapshotError(e) => write!(f, "snapshot error: {e}"),
+        }
+    }
 }
 
+impl std::error::Error for StoreError {}
+
 impl From<monotree::Errors> for StoreError {
     fn from(err: monotree::Errors) -> Self {
         StoreError::MonotreeError(err.to_string())
@@ -16,6 +63,13 @@ impl From<regex::Error> for StoreError {
     }
 }
 
+#[cfg(feature = "sled")]
+impl From<sled::Error> for StoreError {
+    fn from(err: sled::Error) -> Self {
+        StoreError::BackendError(err.to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,6 +95,34 @@ mod tests {
         assert_eq!(format!("{:?}", err), "MonotreeError(\"test\")");
     }
 
+    #[test]
+    fn store_error_kinds() {
+        for (err, kind) in [
+            (
+                StoreError::RegexError("bad".to_string()),
+                ErrorKind::BadRequest,
+            ),
+            (StoreError::KeyTooLong(40), ErrorKind::BadRequest),
+            (StoreError::ValueTooLarge(2048), ErrorKind::BadRequest),
+            (StoreError::KeyLimitReached(10), ErrorKind::Conflict),
+            (
+                StoreError::MonotreeError("locked".to_string()),
+                ErrorKind::Internal,
+            ),
+            (StoreError::TimestampError, ErrorKind::Internal),
+            (
+                StoreError::BackendError("io".to_string()),
+                ErrorKind::Internal,
+            ),
+            (
+                StoreError::SnapshotError("truncated".to_string()),
+                ErrorKind::Internal,
+            ),
+        ] {
+            assert_eq!(err.kind(), kind, "{err}");
+        }
+    }
+
     #[test]
     fn store_error_eq() {
         let e1 = StoreError::RegexError("bad".to_string());
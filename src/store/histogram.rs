@@ -0,0 +1,105 @@
+use bincode::{Decode, Encode};
+use std::fmt;
+
+/// Number of buckets, the last one counts every size of at least 4 MiB
+pub const BUCKETS: usize = 24;
+
+/// Distribution of sizes in power of two buckets: bucket `0` counts empty
+/// entries and bucket `i` the sizes in `2^(i-1)..2^i`. The buckets are a fixed
+/// array so recording a size never allocates
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Default)]
+pub struct SizeHistogram {
+    pub buckets: [u64; BUCKETS],
+}
+
+impl SizeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bucket(size: usize) -> usize {
+        let bits = (usize::BITS - size.leading_zeros()) as usize;
+        bits.min(BUCKETS - 1)
+    }
+
+    /// Smallest size counted by the bucket
+    pub fn lower_bound(bucket: usize) -> usize {
+        match bucket {
+            0 => 0,
+            _ => 1 << (bucket - 1),
+        }
+    }
+
+    pub fn record(&mut self, size: usize) {
+        self.buckets[Self::bucket(size)] += 1;
+    }
+
+    /// Forgets a size recorded earlier, used when an entry is overwritten or removed
+    pub fn forget(&mut self, size: usize) {
+        let count = &mut self.buckets[Self::bucket(size)];
+        *count = count.saturating_sub(1);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+}
+
+impl fmt::Display for SizeHistogram {
+    /// Non-empty buckets as `lower+: count`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            write!(f, "{}+: {count}", Self::lower_bound(bucket))?;
+        }
+        if first {
+            write!(f, "empty")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_land_in_power_of_two_buckets() {
+        for (size, bucket) in [
+            (0, 0),
+            (1, 1),
+            (2, 2),
+            (3, 2),
+            (4, 3),
+            (1023, 10),
+            (1024, 11),
+        ] {
+            assert_eq!(SizeHistogram::bucket(size), bucket, "{size}");
+            assert!(SizeHistogram::lower_bound(bucket) <= size);
+        }
+        assert_eq!(SizeHistogram::bucket(usize::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn record_and_forget() {
+        let mut histogram = SizeHistogram::new();
+        histogram.record(3);
+        histogram.record(2);
+        histogram.record(100);
+        histogram.forget(3);
+        histogram.forget(7);
+
+        assert_eq!(histogram.buckets[2], 1);
+        assert_eq!(histogram.buckets[7], 1);
+        assert_eq!(histogram.total(), 2);
+        assert_eq!(histogram.to_string(), "2+: 1, 64+: 1");
+        assert_eq!(SizeHistogram::new().to_string(), "empty");
+    }
+}
@@ -1,244 +1,1608 @@
+pub mod backend;
+pub mod builder;
 pub mod command;
 pub mod error;
+pub mod histogram;
 pub mod result;
+pub mod snapshot;
+pub mod value;
+use crate::protocol::PROTOCOL_VERSION;
+use crate::utils::{
+    bin_args::{ExpiryStrategy, KeyHashing},
+    build_info::{CRATE_VERSION, GIT_HASH},
+    clock::Clock,
+    hex::to_hex,
+};
+use backend::{KvBackend, MemoryBackend};
+use builder::StoreBuilder;
 use command::*;
 use error::*;
+use histogram::SizeHistogram;
 use monotree::*;
 use result::*;
 use sha2::{Digest, Sha256};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
 use std::result::Result as StdResult;
+use std::sync::Arc;
+use value::StoreValue;
+
+/// Reply to a command run against a key holding a value of another type
+const WRONG_TYPE: &str = "operation against a key holding the wrong kind of value";
+
+/// Estimated bytes a key costs beyond its own and its value bytes: the entries
+/// of the backend, version, creation and access maps and its monotree leaf
+const KEY_OVERHEAD: usize = 256;
+
+/// Estimated bytes the deadline of a volatile key costs beyond the key bytes
+const VOLATILE_KEY_OVERHEAD: usize = 64;
+
+/// A key with the value, deadline and version it had before a write that may
+/// have to be rolled back
+type PreviousEntry<'k> = (&'k str, Option<StoreValue>, Option<u128>, Option<u64>);
+
+/// How many keys an `update_full_store` added, removed and changed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UpdateSummary {
+    pub added: usize,
+    pub removed: usize,
+    /// keys present before and after but holding another value
+    pub changed: usize,
+}
 
-pub struct Store {
+/// Merkle-rooted key-value store, keeping its pairs in the backend `B`
+pub struct Store<B = MemoryBackend> {
     root: Option<Hash>,
     monotree: Monotree<DefaultDatabase, DefaultHasher>,
-    main_store: HashMap<String, String>,
+    /// number of monotree updates, a batch of inserts counts once
+    tree_writes: u64,
+    data: B,
+    /// expiration deadlines (unix millis) of volatile keys, ordered so the
+    /// reaper can resume from a key
+    expires: BTreeMap<String, u128>,
+    /// last volatile key checked by the reaper, it starts over when `None`
+    reaper_cursor: Option<String>,
+    /// number of volatile keys checked per reaper step
+    reaper_batch_size: usize,
+    /// keys lazy expiry removed for the last command run, see `take_expired`
+    touched_expired: Vec<String>,
+    /// number of writes and deletes of each key, kept as a tombstone once the
+    /// key is deleted so a recreated key never reuses a version, and shipped
+    /// along with a repair so every node agrees on them
+    versions: HashMap<String, u64>,
+    /// time (unix millis) each key was first set, kept on overwrite
+    created: HashMap<String, u128>,
+    /// time (unix millis) each key was last written or read
+    accessed: HashMap<String, u128>,
+    /// xor of the hashes of every key-value pair
+    fingerprint: u64,
+    /// lengths of the stored keys
+    key_sizes: SizeHistogram,
+    /// lengths of the stored values, see `StoreValue::size`
+    value_sizes: SizeHistogram,
+    key_hashing: KeyHashing,
+    expiry_strategy: ExpiryStrategy,
+    max_keys: Option<usize>,
+    max_value_size: Option<usize>,
+    /// longest key a write accepts, in bytes
+    max_key_bytes: Option<usize>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Store {
     pub fn new() -> Self {
-        Self {
-            monotree: Monotree::default(),
-            root: None,
-            main_store: HashMap::new(),
-        }
+        StoreBuilder::new().build()
+    }
+}
+
+impl<B: KvBackend> Store<B> {
+    /// Time source of the store, shared by the node serving it
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Whether expired keys should also be removed in the background
+    pub fn expiry_strategy(&self) -> ExpiryStrategy {
+        self.expiry_strategy
     }
 
     pub fn execute(&mut self, cmd: StoreCommand) -> StdResult<StoreCommandResult<'_>, StoreError> {
+        let now = self.clock.now_millis().ok_or(StoreError::TimestampError)?;
+        self.execute_at(cmd, now)
+    }
+
+    /// Runs the command, a write to a key over `max_key_bytes` is answered
+    /// with a `BadRequest` error and leaves the store untouched
+    fn execute_at(
+        &mut self,
+        cmd: StoreCommand,
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'_>, StoreError> {
+        let max_key_bytes = self.max_key_bytes;
+        match self.dispatch(cmd, now) {
+            Err(StoreError::KeyTooLong(len)) if max_key_bytes.is_some_and(|max| len > max) => {
+                Ok(StoreCommandResult::error(
+                    ErrorKind::BadRequest,
+                    format!("key of {len} bytes is longer than allowed"),
+                ))
+            }
+            result => result,
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        cmd: StoreCommand,
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'_>, StoreError> {
+        self.expire_touched(&cmd, now)?;
+
         match cmd {
             StoreCommand::DEL(DELParams { keys }) => {
                 let count = self.del(&keys)?;
-                Ok(StoreCommandResult::del(count))
+                Ok(StoreCommandResult::del(count, self.root))
             }
             StoreCommand::EXISTS(EXISTSParams { keys }) => {
-                let count = self.exists(&keys);
+                let count = self.exists(&keys)?;
                 Ok(StoreCommandResult::exists(count))
             }
+            StoreCommand::EXISTSALL(EXISTSParams { keys }) => {
+                Ok(StoreCommandResult::bool(self.contains_all(&keys)?))
+            }
+            StoreCommand::EXISTSANY(EXISTSParams { keys }) => {
+                Ok(StoreCommandResult::bool(self.contains_any(&keys)?))
+            }
             StoreCommand::GET(GETParams { key }) => {
-                let value = self.get(&key);
-                Ok(StoreCommandResult::get(value))
+                if self.data.get(&key)?.is_some_and(|v| v.as_str().is_none()) {
+                    return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE));
+                }
+                self.record_access(&key, now);
+                let value = self.get(&key)?;
+                Ok(StoreCommandResult::get(value, self.version(&key)))
             }
             StoreCommand::KEYS(KEYSParams { pattern }) => {
                 let keys = self.keys(&pattern)?;
                 Ok(StoreCommandResult::keys(keys))
             }
-            StoreCommand::SET(SETParams { key, value }) => {
-                let is_ok = self.set(&key, &value)?;
-                Ok(StoreCommandResult::set(is_ok))
+            StoreCommand::SET(SETParams {
+                key,
+                value,
+                return_old,
+            }) => {
+                if !return_old {
+                    let is_ok = self.set(&key, &value)?;
+                    self.record_write(&key, now);
+                    return Ok(StoreCommandResult::set(is_ok, self.root));
+                }
+                // like GET, a value of another type is left untouched
+                if self.data.get(&key)?.is_some_and(|v| v.as_str().is_none()) {
+                    return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE));
+                }
+                let old = self.get(&key)?.map(|old| old.into_owned());
+                self.set(&key, &value)?;
+                self.record_write(&key, now);
+                Ok(StoreCommandResult::get(old, self.version(&key)))
+            }
+            StoreCommand::EXPIRE(EXPIREParams { key, seconds }) => {
+                let is_set = self.expire(&key, seconds, now)?;
+                Ok(StoreCommandResult::expire(is_set))
+            }
+            StoreCommand::TTLS => Ok(StoreCommandResult::ttls(self.ttls(now))),
+            StoreCommand::CASV(CASVParams {
+                key,
+                expected_version,
+                value,
+            }) => {
+                let is_set = self.version(&key) == expected_version && self.set(&key, &value)?;
+                if is_set {
+                    self.record_write(&key, now);
+                }
+                Ok(StoreCommandResult::casv(is_set, self.version(&key)))
+            }
+            StoreCommand::OBJECT(OBJECTParams { subcommand, key }) => {
+                let seconds = self.object(subcommand, &key, now);
+                Ok(StoreCommandResult::int(seconds))
+            }
+            StoreCommand::INCRBY(INCRBYParams { key, amount }) => {
+                self.incr_by(&key, |value| value.saturating_add(amount), now)
+            }
+            StoreCommand::DECRBY(DECRBYParams { key, amount }) => {
+                self.incr_by(&key, |value| value.saturating_sub(amount), now)
+            }
+            StoreCommand::DUMPJSON => Ok(StoreCommandResult::dumpjson(self.to_json()?)),
+            StoreCommand::KEYHASHES => Ok(StoreCommandResult::keyhashes(self.key_hashes(None)?)),
+            StoreCommand::KEYHASHESMATCHING(KEYSParams { pattern }) => Ok(
+                StoreCommandResult::keyhashes(self.key_hashes(Some(&pattern))?),
+            ),
+            StoreCommand::TREEINFO => {
+                let depth = self.tree_depth()?;
+                Ok(StoreCommandResult::treeinfo(
+                    self.root,
+                    self.data.len(),
+                    depth,
+                ))
+            }
+            StoreCommand::LPUSH(LPUSHParams { key, values }) => {
+                self.push(&key, values.iter().map(|v| v.as_ref()), true, now)
+            }
+            StoreCommand::RPUSH(RPUSHParams { key, values }) => {
+                self.push(&key, values.iter().map(|v| v.as_ref()), false, now)
+            }
+            StoreCommand::LRANGE(LRANGEParams { key, start, stop }) => {
+                self.record_access(&key, now);
+                match self.data.get(&key)?.as_deref() {
+                    None => Ok(StoreCommandResult::lrange(Vec::<&str>::new())),
+                    Some(StoreValue::List(items)) => Ok(StoreCommandResult::lrange(
+                        list_range(items, start, stop).iter().cloned(),
+                    )),
+                    Some(_) => Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+                }
+            }
+            StoreCommand::LLEN(LLENParams { key }) => match self.data.get(&key)?.as_deref() {
+                None => Ok(StoreCommandResult::int(0)),
+                Some(StoreValue::List(items)) => Ok(StoreCommandResult::int(
+                    i64::try_from(items.len()).unwrap_or(i64::MAX),
+                )),
+                Some(_) => Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+            },
+            StoreCommand::HSET(HSETParams { key, field, value }) => {
+                self.hset(&key, &field, &value, now)
+            }
+            StoreCommand::HINCRBY(HINCRBYParams { key, field, amount }) => {
+                self.hincr_by(&key, &field, amount, now)
+            }
+            StoreCommand::MSETEX(MSETEXParams { seconds, pairs }) => {
+                let count = self.msetex(&pairs, seconds, now)?;
+                Ok(StoreCommandResult::int(
+                    i64::try_from(count).unwrap_or(i64::MAX),
+                ))
+            }
+            StoreCommand::HGET(HGETParams { key, field }) => {
+                self.record_access(&key, now);
+                match self.data.get(&key)?.as_deref() {
+                    None => Ok(StoreCommandResult::hget::<&str>(None)),
+                    Some(StoreValue::Hash(fields)) => Ok(StoreCommandResult::hget(
+                        fields.get(field.as_ref()).cloned(),
+                    )),
+                    Some(_) => Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+                }
+            }
+            StoreCommand::HGETALL(HGETALLParams { key }) => {
+                self.record_access(&key, now);
+                match self.data.get(&key)?.as_deref() {
+                    None => Ok(StoreCommandResult::hgetall(Vec::<(&str, &str)>::new())),
+                    Some(StoreValue::Hash(fields)) => {
+                        let sorted: BTreeMap<String, String> = fields.clone().into_iter().collect();
+                        Ok(StoreCommandResult::hgetall(sorted))
+                    }
+                    Some(_) => Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+                }
             }
+            StoreCommand::HDEL(HDELParams { key, fields }) => self.hdel(&key, &fields, now),
+            StoreCommand::SADD(SADDParams { key, members }) => self.sadd(&key, &members, now),
+            StoreCommand::SREM(SREMParams { key, members }) => self.srem(&key, &members, now),
+            StoreCommand::SMEMBERS(SMEMBERSParams { key }) => {
+                self.record_access(&key, now);
+                match self.data.get(&key)?.as_deref() {
+                    None => Ok(StoreCommandResult::smembers(Vec::<&str>::new())),
+                    Some(StoreValue::Set(members)) => {
+                        let sorted: BTreeSet<String> = members.iter().cloned().collect();
+                        Ok(StoreCommandResult::smembers(sorted))
+                    }
+                    Some(_) => Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+                }
+            }
+            StoreCommand::SISMEMBER(SISMEMBERParams { key, member }) => {
+                self.record_access(&key, now);
+                match self.data.get(&key)?.as_deref() {
+                    None => Ok(StoreCommandResult::int(0)),
+                    Some(StoreValue::Set(members)) => Ok(StoreCommandResult::int(i64::from(
+                        members.contains(member.as_ref()),
+                    ))),
+                    Some(_) => Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+                }
+            }
+            StoreCommand::VERSION => Ok(StoreCommandResult::version(
+                CRATE_VERSION,
+                GIT_HASH,
+                PROTOCOL_VERSION,
+            )),
+            StoreCommand::REBUILD => Ok(StoreCommandResult::rebuild(self.rebuild_tree()?)),
+            StoreCommand::PEERS => Ok(StoreCommandResult::error(
+                ErrorKind::BadRequest,
+                "PEERS is answered by the node, not by its store",
+            )),
+            StoreCommand::TRACKER => Ok(StoreCommandResult::error(
+                ErrorKind::BadRequest,
+                "TRACKER is answered by the node, not by its store",
+            )),
+            StoreCommand::MEMUSAGE => Ok(StoreCommandResult::int(
+                i64::try_from(self.memory_estimate()?).unwrap_or(i64::MAX),
+            )),
+            StoreCommand::FLUSH => Ok(StoreCommandResult::int(
+                i64::try_from(self.data.flush()?).unwrap_or(i64::MAX),
+            )),
+            StoreCommand::METRICS => Ok(StoreCommandResult::metrics(
+                self.key_sizes,
+                self.value_sizes,
+            )),
+            StoreCommand::RENAME(RENAMEParams { key, newkey }) => {
+                match self.rename(&key, &newkey, false, now)? {
+                    Some(is_renamed) => Ok(StoreCommandResult::set(is_renamed, self.root)),
+                    None => Ok(StoreCommandResult::error(
+                        ErrorKind::NotFound,
+                        "no such key",
+                    )),
+                }
+            }
+            StoreCommand::RENAMENX(RENAMEParams { key, newkey }) => {
+                match self.rename(&key, &newkey, true, now)? {
+                    Some(is_renamed) => Ok(StoreCommandResult::set(is_renamed, self.root)),
+                    None => Ok(StoreCommandResult::error(
+                        ErrorKind::NotFound,
+                        "no such key",
+                    )),
+                }
+            }
+            StoreCommand::TYPE(TYPEParams { key }) => Ok(StoreCommandResult::key_type(
+                self.data
+                    .get(&key)?
+                    .as_deref()
+                    .map_or("none", StoreValue::type_name),
+            )),
         }
     }
 
+    /// Removes the given keys and returns how many were actually removed.
+    ///
+    /// Like Redis, a key repeated in `keys` is counted once: after the first
+    /// occurrence removes it, the next ones find nothing and neither touch the
+    /// monotree nor the key version.
     fn del(&mut self, keys: &[Cow<'_, str>]) -> StdResult<usize, StoreError> {
         let mut removed = 0;
         for key in keys {
-            if self.main_store.remove(key.as_ref()).is_some() {
-                let key_hash: [u8; 32] = Sha256::digest(key.as_bytes()).into();
-                self.root = self
-                    .monotree
-                    .remove(self.root.as_ref(), &key_hash)
-                    .map_err(StoreError::from)?;
+            if self.remove(key)? {
                 removed += 1;
             }
         }
         Ok(removed)
     }
 
-    fn exists(&self, keys: &[Cow<'_, str>]) -> usize {
-        keys.iter()
-            .filter(|k| self.main_store.contains_key(k.as_ref()))
-            .count()
+    fn remove(&mut self, key: &str) -> StdResult<bool, StoreError> {
+        self.expires.remove(key);
+        self.created.remove(key);
+        self.accessed.remove(key);
+        let Some(value) = self.data.del(key)? else {
+            return Ok(false);
+        };
+        self.fingerprint ^= entry_fingerprint(key, &value);
+        self.key_sizes.forget(key.len());
+        self.value_sizes.forget(value.size());
+        self.bump_version(key);
+
+        let key_hash = self.key_hash(key)?;
+        self.tree_writes += 1;
+        self.root = self
+            .monotree
+            .remove(self.root.as_ref(), &key_hash)
+            .map_err(StoreError::from)?;
+        Ok(true)
+    }
+
+    /// Moves the value at key to newkey, overwriting newkey unless `only_new`
+    /// is set. The timeout and creation time of key move along, so a volatile
+    /// key keeps its countdown. `None` if key does not exist, `false` if newkey
+    /// was left untouched
+    fn rename(
+        &mut self,
+        key: &str,
+        newkey: &str,
+        only_new: bool,
+        now: u128,
+    ) -> StdResult<Option<bool>, StoreError> {
+        let Some(value) = self.data.get(key)?.map(Cow::into_owned) else {
+            return Ok(None);
+        };
+        if key == newkey {
+            return Ok(Some(!only_new));
+        }
+        if only_new && self.data.contains_key(newkey)? {
+            return Ok(Some(false));
+        }
+        // the only ways the put below can fail, checked before key is removed
+        self.check_key_length(newkey)?;
+        self.key_hash(newkey)?;
+
+        let deadline = self.expires.get(key).copied();
+        let created = self.created.get(key).copied();
+        self.remove(key)?;
+        self.put(newkey, value)?;
+        match deadline {
+            Some(deadline) => self.expires.insert(newkey.to_string(), deadline),
+            None => self.expires.remove(newkey),
+        };
+        match created {
+            Some(created) => self.created.insert(newkey.to_string(), created),
+            None => self.created.remove(newkey),
+        };
+        self.record_write(newkey, now);
+        Ok(Some(true))
     }
 
-    fn get(&self, key: &str) -> Option<&str> {
-        self.main_store.get(key).map(|s| s.as_str())
+    /// Whether every key exists, stops at the first missing one
+    pub fn contains_all<K: AsRef<str>>(&self, keys: &[K]) -> StdResult<bool, StoreError> {
+        for key in keys {
+            if !self.data.contains_key(key.as_ref())? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
-    fn keys(&self, pattern: &str) -> StdResult<Vec<&str>, StoreError> {
-        if pattern == "*" {
-            return Ok(self.main_store.keys().map(|k| k.as_str()).collect());
+    /// Whether at least one key exists, stops at the first existing one
+    pub fn contains_any<K: AsRef<str>>(&self, keys: &[K]) -> StdResult<bool, StoreError> {
+        for key in keys {
+            if self.data.contains_key(key.as_ref())? {
+                return Ok(true);
+            }
         }
+        Ok(false)
+    }
 
-        let regex_pattern = pattern.replace("*", ".*");
-        let re = regex::Regex::new(&format!("^{regex_pattern}$")).map_err(StoreError::from)?;
+    /// Approximate number of bytes the keys and values take in memory, with
+    /// the per key cost of the maps and the monotree. Values are counted
+    /// uncompressed whatever the backend, so it is an upper bound of what a
+    /// compressing or on-disk backend holds in memory
+    pub fn memory_estimate(&self) -> StdResult<usize, StoreError> {
+        let mut total = 0;
+        for entry in self.data.iter() {
+            let (key, value) = entry?;
+            // the key is also held by the version, creation and access maps
+            total += 4 * key.len() + value.heap_size() + KEY_OVERHEAD;
+        }
+        for key in self.expires.keys() {
+            total += key.len() + VOLATILE_KEY_OVERHEAD;
+        }
+        Ok(total)
+    }
 
-        Ok(self
-            .main_store
-            .keys()
-            .filter(|k| re.is_match(k))
-            .map(|k| k.as_str())
-            .collect())
+    fn exists(&self, keys: &[Cow<'_, str>]) -> StdResult<usize, StoreError> {
+        let mut count = 0;
+        for key in keys {
+            if self.data.contains_key(key)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// The string stored at key, `None` if it is missing or holds another type
+    fn get(&self, key: &str) -> StdResult<Option<Cow<'_, str>>, StoreError> {
+        Ok(match self.data.get(key)? {
+            Some(Cow::Borrowed(value)) => value.as_str().map(Cow::Borrowed),
+            Some(Cow::Owned(StoreValue::Str(value))) => Some(Cow::Owned(value)),
+            _ => None,
+        })
+    }
+
+    fn keys(&self, pattern: &str) -> StdResult<Vec<Cow<'_, str>>, StoreError> {
+        if pattern == "*" {
+            return self.data.keys().collect();
+        }
+
+        let re = glob_regex(pattern)?;
+        let mut keys = Vec::new();
+        for key in self.data.keys() {
+            let key = key?;
+            if re.is_match(&key) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
     }
 
     fn set(&mut self, key: &str, value: &str) -> StdResult<bool, StoreError> {
-        let key_hash: [u8; 32] = Sha256::digest(key.as_bytes()).into();
-        let value_hash: [u8; 32] = Sha256::digest(value.as_bytes()).into();
+        self.put(key, StoreValue::from(value))?;
+        self.expires.remove(key);
+        Ok(true)
+    }
+
+    /// Stores every pair like `set` but inserts them into the monotree in a
+    /// single batch, so the root is only recomputed once. Pairs stored before
+    /// one is rejected stay stored, as if they were set one by one
+    pub fn set_many<'k>(
+        &mut self,
+        pairs: impl IntoIterator<Item = (&'k str, StoreValue)>,
+    ) -> StdResult<(), StoreError> {
+        let mut keys: Vec<Hash> = Vec::new();
+        let mut leaves: Vec<Hash> = Vec::new();
+        // the batch is sorted by key, a repeated key must keep a single leaf
+        let mut positions: HashMap<Hash, usize> = HashMap::new();
+        let mut staged = Ok(());
+        for (key, value) in pairs {
+            let (key_hash, value_hash) = match self.stage(key, value) {
+                Ok(hashes) => hashes,
+                Err(e) => {
+                    staged = Err(e);
+                    break;
+                }
+            };
+            self.expires.remove(key);
+            match positions.get(&key_hash) {
+                Some(&position) => leaves[position] = value_hash,
+                None => {
+                    positions.insert(key_hash, keys.len());
+                    keys.push(key_hash);
+                    leaves.push(value_hash);
+                }
+            }
+        }
+
+        if !keys.is_empty() {
+            self.tree_writes += 1;
+            self.root = self
+                .monotree
+                .inserts(self.root.as_ref(), &keys, &leaves)
+                .map_err(StoreError::from)?;
+        }
+        staged
+    }
+
+    /// Sets every pair like `set_many` then gives all the keys the same
+    /// timeout, and returns how many keys were set. When a pair is rejected
+    /// the keys already set get back the value and timeout they had before
+    /// and the error is returned, so none of the pairs is applied
+    fn msetex(
+        &mut self,
+        pairs: &[(Cow<'_, str>, Cow<'_, str>)],
+        seconds: u64,
+        now: u128,
+    ) -> StdResult<usize, StoreError> {
+        let mut previous: Vec<PreviousEntry<'_>> = Vec::new();
+        for (key, _) in pairs {
+            let key = key.as_ref();
+            if previous.iter().any(|&(seen, ..)| seen == key) {
+                continue;
+            }
+            let value = self.data.get(key)?.map(Cow::into_owned);
+            let deadline = self.expires.get(key).copied();
+            previous.push((key, value, deadline, self.versions.get(key).copied()));
+        }
+
+        let staged = self.set_many(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.as_ref(), StoreValue::from(value.as_ref()))),
+        );
+        if let Err(e) = staged {
+            for (key, value, deadline, version) in previous {
+                match value {
+                    Some(value) => self.put(key, value)?,
+                    None => {
+                        self.remove(key)?;
+                    }
+                }
+                if let Some(deadline) = deadline {
+                    self.expires.insert(key.to_string(), deadline);
+                }
+                match version {
+                    Some(version) => self.versions.insert(key.to_string(), version),
+                    None => self.versions.remove(key),
+                };
+            }
+            return Err(e);
+        }
+
+        let deadline = now + u128::from(seconds) * 1000;
+        for &(key, ..) in &previous {
+            self.expires.insert(key.to_string(), deadline);
+            self.record_write(key, now);
+        }
+        Ok(previous.len())
+    }
+
+    /// Loads string pairs in a single monotree batch and returns how many were
+    /// loaded, for seeding a store at startup or in benchmarks. It bypasses
+    /// `execute`: no command or result is built, no audit record or key change
+    /// is published and peers only catch up through a repair
+    pub fn bulk_load(
+        &mut self,
+        pairs: impl Iterator<Item = (String, String)>,
+    ) -> StdResult<usize, StoreError> {
+        let pairs: Vec<(String, String)> = pairs.collect();
+        let now = self.clock.now_millis().ok_or(StoreError::TimestampError)?;
+        self.set_many(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.as_str(), StoreValue::from(value.as_str()))),
+        )?;
+        for (key, _) in &pairs {
+            self.record_write(key, now);
+        }
+        Ok(pairs.len())
+    }
 
-        self.main_store.insert(key.to_string(), value.to_string());
+    /// Stores the value at key whatever it held before, keeping its timeout
+    fn put(&mut self, key: &str, value: StoreValue) -> StdResult<(), StoreError> {
+        let (key_hash, value_hash) = self.stage(key, value)?;
+        self.tree_writes += 1;
         self.root = self
             .monotree
             .insert(self.root.as_ref(), &key_hash, &value_hash)
             .map_err(StoreError::from)?;
 
-        Ok(true)
+        Ok(())
     }
 
-    pub fn reveal_root(&self) -> Option<Hash> {
-        self.root
+    /// Everything `put` does but the monotree insert, returns the monotree key
+    /// and leaf to insert
+    fn stage(&mut self, key: &str, value: StoreValue) -> StdResult<(Hash, Hash), StoreError> {
+        self.check_key_length(key)?;
+        let key_hash = self.key_hash(key)?;
+        self.check_limits(key, &value)?;
+        let value_size = value.size();
+        let value_hash: [u8; 32] = Sha256::digest(value.canonical_encode()).into();
+
+        self.fingerprint ^= entry_fingerprint(key, &value);
+        match self.data.set(key, value)? {
+            Some(old) => {
+                self.fingerprint ^= entry_fingerprint(key, &old);
+                self.value_sizes.forget(old.size());
+            }
+            None => self.key_sizes.record(key.len()),
+        }
+        self.value_sizes.record(value_size);
+        self.bump_version(key);
+
+        Ok((key_hash, value_hash))
     }
 
-    pub fn get_main_store(&self) -> HashMap<String, String> {
-        self.main_store.clone()
+    /// Adds the values to the head (`front`) or the tail of the list at key,
+    /// creating it if needed, and replies with the new length. Like Redis the
+    /// values are pushed one by one, so `LPUSH` leaves them in reverse order
+    fn push<'v>(
+        &mut self,
+        key: &str,
+        values: impl Iterator<Item = &'v str>,
+        front: bool,
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'static>, StoreError> {
+        let mut items = match self.data.get(key)?.as_deref() {
+            None => Vec::new(),
+            Some(StoreValue::List(items)) => items.clone(),
+            Some(_) => return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+        };
+        for value in values {
+            if front {
+                items.insert(0, value.to_string());
+            } else {
+                items.push(value.to_string());
+            }
+        }
+        let len = i64::try_from(items.len()).unwrap_or(i64::MAX);
+        self.put(key, StoreValue::List(items))?;
+        self.record_write(key, now);
+        Ok(StoreCommandResult::int(len))
     }
 
-    pub fn update_full_store(
+    /// Applies `step` to the integer stored at key, a missing key counts as `0`.
+    /// The counters saturate at the `i64` bounds instead of overflowing, and
+    /// like the other in-place updates keep the timeout of the key
+    fn incr_by(
         &mut self,
-        main_store: HashMap<String, String>,
-    ) -> std::result::Result<(), StoreError> {
-        self.main_store = main_store.clone();
-        self.monotree = Monotree::default();
-        self.root = None;
+        key: &str,
+        step: impl FnOnce(i64) -> i64,
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'static>, StoreError> {
+        let current = match self.data.get(key)?.as_deref() {
+            None => 0,
+            Some(StoreValue::Str(value)) => match value.parse::<i64>() {
+                Ok(value) => value,
+                Err(_) => {
+                    return Ok(StoreCommandResult::error(
+                        ErrorKind::Conflict,
+                        "value is not an integer",
+                    ));
+                }
+            },
+            Some(_) => return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+        };
+        let value = step(current);
+        self.put(key, StoreValue::from(value.to_string()))?;
+        self.record_write(key, now);
+        Ok(StoreCommandResult::int(value))
+    }
+
+    /// Sets field in the hash at key, creating it if needed, and replies `1` if
+    /// the field is new or `0` if its value was overwritten
+    fn hset(
+        &mut self,
+        key: &str,
+        field: &str,
+        value: &str,
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'static>, StoreError> {
+        let mut fields = match self.data.get(key)?.as_deref() {
+            None => HashMap::new(),
+            Some(StoreValue::Hash(fields)) => fields.clone(),
+            Some(_) => return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+        };
+        let added = fields
+            .insert(field.to_string(), value.to_string())
+            .is_none();
+        self.put(key, StoreValue::Hash(fields))?;
+        self.record_write(key, now);
+        Ok(StoreCommandResult::int(i64::from(added)))
+    }
 
-        for (key, value) in main_store.iter() {
-            self.set(key, value)?;
+    /// Adds amount to the integer in field of the hash at key, creating the hash
+    /// and the field at `0` if needed, and replies with the new value. Saturates
+    /// at the `i64` bounds like `incr_by`
+    fn hincr_by(
+        &mut self,
+        key: &str,
+        field: &str,
+        amount: i64,
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'static>, StoreError> {
+        let mut fields = match self.data.get(key)?.as_deref() {
+            None => HashMap::new(),
+            Some(StoreValue::Hash(fields)) => fields.clone(),
+            Some(_) => return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+        };
+        let current = match fields.get(field).map(|value| value.parse::<i64>()) {
+            None => 0,
+            Some(Ok(value)) => value,
+            Some(Err(_)) => {
+                return Ok(StoreCommandResult::error(
+                    ErrorKind::Conflict,
+                    "hash value is not an integer",
+                ));
+            }
+        };
+        let value = current.saturating_add(amount);
+        fields.insert(field.to_string(), value.to_string());
+        self.put(key, StoreValue::Hash(fields))?;
+        self.record_write(key, now);
+        Ok(StoreCommandResult::int(value))
+    }
+
+    /// Removes the fields from the hash at key and replies with how many were
+    /// removed, a hash left without fields is removed altogether
+    fn hdel(
+        &mut self,
+        key: &str,
+        fields: &[Cow<'_, str>],
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'static>, StoreError> {
+        let mut hash = match self.data.get(key)?.as_deref() {
+            None => return Ok(StoreCommandResult::int(0)),
+            Some(StoreValue::Hash(hash)) => hash.clone(),
+            Some(_) => return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+        };
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(field.as_ref()).is_some())
+            .count();
+        if removed > 0 {
+            if hash.is_empty() {
+                self.remove(key)?;
+            } else {
+                self.put(key, StoreValue::Hash(hash))?;
+                self.record_write(key, now);
+            }
         }
-        Ok(())
+        Ok(StoreCommandResult::int(
+            i64::try_from(removed).unwrap_or(i64::MAX),
+        ))
     }
-}
 
-impl Default for Store {
-    fn default() -> Self {
-        Self::new()
+    /// Adds the members to the set at key, creating it if needed, and replies
+    /// with how many were not already there
+    fn sadd(
+        &mut self,
+        key: &str,
+        members: &[Cow<'_, str>],
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'static>, StoreError> {
+        let mut set = match self.data.get(key)?.as_deref() {
+            None => HashSet::new(),
+            Some(StoreValue::Set(set)) => set.clone(),
+            Some(_) => return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+        };
+        let added = members
+            .iter()
+            .filter(|member| set.insert(member.to_string()))
+            .count();
+        // an unchanged set keeps its root and its version
+        if added > 0 {
+            self.put(key, StoreValue::Set(set))?;
+            self.record_write(key, now);
+        }
+        Ok(StoreCommandResult::int(
+            i64::try_from(added).unwrap_or(i64::MAX),
+        ))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::borrow::Cow;
-    use std::result::Result as StdResult;
+    /// Removes the members from the set at key and replies with how many were
+    /// removed, a set left without members is removed altogether
+    fn srem(
+        &mut self,
+        key: &str,
+        members: &[Cow<'_, str>],
+        now: u128,
+    ) -> StdResult<StoreCommandResult<'static>, StoreError> {
+        let mut set = match self.data.get(key)?.as_deref() {
+            None => return Ok(StoreCommandResult::int(0)),
+            Some(StoreValue::Set(set)) => set.clone(),
+            Some(_) => return Ok(StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)),
+        };
+        let removed = members
+            .iter()
+            .filter(|member| set.remove(member.as_ref()))
+            .count();
+        if removed > 0 {
+            if set.is_empty() {
+                self.remove(key)?;
+            } else {
+                self.put(key, StoreValue::Set(set))?;
+                self.record_write(key, now);
+            }
+        }
+        Ok(StoreCommandResult::int(
+            i64::try_from(removed).unwrap_or(i64::MAX),
+        ))
+    }
 
-    fn set_keys(store: &mut Store, pairs: &[(&str, &str)]) -> StdResult<(), StoreError> {
-        for &(k, v) in pairs {
-            store.execute(StoreCommand::SET(SETParams {
-                key: Cow::Borrowed(k),
-                value: Cow::Borrowed(v),
-            }))?;
+    fn check_limits(&self, key: &str, value: &StoreValue) -> StdResult<(), StoreError> {
+        let size = value.size();
+        if let Some(max_value_size) = self.max_value_size
+            && size > max_value_size
+        {
+            return Err(StoreError::ValueTooLarge(size));
+        }
+        if let Some(max_keys) = self.max_keys
+            && self.data.len() >= max_keys
+            && !self.data.contains_key(key)?
+        {
+            return Err(StoreError::KeyLimitReached(max_keys));
         }
         Ok(())
     }
 
-    #[test]
-    fn test_set_and_get_basic() -> StdResult<(), StoreError> {
-        let mut store = Store::new();
+    /// Rejects keys over `max_key_bytes`, before anything is written
+    fn check_key_length(&self, key: &str) -> StdResult<(), StoreError> {
+        match self.max_key_bytes {
+            Some(max_key_bytes) if key.len() > max_key_bytes => {
+                Err(StoreError::KeyTooLong(key.len()))
+            }
+            _ => Ok(()),
+        }
+    }
 
-        // SET
-        let result = store.execute(StoreCommand::SET(SETParams {
-            key: Cow::Borrowed("view"),
-            value: Cow::Borrowed("different"),
-        }))?;
-        assert_eq!(result, StoreCommandResult::set(true));
+    /// Monotree key of `key` according to the configured hashing
+    fn key_hash(&self, key: &str) -> StdResult<Hash, StoreError> {
+        match self.key_hashing {
+            KeyHashing::Sha256 => Ok(Sha256::digest(key.as_bytes()).into()),
+            KeyHashing::Raw => {
+                let bytes = key.as_bytes();
+                if bytes.len() > 32 {
+                    return Err(StoreError::KeyTooLong(bytes.len()));
+                }
+                let mut hash = [0u8; 32];
+                hash[..bytes.len()].copy_from_slice(bytes);
+                Ok(hash)
+            }
+        }
+    }
 
-        // GET existing
-        let result = store.execute(StoreCommand::GET(GETParams {
-            key: Cow::Borrowed("view"),
-        }))?;
-        assert_eq!(result, StoreCommandResult::get(Some("different")));
+    fn version(&self, key: &str) -> u64 {
+        self.versions.get(key).copied().unwrap_or(0)
+    }
 
-        // GET non-existent
-        let result = store.execute(StoreCommand::GET(GETParams {
-            key: Cow::Borrowed("nope"),
-        }))?;
-        assert_eq!(result, StoreCommandResult::get::<&str>(None));
+    /// Versions of the keys matching the glob `pattern`, the deleted ones
+    /// included, sent along with a repair
+    pub fn versions_of(&self, pattern: &str) -> StdResult<HashMap<String, u64>, StoreError> {
+        let re = glob_regex(pattern)?;
+        Ok(self
+            .versions
+            .iter()
+            .filter(|(key, _)| re.is_match(key))
+            .map(|(key, &version)| (key.clone(), version))
+            .collect())
+    }
 
+    /// Replaces the versions of the keys matching the glob `pattern` with the
+    /// ones of a repairing peer, so a `CASV` expecting a version succeeds or
+    /// fails on every node alike
+    pub fn adopt_versions(
+        &mut self,
+        pattern: &str,
+        versions: HashMap<String, u64>,
+    ) -> StdResult<(), StoreError> {
+        let re = glob_regex(pattern)?;
+        self.versions.retain(|key, _| !re.is_match(key));
+        self.versions
+            .extend(versions.into_iter().filter(|(key, _)| re.is_match(key)));
         Ok(())
     }
 
-    #[test]
-    fn test_set_checking_for_overwriting() -> StdResult<(), StoreError> {
-        let mut store = Store::new();
+    fn bump_version(&mut self, key: &str) {
+        *self.versions.entry(key.to_string()).or_insert(0) += 1;
+    }
 
-        store.execute(StoreCommand::SET(SETParams {
-            key: Cow::Borrowed("view"),
-            value: Cow::Borrowed("different"),
-        }))?;
+    fn record_write(&mut self, key: &str, now: u128) {
+        self.created.entry(key.to_string()).or_insert(now);
+        self.accessed.insert(key.to_string(), now);
+    }
 
-        let result = store.execute(StoreCommand::GET(GETParams {
-            key: Cow::Borrowed("view"),
-        }))?;
-        assert_eq!(result, StoreCommandResult::get(Some("different")));
+    fn record_access(&mut self, key: &str, now: u128) {
+        if let Some(accessed) = self.accessed.get_mut(key) {
+            *accessed = now;
+        }
+    }
 
-        store.execute(StoreCommand::SET(SETParams {
-            key: Cow::Borrowed("view"),
-            value: Cow::Borrowed("another"),
-        }))?;
+    /// Seconds since creation or last access of the key, `-2` if it does not exist
+    fn object(&self, subcommand: ObjectSubcommand, key: &str, now: u128) -> i64 {
+        let since = match subcommand {
+            ObjectSubcommand::AGE => self.created.get(key),
+            ObjectSubcommand::IDLETIME => self.accessed.get(key),
+        };
+        match since {
+            Some(&since) => i64::try_from(now.saturating_sub(since) / 1000).unwrap_or(i64::MAX),
+            None => -2,
+        }
+    }
 
-        let result = store.execute(StoreCommand::GET(GETParams {
-            key: Cow::Borrowed("view"),
-        }))?;
-        assert_eq!(result, StoreCommandResult::get(Some("another")));
+    fn expire(&mut self, key: &str, seconds: u64, now: u128) -> StdResult<bool, StoreError> {
+        if !self.data.contains_key(key)? {
+            return Ok(false);
+        }
+        let deadline = now + u128::from(seconds) * 1000;
+        self.expires.insert(key.to_string(), deadline);
+        Ok(true)
+    }
+
+    fn ttls(&self, now: u128) -> Vec<(&str, u64)> {
+        let mut ttls: Vec<(&str, u64)> = self
+            .expires
+            .iter()
+            .filter(|&(_, &deadline)| deadline > now)
+            .map(|(key, &deadline)| {
+                let remaining = (deadline - now).div_ceil(1000);
+                (key.as_str(), u64::try_from(remaining).unwrap_or(u64::MAX))
+            })
+            .collect();
+        ttls.sort_unstable();
+        ttls
+    }
 
+    /// Lazy expiry: removes the expired keys the command is about to touch, the
+    /// whole keyspace is only swept by commands listing it
+    fn expire_touched(&mut self, cmd: &StoreCommand, now: u128) -> StdResult<(), StoreError> {
+        self.touched_expired.clear();
+        match cmd {
+            StoreCommand::DEL(DELParams { keys })
+            | StoreCommand::EXISTS(EXISTSParams { keys })
+            | StoreCommand::EXISTSALL(EXISTSParams { keys })
+            | StoreCommand::EXISTSANY(EXISTSParams { keys }) => {
+                for key in keys {
+                    self.expire_if_due(key, now)?;
+                }
+            }
+            StoreCommand::MSETEX(MSETEXParams { pairs, .. }) => {
+                for (key, _) in pairs {
+                    self.expire_if_due(key, now)?;
+                }
+            }
+            StoreCommand::GET(GETParams { key })
+            | StoreCommand::SET(SETParams { key, .. })
+            | StoreCommand::EXPIRE(EXPIREParams { key, .. })
+            | StoreCommand::CASV(CASVParams { key, .. })
+            | StoreCommand::OBJECT(OBJECTParams { key, .. })
+            | StoreCommand::INCRBY(INCRBYParams { key, .. })
+            | StoreCommand::DECRBY(DECRBYParams { key, .. })
+            | StoreCommand::LPUSH(LPUSHParams { key, .. })
+            | StoreCommand::RPUSH(RPUSHParams { key, .. })
+            | StoreCommand::LRANGE(LRANGEParams { key, .. })
+            | StoreCommand::LLEN(LLENParams { key })
+            | StoreCommand::TYPE(TYPEParams { key })
+            | StoreCommand::HSET(HSETParams { key, .. })
+            | StoreCommand::HINCRBY(HINCRBYParams { key, .. })
+            | StoreCommand::HGET(HGETParams { key, .. })
+            | StoreCommand::HGETALL(HGETALLParams { key })
+            | StoreCommand::HDEL(HDELParams { key, .. })
+            | StoreCommand::SADD(SADDParams { key, .. })
+            | StoreCommand::SREM(SREMParams { key, .. })
+            | StoreCommand::SMEMBERS(SMEMBERSParams { key })
+            | StoreCommand::SISMEMBER(SISMEMBERParams { key, .. }) => {
+                self.expire_if_due(key, now)?;
+            }
+            StoreCommand::KEYS(_)
+            | StoreCommand::TTLS
+            | StoreCommand::TREEINFO
+            | StoreCommand::DUMPJSON
+            | StoreCommand::KEYHASHES
+            | StoreCommand::KEYHASHESMATCHING(_)
+            | StoreCommand::METRICS
+            | StoreCommand::REBUILD
+            | StoreCommand::MEMUSAGE => {
+                let swept = self.sweep_expired(now)?;
+                self.touched_expired.extend(swept);
+            }
+            StoreCommand::RENAME(RENAMEParams { key, newkey })
+            | StoreCommand::RENAMENX(RENAMEParams { key, newkey }) => {
+                self.expire_if_due(key, now)?;
+                self.expire_if_due(newkey, now)?;
+            }
+            StoreCommand::VERSION
+            | StoreCommand::PEERS
+            | StoreCommand::FLUSH
+            | StoreCommand::TRACKER => {}
+        }
         Ok(())
     }
 
-    #[test]
-    fn test_exists_exact_match() -> StdResult<(), StoreError> {
-        let mut store = Store::new();
-        set_keys(
-            &mut store,
-            &[
-                ("first", "some_data"),
-                ("second", "some_data"),
-                ("third", "some_data"),
-                ("fourth", "some_data"),
-                ("fifth", "some_data"),
-            ],
-        )?;
+    fn expire_if_due(&mut self, key: &str, now: u128) -> StdResult<bool, StoreError> {
+        let due = self.expires.get(key).is_some_and(|&deadline| deadline <= now);
+        if due && self.remove(key)? {
+            self.touched_expired.push(key.to_string());
+            return Ok(true);
+        }
+        Ok(false)
+    }
 
-        let result = store.execute(StoreCommand::EXISTS(EXISTSParams {
-            keys: vec![
-                Cow::Borrowed("first"),
-                Cow::Borrowed("second"),
-                Cow::Borrowed("third"),
-                Cow::Borrowed("fourth"),
-                Cow::Borrowed("fifth"),
-            ],
-        }))?;
+    /// Keys lazy expiry removed while running the last command, so the node
+    /// can report them like the ones of the active expiry
+    pub fn take_expired(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.touched_expired)
+    }
 
-        assert_eq!(result, StoreCommandResult::exists(5));
-        Ok(())
+    /// Active expiry: removes every key whose deadline has passed, returning how
+    /// many were removed
+    pub fn purge_expired(&mut self) -> StdResult<usize, StoreError> {
+        let now = self.clock.now_millis().ok_or(StoreError::TimestampError)?;
+        Ok(self.sweep_expired(now)?.len())
     }
 
-    #[test]
-    fn test_exists_partial_match() -> StdResult<(), StoreError> {
-        let mut store = Store::new();
-        set_keys(
-            &mut store,
-            &[
-                ("first", "some_data"),
-                ("second", "some_data"),
-                ("third", "some_data"),
+    /// Incremental active expiry: checks the next `reaper_batch_size` volatile
+    /// keys after the cursor and removes the expired ones, so a large keyspace
+    /// is reaped over several calls without holding the store for long.
+    /// Returns the removed keys
+    pub fn reap_expired(&mut self) -> StdResult<Vec<String>, StoreError> {
+        let now = self.clock.now_millis().ok_or(StoreError::TimestampError)?;
+        self.reap_expired_at(now)
+    }
+
+    fn reap_expired_at(&mut self, now: u128) -> StdResult<Vec<String>, StoreError> {
+        let start = match &self.reaper_cursor {
+            Some(cursor) => Bound::Excluded(cursor.as_str()),
+            None => Bound::Unbounded,
+        };
+        let batch: Vec<(String, u128)> = self
+            .expires
+            .range::<str, _>((start, Bound::Unbounded))
+            .take(self.reaper_batch_size)
+            .map(|(key, &deadline)| (key.clone(), deadline))
+            .collect();
+
+        // once the last volatile key is checked the next step starts over
+        self.reaper_cursor = batch.last().map(|(key, _)| key.clone()).filter(|last| {
+            let after = (Bound::Excluded(last.as_str()), Bound::Unbounded);
+            self.expires.range::<str, _>(after).next().is_some()
+        });
+
+        let mut removed = Vec::new();
+        for (key, deadline) in batch {
+            if deadline <= now && self.remove(&key)? {
+                removed.push(key);
+            }
+        }
+        Ok(removed)
+    }
+
+    fn sweep_expired(&mut self, now: u128) -> StdResult<Vec<String>, StoreError> {
+        let expired: Vec<String> = self
+            .expires
+            .iter()
+            .filter(|&(_, &deadline)| deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            self.remove(key)?;
+        }
+        Ok(expired)
+    }
+
+    /// Length of the longest root-to-leaf path, taken from the merkle proof of every key
+    fn tree_depth(&mut self) -> StdResult<usize, StoreError> {
+        let mut depth = 0;
+        for key in self.data.keys() {
+            let key_hash = self.key_hash(&key?)?;
+            let proof = self
+                .monotree
+                .get_merkle_proof(self.root.as_ref(), &key_hash)
+                .map_err(StoreError::from)?;
+            depth = depth.max(proof.map_or(0, |p| p.len()));
+        }
+        Ok(depth)
+    }
+
+    pub fn reveal_root(&self) -> Option<Hash> {
+        self.root
+    }
+
+    /// Order-independent digest of the whole store, kept up to date on every
+    /// write. Much cheaper to compare than the root but may collide, so equal
+    /// fingerprints still need the roots to be compared
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// Number of monotree updates so far, a `set_many` batch counts once
+    pub fn tree_writes(&self) -> u64 {
+        self.tree_writes
+    }
+
+    /// SHA-256 of the value of every key, or of the keys matching the glob
+    /// `pattern` only, lets two stores be compared without sending the values
+    fn key_hashes(
+        &self,
+        pattern: Option<&str>,
+    ) -> StdResult<HashMap<String, [u8; 32]>, StoreError> {
+        let re = pattern.map(glob_regex).transpose()?;
+        let mut hashes = HashMap::new();
+        for entry in self.data.iter() {
+            let (key, value) = entry?;
+            if re.as_ref().is_none_or(|re| re.is_match(&key)) {
+                let hash = Sha256::digest(value.canonical_encode()).into();
+                hashes.insert(key.into_owned(), hash);
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// SHA-256 of the value at key, the hash `KEYHASHES` reports
+    pub fn value_hash(&self, key: &str) -> StdResult<Option<[u8; 32]>, StoreError> {
+        Ok(self
+            .data
+            .get(key)?
+            .map(|value| Sha256::digest(value.canonical_encode()).into()))
+    }
+
+    /// Human-readable dump of the keys and the root, not meant to be loaded back
+    pub fn to_json(&self) -> StdResult<String, StoreError> {
+        let mut keys: BTreeMap<String, serde_json::Value> = BTreeMap::new();
+        for entry in self.data.iter() {
+            let (key, value) = entry?;
+            keys.insert(key.into_owned(), value.to_json());
+        }
+        let dump = serde_json::json!({
+            "root": self.root.map(|root| to_hex(&root)),
+            "keys": keys,
+        });
+        Ok(serde_json::to_string_pretty(&dump).expect("string maps always serialize"))
+    }
+
+    /// Copy of every pair, what a full repair sends
+    pub fn get_main_store(&self) -> StdResult<HashMap<String, StoreValue>, StoreError> {
+        self.data
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((key.into_owned(), value.into_owned()))
+            })
+            .collect()
+    }
+
+    /// Rebuilds the monotree, the fingerprint and the size histograms from the
+    /// pairs the backend already holds, e.g. a sled database opened again
+    fn index_backend(&mut self) -> StdResult<(), StoreError> {
+        let mut keys: Vec<Hash> = Vec::new();
+        let mut leaves: Vec<Hash> = Vec::new();
+        for entry in self.data.iter() {
+            let (key, value) = entry?;
+            keys.push(self.key_hash(&key)?);
+            leaves.push(Sha256::digest(value.canonical_encode()).into());
+            self.fingerprint ^= entry_fingerprint(&key, &value);
+            self.key_sizes.record(key.len());
+            self.value_sizes.record(value.size());
+        }
+
+        if !keys.is_empty() {
+            self.tree_writes += 1;
+            self.root = self
+                .monotree
+                .inserts(self.root.as_ref(), &keys, &leaves)
+                .map_err(StoreError::from)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the monotree and inserts every stored value again, returning the
+    /// new root. Removals leave the tree shaped by the history of the store,
+    /// so the root afterwards may differ from the previous one, but it is the
+    /// root of a store holding the same values built from scratch
+    pub fn rebuild_tree(&mut self) -> StdResult<Option<Hash>, StoreError> {
+        self.monotree = Monotree::default();
+        self.root = None;
+        self.fingerprint = 0;
+        self.key_sizes = SizeHistogram::new();
+        self.value_sizes = SizeHistogram::new();
+        self.index_backend()?;
+        Ok(self.root)
+    }
+
+    /// Replaces the whole store with `main_store`, as a full repair does, and
+    /// reports how far the previous content was from it
+    pub fn update_full_store(
+        &mut self,
+        main_store: HashMap<String, StoreValue>,
+    ) -> std::result::Result<UpdateSummary, StoreError> {
+        let mut dropped: Vec<String> = Vec::new();
+        for key in self.data.keys() {
+            let key = key?;
+            if !main_store.contains_key(key.as_ref()) {
+                dropped.push(key.into_owned());
+            }
+        }
+        let mut summary = UpdateSummary {
+            removed: dropped.len(),
+            ..UpdateSummary::default()
+        };
+        let mut unchanged: HashSet<&str> = HashSet::new();
+        for (key, value) in &main_store {
+            match self.data.get(key)? {
+                None => summary.added += 1,
+                Some(old) if *old != *value => summary.changed += 1,
+                Some(_) => {
+                    unchanged.insert(key);
+                }
+            }
+        }
+
+        self.data.clear()?;
+        self.monotree = Monotree::default();
+        self.root = None;
+        self.fingerprint = 0;
+        self.key_sizes = SizeHistogram::new();
+        self.value_sizes = SizeHistogram::new();
+
+        let now = self.clock.now_millis().ok_or(StoreError::TimestampError)?;
+        let expires = std::mem::take(&mut self.expires);
+        let versions = std::mem::take(&mut self.versions);
+        self.set_many(
+            main_store
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.clone())),
+        )?;
+        // the data was cleared, so every key was staged as a new one, the
+        // dropped keys are tombstoned like on a delete
+        self.versions = versions;
+        for key in main_store.keys().chain(&dropped) {
+            if !unchanged.contains(key.as_str()) {
+                self.bump_version(key);
+            }
+        }
+        for key in main_store.keys() {
+            self.record_write(key, now);
+        }
+        self.expires = expires
+            .into_iter()
+            .filter(|(key, _)| main_store.contains_key(key))
+            .collect();
+        self.created.retain(|key, _| main_store.contains_key(key));
+        self.accessed.retain(|key, _| main_store.contains_key(key));
+        Ok(summary)
+    }
+
+    /// The entries whose key matches the glob `pattern`, what a partial repair sends
+    pub fn get_matching(
+        &self,
+        pattern: &str,
+    ) -> StdResult<HashMap<String, StoreValue>, StoreError> {
+        let re = glob_regex(pattern)?;
+        let mut matching = HashMap::new();
+        for entry in self.data.iter() {
+            let (key, value) = entry?;
+            if re.is_match(&key) {
+                matching.insert(key.into_owned(), value.into_owned());
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Replaces the keys matching the glob `pattern` with `data` and leaves the
+    /// other keys untouched, entries of `data` outside the pattern are ignored.
+    /// Keys already holding the received value are not written again
+    pub fn merge_matching(
+        &mut self,
+        pattern: &str,
+        data: HashMap<String, StoreValue>,
+    ) -> StdResult<(), StoreError> {
+        let re = glob_regex(pattern)?;
+        let mut stale: Vec<String> = Vec::new();
+        for key in self.data.keys() {
+            let key = key?;
+            if re.is_match(&key) && !data.contains_key(key.as_ref()) {
+                stale.push(key.into_owned());
+            }
+        }
+        for key in &stale {
+            self.remove(key)?;
+        }
+
+        let now = self.clock.now_millis().ok_or(StoreError::TimestampError)?;
+        let mut changed: Vec<(String, StoreValue)> = Vec::new();
+        for (key, value) in data {
+            if re.is_match(&key) && self.data.get(&key)?.as_deref() != Some(&value) {
+                changed.push((key, value));
+            }
+        }
+        // repaired keys keep their timeout, like with a full repair
+        let expires: Vec<(String, u128)> = changed
+            .iter()
+            .filter_map(|(key, _)| Some((key.clone(), *self.expires.get(key)?)))
+            .collect();
+        self.set_many(
+            changed
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.clone())),
+        )?;
+        for (key, _) in &changed {
+            self.record_write(key, now);
+        }
+        self.expires.extend(expires);
+        Ok(())
+    }
+}
+
+/// Regex matching the keys selected by a glob pattern, where `*` matches any
+/// sequence of characters
+fn glob_regex(pattern: &str) -> StdResult<regex::Regex, StoreError> {
+    let regex_pattern = pattern.replace("*", ".*");
+    regex::Regex::new(&format!("^{regex_pattern}$")).map_err(StoreError::from)
+}
+
+fn entry_fingerprint(key: &str, value: &StoreValue) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(key.as_bytes()));
+    hasher.update(Sha256::digest(value.canonical_encode()));
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("sha256 digest is 32 bytes"))
+}
+
+/// Elements from `start` to `stop` inclusive, negative indexes count from the
+/// end of the list and out of range indexes are clamped like in Redis
+fn list_range(items: &[String], start: i64, stop: i64) -> &[String] {
+    let len = i64::try_from(items.len()).unwrap_or(i64::MAX);
+    let resolve = |index: i64| if index < 0 { len + index } else { index };
+    let start = resolve(start).max(0);
+    let stop = resolve(stop).min(len - 1);
+    if start > stop {
+        return &[];
+    }
+    // both bounds are within 0..len here
+    &items[start as usize..=stop as usize]
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+    use std::borrow::Cow;
+    use std::result::Result as StdResult;
+    use std::time::Duration;
+
+    fn set_keys(store: &mut Store, pairs: &[(&str, &str)]) -> StdResult<(), StoreError> {
+        for &(k, v) in pairs {
+            store.execute(StoreCommand::SET(SETParams {
+                key: Cow::Borrowed(k),
+                value: Cow::Borrowed(v),
+                return_old: false,
+            }))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_many_batches_monotree_inserts() -> StdResult<(), StoreError> {
+        let pairs: Vec<(String, String)> = (0..50)
+            .map(|i| (format!("key{i}"), format!("value{i}")))
+            .collect();
+
+        let mut one_by_one = Store::new();
+        for (key, value) in &pairs {
+            set_keys(&mut one_by_one, &[(key, value)])?;
+        }
+        let mut batched = Store::new();
+        batched.set_many(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.as_str(), StoreValue::from(value.as_str()))),
+        )?;
+
+        assert_eq!(one_by_one.tree_writes(), 50);
+        assert_eq!(batched.tree_writes(), 1);
+        assert_eq!(batched.reveal_root(), one_by_one.reveal_root());
+        assert_eq!(batched.fingerprint(), one_by_one.fingerprint());
+
+        // a repeated key keeps its last value
+        batched.set_many([("key0", "first".into()), ("key0", "second".into())])?;
+        set_keys(&mut one_by_one, &[("key0", "second")])?;
+        assert_eq!(batched.reveal_root(), one_by_one.reveal_root());
+        assert_eq!(batched.get("key0")?.as_deref(), Some("second"));
+
+        let mut restored = Store::new();
+        restored.update_full_store(one_by_one.get_main_store()?)?;
+        assert_eq!(restored.tree_writes(), 1);
+        assert_eq!(restored.reveal_root(), one_by_one.reveal_root());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bulk_load_matches_individual_sets() -> StdResult<(), StoreError> {
+        let pairs: Vec<(String, String)> = (0..200)
+            .map(|i| (format!("key{i}"), format!("value{i}")))
+            .collect();
+
+        let mut individual = Store::new();
+        for (key, value) in &pairs {
+            individual.execute(StoreCommand::set(key.as_str(), value.as_str()))?;
+        }
+        let mut preloaded = Store::new();
+        assert_eq!(preloaded.bulk_load(pairs.into_iter())?, 200);
+
+        assert_eq!(preloaded.tree_writes(), 1);
+        assert_eq!(preloaded.reveal_root(), individual.reveal_root());
+        assert_eq!(preloaded.fingerprint(), individual.fingerprint());
+        assert_eq!(
+            preloaded.execute(StoreCommand::get("key7"))?,
+            StoreCommandResult::get(Some("value7"), 1)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_and_get_basic() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        // SET
+        let result = store.execute(StoreCommand::SET(SETParams {
+            key: Cow::Borrowed("view"),
+            value: Cow::Borrowed("different"),
+            return_old: false,
+        }))?;
+        assert!(matches!(
+            result,
+            StoreCommandResult::SET(SETResult {
+                payload: true,
+                root: Some(_)
+            })
+        ));
+
+        // GET existing
+        let result = store.execute(StoreCommand::GET(GETParams {
+            key: Cow::Borrowed("view"),
+        }))?;
+        assert_eq!(result, StoreCommandResult::get(Some("different"), 1));
+
+        // GET non-existent
+        let result = store.execute(StoreCommand::GET(GETParams {
+            key: Cow::Borrowed("nope"),
+        }))?;
+        assert_eq!(result, StoreCommandResult::get::<&str>(None, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_checking_for_overwriting() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        store.execute(StoreCommand::SET(SETParams {
+            key: Cow::Borrowed("view"),
+            value: Cow::Borrowed("different"),
+            return_old: false,
+        }))?;
+
+        let result = store.execute(StoreCommand::GET(GETParams {
+            key: Cow::Borrowed("view"),
+        }))?;
+        assert_eq!(result, StoreCommandResult::get(Some("different"), 1));
+
+        store.execute(StoreCommand::SET(SETParams {
+            key: Cow::Borrowed("view"),
+            value: Cow::Borrowed("another"),
+            return_old: false,
+        }))?;
+
+        let result = store.execute(StoreCommand::GET(GETParams {
+            key: Cow::Borrowed("view"),
+        }))?;
+        assert_eq!(result, StoreCommandResult::get(Some("another"), 2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_get_returns_the_replaced_value() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        assert_eq!(
+            store.execute(StoreCommand::set_get("view", "first"))?,
+            StoreCommandResult::get::<&str>(None, 1)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::set_get("view", "second"))?,
+            StoreCommandResult::get(Some("first"), 2)
+        );
+        assert!(matches!(
+            store.execute(StoreCommand::set("view", "third"))?,
+            StoreCommandResult::SET(SETResult { payload: true, .. })
+        ));
+        assert_eq!(store.get("view")?.as_deref(), Some("third"));
+
+        store.execute(StoreCommand::rpush("list", ["a"]))?;
+        assert!(matches!(
+            store.execute(StoreCommand::set_get("list", "value"))?,
+            StoreCommandResult::ERROR(ErrorResult {
+                kind: ErrorKind::Conflict,
+                ..
+            })
+        ));
+        assert_eq!(
+            store.execute(StoreCommand::lrange("list", 0, -1))?,
+            StoreCommandResult::lrange(vec!["a"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_exists_exact_match() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[
+                ("first", "some_data"),
+                ("second", "some_data"),
+                ("third", "some_data"),
+                ("fourth", "some_data"),
+                ("fifth", "some_data"),
+            ],
+        )?;
+
+        let result = store.execute(StoreCommand::EXISTS(EXISTSParams {
+            keys: vec![
+                Cow::Borrowed("first"),
+                Cow::Borrowed("second"),
+                Cow::Borrowed("third"),
+                Cow::Borrowed("fourth"),
+                Cow::Borrowed("fifth"),
+            ],
+        }))?;
+
+        assert_eq!(result, StoreCommandResult::exists(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exists_partial_match() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[
+                ("first", "some_data"),
+                ("second", "some_data"),
+                ("third", "some_data"),
                 ("fourth", "some_data"),
                 ("fifth", "some_data"),
             ],
@@ -279,8 +1643,39 @@ mod tests {
         }))?;
 
         assert_eq!(result, StoreCommandResult::exists(3));
-        assert!(store.get("fourth").is_none());
-        assert!(store.get("fifth").is_none());
+        assert!(store.get("fourth")?.is_none());
+        assert!(store.get("fifth")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_existsall_existsany() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("first", "some_data"), ("second", "some_data")],
+        )?;
+
+        let all_present = ["first", "second", "first"];
+        let none_present = ["third", "fourth"];
+        let partial = ["first", "third"];
+
+        for (keys, all, any) in [
+            (&all_present[..], true, true),
+            (&none_present[..], false, false),
+            (&partial[..], false, true),
+        ] {
+            assert_eq!(
+                store.execute(StoreCommand::existsall(keys.iter().copied()))?,
+                StoreCommandResult::bool(all)
+            );
+            assert_eq!(
+                store.execute(StoreCommand::existsany(keys.iter().copied()))?,
+                StoreCommandResult::bool(any)
+            );
+            assert_eq!(store.contains_all(keys)?, all);
+            assert_eq!(store.contains_any(keys)?, any);
+        }
         Ok(())
     }
 
@@ -308,12 +1703,12 @@ mod tests {
             ],
         }))?;
 
-        assert_eq!(result, StoreCommandResult::del(5));
-        assert!(store.get("first").is_none());
-        assert!(store.get("second").is_none());
-        assert!(store.get("third").is_none());
-        assert!(store.get("fourth").is_none());
-        assert!(store.get("fifth").is_none());
+        assert_eq!(result, StoreCommandResult::del(5, None));
+        assert!(store.get("first")?.is_none());
+        assert!(store.get("second")?.is_none());
+        assert!(store.get("third")?.is_none());
+        assert!(store.get("fourth")?.is_none());
+        assert!(store.get("fifth")?.is_none());
         Ok(())
     }
 
@@ -339,12 +1734,18 @@ mod tests {
             ],
         }))?;
 
-        assert_eq!(result, StoreCommandResult::del(3));
-        assert!(store.get("first").is_none());
-        assert!(store.get("second").is_none());
-        assert!(store.get("third").is_none());
-        assert!(store.get("fourth").is_some());
-        assert!(store.get("fifth").is_some());
+        assert!(matches!(
+            result,
+            StoreCommandResult::DEL(DELResult {
+                payload: 3,
+                root: Some(_)
+            })
+        ));
+        assert!(store.get("first")?.is_none());
+        assert!(store.get("second")?.is_none());
+        assert!(store.get("third")?.is_none());
+        assert!(store.get("fourth")?.is_some());
+        assert!(store.get("fifth")?.is_some());
         Ok(())
     }
 
@@ -370,12 +1771,53 @@ mod tests {
             ],
         }))?;
 
-        assert_eq!(result, StoreCommandResult::del(3));
-        assert!(store.get("first").is_none());
-        assert!(store.get("second").is_none());
-        assert!(store.get("third").is_none());
-        assert!(store.get("fourth").is_none());
-        assert!(store.get("fifth").is_none());
+        assert_eq!(result, StoreCommandResult::del(3, None));
+        assert!(store.get("first")?.is_none());
+        assert!(store.get("second")?.is_none());
+        assert!(store.get("third")?.is_none());
+        assert!(store.get("fourth")?.is_none());
+        assert!(store.get("fifth")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_del_duplicate_keys_counts_once() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("first", "some_data"), ("second", "some_data")],
+        )?;
+
+        let result = store.execute(StoreCommand::del(["first", "first"]))?;
+
+        assert!(matches!(
+            result,
+            StoreCommandResult::DEL(DELResult {
+                payload: 1,
+                root: Some(_)
+            })
+        ));
+        assert!(store.get("first")?.is_none());
+        assert!(store.get("second")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_del_duplicate_keys_no_spurious_root_change() -> StdResult<(), StoreError> {
+        let mut single = Store::new();
+        let mut duplicated = Store::new();
+        for store in [&mut single, &mut duplicated] {
+            set_keys(store, &[("first", "some_data"), ("second", "some_data")])?;
+        }
+
+        single.execute(StoreCommand::del(["first"]))?;
+        duplicated.execute(StoreCommand::del(["first", "first"]))?;
+
+        assert_eq!(single.reveal_root(), duplicated.reveal_root());
+        assert_eq!(
+            duplicated.execute(StoreCommand::get("first"))?,
+            StoreCommandResult::get::<&str>(None, 2)
+        );
         Ok(())
     }
 
@@ -495,6 +1937,7 @@ mod tests {
         store.execute(StoreCommand::SET(SETParams {
             key: Cow::Borrowed("view"),
             value: Cow::Borrowed("different"),
+            return_old: false,
         }))?;
 
         let root_after = store.reveal_root();
@@ -510,6 +1953,7 @@ mod tests {
         store.execute(StoreCommand::SET(SETParams {
             key: Cow::Borrowed("view"),
             value: Cow::Borrowed("different"),
+            return_old: false,
         }))?;
         let root_after_set = store.reveal_root();
 
@@ -537,18 +1981,1407 @@ mod tests {
         let old_root = store.reveal_root();
 
         let new_data = HashMap::from([
-            ("fourth".to_string(), "some_data".to_string()),
-            ("fifth".to_string(), "some_data".to_string()),
+            ("fourth".to_string(), StoreValue::from("some_data")),
+            ("fifth".to_string(), StoreValue::from("some_data")),
         ]);
 
         store.update_full_store(new_data.clone())?;
 
-        assert_eq!(store.get_main_store(), new_data);
-        assert_eq!(store.get("fourth"), Some("some_data"));
-        assert_eq!(store.get("sixth"), None);
+        assert_eq!(store.get_main_store()?, new_data);
+        assert_eq!(store.get("fourth")?.as_deref(), Some("some_data"));
+        assert_eq!(store.get("sixth")?.as_deref(), None);
 
         let new_root = store.reveal_root();
         assert_ne!(old_root, new_root);
         Ok(())
     }
+
+    #[test]
+    fn test_rebuild_keeps_the_root_of_a_consistent_store() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        assert_eq!(
+            store.execute(StoreCommand::rebuild())?,
+            StoreCommandResult::rebuild(None)
+        );
+
+        set_keys(
+            &mut store,
+            &[("first", "1"), ("second", "2"), ("third", "3")],
+        )?;
+        store.execute(StoreCommand::rpush("queue", ["a", "b"]))?;
+        let root = store.reveal_root();
+        let fingerprint = store.fingerprint();
+        assert_eq!(
+            store.execute(StoreCommand::rebuild())?,
+            StoreCommandResult::rebuild(root)
+        );
+        assert_eq!(store.reveal_root(), root);
+        assert_eq!(store.fingerprint(), fingerprint);
+        assert_eq!(store.get("second")?.as_deref(), Some("2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_after_removal_matches_a_fresh_store() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("first", "1"), ("second", "2"), ("third", "3")],
+        )?;
+        store.execute(StoreCommand::del(["second"]))?;
+
+        let mut fresh = Store::new();
+        set_keys(&mut fresh, &[("first", "1"), ("third", "3")])?;
+        assert_eq!(store.rebuild_tree()?, fresh.reveal_root());
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_full_store_keeps_the_value_types() -> StdResult<(), StoreError> {
+        let mut source = Store::new();
+        for cmd in [
+            StoreCommand::set("color", "red"),
+            StoreCommand::rpush("queue", ["a", "b", "c"]),
+            StoreCommand::hset("user:1", "name", "ada"),
+            StoreCommand::hset("user:1", "lang", "en"),
+            StoreCommand::sadd("tags", ["x", "y"]),
+        ] {
+            source.execute(cmd)?;
+        }
+
+        let mut repaired = Store::new();
+        set_keys(&mut repaired, &[("queue", "not a list")])?;
+        repaired.update_full_store(source.get_main_store()?)?;
+
+        for (key, type_name) in [
+            ("color", "string"),
+            ("queue", "list"),
+            ("user:1", "hash"),
+            ("tags", "set"),
+        ] {
+            assert_eq!(
+                repaired.execute(StoreCommand::key_type(key))?,
+                StoreCommandResult::key_type(type_name)
+            );
+        }
+        assert_eq!(
+            repaired.execute(StoreCommand::lrange("queue", 0, -1))?,
+            StoreCommandResult::lrange(["a", "b", "c"])
+        );
+        assert_eq!(
+            repaired.execute(StoreCommand::hget("user:1", "lang"))?,
+            StoreCommandResult::hget(Some("en"))
+        );
+        assert_eq!(repaired.get_main_store()?, source.get_main_store()?);
+        assert_eq!(repaired.reveal_root(), source.reveal_root());
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_full_store_counts_the_differences() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("kept", "same"), ("edited", "old"), ("dropped", "value")],
+        )?;
+
+        let new_data = HashMap::from([
+            ("kept".to_string(), StoreValue::from("same")),
+            ("edited".to_string(), StoreValue::from("new")),
+            ("first".to_string(), StoreValue::from("added")),
+            ("second".to_string(), StoreValue::from("added")),
+        ]);
+        let summary = store.update_full_store(new_data.clone())?;
+        assert_eq!(
+            summary,
+            UpdateSummary {
+                added: 2,
+                removed: 1,
+                changed: 1,
+            }
+        );
+
+        assert_eq!(store.update_full_store(new_data)?, UpdateSummary::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_key_bytes_rejects_longer_keys() -> StdResult<(), StoreError> {
+        let mut store = StoreBuilder::new().max_key_bytes(8).build();
+        let at_limit = "k".repeat(8);
+        let over_limit = "k".repeat(9);
+
+        assert!(matches!(
+            store.execute(StoreCommand::set(at_limit.as_str(), "value"))?,
+            StoreCommandResult::SET(SETResult { payload: true, .. })
+        ));
+        let root = store.reveal_root();
+        for cmd in [
+            StoreCommand::set(over_limit.as_str(), "value"),
+            StoreCommand::rpush(over_limit.as_str(), ["value"]),
+            StoreCommand::incrby(over_limit.as_str(), 1),
+            StoreCommand::rename(at_limit.as_str(), over_limit.as_str()),
+        ] {
+            assert_eq!(
+                store.execute(cmd)?,
+                StoreCommandResult::error(
+                    ErrorKind::BadRequest,
+                    "key of 9 bytes is longer than allowed"
+                )
+            );
+        }
+        assert_eq!(store.reveal_root(), root);
+        assert_eq!(store.get(&at_limit)?.as_deref(), Some("value"));
+
+        // reads with an over-long key simply find nothing
+        assert_eq!(
+            store.execute(StoreCommand::exists([over_limit.as_str()]))?,
+            StoreCommandResult::exists(0)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::keys(over_limit.as_str()))?,
+            StoreCommandResult::keys(Vec::<&str>::new())
+        );
+        assert_eq!(
+            store.execute(StoreCommand::del([over_limit.as_str()]))?,
+            StoreCommandResult::del(0, root)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_compression_is_transparent() -> StdResult<(), StoreError> {
+        let mut compressed = StoreBuilder::new().value_compression(64).build();
+        let mut plain = Store::new();
+        let large = "abc".repeat(500);
+
+        for store in [&mut compressed, &mut plain] {
+            store.execute(StoreCommand::set("large", large.as_str()))?;
+            store.execute(StoreCommand::set("small", "value"))?;
+            store.execute(StoreCommand::rpush("list", vec![large.as_str(); 4]))?;
+        }
+        assert_eq!(compressed.get("large")?.as_deref(), Some(large.as_str()));
+        assert_eq!(
+            compressed.execute(StoreCommand::lrange("list", 0, -1))?,
+            StoreCommandResult::lrange(vec![large.as_str(); 4])
+        );
+        assert_eq!(compressed.reveal_root(), plain.reveal_root());
+        assert_eq!(compressed.rebuild_tree()?, plain.reveal_root());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttls_lists_only_volatile_keys() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[
+                ("session", "some_data"),
+                ("token", "some_data"),
+                ("persistent", "some_data"),
+            ],
+        )?;
+
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::expire("session", 10), now)?;
+        store.execute_at(StoreCommand::expire("token", 60), now)?;
+
+        let result = store.execute_at(StoreCommand::ttls(), now + 2_500)?;
+        assert_eq!(
+            result,
+            StoreCommandResult::ttls(vec![("session", 8), ("token", 58)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttls_excludes_expired_keys() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("session", "some_data"), ("token", "some_data")],
+        )?;
+
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::expire("session", 1), now)?;
+        store.execute_at(StoreCommand::expire("token", 60), now)?;
+
+        let result = store.execute_at(StoreCommand::ttls(), now + 1_000)?;
+        assert_eq!(result, StoreCommandResult::ttls(vec![("token", 59)]));
+        assert!(store.get("session")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_msetex_gives_every_key_the_ttl() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        store.execute_at(StoreCommand::expire("color", 5), 1_000_000)?;
+
+        let now = 2_000_000;
+        let result = store.execute_at(
+            StoreCommand::msetex(60, [("color", "blue"), ("size", "xl"), ("color", "green")]),
+            now,
+        )?;
+        assert_eq!(result, StoreCommandResult::int(2));
+        assert_eq!(store.get("color")?.as_deref(), Some("green"));
+        assert_eq!(store.get("size")?.as_deref(), Some("xl"));
+        assert_eq!(
+            store.execute_at(StoreCommand::ttls(), now)?,
+            StoreCommandResult::ttls(vec![("color", 60), ("size", 60)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_msetex_rejected_pair_applies_none() -> StdResult<(), StoreError> {
+        let mut store = StoreBuilder::new().max_keys(2).build();
+        set_keys(&mut store, &[("color", "red")])?;
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::expire("color", 30), now)?;
+
+        let result = store.execute_at(
+            StoreCommand::msetex(60, [("color", "blue"), ("size", "xl"), ("shape", "round")]),
+            now,
+        );
+        assert!(matches!(result, Err(StoreError::KeyLimitReached(2))));
+        assert_eq!(store.get("color")?.as_deref(), Some("red"));
+        assert!(store.get("size")?.is_none());
+        assert_eq!(
+            store.execute_at(StoreCommand::ttls(), now)?,
+            StoreCommandResult::ttls(vec![("color", 30)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_expire_missing_key() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let result = store.execute_at(StoreCommand::expire("nope", 10), 0)?;
+        assert_eq!(result, StoreCommandResult::expire(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_clears_expiry() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        store.execute_at(StoreCommand::set("session", "some_data"), 0)?;
+        store.execute_at(StoreCommand::expire("session", 10), 0)?;
+        store.execute_at(StoreCommand::set("session", "other_data"), 0)?;
+
+        let result = store.execute_at(StoreCommand::ttls(), 0)?;
+        assert_eq!(result, StoreCommandResult::ttls(Vec::<(&str, u64)>::new()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_casv_with_matching_version() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        let result = store.execute(StoreCommand::casv("color", 0, "red"))?;
+        assert_eq!(result, StoreCommandResult::casv(true, 1));
+
+        let result = store.execute(StoreCommand::casv("color", 1, "blue"))?;
+        assert_eq!(result, StoreCommandResult::casv(true, 2));
+
+        let result = store.execute(StoreCommand::get("color"))?;
+        assert_eq!(result, StoreCommandResult::get(Some("blue"), 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_casv_with_stale_version() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red"), ("color", "blue")])?;
+
+        let result = store.execute(StoreCommand::casv("color", 1, "green"))?;
+        assert_eq!(result, StoreCommandResult::casv(false, 2));
+
+        let result = store.execute(StoreCommand::get("color"))?;
+        assert_eq!(result, StoreCommandResult::get(Some("blue"), 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_survives_delete() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        store.execute(StoreCommand::del(["color"]))?;
+
+        // a recreated key must not be mistaken for the one read before deletion
+        let result = store.execute(StoreCommand::casv("color", 1, "red"))?;
+        assert_eq!(result, StoreCommandResult::casv(false, 2));
+
+        let result = store.execute(StoreCommand::casv("color", 2, "red"))?;
+        assert_eq!(result, StoreCommandResult::casv(true, 3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_counts_every_write() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red"), ("color", "red")])?;
+        assert_eq!(store.version("color"), 2);
+
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::set("size", "xl"), now)?;
+        store.execute_at(StoreCommand::expire("size", 1), now)?;
+        store.execute_at(StoreCommand::get("size"), now + 1_000)?;
+        assert_eq!(store.version("size"), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_repair_keeps_versions_of_unchanged_keys() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("color", "red"), ("color", "blue"), ("size", "xl"), ("gone", "x")],
+        )?;
+
+        let mut main_store = store.get_main_store()?;
+        main_store.remove("gone");
+        main_store.insert("size".to_string(), StoreValue::from("s"));
+        main_store.insert("shape".to_string(), StoreValue::from("round"));
+        store.update_full_store(main_store)?;
+
+        assert_eq!(store.version("color"), 2);
+        assert_eq!(store.version("size"), 2);
+        assert_eq!(store.version("shape"), 1);
+        assert_eq!(store.version("gone"), 2);
+
+        // the versions of the repairing peer win over the local ones, the
+        // tombstones included
+        let mut peer = Store::new();
+        set_keys(&mut peer, &[("color", "blue")])?;
+        peer.adopt_versions("*", store.versions_of("*")?)?;
+        assert_eq!(peer.version("color"), 2);
+        assert_eq!(peer.version("gone"), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_age_preserved_on_overwrite() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::set("color", "red"), now)?;
+        store.execute_at(StoreCommand::set("color", "blue"), now + 5_000)?;
+
+        let age = store.execute_at(
+            StoreCommand::object(ObjectSubcommand::AGE, "color"),
+            now + 8_000,
+        )?;
+        assert_eq!(age, StoreCommandResult::int(8));
+
+        let idle = store.execute_at(
+            StoreCommand::object(ObjectSubcommand::IDLETIME, "color"),
+            now + 8_000,
+        )?;
+        assert_eq!(idle, StoreCommandResult::int(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_idletime_resets_on_get() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::set("color", "red"), now)?;
+        store.execute_at(StoreCommand::get("color"), now + 4_000)?;
+
+        let idle = store.execute_at(
+            StoreCommand::object(ObjectSubcommand::IDLETIME, "color"),
+            now + 6_000,
+        )?;
+        assert_eq!(idle, StoreCommandResult::int(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_absent_key() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::set("color", "red"), now)?;
+        store.execute_at(StoreCommand::del(["color"]), now)?;
+
+        let age = store.execute_at(StoreCommand::object(ObjectSubcommand::AGE, "color"), now)?;
+        assert_eq!(age, StoreCommandResult::int(-2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_insertion_order() -> StdResult<(), StoreError> {
+        let mut first = Store::new();
+        set_keys(&mut first, &[("a", "1"), ("b", "2"), ("c", "3")])?;
+        let mut second = Store::new();
+        set_keys(&mut second, &[("c", "3"), ("a", "1"), ("b", "2")])?;
+
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        assert_ne!(first.fingerprint(), Store::new().fingerprint());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_changes_on_single_mutation() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("a", "1"), ("b", "2")])?;
+        let before = store.fingerprint();
+
+        set_keys(&mut store, &[("b", "3")])?;
+        assert_ne!(store.fingerprint(), before);
+
+        set_keys(&mut store, &[("b", "2")])?;
+        assert_eq!(store.fingerprint(), before);
+
+        store.execute(StoreCommand::del(["a"]))?;
+        assert_ne!(store.fingerprint(), before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_matches_after_full_store_update() -> StdResult<(), StoreError> {
+        let mut source = Store::new();
+        set_keys(&mut source, &[("a", "1"), ("b", "2")])?;
+
+        let mut replica = Store::new();
+        set_keys(&mut replica, &[("a", "0"), ("z", "9")])?;
+        replica.update_full_store(source.get_main_store()?)?;
+
+        assert_eq!(replica.fingerprint(), source.fingerprint());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_matching_keeps_other_keys() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("user:1", "old"), ("user:2", "gone"), ("team:1", "kept")],
+        )?;
+        let version = store.version("user:1");
+
+        let mut fresh = Store::new();
+        set_keys(
+            &mut fresh,
+            &[("user:1", "old"), ("user:3", "new"), ("team:1", "other")],
+        )?;
+        let matching = fresh.get_matching("user:*")?;
+        assert_eq!(matching.len(), 2);
+
+        // entries outside the pattern are ignored even if sent
+        let mut data = matching.clone();
+        data.insert("team:2".to_string(), StoreValue::from("ignored"));
+        store.merge_matching("user:*", data)?;
+
+        assert_eq!(store.get_matching("user:*")?, matching);
+        assert_eq!(store.get("team:1")?.as_deref(), Some("kept"));
+        assert!(!store.get_main_store()?.contains_key("team:2"));
+        // a key already holding the received value isn't written again
+        assert_eq!(store.version("user:1"), version);
+        Ok(())
+    }
+
+    /// Commands of every kind, each reply has a deterministic order
+    #[cfg(feature = "sled")]
+    fn suite_commands() -> Vec<StoreCommand<'static>> {
+        vec![
+            StoreCommand::set("color", "red"),
+            StoreCommand::set("n", "1"),
+            StoreCommand::incrby("n", 5),
+            StoreCommand::rpush("list", ["a", "b", "c"]),
+            StoreCommand::lrange("list", 0, -1),
+            StoreCommand::hset("hash", "field", "value"),
+            StoreCommand::hgetall("hash"),
+            StoreCommand::sadd("set", ["x", "y"]),
+            StoreCommand::srem("set", ["y"]),
+            StoreCommand::smembers("set"),
+            StoreCommand::keys("col*"),
+            StoreCommand::del(["color"]),
+            StoreCommand::exists(["color", "n"]),
+            StoreCommand::get("n"),
+            StoreCommand::key_type("list"),
+            StoreCommand::expire("n", 10),
+            StoreCommand::rename("n", "m"),
+            StoreCommand::renamenx("list", "m"),
+            StoreCommand::ttls(),
+            StoreCommand::treeinfo(),
+            StoreCommand::dumpjson(),
+        ]
+    }
+
+    #[cfg(feature = "sled")]
+    fn temporary_sled() -> StdResult<backend::SledBackend, StoreError> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Ok(backend::SledBackend::new(db))
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_backend_matches_memory() -> StdResult<(), StoreError> {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let mut memory = StoreBuilder::new().clock(clock.clone()).build();
+        let mut sled = StoreBuilder::new()
+            .clock(clock.clone())
+            .build_with_backend(temporary_sled()?)?;
+
+        for (expected, cmd) in suite_commands().into_iter().zip(suite_commands()) {
+            assert_eq!(sled.execute(cmd)?, memory.execute(expected)?);
+        }
+        assert_eq!(sled.reveal_root(), memory.reveal_root());
+        assert_eq!(sled.fingerprint(), memory.fingerprint());
+        assert_eq!(sled.get_main_store()?, memory.get_main_store()?);
+        Ok(())
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_backend_is_indexed_when_reopened() -> StdResult<(), StoreError> {
+        let path = std::env::temp_dir().join(format!("difiew-store-{}", std::process::id()));
+        let mut expected = Store::new();
+        {
+            let backend = backend::SledBackend::open(&path)?;
+            let mut store = StoreBuilder::new().build_with_backend(backend)?;
+            for cmd in suite_commands() {
+                store.execute(cmd)?;
+            }
+            expected.update_full_store(store.get_main_store()?)?;
+        }
+
+        let reopened = StoreBuilder::new().build_with_backend(backend::SledBackend::open(&path)?);
+        std::fs::remove_dir_all(&path).ok();
+        let mut reopened = reopened?;
+        assert_eq!(reopened.reveal_root(), expected.reveal_root());
+        assert_eq!(reopened.fingerprint(), expected.fingerprint());
+        assert_eq!(
+            reopened.execute(StoreCommand::lrange("list", 0, -1))?,
+            StoreCommandResult::lrange(["a", "b", "c"])
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_flush_syncs_the_sled_backend() -> StdResult<(), StoreError> {
+        let path = std::env::temp_dir().join(format!("difiew-flush-{}", std::process::id()));
+        let flushed = || -> StdResult<Vec<i64>, StoreError> {
+            let backend = backend::SledBackend::open(&path)?;
+            let mut store = StoreBuilder::new().build_with_backend(backend)?;
+            store.execute(StoreCommand::set("color", "x".repeat(10_000)))?;
+            // nothing is left to write by the second flush
+            (0..2)
+                .map(|_| match store.execute(StoreCommand::flush())? {
+                    StoreCommandResult::INT(r) => Ok(r.payload),
+                    other => panic!("Expected INT result, got {other:?}"),
+                })
+                .collect()
+        };
+        let flushed = flushed();
+        std::fs::remove_dir_all(&path).ok();
+
+        let flushed = flushed?;
+        assert!(flushed[0] >= 10_000, "{flushed:?}");
+        assert_eq!(flushed[1], 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_of_a_memory_store_writes_nothing() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        assert_eq!(
+            store.execute(StoreCommand::flush())?,
+            StoreCommandResult::int(0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mock_clock_drives_expiry() -> StdResult<(), StoreError> {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let mut store = StoreBuilder::new().clock(clock.clone()).build();
+        set_keys(&mut store, &[("session", "some_data")])?;
+        store.execute(StoreCommand::expire("session", 10))?;
+
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(store.purge_expired()?, 0);
+        assert_eq!(
+            store.execute(StoreCommand::get("session"))?,
+            StoreCommandResult::get(Some("some_data"), 1)
+        );
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            store.execute(StoreCommand::get("session"))?,
+            StoreCommandResult::get::<&str>(None, 2)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_carries_the_timeout_over() -> StdResult<(), StoreError> {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let mut store = StoreBuilder::new().clock(clock.clone()).build();
+        set_keys(&mut store, &[("session", "some_data"), ("color", "red")])?;
+        store.execute(StoreCommand::expire("session", 10))?;
+        store.execute(StoreCommand::expire("color", 100))?;
+
+        clock.advance(Duration::from_secs(4));
+        assert!(matches!(
+            store.execute(StoreCommand::rename("session", "renamed"))?,
+            StoreCommandResult::SET(SETResult { payload: true, .. })
+        ));
+        assert_eq!(
+            store.execute(StoreCommand::ttls())?,
+            StoreCommandResult::ttls([("color", 96), ("renamed", 6)])
+        );
+
+        // a persistent key drops the timeout newkey had
+        set_keys(&mut store, &[("plain", "value")])?;
+        store.execute(StoreCommand::rename("plain", "color"))?;
+        assert_eq!(
+            store.execute(StoreCommand::ttls())?,
+            StoreCommandResult::ttls([("renamed", 6)])
+        );
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(
+            store.execute(StoreCommand::get("renamed"))?,
+            StoreCommandResult::get::<&str>(None, 2)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::rename("session", "other"))?,
+            StoreCommandResult::error(ErrorKind::NotFound, "no such key")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_renamenx_keeps_an_existing_newkey() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("old", "1"), ("taken", "2")])?;
+        let root = store.reveal_root();
+
+        assert_eq!(
+            store.execute(StoreCommand::renamenx("old", "taken"))?,
+            StoreCommandResult::set(false, root)
+        );
+        assert_eq!(store.reveal_root(), root);
+        assert_eq!(
+            store.execute(StoreCommand::get("taken"))?,
+            StoreCommandResult::get(Some("2"), 1)
+        );
+
+        store.execute(StoreCommand::renamenx("old", "free"))?;
+        assert_eq!(
+            store.execute(StoreCommand::exists(["old", "free"]))?,
+            StoreCommandResult::exists(1)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::get("free"))?,
+            StoreCommandResult::get(Some("1"), 1)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_expiry_keeps_untouched_keys() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("session", "some_data"), ("color", "red")])?;
+        let root = store.reveal_root();
+
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::expire("session", 1), now)?;
+        store.execute_at(StoreCommand::get("color"), now + 2_000)?;
+
+        assert!(store.get_main_store()?.contains_key("session"));
+        assert_eq!(store.reveal_root(), root);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_expiry_on_access() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("session", "some_data"), ("color", "red")])?;
+
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::expire("session", 1), now)?;
+        let result = store.execute_at(StoreCommand::exists(["session", "color"]), now + 2_000)?;
+
+        assert_eq!(result, StoreCommandResult::exists(1));
+        assert!(!store.get_main_store()?.contains_key("session"));
+        assert_eq!(store.take_expired(), ["session"]);
+        assert!(store.take_expired().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_active_expiry_sweeps_all_keys() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        let fingerprint = store.fingerprint();
+        set_keys(
+            &mut store,
+            &[("session", "some_data"), ("token", "some_data")],
+        )?;
+        let root = store.reveal_root();
+
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::expire("session", 1), now)?;
+        store.execute_at(StoreCommand::expire("token", 1), now)?;
+
+        assert_eq!(store.sweep_expired(now + 1_000)?.len(), 2);
+        assert_ne!(store.reveal_root(), root);
+        assert_eq!(store.fingerprint(), fingerprint);
+        assert_eq!(store.get_main_store()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reaper_removes_expired_keys_in_batches() -> StdResult<(), StoreError> {
+        let mut store = StoreBuilder::new().reaper_batch_size(100).build();
+        let keys: Vec<String> = (0..1000).map(|i| format!("session{i:04}")).collect();
+        store.set_many(
+            keys.iter()
+                .map(|key| (key.as_str(), StoreValue::from("data"))),
+        )?;
+        set_keys(&mut store, &[("color", "red")])?;
+
+        let now = 1_000_000;
+        for key in &keys {
+            store.execute_at(StoreCommand::expire(key.as_str(), 1), now)?;
+        }
+
+        // nothing is due yet, the cursor still walks the volatile keys
+        assert!(store.reap_expired_at(now)?.is_empty());
+        assert_eq!(store.reaper_cursor.as_deref(), Some("session0099"));
+
+        let mut ticks = 0;
+        while store.get_main_store()?.len() > 1 {
+            assert!(store.reap_expired_at(now + 1_000)?.len() <= 100);
+            ticks += 1;
+        }
+        // resuming after the keys already checked, then wrapping around
+        assert_eq!(ticks, 10);
+        assert!(store.expires.is_empty());
+        assert!(store.reap_expired_at(now + 1_000)?.is_empty());
+        assert_eq!(store.get("color")?.as_deref(), Some("red"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_treeinfo_empty_store() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let result = store.execute(StoreCommand::treeinfo())?;
+        assert_eq!(result, StoreCommandResult::treeinfo(None, 0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_treeinfo_small_tree() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("a", "1")])?;
+        let root = store.reveal_root();
+        let result = store.execute(StoreCommand::treeinfo())?;
+        assert_eq!(result, StoreCommandResult::treeinfo(root, 1, 1));
+
+        set_keys(&mut store, &[("b", "2"), ("c", "3"), ("d", "4")])?;
+        let root = store.reveal_root();
+        let StoreCommandResult::TREEINFO(info) = store.execute(StoreCommand::treeinfo())? else {
+            panic!("Expected TREEINFO result");
+        };
+        assert_eq!(info.root, root);
+        assert_eq!(info.keys, 4);
+        // the sha256 key hashes of a..d split into a three level tree
+        assert_eq!(info.depth, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_hashing_roots_are_stable() -> StdResult<(), StoreError> {
+        for key_hashing in [KeyHashing::Sha256, KeyHashing::Raw] {
+            let mut first = StoreBuilder::new().key_hashing(key_hashing).build();
+            set_keys(&mut first, &[("a", "1"), ("b", "2"), ("c", "3")])?;
+            let mut second = StoreBuilder::new().key_hashing(key_hashing).build();
+            set_keys(&mut second, &[("a", "1"), ("b", "2"), ("c", "3")])?;
+
+            assert!(first.reveal_root().is_some());
+            assert_eq!(first.reveal_root(), second.reveal_root());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_hashing_modes_differ() -> StdResult<(), StoreError> {
+        let mut sha = StoreBuilder::new().key_hashing(KeyHashing::Sha256).build();
+        set_keys(&mut sha, &[("a", "1")])?;
+        let mut raw = StoreBuilder::new().key_hashing(KeyHashing::Raw).build();
+        set_keys(&mut raw, &[("a", "1")])?;
+
+        assert_ne!(sha.reveal_root(), raw.reveal_root());
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw_key_hashing_rejects_long_keys() -> StdResult<(), StoreError> {
+        let mut store = StoreBuilder::new().key_hashing(KeyHashing::Raw).build();
+        let key = "k".repeat(33);
+
+        let result = store.execute(StoreCommand::set(key.as_str(), "value"));
+        assert_eq!(result, Err(StoreError::KeyTooLong(33)));
+        assert!(store.get_main_store()?.is_empty());
+        assert_eq!(store.reveal_root(), None);
+
+        set_keys(&mut store, &[(&"k".repeat(32), "value")])?;
+        assert!(store.reveal_root().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutation_results_carry_root() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let StoreCommandResult::SET(set) = store.execute(StoreCommand::set("color", "red"))? else {
+            panic!("Expected SET result");
+        };
+        assert!(set.root.is_some());
+        assert_eq!(set.root, store.reveal_root());
+
+        let StoreCommandResult::DEL(del) = store.execute(StoreCommand::del(["color"]))? else {
+            panic!("Expected DEL result");
+        };
+        assert_eq!(del.root, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_incrby_positive_and_negative() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        let result = store.execute(StoreCommand::incrby("counter", 5))?;
+        assert_eq!(result, StoreCommandResult::int(5));
+        let result = store.execute(StoreCommand::incrby("counter", -8))?;
+        assert_eq!(result, StoreCommandResult::int(-3));
+        let result = store.execute(StoreCommand::decrby("counter", 4))?;
+        assert_eq!(result, StoreCommandResult::int(-7));
+
+        assert_eq!(store.get("counter")?.as_deref(), Some("-7"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_incrby_saturates_on_overflow() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("high", "9223372036854775800"), ("low", "-2")],
+        )?;
+
+        let result = store.execute(StoreCommand::incrby("high", 100))?;
+        assert_eq!(result, StoreCommandResult::int(i64::MAX));
+        let result = store.execute(StoreCommand::decrby("low", i64::MAX))?;
+        assert_eq!(result, StoreCommandResult::int(i64::MIN));
+        Ok(())
+    }
+
+    #[test]
+    fn test_incrby_keeps_the_ttl() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("visits", "1")])?;
+        let now = 1_000_000;
+        store.execute_at(StoreCommand::expire("visits", 60), now)?;
+
+        store.execute_at(StoreCommand::incrby("visits", 2), now)?;
+        store.execute_at(StoreCommand::decrby("visits", 1), now)?;
+        assert_eq!(store.get("visits")?.as_deref(), Some("2"));
+        assert_eq!(
+            store.execute_at(StoreCommand::ttls(), now)?,
+            StoreCommandResult::ttls(vec![("visits", 60)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_incrby_rejects_non_integer_value() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        let root = store.reveal_root();
+
+        let result = store.execute(StoreCommand::incrby("color", 1))?;
+        assert!(matches!(
+            result,
+            StoreCommandResult::ERROR(ErrorResult {
+                kind: ErrorKind::Conflict,
+                ..
+            })
+        ));
+        assert_eq!(store.get("color")?.as_deref(), Some("red"));
+        assert_eq!(store.reveal_root(), root);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_json_contains_keys_and_root() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red"), ("size", "XL")])?;
+
+        let json: serde_json::Value = serde_json::from_str(&store.to_json()?).unwrap();
+        assert_eq!(json["keys"]["color"], "red");
+        assert_eq!(json["keys"]["size"], "XL");
+        assert_eq!(json["root"], to_hex(&store.reveal_root().unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_json_empty_store() {
+        let json: serde_json::Value =
+            serde_json::from_str(&Store::new().to_json().unwrap()).unwrap();
+        assert!(json["root"].is_null());
+        assert_eq!(json["keys"], serde_json::json!({}));
+    }
+
+    fn lrange(store: &mut Store, key: &str, start: i64, stop: i64) -> Vec<String> {
+        let StoreCommandResult::LRANGE(range) = store
+            .execute(StoreCommand::lrange(key, start, stop))
+            .unwrap()
+        else {
+            panic!("Expected LRANGE result");
+        };
+        range.payload.into_iter().map(|i| i.into_owned()).collect()
+    }
+
+    #[test]
+    fn test_list_push_order() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        let result = store.execute(StoreCommand::rpush("queue", ["b", "c"]))?;
+        assert_eq!(result, StoreCommandResult::int(2));
+        let result = store.execute(StoreCommand::lpush("queue", ["a", "z"]))?;
+        assert_eq!(result, StoreCommandResult::int(4));
+
+        assert_eq!(lrange(&mut store, "queue", 0, -1), ["z", "a", "b", "c"]);
+        assert_eq!(
+            store.execute(StoreCommand::llen("queue"))?,
+            StoreCommandResult::int(4)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::llen("missing"))?,
+            StoreCommandResult::int(0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_range_slicing() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        store.execute(StoreCommand::rpush("queue", ["a", "b", "c", "d", "e"]))?;
+
+        assert_eq!(lrange(&mut store, "queue", 1, 3), ["b", "c", "d"]);
+        assert_eq!(lrange(&mut store, "queue", -2, -1), ["d", "e"]);
+        assert_eq!(lrange(&mut store, "queue", -100, 1), ["a", "b"]);
+        assert_eq!(lrange(&mut store, "queue", 3, 100), ["d", "e"]);
+        assert!(lrange(&mut store, "queue", 3, 1).is_empty());
+        assert!(lrange(&mut store, "queue", 5, 10).is_empty());
+        assert!(lrange(&mut store, "missing", 0, -1).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_root_stability() -> StdResult<(), StoreError> {
+        let mut pushed = Store::new();
+        pushed.execute(StoreCommand::rpush("queue", ["a", "b"]))?;
+        pushed.execute(StoreCommand::rpush("queue", ["c"]))?;
+        let mut prepended = Store::new();
+        prepended.execute(StoreCommand::lpush("queue", ["c", "b", "a"]))?;
+
+        assert_eq!(pushed.reveal_root(), prepended.reveal_root());
+        assert_eq!(pushed.fingerprint(), prepended.fingerprint());
+
+        let mut reordered = Store::new();
+        reordered.execute(StoreCommand::rpush("queue", ["b", "a", "c"]))?;
+        assert_ne!(pushed.reveal_root(), reordered.reveal_root());
+
+        let mut replica = Store::new();
+        replica.update_full_store(pushed.get_main_store()?)?;
+        assert_eq!(replica.reveal_root(), pushed.reveal_root());
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_wrong_type() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        store.execute(StoreCommand::rpush("queue", ["a"]))?;
+        let root = store.reveal_root();
+
+        for cmd in [
+            StoreCommand::lpush("color", ["a"]),
+            StoreCommand::lrange("color", 0, -1),
+            StoreCommand::llen("color"),
+            StoreCommand::get("queue"),
+            StoreCommand::incrby("queue", 1),
+        ] {
+            assert_eq!(
+                store.execute(cmd)?,
+                StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)
+            );
+        }
+        assert_eq!(store.reveal_root(), root);
+
+        // SET replaces the value whatever its type
+        set_keys(&mut store, &[("queue", "plain")])?;
+        assert_eq!(store.get("queue")?.as_deref(), Some("plain"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_reports_value_type() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        store.execute(StoreCommand::rpush("queue", ["a"]))?;
+
+        for (key, expected) in [("color", "string"), ("queue", "list"), ("missing", "none")] {
+            assert_eq!(
+                store.execute(StoreCommand::key_type(key))?,
+                StoreCommandResult::key_type(expected)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_field_set_get_delete() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        let result = store.execute(StoreCommand::hset("user:1", "name", "alice"))?;
+        assert_eq!(result, StoreCommandResult::int(1));
+        let result = store.execute(StoreCommand::hset("user:1", "name", "bob"))?;
+        assert_eq!(result, StoreCommandResult::int(0));
+        store.execute(StoreCommand::hset("user:1", "age", "30"))?;
+
+        assert_eq!(
+            store.execute(StoreCommand::hget("user:1", "name"))?,
+            StoreCommandResult::hget(Some("bob"))
+        );
+        assert_eq!(
+            store.execute(StoreCommand::hget("user:1", "email"))?,
+            StoreCommandResult::hget::<&str>(None)
+        );
+
+        let result = store.execute(StoreCommand::hdel("user:1", ["name", "email"]))?;
+        assert_eq!(result, StoreCommandResult::int(1));
+        assert_eq!(
+            store.execute(StoreCommand::hget("user:1", "name"))?,
+            StoreCommandResult::hget::<&str>(None)
+        );
+
+        // removing the last field removes the key
+        store.execute(StoreCommand::hdel("user:1", ["age"]))?;
+        assert!(store.get_main_store()?.is_empty());
+        assert_eq!(store.reveal_root(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hgetall_returns_all_fields() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        for (field, value) in [("name", "alice"), ("age", "30"), ("city", "paris")] {
+            store.execute(StoreCommand::hset("user:1", field, value))?;
+        }
+
+        assert_eq!(
+            store.execute(StoreCommand::hgetall("user:1"))?,
+            StoreCommandResult::hgetall([("age", "30"), ("city", "paris"), ("name", "alice")])
+        );
+        assert_eq!(
+            store.execute(StoreCommand::hgetall("missing"))?,
+            StoreCommandResult::hgetall(Vec::<(&str, &str)>::new())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_root_ignores_field_order() -> StdResult<(), StoreError> {
+        let mut first = Store::new();
+        first.execute(StoreCommand::hset("user:1", "name", "alice"))?;
+        first.execute(StoreCommand::hset("user:1", "age", "30"))?;
+        let mut second = Store::new();
+        second.execute(StoreCommand::hset("user:1", "age", "30"))?;
+        second.execute(StoreCommand::hset("user:1", "name", "alice"))?;
+
+        assert_eq!(first.reveal_root(), second.reveal_root());
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hincrby_creates_and_increments_a_field() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        assert_eq!(
+            store.execute(StoreCommand::hincrby("user:1", "visits", 5))?,
+            StoreCommandResult::int(5)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::hincrby("user:1", "visits", -2))?,
+            StoreCommandResult::int(3)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::hget("user:1", "visits"))?,
+            StoreCommandResult::hget(Some("3"))
+        );
+
+        store.execute(StoreCommand::hset("user:1", "name", "alice"))?;
+        let root = store.reveal_root();
+        assert_eq!(
+            store.execute(StoreCommand::hincrby("user:1", "name", 1))?,
+            StoreCommandResult::error(ErrorKind::Conflict, "hash value is not an integer")
+        );
+        assert_eq!(store.reveal_root(), root);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_wrong_type() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        store.execute(StoreCommand::hset("user:1", "name", "alice"))?;
+        let root = store.reveal_root();
+
+        for cmd in [
+            StoreCommand::hset("color", "name", "alice"),
+            StoreCommand::hget("color", "name"),
+            StoreCommand::hgetall("color"),
+            StoreCommand::hdel("color", ["name"]),
+            StoreCommand::hincrby("color", "visits", 1),
+            StoreCommand::get("user:1"),
+            StoreCommand::rpush("user:1", ["a"]),
+        ] {
+            assert_eq!(
+                store.execute(cmd)?,
+                StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)
+            );
+        }
+        assert_eq!(store.reveal_root(), root);
+        assert_eq!(
+            store.execute(StoreCommand::key_type("user:1"))?,
+            StoreCommandResult::key_type("hash")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_add_remove_and_membership() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+
+        let result = store.execute(StoreCommand::sadd("tags", ["red", "blue", "red"]))?;
+        assert_eq!(result, StoreCommandResult::int(2));
+        let result = store.execute(StoreCommand::sadd("tags", ["blue", "green"]))?;
+        assert_eq!(result, StoreCommandResult::int(1));
+
+        assert_eq!(
+            store.execute(StoreCommand::sismember("tags", "red"))?,
+            StoreCommandResult::int(1)
+        );
+        assert_eq!(
+            store.execute(StoreCommand::sismember("tags", "pink"))?,
+            StoreCommandResult::int(0)
+        );
+
+        let result = store.execute(StoreCommand::srem("tags", ["red", "pink"]))?;
+        assert_eq!(result, StoreCommandResult::int(1));
+        store.execute(StoreCommand::srem("tags", ["blue", "green"]))?;
+        assert!(store.get_main_store()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_smembers_sorted() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        store.execute(StoreCommand::sadd(
+            "tags",
+            ["red", "blue", "green", "amber"],
+        ))?;
+
+        assert_eq!(
+            store.execute(StoreCommand::smembers("tags"))?,
+            StoreCommandResult::smembers(["amber", "blue", "green", "red"])
+        );
+        assert_eq!(
+            store.execute(StoreCommand::smembers("missing"))?,
+            StoreCommandResult::smembers(Vec::<&str>::new())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_duplicate_members_keep_root() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        store.execute(StoreCommand::sadd("tags", ["red", "blue"]))?;
+        let root = store.reveal_root();
+        let fingerprint = store.fingerprint();
+
+        let result = store.execute(StoreCommand::sadd("tags", ["blue", "red"]))?;
+        assert_eq!(result, StoreCommandResult::int(0));
+        assert_eq!(store.reveal_root(), root);
+        assert_eq!(store.fingerprint(), fingerprint);
+
+        let mut reversed = Store::new();
+        reversed.execute(StoreCommand::sadd("tags", ["blue"]))?;
+        reversed.execute(StoreCommand::sadd("tags", ["red"]))?;
+        assert_eq!(reversed.reveal_root(), root);
+        Ok(())
+    }
+
+    #[test]
+    fn test_structured_roots_ignore_insertion_order() -> StdResult<(), StoreError> {
+        let members: Vec<String> = (0..32).map(|i| format!("member{i}")).collect();
+        let mut first = Store::new();
+        let mut second = Store::new();
+        for member in &members {
+            first.execute(StoreCommand::sadd("tags", [member.as_str()]))?;
+            first.execute(StoreCommand::hset("user:1", member.as_str(), "1"))?;
+        }
+        for member in members.iter().rev() {
+            second.execute(StoreCommand::hset("user:1", member.as_str(), "1"))?;
+            second.execute(StoreCommand::sadd("tags", [member.as_str()]))?;
+        }
+        assert_eq!(first.reveal_root(), second.reveal_root());
+        assert_eq!(first.fingerprint(), second.fingerprint());
+
+        // a repair rebuilds the values from a map decoded off the wire
+        let mut repaired = Store::new();
+        repaired.update_full_store(second.get_main_store()?)?;
+        assert_eq!(repaired.reveal_root(), first.reveal_root());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_wrong_type() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red")])?;
+        store.execute(StoreCommand::sadd("tags", ["red"]))?;
+
+        for cmd in [
+            StoreCommand::sadd("color", ["red"]),
+            StoreCommand::smembers("color"),
+            StoreCommand::hget("tags", "red"),
+        ] {
+            assert_eq!(
+                store.execute(cmd)?,
+                StoreCommandResult::error(ErrorKind::Conflict, WRONG_TYPE)
+            );
+        }
+        assert_eq!(
+            store.execute(StoreCommand::key_type("tags"))?,
+            StoreCommandResult::key_type("set")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_reports_build() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let StoreCommandResult::VERSION(version) = store.execute(StoreCommand::version())? else {
+            panic!("Expected VERSION result");
+        };
+        assert_eq!(version.crate_version, env!("CARGO_PKG_VERSION"));
+        assert!(!version.git_hash.is_empty());
+        assert_eq!(version.protocol_version, PROTOCOL_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics_size_histograms() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("a", ""), ("bb", "xyz"), ("cccc", &"v".repeat(100))],
+        )?;
+        // overwriting moves the value to another bucket without a new key
+        set_keys(&mut store, &[("bb", "xy")])?;
+        store.execute(StoreCommand::del(["cccc"]))?;
+
+        let StoreCommandResult::METRICS(metrics) = store.execute(StoreCommand::metrics())? else {
+            panic!("Expected METRICS result");
+        };
+        assert_eq!(metrics.key_sizes.total(), 2);
+        assert_eq!(metrics.key_sizes.buckets[1], 1);
+        assert_eq!(metrics.key_sizes.buckets[2], 1);
+        assert_eq!(metrics.value_sizes.total(), 2);
+        assert_eq!(metrics.value_sizes.buckets[0], 1);
+        assert_eq!(metrics.value_sizes.buckets[2], 1);
+        assert_eq!(metrics.value_sizes.buckets[7], 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memusage_grows_with_the_stored_values() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        let memusage = |store: &mut Store| match store.execute(StoreCommand::memusage()) {
+            Ok(StoreCommandResult::INT(r)) => r.payload,
+            other => panic!("Expected INT result, got {other:?}"),
+        };
+        let empty = memusage(&mut store);
+        assert_eq!(empty, 0);
+
+        set_keys(&mut store, &[("small", "x")])?;
+        let small = memusage(&mut store);
+        assert!(small > empty);
+
+        set_keys(&mut store, &[("large", &"v".repeat(100_000))])?;
+        let large = memusage(&mut store);
+        assert!(large - small >= 100_000);
+        assert!(large - small < 101_000);
+
+        store.execute(StoreCommand::del(["large"]))?;
+        assert_eq!(memusage(&mut store), small);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_json_lists_are_arrays() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        store.execute(StoreCommand::rpush("queue", ["a", "b"]))?;
+
+        let json: serde_json::Value = serde_json::from_str(&store.to_json()?).unwrap();
+        assert_eq!(json["keys"]["queue"], serde_json::json!(["a", "b"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyhashes_hash_values() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(&mut store, &[("color", "red"), ("size", "XL")])?;
+
+        let StoreCommandResult::KEYHASHES(result) = store.execute(StoreCommand::keyhashes())?
+        else {
+            panic!("Expected KEYHASHES result");
+        };
+        let red = Sha256::digest(StoreValue::from("red").canonical_encode());
+        let red: [u8; 32] = red.into();
+        assert_eq!(result.payload.len(), 2);
+        assert_eq!(result.payload.get("color"), Some(&red));
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyhashes_matching_a_pattern() -> StdResult<(), StoreError> {
+        let mut store = Store::new();
+        set_keys(
+            &mut store,
+            &[("user:1", "alice"), ("user:2", "bob"), ("team:1", "red")],
+        )?;
+
+        let StoreCommandResult::KEYHASHES(result) =
+            store.execute(StoreCommand::keyhashes_matching("user:*"))?
+        else {
+            panic!("Expected KEYHASHES result");
+        };
+        let expected: HashMap<String, [u8; 32]> = [("user:1", "alice"), ("user:2", "bob")]
+            .into_iter()
+            .map(|(key, value)| {
+                let hash = Sha256::digest(StoreValue::from(value).canonical_encode());
+                (key.to_string(), hash.into())
+            })
+            .collect();
+        assert_eq!(result.payload, expected);
+        Ok(())
+    }
 }
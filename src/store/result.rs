@@ -1,5 +1,11 @@
 use bincode::{Decode, Encode};
 use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::error::StoreError;
+use super::histogram::SizeHistogram;
+use crate::utils::hex::to_hex;
+use serde_json::json;
 
 #[derive(Debug, Encode, Decode, PartialEq, Clone)]
 pub enum StoreCommandResult<'a> {
@@ -9,23 +15,47 @@ pub enum StoreCommandResult<'a> {
     KEYS(KEYSResult<'a>),
     SET(SETResult),
     UNDEFINED(UNDEFINEDResult<'a>),
+    EXPIRE(EXPIREResult),
+    TTLS(TTLSResult<'a>),
+    CASV(CASVResult),
+    INT(IntResult),
+    TREEINFO(TREEINFOResult),
+    DUMPJSON(DUMPJSONResult<'a>),
+    KEYHASHES(KEYHASHESResult),
+    LRANGE(LRANGEResult<'a>),
+    TYPE(TYPEResult<'a>),
+    HGET(HGETResult<'a>),
+    HGETALL(HGETALLResult<'a>),
+    SMEMBERS(SMEMBERSResult<'a>),
+    /// boxed, the histograms are much larger than any other result
+    METRICS(Box<METRICSResult>),
+    VERSION(VERSIONResult<'a>),
+    PEERS(PEERSResult),
+    REBUILD(REBUILDResult),
+    BOOL(BoolResult),
+    TRACKER(TRACKERResult),
+    ERROR(ErrorResult<'a>),
 }
 
 impl<'a> StoreCommandResult<'a> {
-    pub fn del(removed: usize) -> Self {
-        StoreCommandResult::DEL(DELResult { payload: removed })
+    pub fn del(removed: usize, root: Option<[u8; 32]>) -> Self {
+        StoreCommandResult::DEL(DELResult {
+            payload: removed,
+            root,
+        })
     }
 
     pub fn exists(count: usize) -> Self {
         StoreCommandResult::EXISTS(EXISTSResult { payload: count })
     }
 
-    pub fn get<V>(value: Option<V>) -> Self
+    pub fn get<V>(value: Option<V>, version: u64) -> Self
     where
         V: Into<Cow<'a, str>>,
     {
         StoreCommandResult::GET(GETResult {
             payload: value.map(|v| v.into()),
+            version,
         })
     }
 
@@ -38,8 +68,11 @@ impl<'a> StoreCommandResult<'a> {
         StoreCommandResult::KEYS(KEYSResult { payload })
     }
 
-    pub fn set(success: bool) -> Self {
-        StoreCommandResult::SET(SETResult { payload: success })
+    pub fn set(success: bool, root: Option<[u8; 32]>) -> Self {
+        StoreCommandResult::SET(SETResult {
+            payload: success,
+            root,
+        })
     }
 
     pub fn undefined<V>(message: V) -> Self
@@ -50,12 +83,263 @@ impl<'a> StoreCommandResult<'a> {
             payload: message.into(),
         })
     }
+
+    pub fn error<V>(kind: ErrorKind, message: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommandResult::ERROR(ErrorResult {
+            kind,
+            message: message.into(),
+        })
+    }
+
+    pub fn expire(is_set: bool) -> Self {
+        StoreCommandResult::EXPIRE(EXPIREResult { payload: is_set })
+    }
+
+    pub fn ttls<I, K>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = (K, u64)>,
+        K: Into<Cow<'a, str>>,
+    {
+        let payload = entries
+            .into_iter()
+            .map(|(k, ttl)| (k.into(), ttl))
+            .collect();
+        StoreCommandResult::TTLS(TTLSResult { payload })
+    }
+
+    pub fn int(value: i64) -> Self {
+        StoreCommandResult::INT(IntResult { payload: value })
+    }
+
+    pub fn bool(value: bool) -> Self {
+        StoreCommandResult::BOOL(BoolResult { payload: value })
+    }
+
+    pub fn treeinfo(root: Option<[u8; 32]>, keys: usize, depth: usize) -> Self {
+        StoreCommandResult::TREEINFO(TREEINFOResult { root, keys, depth })
+    }
+
+    pub fn dumpjson<V>(json: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommandResult::DUMPJSON(DUMPJSONResult {
+            payload: json.into(),
+        })
+    }
+
+    pub fn keyhashes(hashes: HashMap<String, [u8; 32]>) -> Self {
+        StoreCommandResult::KEYHASHES(KEYHASHESResult { payload: hashes })
+    }
+
+    pub fn lrange<I>(items: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'a, str>>,
+    {
+        let payload = items.into_iter().map(|i| i.into()).collect();
+        StoreCommandResult::LRANGE(LRANGEResult { payload })
+    }
+
+    pub fn key_type<V>(name: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommandResult::TYPE(TYPEResult {
+            payload: name.into(),
+        })
+    }
+
+    pub fn hget<V>(value: Option<V>) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommandResult::HGET(HGETResult {
+            payload: value.map(|v| v.into()),
+        })
+    }
+
+    pub fn hgetall<I, F, V>(fields: I) -> Self
+    where
+        I: IntoIterator<Item = (F, V)>,
+        F: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        let payload = fields
+            .into_iter()
+            .map(|(f, v)| (f.into(), v.into()))
+            .collect();
+        StoreCommandResult::HGETALL(HGETALLResult { payload })
+    }
+
+    pub fn smembers<I>(members: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'a, str>>,
+    {
+        let payload = members.into_iter().map(|m| m.into()).collect();
+        StoreCommandResult::SMEMBERS(SMEMBERSResult { payload })
+    }
+
+    pub fn metrics(key_sizes: SizeHistogram, value_sizes: SizeHistogram) -> Self {
+        StoreCommandResult::METRICS(Box::new(METRICSResult {
+            key_sizes,
+            value_sizes,
+        }))
+    }
+
+    pub fn version<V>(crate_version: V, git_hash: V, protocol_version: u32) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        StoreCommandResult::VERSION(VERSIONResult {
+            crate_version: crate_version.into(),
+            git_hash: git_hash.into(),
+            protocol_version,
+        })
+    }
+
+    pub fn rebuild(root: Option<[u8; 32]>) -> Self {
+        StoreCommandResult::REBUILD(REBUILDResult { root })
+    }
+
+    pub fn peers(agree: Vec<String>, disagree: Vec<String>) -> Self {
+        StoreCommandResult::PEERS(PEERSResult { agree, disagree })
+    }
+
+    pub fn tracker(peers: Vec<TrackedPeer>) -> Self {
+        StoreCommandResult::TRACKER(TRACKERResult { peers })
+    }
+
+    pub fn casv(success: bool, version: u64) -> Self {
+        StoreCommandResult::CASV(CASVResult {
+            payload: success,
+            version,
+        })
+    }
+
+    /// The result as a JSON object whose `type` is the variant name, roots and
+    /// hashes are hex strings
+    pub fn to_json(&self) -> serde_json::Value {
+        let root = |root: &Option<[u8; 32]>| root.as_ref().map(|root| to_hex(root));
+        match self {
+            StoreCommandResult::DEL(r) => {
+                json!({"type": "DEL", "payload": r.payload, "root": root(&r.root)})
+            }
+            StoreCommandResult::EXISTS(r) => json!({"type": "EXISTS", "payload": r.payload}),
+            StoreCommandResult::GET(r) => {
+                json!({"type": "GET", "payload": r.payload, "version": r.version})
+            }
+            StoreCommandResult::KEYS(r) => json!({"type": "KEYS", "payload": r.payload}),
+            StoreCommandResult::SET(r) => {
+                json!({"type": "SET", "payload": r.payload, "root": root(&r.root)})
+            }
+            StoreCommandResult::UNDEFINED(r) => {
+                json!({"type": "UNDEFINED", "payload": r.payload})
+            }
+            StoreCommandResult::EXPIRE(r) => json!({"type": "EXPIRE", "payload": r.payload}),
+            StoreCommandResult::TTLS(r) => {
+                let ttls: serde_json::Map<String, serde_json::Value> = r
+                    .payload
+                    .iter()
+                    .map(|(key, ttl)| (key.to_string(), json!(ttl)))
+                    .collect();
+                json!({"type": "TTLS", "payload": ttls})
+            }
+            StoreCommandResult::CASV(r) => {
+                json!({"type": "CASV", "payload": r.payload, "version": r.version})
+            }
+            StoreCommandResult::INT(r) => json!({"type": "INT", "payload": r.payload}),
+            StoreCommandResult::TREEINFO(r) => json!({
+                "type": "TREEINFO",
+                "root": root(&r.root),
+                "keys": r.keys,
+                "depth": r.depth,
+            }),
+            StoreCommandResult::DUMPJSON(r) => {
+                // embedded as is unless the node sent something that isn't JSON
+                let payload = serde_json::from_str(&r.payload)
+                    .unwrap_or_else(|_| serde_json::Value::from(r.payload.as_ref()));
+                json!({"type": "DUMPJSON", "payload": payload})
+            }
+            StoreCommandResult::KEYHASHES(r) => {
+                let hashes: serde_json::Map<String, serde_json::Value> = r
+                    .payload
+                    .iter()
+                    .map(|(key, hash)| (key.clone(), json!(to_hex(hash))))
+                    .collect();
+                json!({"type": "KEYHASHES", "payload": hashes})
+            }
+            StoreCommandResult::LRANGE(r) => json!({"type": "LRANGE", "payload": r.payload}),
+            StoreCommandResult::TYPE(r) => json!({"type": "TYPE", "payload": r.payload}),
+            StoreCommandResult::HGET(r) => json!({"type": "HGET", "payload": r.payload}),
+            StoreCommandResult::HGETALL(r) => {
+                let fields: serde_json::Map<String, serde_json::Value> = r
+                    .payload
+                    .iter()
+                    .map(|(field, value)| (field.to_string(), json!(value)))
+                    .collect();
+                json!({"type": "HGETALL", "payload": fields})
+            }
+            StoreCommandResult::SMEMBERS(r) => {
+                json!({"type": "SMEMBERS", "payload": r.payload})
+            }
+            StoreCommandResult::METRICS(r) => json!({
+                "type": "METRICS",
+                "key_sizes": r.key_sizes.buckets,
+                "value_sizes": r.value_sizes.buckets,
+            }),
+            StoreCommandResult::VERSION(r) => json!({
+                "type": "VERSION",
+                "crate_version": r.crate_version,
+                "git_hash": r.git_hash,
+                "protocol_version": r.protocol_version,
+            }),
+            StoreCommandResult::PEERS(r) => json!({
+                "type": "PEERS",
+                "agree": r.agree,
+                "disagree": r.disagree,
+            }),
+            StoreCommandResult::REBUILD(r) => {
+                json!({"type": "REBUILD", "root": root(&r.root)})
+            }
+            StoreCommandResult::BOOL(r) => json!({"type": "BOOL", "payload": r.payload}),
+            StoreCommandResult::TRACKER(r) => {
+                let peers: Vec<_> = r
+                    .peers
+                    .iter()
+                    .map(|peer| {
+                        // json numbers cannot hold every u128
+                        let local_timestamp =
+                            u64::try_from(peer.local_timestamp).unwrap_or(u64::MAX);
+                        json!({
+                            "peer_id": peer.peer_id,
+                            "root": root(&peer.root),
+                            "local_timestamp": local_timestamp,
+                            "weight": peer.weight,
+                        })
+                    })
+                    .collect();
+                json!({"type": "TRACKER", "peers": peers})
+            }
+            StoreCommandResult::ERROR(r) => json!({
+                "type": "ERROR",
+                "kind": r.kind.name(),
+                "message": r.message,
+            }),
+        }
+    }
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Clone)]
 pub struct DELResult {
     /// the number of keys that were removed
     pub payload: usize,
+    /// the monotree root after the command
+    pub root: Option<[u8; 32]>,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Clone)]
@@ -68,6 +352,8 @@ pub struct EXISTSResult {
 pub struct GETResult<'a> {
     /// the value associated with the key, or `None` if not found
     pub payload: Option<Cow<'a, str>>,
+    /// the number of writes applied to the key, `0` if it was never written
+    pub version: u64,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Clone)]
@@ -80,6 +366,8 @@ pub struct KEYSResult<'a> {
 pub struct SETResult {
     /// `true` if the key was set
     pub payload: bool,
+    /// the monotree root after the command
+    pub root: Option<[u8; 32]>,
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Clone)]
@@ -88,6 +376,190 @@ pub struct UNDEFINEDResult<'a> {
     pub payload: Cow<'a, str>,
 }
 
+/// Whose fault a failed command is, the request or the node
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorKind {
+    /// the command can never succeed as sent, e.g. an invalid pattern
+    BadRequest,
+    /// the key or field the command needs does not exist
+    NotFound,
+    /// the command is valid but the current state rejects it, e.g. a key of
+    /// another type or a full store
+    Conflict,
+    /// the node failed to run the command, retrying may succeed
+    Internal,
+    /// the `--acl` of the node refuses the command to the manager
+    Forbidden,
+}
+
+impl ErrorKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::BadRequest => "BAD_REQUEST",
+            ErrorKind::NotFound => "NOT_FOUND",
+            ErrorKind::Conflict => "CONFLICT",
+            ErrorKind::Internal => "INTERNAL",
+            ErrorKind::Forbidden => "FORBIDDEN",
+        }
+    }
+
+    /// Exit status a script running the command would report, from `sysexits.h`
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::BadRequest => 64,
+            ErrorKind::NotFound => 66,
+            ErrorKind::Conflict => 65,
+            ErrorKind::Internal => 70,
+            ErrorKind::Forbidden => 77,
+        }
+    }
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct ErrorResult<'a> {
+    pub kind: ErrorKind,
+    /// what went wrong, for a human
+    pub message: Cow<'a, str>,
+}
+
+impl From<StoreError> for StoreCommandResult<'_> {
+    fn from(err: StoreError) -> Self {
+        StoreCommandResult::error(err.kind(), err.to_string())
+    }
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct EXPIREResult {
+    /// `true` if the timeout was set, `false` if the key does not exist
+    pub payload: bool,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct TTLSResult<'a> {
+    /// volatile keys with their remaining time to live in seconds, sorted by key
+    pub payload: Vec<(Cow<'a, str>, u64)>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct CASVResult {
+    /// `true` if the expected version matched and the value was written
+    pub payload: bool,
+    /// the version of the key after the command
+    pub version: u64,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct BoolResult {
+    /// a yes or no reply, like whether all of the keys exist
+    pub payload: bool,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct IntResult {
+    /// an integer reply, negative values report special cases like a missing key
+    pub payload: i64,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct TREEINFOResult {
+    /// the monotree root, `None` for an empty store
+    pub root: Option<[u8; 32]>,
+    /// the number of keys in the store
+    pub keys: usize,
+    /// the number of nodes on the longest path from the root to a leaf
+    pub depth: usize,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct DUMPJSONResult<'a> {
+    /// the store as JSON, see `Store::to_json`
+    pub payload: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct KEYHASHESResult {
+    /// every key with the SHA-256 hash of its value
+    pub payload: HashMap<String, [u8; 32]>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct LRANGEResult<'a> {
+    /// the requested elements of the list, empty if the key does not exist
+    pub payload: Vec<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct TYPEResult<'a> {
+    /// `string`, `list`, `hash`, `set`, or `none` if the key does not exist
+    pub payload: Cow<'a, str>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct HGETResult<'a> {
+    /// the value of the field, or `None` if the field or the key does not exist
+    pub payload: Option<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct HGETALLResult<'a> {
+    /// every field of the hash with its value, sorted by field
+    pub payload: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct SMEMBERSResult<'a> {
+    /// every member of the set, sorted, empty if the key does not exist
+    pub payload: Vec<Cow<'a, str>>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct METRICSResult {
+    /// lengths of the stored keys
+    pub key_sizes: SizeHistogram,
+    /// lengths of the stored values
+    pub value_sizes: SizeHistogram,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct VERSIONResult<'a> {
+    /// the crate version of the node build
+    pub crate_version: Cow<'a, str>,
+    /// the commit of the node build, `unknown` if it wasn't known at build time
+    pub git_hash: Cow<'a, str>,
+    /// see `protocol::PROTOCOL_VERSION`
+    pub protocol_version: u32,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct PEERSResult {
+    /// peers whose last signature has the root of the node
+    pub agree: Vec<String>,
+    /// peers whose last signature has another root
+    pub disagree: Vec<String>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct REBUILDResult {
+    /// the monotree root once rebuilt, `None` for an empty store
+    pub root: Option<[u8; 32]>,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct TRACKERResult {
+    /// sorted by peer id
+    pub peers: Vec<TrackedPeer>,
+}
+
+/// The last signature a node holds for one peer
+#[derive(Encode, Decode, Debug, PartialEq, Clone)]
+pub struct TrackedPeer {
+    pub peer_id: String,
+    pub root: Option<[u8; 32]>,
+    /// local time of the peer when it signed, in milliseconds
+    pub local_timestamp: u128,
+    pub weight: u32,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,8 +567,14 @@ mod tests {
 
     #[test]
     fn result_del() {
-        let res = StoreCommandResult::del(3);
-        assert_eq!(res, StoreCommandResult::DEL(DELResult { payload: 3 }));
+        let res = StoreCommandResult::del(3, None);
+        assert_eq!(
+            res,
+            StoreCommandResult::DEL(DELResult {
+                payload: 3,
+                root: None
+            })
+        );
     }
 
     #[test]
@@ -107,15 +585,15 @@ mod tests {
 
     #[test]
     fn result_get_some() {
-        let res = StoreCommandResult::get(Some("value"));
+        let res = StoreCommandResult::get(Some("value"), 3);
         assert!(
-            matches!(res, StoreCommandResult::GET(r) if r.payload == Some(Cow::Borrowed("value")))
+            matches!(res, StoreCommandResult::GET(r) if r.payload == Some(Cow::Borrowed("value")) && r.version == 3)
         );
     }
 
     #[test]
     fn result_get_none() {
-        let res = StoreCommandResult::get::<String>(None);
+        let res = StoreCommandResult::get::<String>(None, 0);
         assert!(matches!(res, StoreCommandResult::GET(r) if r.payload.is_none()));
     }
 
@@ -128,8 +606,14 @@ mod tests {
 
     #[test]
     fn result_set_success() {
-        let res = StoreCommandResult::set(true);
-        assert_eq!(res, StoreCommandResult::SET(SETResult { payload: true }));
+        let res = StoreCommandResult::set(true, Some([1; 32]));
+        assert_eq!(
+            res,
+            StoreCommandResult::SET(SETResult {
+                payload: true,
+                root: Some([1; 32])
+            })
+        );
     }
 
     #[test]
@@ -138,9 +622,75 @@ mod tests {
         assert!(matches!(res, StoreCommandResult::UNDEFINED(r) if r.payload == "not supported"));
     }
 
+    #[test]
+    fn result_error() {
+        let res = StoreCommandResult::from(StoreError::KeyTooLong(40));
+        assert!(matches!(
+            &res,
+            StoreCommandResult::ERROR(r) if r.kind == ErrorKind::BadRequest && r.message.contains("40")
+        ));
+        assert_eq!(res.to_json()["kind"], serde_json::json!("BAD_REQUEST"));
+    }
+
+    #[test]
+    fn result_ttls() {
+        let res = StoreCommandResult::ttls(vec![("a", 5), ("b", 10)]);
+        assert_eq!(
+            res,
+            StoreCommandResult::TTLS(TTLSResult {
+                payload: vec![(Cow::Borrowed("a"), 5), (Cow::Borrowed("b"), 10)]
+            })
+        );
+    }
+
+    #[test]
+    fn result_casv() {
+        let res = StoreCommandResult::casv(false, 4);
+        assert_eq!(
+            res,
+            StoreCommandResult::CASV(CASVResult {
+                payload: false,
+                version: 4
+            })
+        );
+    }
+
+    #[test]
+    fn result_to_json() {
+        assert_eq!(
+            StoreCommandResult::set(true, Some([0xab; 32])).to_json(),
+            serde_json::json!({"type": "SET", "payload": true, "root": "ab".repeat(32)})
+        );
+        assert_eq!(
+            StoreCommandResult::get::<String>(None, 0).to_json(),
+            serde_json::json!({"type": "GET", "payload": null, "version": 0})
+        );
+        assert_eq!(
+            StoreCommandResult::dumpjson(r#"{"keys":{}}"#).to_json()["payload"],
+            serde_json::json!({"keys": {}})
+        );
+        assert_eq!(
+            StoreCommandResult::peers(vec!["p1".to_string()], Vec::new()).to_json(),
+            serde_json::json!({"type": "PEERS", "agree": ["p1"], "disagree": []})
+        );
+        assert_eq!(
+            StoreCommandResult::bool(false).to_json(),
+            serde_json::json!({"type": "BOOL", "payload": false})
+        );
+    }
+
+    #[test]
+    fn bincode_roundtrip_version() {
+        let original = StoreCommandResult::version("0.1.0", "abc1234", 1);
+        let encoded = bincode::encode_to_vec(&original, bincode::config::standard()).unwrap();
+        let (decoded, _): (StoreCommandResult, _) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        assert_eq!(original, decoded);
+    }
+
     #[test]
     fn bincode_roundtrip_del() {
-        let original = StoreCommandResult::del(5);
+        let original = StoreCommandResult::del(5, Some([7; 32]));
         let encoded = bincode::encode_to_vec(&original, bincode::config::standard()).unwrap();
         let (decoded, _): (StoreCommandResult, _) =
             bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
@@ -149,7 +699,7 @@ mod tests {
 
     #[test]
     fn bincode_roundtrip_get_with_string() {
-        let original = StoreCommandResult::get(Some("hello".to_string()));
+        let original = StoreCommandResult::get(Some("hello".to_string()), 1);
         let encoded = bincode::encode_to_vec(&original, bincode::config::standard()).unwrap();
         let (decoded, _): (StoreCommandResult, _) =
             bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
@@ -0,0 +1,237 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use super::{backend::KvBackend, error::StoreError, value::StoreValue, Store};
+use crate::utils::bin_args::SnapshotFormat;
+
+/// First bytes of a bincode snapshot, a JSON snapshot starts with `{`
+const BINCODE_MAGIC: &[u8] = b"DIFIEW-SNAPSHOT\0";
+
+/// A key, its value and its expiration deadline (unix millis)
+type Entry = (String, StoreValue, Option<u128>);
+
+impl<B: KvBackend> Store<B> {
+    /// Writes every pair and its deadline to `path`, see `--snapshot-format`
+    pub fn save_to_path(
+        &self,
+        path: impl AsRef<Path>,
+        format: SnapshotFormat,
+    ) -> Result<(), StoreError> {
+        let mut entries: Vec<Entry> = Vec::new();
+        for entry in self.data.iter() {
+            let (key, value) = entry?;
+            let deadline = self.expires.get(key.as_ref()).copied();
+            entries.push((key.into_owned(), value.into_owned(), deadline));
+        }
+        entries.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        let bytes = match format {
+            SnapshotFormat::Bincode => {
+                let mut bytes = BINCODE_MAGIC.to_vec();
+                bytes.extend(
+                    bincode::encode_to_vec(&entries, bincode::config::standard())
+                        .map_err(snapshot_error)?,
+                );
+                bytes
+            }
+            SnapshotFormat::Json => encode_json(&entries).into_bytes(),
+        };
+        std::fs::write(path, bytes).map_err(snapshot_error)
+    }
+
+    /// Replaces the content of the store with the snapshot at `path`, written
+    /// in either format, and returns how many keys were loaded. Keys keep the
+    /// deadline they had when saved, the ones past it expire as usual
+    pub fn load_from_path(&mut self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(snapshot_error)?;
+        let entries = match detect_format(path, &bytes) {
+            Some(SnapshotFormat::Bincode) => {
+                let (entries, _): (Vec<Entry>, _) = bincode::decode_from_slice(
+                    &bytes[BINCODE_MAGIC.len()..],
+                    bincode::config::standard(),
+                )
+                .map_err(snapshot_error)?;
+                entries
+            }
+            Some(SnapshotFormat::Json) => decode_json(&bytes)?,
+            None => return Err(snapshot_error("unknown snapshot format")),
+        };
+
+        let loaded = entries.len();
+        let expires: BTreeMap<String, u128> = entries
+            .iter()
+            .filter_map(|(key, _, deadline)| Some((key.clone(), (*deadline)?)))
+            .collect();
+        self.update_full_store(
+            entries
+                .into_iter()
+                .map(|(key, value, _)| (key, value))
+                .collect(),
+        )?;
+        self.expires = expires;
+        self.reaper_cursor = None;
+        Ok(loaded)
+    }
+}
+
+/// Format of a snapshot: bincode when it starts with its header, JSON when
+/// the file is named `*.json` or its content looks like an object
+pub fn detect_format(path: &Path, bytes: &[u8]) -> Option<SnapshotFormat> {
+    if bytes.starts_with(BINCODE_MAGIC) {
+        return Some(SnapshotFormat::Bincode);
+    }
+    let json_extension = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let json_content = bytes.trim_ascii_start().starts_with(b"{");
+    (json_extension || json_content).then_some(SnapshotFormat::Json)
+}
+
+/// Every value is tagged with its type, lists and sets are both arrays
+fn encode_json(entries: &[Entry]) -> String {
+    let keys: serde_json::Map<String, Value> = entries
+        .iter()
+        .map(|(key, value, deadline)| {
+            let mut entry = serde_json::json!({
+                "type": value.type_name(),
+                "value": value.to_json(),
+            });
+            if let Some(deadline) = deadline {
+                entry["expires"] = Value::from(u64::try_from(*deadline).unwrap_or(u64::MAX));
+            }
+            (key.clone(), entry)
+        })
+        .collect();
+    serde_json::to_string_pretty(&serde_json::json!({ "keys": keys }))
+        .expect("json values always serialize")
+}
+
+fn decode_json(bytes: &[u8]) -> Result<Vec<Entry>, StoreError> {
+    let snapshot: Value = serde_json::from_slice(bytes).map_err(snapshot_error)?;
+    let keys = snapshot
+        .get("keys")
+        .and_then(Value::as_object)
+        .ok_or_else(|| snapshot_error("no keys object"))?;
+
+    let mut entries = Vec::new();
+    for (key, entry) in keys {
+        let value = entry
+            .get("type")
+            .and_then(Value::as_str)
+            .zip(entry.get("value"))
+            .and_then(|(kind, value)| value_from_json(kind, value))
+            .ok_or_else(|| snapshot_error(format!("invalid value for {key}")))?;
+        let deadline = entry
+            .get("expires")
+            .and_then(Value::as_u64)
+            .map(u128::from);
+        entries.push((key.clone(), value, deadline));
+    }
+    Ok(entries)
+}
+
+fn value_from_json(kind: &str, value: &Value) -> Option<StoreValue> {
+    let strings = || -> Option<Vec<String>> {
+        value
+            .as_array()?
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect()
+    };
+    match kind {
+        "string" => value.as_str().map(StoreValue::from),
+        "list" => strings().map(StoreValue::List),
+        "set" => strings().map(|members| StoreValue::Set(members.into_iter().collect())),
+        "hash" => value
+            .as_object()?
+            .iter()
+            .map(|(field, value)| Some((field.clone(), value.as_str()?.to_string())))
+            .collect::<Option<HashMap<String, String>>>()
+            .map(StoreValue::Hash),
+        _ => None,
+    }
+}
+
+fn snapshot_error(err: impl ToString) -> StoreError {
+    StoreError::SnapshotError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::command::StoreCommand;
+
+    fn populated_store() -> Store {
+        let mut store = Store::new();
+        for cmd in [
+            StoreCommand::set("color", "red"),
+            StoreCommand::rpush("queue", ["a", "b"]),
+            StoreCommand::hset("user:1", "name", "ada"),
+            StoreCommand::sadd("tags", ["x", "y"]),
+            StoreCommand::set("session", "abc"),
+            StoreCommand::expire("session", 3600),
+        ] {
+            store.execute(cmd).unwrap();
+        }
+        store
+    }
+
+    fn snapshot_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("difiew-{name}-{}", std::process::id()))
+    }
+
+    fn roundtrip(format: SnapshotFormat, name: &str) {
+        let store = populated_store();
+        let path = snapshot_path(name);
+        store.save_to_path(&path, format).unwrap();
+
+        let mut loaded = Store::new();
+        let count = loaded.load_from_path(&path);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(count.unwrap(), 5);
+        assert_eq!(detect_format(&path, &bytes), Some(format));
+        assert_eq!(loaded.get_main_store(), store.get_main_store());
+        assert_eq!(loaded.reveal_root(), store.reveal_root());
+        assert_eq!(loaded.expires, store.expires);
+    }
+
+    #[test]
+    fn bincode_snapshot_roundtrip() {
+        roundtrip(SnapshotFormat::Bincode, "snapshot-bincode");
+    }
+
+    #[test]
+    fn json_snapshot_roundtrip() {
+        roundtrip(SnapshotFormat::Json, "snapshot-json");
+    }
+
+    #[test]
+    fn format_is_detected_by_header_extension_or_content() {
+        let bin = Path::new("store.snap");
+        let json = Path::new("store.json");
+        assert_eq!(
+            detect_format(bin, BINCODE_MAGIC),
+            Some(SnapshotFormat::Bincode)
+        );
+        assert_eq!(detect_format(bin, b" {\"keys\": {}}"), Some(SnapshotFormat::Json));
+        assert_eq!(detect_format(json, b""), Some(SnapshotFormat::Json));
+        assert_eq!(detect_format(bin, b"\x00\x01"), None);
+    }
+
+    #[test]
+    fn unknown_snapshot_is_refused() {
+        let path = snapshot_path("snapshot-unknown");
+        std::fs::write(&path, b"\x00\x01").unwrap();
+        let mut store = populated_store();
+        let result = store.load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(StoreError::SnapshotError(_))));
+        // a refused snapshot leaves the store as it was
+        assert_eq!(store.get_main_store(), populated_store().get_main_store());
+    }
+}
@@ -0,0 +1,235 @@
+use bincode::{Decode, Encode};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Bytes of the length prefixing every element in `canonical_encode`
+const PREFIX_LEN: usize = std::mem::size_of::<u64>();
+
+/// Value held by a key
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+pub enum StoreValue {
+    Str(String),
+    List(Vec<String>),
+    Hash(HashMap<String, String>),
+    Set(HashSet<String>),
+}
+
+impl StoreValue {
+    /// Name of the value type as reported by `TYPE`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            StoreValue::Str(_) => "string",
+            StoreValue::List(_) => "list",
+            StoreValue::Hash(_) => "hash",
+            StoreValue::Set(_) => "set",
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            StoreValue::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Bytes hashed into the monotree and the fingerprint. Unlike the bincode
+    /// wire encoding they only depend on the content of the value, never on
+    /// how the node holding it happens to lay it out in memory.
+    ///
+    /// Every type, strings included, starts with its own tag byte and
+    /// length-prefixes every element, so two different values never share
+    /// their bytes and element boundaries cannot be shifted. Hash fields and
+    /// set members are sorted first, their order in memory is arbitrary.
+    pub fn canonical_encode(&self) -> Vec<u8> {
+        match self {
+            StoreValue::Str(value) => self.tagged_bytes(std::iter::once(value)),
+            StoreValue::List(items) => self.tagged_bytes(items.iter()),
+            StoreValue::Hash(fields) => {
+                let sorted: BTreeMap<&String, &String> = fields.iter().collect();
+                self.tagged_bytes(sorted.into_iter().flat_map(|(f, v)| [f, v]))
+            }
+            StoreValue::Set(members) => {
+                let sorted: BTreeSet<&String> = members.iter().collect();
+                self.tagged_bytes(sorted.into_iter())
+            }
+        }
+    }
+
+    /// Bytes the value weighs against `max_value_size` and in the size
+    /// histograms: the bytes of a string, the length of the canonical
+    /// encoding of the other types
+    pub fn size(&self) -> usize {
+        let prefixed = |element: &String| PREFIX_LEN + element.len();
+        match self {
+            StoreValue::Str(value) => value.len(),
+            StoreValue::List(items) => 1 + items.iter().map(prefixed).sum::<usize>(),
+            StoreValue::Hash(fields) => {
+                1 + fields
+                    .iter()
+                    .map(|(f, v)| prefixed(f) + prefixed(v))
+                    .sum::<usize>()
+            }
+            StoreValue::Set(members) => 1 + members.iter().map(prefixed).sum::<usize>(),
+        }
+    }
+
+    /// Approximate number of bytes the value takes in memory, the element
+    /// bytes plus the header of every `String` and the map slots
+    pub fn heap_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match self {
+                StoreValue::Str(value) => value.len(),
+                StoreValue::List(items) => strings_size(items.iter()),
+                StoreValue::Hash(fields) => {
+                    strings_size(fields.iter().flat_map(|(f, v)| [f, v])) + fields.len() * 8
+                }
+                StoreValue::Set(members) => strings_size(members.iter()) + members.len() * 8,
+            }
+    }
+
+    /// Tag byte of the type in `canonical_encode`
+    fn tag(&self) -> u8 {
+        match self {
+            StoreValue::Str(_) => 0,
+            StoreValue::List(_) => 1,
+            StoreValue::Hash(_) => 2,
+            StoreValue::Set(_) => 3,
+        }
+    }
+
+    fn tagged_bytes<'s>(&self, elements: impl Iterator<Item = &'s String>) -> Vec<u8> {
+        let mut bytes = vec![self.tag()];
+        for element in elements {
+            bytes.extend_from_slice(&(element.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(element.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            StoreValue::Str(value) => serde_json::Value::from(value.as_str()),
+            StoreValue::List(items) => serde_json::Value::from(items.clone()),
+            StoreValue::Hash(fields) => serde_json::json!(fields),
+            StoreValue::Set(members) => serde_json::json!(members.iter().collect::<BTreeSet<_>>()),
+        }
+    }
+}
+
+/// Bytes of the strings with their headers
+fn strings_size<'s>(strings: impl Iterator<Item = &'s String>) -> usize {
+    strings
+        .map(|string| string.len() + std::mem::size_of::<String>())
+        .sum()
+}
+
+impl From<&str> for StoreValue {
+    fn from(value: &str) -> Self {
+        StoreValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for StoreValue {
+    fn from(value: String) -> Self {
+        StoreValue::Str(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[&str]) -> StoreValue {
+        StoreValue::List(items.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn string_canonical_encoding_is_tagged() {
+        assert_eq!(
+            StoreValue::from("red").canonical_encode(),
+            b"\x00\x03\x00\x00\x00\x00\x00\x00\x00red"
+        );
+    }
+
+    #[test]
+    fn canonical_encodings_of_different_types_never_collide() {
+        // the encoding of the list ["a"] used to be a valid string on its own
+        let mut forged = b"list".to_vec();
+        forged.extend_from_slice(&1u64.to_le_bytes());
+        forged.push(b'a');
+        let forged = StoreValue::from(String::from_utf8(forged).unwrap());
+        assert_ne!(forged.canonical_encode(), list(&["a"]).canonical_encode());
+
+        let forged = StoreValue::from(
+            String::from_utf8(list(&["a"]).canonical_encode()).unwrap(),
+        );
+        assert_ne!(forged.canonical_encode(), list(&["a"]).canonical_encode());
+        assert_ne!(
+            StoreValue::from("").canonical_encode(),
+            list(&[]).canonical_encode()
+        );
+    }
+
+    #[test]
+    fn size_is_the_string_or_encoding_length() {
+        assert_eq!(StoreValue::from("red").size(), 3);
+        let values = [
+            list(&["ab", "c"]),
+            StoreValue::Hash(HashMap::from([("f".to_string(), "v".to_string())])),
+            StoreValue::Set(HashSet::from(["a".to_string()])),
+        ];
+        for value in values {
+            assert_eq!(value.size(), value.canonical_encode().len());
+        }
+    }
+
+    #[test]
+    fn list_encoding_keeps_element_boundaries() {
+        assert_ne!(
+            list(&["ab", "c"]).canonical_encode(),
+            list(&["a", "bc"]).canonical_encode()
+        );
+        assert_ne!(
+            list(&["a", "b"]).canonical_encode(),
+            list(&["b", "a"]).canonical_encode()
+        );
+        assert_ne!(list(&[]).canonical_encode(), list(&[""]).canonical_encode());
+    }
+
+    #[test]
+    fn hash_encoding_ignores_field_order() {
+        let mut first = HashMap::new();
+        let mut second = HashMap::new();
+        for i in 0..16 {
+            first.insert(format!("field{i}"), i.to_string());
+        }
+        for i in (0..16).rev() {
+            second.insert(format!("field{i}"), i.to_string());
+        }
+        assert_eq!(
+            StoreValue::Hash(first).canonical_encode(),
+            StoreValue::Hash(second).canonical_encode()
+        );
+    }
+
+    #[test]
+    fn set_encoding_ignores_insertion_order() {
+        let first: HashSet<String> = (0..16).map(|i| i.to_string()).collect();
+        let second: HashSet<String> = (0..16).rev().map(|i| i.to_string()).collect();
+        assert_eq!(
+            StoreValue::Set(first).canonical_encode(),
+            StoreValue::Set(second).canonical_encode()
+        );
+        assert_ne!(
+            StoreValue::Set(HashSet::from(["a".to_string()])).canonical_encode(),
+            list(&["a"]).canonical_encode()
+        );
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(StoreValue::from("red").type_name(), "string");
+        assert_eq!(list(&["red"]).type_name(), "list");
+        assert_eq!(StoreValue::Hash(HashMap::new()).type_name(), "hash");
+        assert_eq!(StoreValue::Set(HashSet::new()).type_name(), "set");
+    }
+}
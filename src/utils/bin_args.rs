@@ -1,8 +1,75 @@
-use clap::Parser;
+use clap::{
+    parser::ValueSource, ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum,
+};
+use std::ffi::OsString;
+use std::io;
 
-#[derive(Parser, Debug, Clone, Default)]
-#[command(version, about)]
+use super::config_file;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryStrategy {
+    /// Expired keys are removed only when a command touches them
+    Lazy,
+    /// Expired keys are also removed periodically in the background
+    Active,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairMode {
+    /// A node noticing it diverged asks a majority peer for its data
+    Pull,
+    /// A majority node sends its data to the peers it sees diverging
+    Push,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyHashing {
+    /// Keys are hashed with SHA-256 before being inserted into the monotree
+    Sha256,
+    /// Key bytes zero-padded to 32 are used as is, faster for short keys but
+    /// keys must be at most 32 bytes and unique once padded
+    Raw,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BincodeConfig {
+    /// Variable-length integers, `bincode::config::standard()`
+    Standard,
+    /// Integers at their full width, for tools expecting the fixed int encoding
+    Fixint,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Muxer {
+    /// Yamux, spoken by every libp2p implementation
+    Yamux,
+    /// Mplex, deprecated upstream, for peers that only speak it
+    Mplex,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// Compact binary encoding of the values
+    Bincode,
+    /// JSON object of the keys, readable and editable by hand
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable lines
+    Plain,
+    /// One JSON object per received result, for scripts
+    Json,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, args_override_self = true)]
 pub struct BinArgs {
+    /// JSON file of flags read before the command line, see `BinArgs::parse_with_config`
+    #[arg(long)]
+    pub config: Option<String>,
+
     #[arg(long, default_value = "test-topic")]
     pub topic: String,
 
@@ -11,4 +78,365 @@ pub struct BinArgs {
 
     #[arg(long, default_value_t = 10)]
     pub heartbeat_interval: u64,
+
+    /// Address of a peer to dial on startup, for networks mDNS doesn't reach.
+    /// Can be repeated
+    #[arg(long)]
+    pub bootstrap: Vec<String>,
+
+    /// How many bootstrap addresses are dialed at once
+    #[arg(
+        long,
+        default_value_t = 8,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub dial_concurrency: usize,
+
+    /// Integer encoding of the published messages, every component of a cluster
+    /// must use the same one
+    #[arg(long, value_enum, default_value_t = BincodeConfig::Standard)]
+    pub bincode_config: BincodeConfig,
+
+    /// Stream multiplexer of the TCP and in-memory connections, QUIC has its
+    /// own. Two peers only connect if they use the same one
+    #[arg(long, value_enum, default_value_t = Muxer::Yamux)]
+    pub muxer: Muxer,
+
+    /// Maximum size in bytes of a single gossipsub message, raise it for large repairs
+    #[arg(long, default_value_t = 65536)]
+    pub gossipsub_max_transmit_size: usize,
+
+    /// How long gossipsub remembers a message id to drop duplicates. Ids hash
+    /// the message data, so a message identical to one sent within this time
+    /// is dropped as well; every message embeds its timestamp, which keeps
+    /// repeated commands distinct
+    #[arg(long, default_value_t = 60)]
+    pub gossipsub_duplicate_cache_time_secs: u64,
+
+    /// Publish own messages to every known peer of the topic rather than only
+    /// to the mesh, more reliable in small clusters at the cost of bandwidth
+    #[arg(long)]
+    pub gossipsub_flood_publish: bool,
+
+    /// Outbound peers every node keeps in its mesh, so a node whose links were
+    /// all opened by others still forwards messages. At most half of the mesh
+    /// size and at most its lower bound
+    #[arg(long, default_value_t = 2)]
+    pub gossipsub_mesh_outbound_min: usize,
+
+    /// How long the manager collects node responses to a quorum command
+    #[arg(long, default_value_t = 500)]
+    pub quorum_window_ms: u64,
+
+    /// Send the majority value to nodes that answered a QGET with a stale one
+    #[arg(long)]
+    pub read_repair: bool,
+
+    /// Answer a GET of a key the manager wrote within `--read-your-writes-window-ms`
+    /// with the written value instead of asking nodes that may lag behind. A
+    /// write is served once a node confirmed it
+    #[arg(long)]
+    pub read_your_writes: bool,
+
+    /// How long the manager serves its own writes with `--read-your-writes`
+    #[arg(long, default_value_t = 5000)]
+    pub read_your_writes_window_ms: u64,
+
+    /// Split the keyspace over this many shards by consistent hashing of the
+    /// keys. The manager publishes a command only to the topic of its shard,
+    /// `<--topic>-<shard>`, on which the nodes of that shard run
+    #[arg(
+        long,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub shards: Option<usize>,
+
+    /// Print the messages the manager would send without publishing them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Only log what the nodes publish, without reading commands from stdin
+    #[arg(long)]
+    pub observer: bool,
+
+    /// Read the commands through a line editor with history and completion of
+    /// the command names, plain stdin is still used when it is not a terminal
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// File of `key value` lines a node loads into its store at startup
+    #[arg(long)]
+    pub preload: Option<String>,
+
+    /// File a node loads its store from at startup, when it exists, and saves
+    /// the store to on shutdown. Either format is detected when loading
+    #[arg(long)]
+    pub snapshot: Option<String>,
+
+    /// How a node writes its `--snapshot`
+    #[arg(long, value_enum, default_value_t = SnapshotFormat::Bincode)]
+    pub snapshot_format: SnapshotFormat,
+
+    /// JSON file of rules granting key prefixes to managers by peer id, a node
+    /// answers `forbidden` to the commands outside of them, see `Acl::read`
+    #[arg(long)]
+    pub acl: Option<String>,
+
+    /// Topic receiving an audit record after every mutation applied by a node
+    #[arg(long)]
+    pub audit_topic: Option<String>,
+
+    /// Log every mutation a node applies with the store root before and after it
+    #[arg(long)]
+    pub log_mutations: bool,
+
+    /// Topic receiving a notification for every key changed by a node, the
+    /// manager prints the ones about keys passed to `WATCH`
+    #[arg(long)]
+    pub notify_topic: Option<String>,
+
+    /// How long a node waits for a repair response before asking another peer
+    #[arg(long, default_value_t = 5000)]
+    pub repair_timeout_ms: u64,
+
+    /// How many other majority peers are asked after the first repair request times out
+    #[arg(long, default_value_t = 3)]
+    pub repair_retries: u32,
+
+    /// Whether a stale node pulls the data or a majority node pushes it. In
+    /// push mode a stale peer is sent the data at most once per `--repair-timeout-ms`
+    #[arg(long, value_enum, default_value_t = RepairMode::Pull)]
+    pub repair_mode: RepairMode,
+
+    /// Every this many seconds a node sends its signature to one known peer in
+    /// turn, and the peer repairs whichever side is stale. Catches divergences
+    /// the signature broadcast missed, 0 disables it
+    #[arg(long, default_value_t = 0)]
+    pub anti_entropy_interval_secs: u64,
+
+    /// How a node removes expired keys
+    #[arg(long, value_enum, default_value_t = ExpiryStrategy::Lazy)]
+    pub expiry_strategy: ExpiryStrategy,
+
+    /// Number of volatile keys the active expiry checks per tick, the rest wait
+    /// for the next ticks
+    #[arg(
+        long,
+        default_value_t = 1000,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub reaper_batch_size: usize,
+
+    /// How keys are turned into monotree keys, `raw` requires unique keys of at most 32 bytes
+    #[arg(long, value_enum, default_value_t = KeyHashing::Sha256)]
+    pub key_hashing: KeyHashing,
+
+    /// Keep the large values compressed in memory, see `--value-compression-threshold`
+    #[arg(long)]
+    pub value_compression: bool,
+
+    /// Size in bytes from which a value is compressed with `--value-compression`
+    #[arg(long, default_value_t = 1024)]
+    pub value_compression_threshold: usize,
+
+    /// Longest key accepted by a write, in bytes, unlimited if not set
+    #[arg(long)]
+    pub max_key_bytes: Option<usize>,
+
+    /// Maximum number of established connections, unlimited if not set
+    #[arg(long)]
+    pub max_connections: Option<u32>,
+
+    /// Maximum number of incoming connections being established at once, unlimited if not set
+    #[arg(long)]
+    pub max_pending_connections: Option<u32>,
+
+    /// How long a connection without any open stream is kept before being closed
+    #[arg(long, default_value_t = 10)]
+    pub idle_connection_timeout_secs: u64,
+
+    /// How much this node counts when the majority root is chosen, authoritative
+    /// seeds can outvote several nodes of weight 1
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub node_weight: u32,
+
+    /// How long a node waits for a store command before replying that it timed out
+    #[arg(long, default_value_t = 5000)]
+    pub command_timeout_ms: u64,
+
+    /// Pre-shared key encrypting every published message, audit records included.
+    /// Components without the same key cannot read them
+    #[arg(long)]
+    pub psk: Option<String>,
+
+    /// Score gossipsub peers and stop exchanging messages with the ones that
+    /// keep sending invalid messages
+    #[arg(long)]
+    pub enable_peer_scoring: bool,
+
+    /// Score below which no gossip is exchanged with a peer
+    #[arg(long, default_value_t = -10.0, allow_negative_numbers = true)]
+    pub peer_score_gossip_threshold: f64,
+
+    /// Score below which nothing is published to a peer
+    #[arg(long, default_value_t = -50.0, allow_negative_numbers = true)]
+    pub peer_score_publish_threshold: f64,
+
+    /// Score below which every message of a peer is ignored
+    #[arg(long, default_value_t = -80.0, allow_negative_numbers = true)]
+    pub peer_score_graylist_threshold: f64,
+
+    /// How the manager prints the results it receives
+    #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+    pub output: OutputFormat,
+}
+
+impl BinArgs {
+    /// Parses the command line of the binaries merged with the flags of the
+    /// `--config` file: a flag given on the command line replaces the file's
+    /// values, a repeated one such as `--bootstrap` too, and a switch the file
+    /// turns on is turned off with `--switch=false`
+    pub fn parse_with_config() -> io::Result<Self> {
+        Self::parse_from_with_config(std::env::args_os())
+    }
+
+    /// Same as [`BinArgs::parse_with_config`] for the given arguments, the
+    /// first one being the binary name
+    pub fn parse_from_with_config<I, T>(args: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let cli = config_command().get_matches_from(&args);
+        let path = cli.get_one::<String>("config").cloned();
+        let (Some(path), Some((bin, rest))) = (path, args.split_first()) else {
+            return Ok(Self::from_matches(&cli));
+        };
+        let file_args = config_file::read_args(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("config {path}: {e}")))?;
+
+        let command = config_command();
+        let set_on_command_line = |file_arg: &String| {
+            let name = file_arg.trim_start_matches("--").split('=').next();
+            command.get_arguments().any(|arg| {
+                arg.get_long() == name
+                    && cli.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+        };
+        let merged: Vec<OsString> = std::iter::once(bin.clone())
+            .chain(
+                file_args
+                    .into_iter()
+                    .filter(|file_arg| !set_on_command_line(file_arg))
+                    .map(OsString::from),
+            )
+            .chain(rest.iter().cloned())
+            .collect();
+        Ok(Self::from_matches(
+            &config_command().get_matches_from(merged),
+        ))
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Self::from_arg_matches(matches).unwrap_or_else(|e| e.exit())
+    }
+}
+
+/// Command of [`BinArgs`] whose switches also take a value, so that
+/// `--switch=false` turns off one the `--config` file turns on
+fn config_command() -> clap::Command {
+    BinArgs::command().mut_args(|arg| match arg.get_action() {
+        ArgAction::SetTrue => arg
+            .action(ArgAction::Set)
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("true")
+            .default_value("false"),
+        _ => arg,
+    })
+}
+
+impl Default for BinArgs {
+    /// Same values as a command line without any flags
+    fn default() -> Self {
+        Self::parse_from([env!("CARGO_PKG_NAME")])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("difiew-{name}-{}.json", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn config_file_populates_args() {
+        let path = config(
+            "config",
+            r#"{"topic": "orders", "heartbeat_interval": 3, "read_repair": true}"#,
+        );
+        let args = BinArgs::parse_from_with_config(["node", "--config", &path]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(args.topic, "orders");
+        assert_eq!(args.heartbeat_interval, 3);
+        assert!(args.read_repair);
+        assert_eq!(args.quorum_window_ms, BinArgs::default().quorum_window_ms);
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let path = config("override", r#"{"topic": "orders", "bootstrap": ["/memory/1"]}"#);
+        let args = BinArgs::parse_from_with_config([
+            "node",
+            "--topic",
+            "payments",
+            "--config",
+            &path,
+            "--bootstrap",
+            "/memory/2",
+        ])
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(args.topic, "payments");
+        assert_eq!(args.bootstrap, ["/memory/2"]);
+    }
+
+    #[test]
+    fn repeated_flag_and_switch_on_the_command_line_override_the_file() {
+        let path = config(
+            "repeated",
+            r#"{"bootstrap": ["/memory/1", "/memory/2"], "read_repair": true, "dry_run": true}"#,
+        );
+        let args = BinArgs::parse_from_with_config([
+            "node",
+            "--config",
+            &path,
+            "--bootstrap",
+            "/memory/3",
+            "--bootstrap",
+            "/memory/4",
+            "--read-repair=false",
+        ])
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(args.bootstrap, ["/memory/3", "/memory/4"]);
+        assert!(!args.read_repair);
+        // untouched on the command line, the file's value holds
+        assert!(args.dry_run);
+    }
+
+    #[test]
+    fn missing_config_file_is_an_error() {
+        let err = BinArgs::parse_from_with_config(["node", "--config", "/nonexistent/difiew.json"])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
 }
@@ -0,0 +1,172 @@
+use crate::node::MyBehaviour;
+use crate::utils::bin_args::BinArgs;
+use libp2p::{
+    swarm::{dial_opts::DialOpts, ConnectionId, DialError},
+    Multiaddr, Swarm,
+};
+use std::collections::{HashSet, VecDeque};
+
+/// Anything able to start a dial, the swarm outside of tests
+pub trait Dialer {
+    /// Starts dialing `address`, the id identifies the attempt in the swarm events
+    fn dial(&mut self, address: Multiaddr) -> Result<ConnectionId, DialError>;
+}
+
+impl Dialer for Swarm<MyBehaviour> {
+    fn dial(&mut self, address: Multiaddr) -> Result<ConnectionId, DialError> {
+        let opts = DialOpts::from(address);
+        let connection_id = opts.connection_id();
+        Swarm::dial(self, opts)?;
+        Ok(connection_id)
+    }
+}
+
+/// Dials the `--bootstrap` addresses at most `--dial-concurrency` at a time,
+/// the next address is dialed once an attempt succeeds or fails
+#[derive(Debug, Default)]
+pub struct Bootstrap {
+    pending: VecDeque<Multiaddr>,
+    in_flight: HashSet<ConnectionId>,
+    concurrency: usize,
+}
+
+impl Bootstrap {
+    pub fn new(addresses: impl IntoIterator<Item = Multiaddr>, concurrency: usize) -> Self {
+        Self {
+            pending: addresses.into_iter().collect(),
+            in_flight: HashSet::new(),
+            concurrency,
+        }
+    }
+
+    /// Addresses of `--bootstrap`, the ones that aren't a valid multiaddr are skipped
+    pub fn from_args(args: &BinArgs) -> Self {
+        let addresses = args
+            .bootstrap
+            .iter()
+            .filter_map(|address| match address.parse() {
+                Ok(address) => Some(address),
+                Err(e) => {
+                    eprintln!("Skipping bootstrap address {address}: {e}");
+                    None
+                }
+            });
+        Self::new(addresses, args.dial_concurrency)
+    }
+
+    /// Starts dials until `concurrency` of them are in flight or no address is left.
+    /// An address that cannot even be dialed doesn't take a slot
+    pub fn dial_next(&mut self, dialer: &mut impl Dialer) {
+        while self.in_flight.len() < self.concurrency
+            && let Some(address) = self.pending.pop_front()
+        {
+            match dialer.dial(address.clone()) {
+                Ok(connection_id) => {
+                    self.in_flight.insert(connection_id);
+                }
+                Err(e) => eprintln!("Failed to dial bootstrap address {address}: {e}"),
+            }
+        }
+    }
+
+    /// Frees the slot of a finished dial and starts the next one, connections
+    /// not started by the bootstrap are ignored
+    pub fn finished(&mut self, connection_id: ConnectionId, dialer: &mut impl Dialer) {
+        if self.in_flight.remove(&connection_id) {
+            self.dial_next(dialer);
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Whether every address was dialed and every dial has finished
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts the dials in flight, they only finish when the test says so
+    #[derive(Default)]
+    struct MockDialer {
+        next_id: usize,
+        dialed: Vec<Multiaddr>,
+        in_flight: usize,
+        max_in_flight: usize,
+    }
+
+    impl Dialer for MockDialer {
+        fn dial(&mut self, address: Multiaddr) -> Result<ConnectionId, DialError> {
+            self.next_id += 1;
+            self.dialed.push(address);
+            self.in_flight += 1;
+            self.max_in_flight = self.max_in_flight.max(self.in_flight);
+            Ok(ConnectionId::new_unchecked(self.next_id))
+        }
+    }
+
+    impl MockDialer {
+        fn finish(&mut self, id: usize, bootstrap: &mut Bootstrap) {
+            self.in_flight -= 1;
+            bootstrap.finished(ConnectionId::new_unchecked(id), self);
+        }
+    }
+
+    fn addresses(count: u16) -> Vec<Multiaddr> {
+        (1..=count)
+            .map(|port| format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn dials_at_most_concurrency_at_once() {
+        let mut dialer = MockDialer::default();
+        let mut bootstrap = Bootstrap::new(addresses(10), 3);
+
+        bootstrap.dial_next(&mut dialer);
+        assert_eq!(bootstrap.in_flight(), 3);
+        assert_eq!(dialer.dialed, addresses(3));
+
+        // dials succeed and fail in any order
+        for id in [2, 1, 4, 3, 6, 5, 8, 7, 10, 9] {
+            dialer.finish(id, &mut bootstrap);
+            assert!(dialer.in_flight <= 3);
+        }
+        assert_eq!(dialer.max_in_flight, 3);
+        assert_eq!(dialer.dialed, addresses(10));
+        assert!(bootstrap.is_done());
+    }
+
+    #[test]
+    fn unrelated_connections_keep_their_slot() {
+        let mut dialer = MockDialer::default();
+        let mut bootstrap = Bootstrap::new(addresses(4), 2);
+        bootstrap.dial_next(&mut dialer);
+
+        bootstrap.finished(ConnectionId::new_unchecked(42), &mut dialer);
+        assert_eq!(dialer.dialed.len(), 2);
+        assert_eq!(bootstrap.in_flight(), 2);
+        assert!(!bootstrap.is_done());
+    }
+
+    #[test]
+    fn invalid_addresses_are_skipped() {
+        let args = BinArgs {
+            bootstrap: vec![
+                "/ip4/127.0.0.1/tcp/4001".to_string(),
+                "nonsense".to_string(),
+            ],
+            dial_concurrency: 4,
+            ..Default::default()
+        };
+        let mut dialer = MockDialer::default();
+        let mut bootstrap = Bootstrap::from_args(&args);
+        bootstrap.dial_next(&mut dialer);
+        assert_eq!(dialer.dialed, ["/ip4/127.0.0.1/tcp/4001".parse().unwrap()]);
+    }
+}
@@ -0,0 +1,6 @@
+/// Version of the crate the binary was built from
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short hash of the commit the binary was built from, `unknown` if the
+/// repository wasn't available, see `build.rs`
+pub const GIT_HASH: &str = env!("DIFIEW_GIT_HASH");
@@ -0,0 +1,65 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::utils::timestamp::timestamp_millis;
+
+/// Source of the wall-clock time used for signatures, metadata and key expiry
+pub trait Clock: Debug + Send + Sync {
+    /// Milliseconds since the unix epoch, `None` if the time is unavailable
+    fn now_millis(&self) -> Option<u128>;
+}
+
+/// The system time, see [`timestamp_millis`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> Option<u128> {
+        timestamp_millis()
+    }
+}
+
+/// Clock that only moves when told to, for deterministic tests
+#[derive(Debug, Default)]
+pub struct MockClock {
+    millis: AtomicU64,
+}
+
+impl MockClock {
+    pub fn new(millis: u64) -> Self {
+        Self {
+            millis: AtomicU64::new(millis),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.millis
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.millis.store(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_millis(&self) -> Option<u128> {
+        Some(u128::from(self.millis.load(Ordering::SeqCst)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_moves_only_when_told() {
+        let clock = MockClock::new(1_000);
+        assert_eq!(clock.now_millis(), Some(1_000));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(clock.now_millis(), Some(3_000));
+        clock.set(500);
+        assert_eq!(clock.now_millis(), Some(500));
+    }
+}
@@ -0,0 +1,74 @@
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+
+/// Flags equivalent to a `--config` file, a JSON object mapping flag names to
+/// their values. Names are the ones of the flags without the leading dashes,
+/// `_` may stand for `-`. A `true` switch becomes the bare flag and a `false`
+/// or `null` value is left out, an array repeats the flag for every element
+pub fn read_args(path: impl AsRef<Path>) -> io::Result<Vec<String>> {
+    parse_args(&std::fs::read_to_string(path)?)
+}
+
+fn parse_args(text: &str) -> io::Result<Vec<String>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let Value::Object(entries) = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?
+    else {
+        return Err(invalid("the config must be a JSON object".to_string()));
+    };
+
+    let mut args = Vec::new();
+    for (name, value) in entries {
+        let flag = format!("--{}", name.replace('_', "-"));
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Null | Value::Bool(false) => {}
+                Value::Bool(true) => args.push(flag.clone()),
+                Value::Number(n) => args.push(format!("{flag}={n}")),
+                Value::String(s) => args.push(format!("{flag}={s}")),
+                Value::Array(_) | Value::Object(_) => {
+                    return Err(invalid(format!("unsupported value for {name}")));
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_values_to_flags() {
+        let mut args = parse_args(
+            r#"{"topic": "orders", "quorum_window_ms": 200, "read-repair": true,
+                "dry_run": false, "bootstrap": ["/ip4/10.0.0.1/tcp/4001", "/ip4/10.0.0.2/tcp/4001"]}"#,
+        )
+        .unwrap();
+        // the order of the entries doesn't matter to clap
+        args.sort();
+        assert_eq!(
+            args,
+            [
+                "--bootstrap=/ip4/10.0.0.1/tcp/4001",
+                "--bootstrap=/ip4/10.0.0.2/tcp/4001",
+                "--quorum-window-ms=200",
+                "--read-repair",
+                "--topic=orders",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_anything_but_an_object() {
+        let err = parse_args(r#"["--topic", "orders"]"#).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = parse_args(r#"{"topic": {"name": "orders"}}"#).unwrap_err();
+        assert!(err.to_string().contains("topic"));
+    }
+}
@@ -1,3 +1,15 @@
 pub mod bin_args;
+#[cfg(feature = "networking")]
+pub mod bootstrap;
+pub mod build_info;
+pub mod clock;
+pub mod config_file;
+pub mod hex;
+pub mod preload;
+#[cfg(feature = "networking")]
+pub mod psk;
+#[cfg(feature = "networking")]
+pub mod subscribe;
+#[cfg(feature = "networking")]
 pub mod swarm_builder;
 pub mod timestamp;
@@ -0,0 +1,50 @@
+use std::io;
+use std::path::Path;
+
+/// Pairs of a `--preload` file, one `key value` pair per line. The value is
+/// the rest of the line, blank lines and lines starting with `#` are skipped
+pub fn read_pairs(path: impl AsRef<Path>) -> io::Result<Vec<(String, String)>> {
+    parse_pairs(&std::fs::read_to_string(path)?)
+}
+
+fn parse_pairs(text: &str) -> io::Result<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(char::is_whitespace) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {} has no value: {line}", number + 1),
+            ));
+        };
+        pairs.push((key.to_string(), value.trim_start().to_string()));
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_one_pair_per_line() {
+        let pairs = parse_pairs("# seed\ncolor red\n\ngreeting  hello world\n").unwrap();
+        assert_eq!(
+            pairs,
+            [
+                ("color".to_string(), "red".to_string()),
+                ("greeting".to_string(), "hello world".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_a_key_without_value() {
+        let err = parse_pairs("color red\nlonely\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"));
+    }
+}
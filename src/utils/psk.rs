@@ -0,0 +1,79 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use sha2::{Digest, Sha256};
+
+/// Length of the random nonce prefixed to every ciphertext
+const NONCE_LEN: usize = 12;
+
+/// Encryption of the published messages with a key shared by the whole cluster
+pub struct Psk {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Psk {
+    /// The key is the SHA-256 of `secret`, so any string can be used as the pre-shared key
+    pub fn new(secret: &str) -> Self {
+        let key: [u8; 32] = Sha256::digest(secret.as_bytes()).into();
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+
+    /// A fresh random nonce followed by the ciphertext
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .expect("messages are far below the ChaCha20-Poly1305 length limit");
+
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+        data
+    }
+
+    /// `None` if the data was encrypted with another key or was tampered with
+    pub fn decrypt(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_roundtrip() {
+        let psk = Psk::new("cluster secret");
+        let data = psk.encrypt(b"SET color red");
+
+        assert_ne!(&data[NONCE_LEN..], b"SET color red");
+        assert_eq!(psk.decrypt(&data).unwrap(), b"SET color red");
+    }
+
+    #[test]
+    fn nonces_are_random() {
+        let psk = Psk::new("cluster secret");
+        assert_ne!(psk.encrypt(b"same"), psk.encrypt(b"same"));
+    }
+
+    #[test]
+    fn decrypt_rejects_other_key_and_tampering() {
+        let data = Psk::new("cluster secret").encrypt(b"SET color red");
+        assert!(Psk::new("another secret").decrypt(&data).is_none());
+
+        let psk = Psk::new("cluster secret");
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(psk.decrypt(&tampered).is_none());
+        assert!(psk.decrypt(&data[..NONCE_LEN - 1]).is_none());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::node::MyBehaviour;
+use crate::ComponentError;
+use libp2p::{gossipsub::IdentTopic, Swarm};
+use std::time::Duration;
+
+/// Attempts made by [`subscribe_with_retry`] in the binaries
+pub const SUBSCRIBE_ATTEMPTS: u32 = 5;
+
+/// Wait before the second attempt, doubled after every failure
+pub const SUBSCRIBE_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Anything able to subscribe to a topic, the swarm outside of tests
+pub trait Subscriber {
+    fn subscribe(&mut self, topic: &IdentTopic) -> Result<(), String>;
+}
+
+impl Subscriber for Swarm<MyBehaviour> {
+    fn subscribe(&mut self, topic: &IdentTopic) -> Result<(), String> {
+        self.behaviour_mut()
+            .gossipsub
+            .subscribe(topic)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Subscribes to `topic`, retrying a failed attempt after a backoff doubled
+/// every time, up to `attempts` attempts in total
+pub async fn subscribe_with_retry(
+    subscriber: &mut impl Subscriber,
+    topic: &IdentTopic,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<(), ComponentError> {
+    let mut delay = backoff;
+    for attempt in 1..=attempts {
+        println!("Subscribing to topic {topic} (attempt {attempt}/{attempts})");
+        match subscriber.subscribe(topic) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt == attempts => return Err(ComponentError::Subscription(e)),
+            Err(e) => {
+                eprintln!("Failed to subscribe to topic {topic}: {e}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+    Err(ComponentError::Subscription(format!(
+        "no attempt made to subscribe to topic {topic}"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails the first `failures` subscriptions
+    #[derive(Default)]
+    struct MockSubscriber {
+        failures: u32,
+        attempts: u32,
+    }
+
+    impl Subscriber for MockSubscriber {
+        fn subscribe(&mut self, _topic: &IdentTopic) -> Result<(), String> {
+            self.attempts += 1;
+            match self.attempts <= self.failures {
+                true => Err("transient failure".to_string()),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_subscriptions_are_retried() {
+        let mut subscriber = MockSubscriber {
+            failures: 2,
+            ..Default::default()
+        };
+        let topic = IdentTopic::new("test-topic");
+        subscribe_with_retry(&mut subscriber, &topic, 3, Duration::from_millis(1))
+            .await
+            .unwrap();
+        assert_eq!(subscriber.attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn subscription_gives_up_after_the_last_attempt() {
+        let mut subscriber = MockSubscriber {
+            failures: 5,
+            ..Default::default()
+        };
+        let topic = IdentTopic::new("test-topic");
+        let result =
+            subscribe_with_retry(&mut subscriber, &topic, 3, Duration::from_millis(1)).await;
+        assert!(matches!(result, Err(ComponentError::Subscription(_))));
+        assert_eq!(subscriber.attempts, 3);
+    }
+}
@@ -1,17 +1,47 @@
 use crate::node::MyBehaviour;
-use crate::utils::bin_args::BinArgs;
-use libp2p::{gossipsub, identity, mdns, noise, tcp, yamux, PeerId, SwarmBuilder};
+use crate::utils::bin_args::{BinArgs, Muxer};
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::{
+    connection_limits::{self, ConnectionLimits},
+    core::{
+        muxing::StreamMuxerBox,
+        transport::{self, MemoryTransport},
+        upgrade,
+    },
+    gossipsub, identity, mdns, noise, swarm, tcp, yamux, PeerId, SwarmBuilder, Transport,
+};
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
     time::Duration,
 };
 
-pub fn build_swarm(
-    key: identity::Keypair,
+/// Upper bound for `--gossipsub-max-transmit-size`, every message is buffered in memory
+pub const MAX_TRANSMIT_SIZE_CEILING: usize = 16 * 1024 * 1024;
+
+pub fn build_gossipsub_config(
     args: &BinArgs,
-) -> Result<libp2p::Swarm<MyBehaviour>, Box<dyn std::error::Error>> {
-    let peer_id = PeerId::from(key.public());
+) -> Result<gossipsub::Config, Box<dyn std::error::Error>> {
+    if args.gossipsub_max_transmit_size == 0
+        || args.gossipsub_max_transmit_size > MAX_TRANSMIT_SIZE_CEILING
+    {
+        return Err(format!(
+            "gossipsub max transmit size must be between 1 and {MAX_TRANSMIT_SIZE_CEILING} bytes, got {}",
+            args.gossipsub_max_transmit_size
+        )
+        .into());
+    }
+
+    // the mesh sizes are the gossipsub defaults, no flag changes them
+    let defaults = gossipsub::Config::default();
+    let max_outbound_min = (defaults.mesh_n() / 2).min(defaults.mesh_n_low());
+    if args.gossipsub_mesh_outbound_min > max_outbound_min {
+        return Err(format!(
+            "gossipsub mesh outbound min must be at most {max_outbound_min}, half of the mesh size, got {}",
+            args.gossipsub_mesh_outbound_min
+        )
+        .into());
+    }
 
     let message_id_fn = |msg: &gossipsub::Message| {
         let mut h = DefaultHasher::new();
@@ -19,37 +49,197 @@ pub fn build_swarm(
         gossipsub::MessageId::from(h.finish().to_string())
     };
 
-    let gossipsub_config = gossipsub::ConfigBuilder::default()
+    let mut builder = gossipsub::ConfigBuilder::default();
+    builder
         .heartbeat_interval(Duration::from_secs(args.heartbeat_interval))
         .validation_mode(gossipsub::ValidationMode::Strict)
         .message_id_fn(message_id_fn)
-        .build()
-        .map_err(std::io::Error::other)?;
+        .max_transmit_size(args.gossipsub_max_transmit_size)
+        .flood_publish(args.gossipsub_flood_publish)
+        .mesh_outbound_min(args.gossipsub_mesh_outbound_min)
+        .duplicate_cache_time(Duration::from_secs(
+            args.gossipsub_duplicate_cache_time_secs,
+        ));
+    if let Some(prefix) = args.bincode_config.gossipsub_protocol_prefix() {
+        builder.protocol_id_prefix(prefix);
+    }
+    if args.enable_peer_scoring {
+        // messages are only forwarded once the component reports them valid
+        builder.validate_messages();
+    }
+
+    Ok(builder.build().map_err(std::io::Error::other)?)
+}
+
+/// Scoring of the peers on the component topics, only invalid messages lower
+/// a score: the cluster is mostly idle and every node may run on the same
+/// host, so mesh delivery rates and IP colocation would penalize honest peers
+pub fn build_peer_score_params(args: &BinArgs) -> gossipsub::PeerScoreParams {
+    let topic_params = gossipsub::TopicScoreParams {
+        topic_weight: 1.0,
+        time_in_mesh_weight: 0.0,
+        first_message_deliveries_weight: 1.0,
+        first_message_deliveries_decay: 0.5,
+        first_message_deliveries_cap: 10.0,
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        invalid_message_deliveries_weight: -10.0,
+        invalid_message_deliveries_decay: 0.9,
+        ..Default::default()
+    };
+
+    let mut params = gossipsub::PeerScoreParams {
+        ip_colocation_factor_weight: 0.0,
+        ..Default::default()
+    };
+    for topic in std::iter::once(&args.topic).chain(&args.audit_topic) {
+        params.topics.insert(
+            gossipsub::IdentTopic::new(topic).hash(),
+            topic_params.clone(),
+        );
+    }
+    params
+}
 
-    let gossipsub = gossipsub::Behaviour::new(
+pub fn build_peer_score_thresholds(args: &BinArgs) -> gossipsub::PeerScoreThresholds {
+    gossipsub::PeerScoreThresholds {
+        gossip_threshold: args.peer_score_gossip_threshold,
+        publish_threshold: args.peer_score_publish_threshold,
+        graylist_threshold: args.peer_score_graylist_threshold,
+        ..Default::default()
+    }
+}
+
+/// Limits from `--max-connections` and `--max-pending-connections`, unset flags
+/// leave the corresponding limit off
+pub fn build_connection_limits(args: &BinArgs) -> ConnectionLimits {
+    ConnectionLimits::default()
+        .with_max_established(args.max_connections)
+        .with_max_pending_incoming(args.max_pending_connections)
+}
+
+fn swarm_config(config: swarm::Config, args: &BinArgs) -> swarm::Config {
+    config.with_idle_connection_timeout(Duration::from_secs(args.idle_connection_timeout_secs))
+}
+
+/// Authenticated and multiplexed transport a swarm is built on
+pub type BoxedTransport = transport::Boxed<(PeerId, StreamMuxerBox)>;
+
+/// Authenticates the connections of a raw transport with noise and
+/// multiplexes them with the `--muxer` choice
+pub fn upgrade_transport<T>(
+    transport: T,
+    key: &identity::Keypair,
+    muxer: Muxer,
+) -> Result<BoxedTransport, noise::Error>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let authenticated = transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::Config::new(key)?);
+    Ok(match muxer {
+        Muxer::Yamux => authenticated.multiplex(yamux::Config::default()).boxed(),
+        Muxer::Mplex => authenticated
+            .multiplex(libp2p_mplex::Config::default())
+            .boxed(),
+    })
+}
+
+fn build_behaviour(
+    key: &identity::Keypair,
+    args: &BinArgs,
+) -> Result<MyBehaviour, Box<dyn std::error::Error>> {
+    let peer_id = PeerId::from(key.public());
+
+    let gossipsub_config = build_gossipsub_config(args)?;
+
+    let mut gossipsub = gossipsub::Behaviour::new(
         gossipsub::MessageAuthenticity::Signed(key.clone()),
         gossipsub_config,
     )?;
+    if args.enable_peer_scoring {
+        gossipsub.with_peer_score(
+            build_peer_score_params(args),
+            build_peer_score_thresholds(args),
+        )?;
+    }
 
     let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id)?;
 
+    let limits = connection_limits::Behaviour::new(build_connection_limits(args));
+
+    Ok(MyBehaviour {
+        gossipsub,
+        mdns,
+        limits,
+    })
+}
+
+pub fn build_swarm(
+    key: identity::Keypair,
+    args: &BinArgs,
+) -> Result<libp2p::Swarm<MyBehaviour>, Box<dyn std::error::Error>> {
+    let behaviour = build_behaviour(&key, args)?;
+    let tcp = upgrade_transport(
+        tcp::tokio::Transport::new(tcp::Config::default()),
+        &key,
+        args.muxer,
+    )?;
+
     Ok(SwarmBuilder::with_existing_identity(key)
         .with_tokio()
-        .with_tcp(
-            tcp::Config::default(),
-            noise::Config::new,
-            yamux::Config::default,
-        )?
         .with_quic()
-        .with_behaviour(|_| MyBehaviour { gossipsub, mdns })?
+        .with_other_transport(|_| tcp)?
+        .with_behaviour(|_| behaviour)?
+        .with_swarm_config(|config| swarm_config(config, args))
         .build())
 }
 
+/// Same as [`build_swarm`] but on top of the given raw transport instead of
+/// TCP and QUIC, e.g. a `MemoryTransport`
+pub fn build_swarm_with_transport<T>(
+    key: identity::Keypair,
+    args: &BinArgs,
+    transport: T,
+) -> Result<libp2p::Swarm<MyBehaviour>, Box<dyn std::error::Error>>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let behaviour = build_behaviour(&key, args)?;
+    let transport = upgrade_transport(transport, &key, args.muxer)?;
+
+    Ok(SwarmBuilder::with_existing_identity(key)
+        .with_tokio()
+        .with_other_transport(|_| transport)?
+        .with_behaviour(|_| behaviour)?
+        .with_swarm_config(|config| swarm_config(config, args))
+        .build())
+}
+
+/// In-process transport listening on `/memory/<port>` addresses, used to run
+/// several components in a single process
+pub fn memory_transport(
+    key: &identity::Keypair,
+    muxer: Muxer,
+) -> Result<BoxedTransport, Box<dyn std::error::Error>> {
+    Ok(upgrade_transport(MemoryTransport::default(), key, muxer)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::bin_args::BinArgs;
-    use libp2p::{identity, PeerId};
+    use crate::utils::bin_args::{BinArgs, BincodeConfig, Muxer};
+    use futures::StreamExt;
+    use libp2p::{identity, swarm::SwarmEvent, PeerId};
 
     #[tokio::test]
     async fn build_swarm_successfully_creates_swarm() {
@@ -65,4 +255,262 @@ mod tests {
         let swarm = result.unwrap();
         assert_eq!(*swarm.local_peer_id(), PeerId::from(key.public()));
     }
+
+    #[tokio::test]
+    async fn build_swarm_with_memory_transport() {
+        let key = identity::Keypair::generate_ed25519();
+        let transport = MemoryTransport::default();
+
+        let mut swarm =
+            build_swarm_with_transport(key.clone(), &BinArgs::default(), transport).unwrap();
+        assert_eq!(*swarm.local_peer_id(), PeerId::from(key.public()));
+        assert!(swarm.listen_on("/memory/0".parse().unwrap()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn build_swarm_with_each_muxer() {
+        assert_eq!(BinArgs::default().muxer, Muxer::Yamux);
+        for muxer in [Muxer::Yamux, Muxer::Mplex] {
+            let args = BinArgs {
+                muxer,
+                ..Default::default()
+            };
+            let key = identity::Keypair::generate_ed25519();
+            assert!(build_swarm(key.clone(), &args).is_ok(), "{muxer:?}");
+            let mut swarm =
+                build_swarm_with_transport(key, &args, MemoryTransport::default()).unwrap();
+            assert!(swarm.listen_on("/memory/0".parse().unwrap()).is_ok());
+
+            // peers using the same muxer negotiate their connection
+            let (mut dialer, mut listener) = connected_idle_swarms(&args).await;
+            let established = tokio::time::timeout(Duration::from_secs(5), async {
+                loop {
+                    tokio::select! {
+                        event = dialer.select_next_some() => {
+                            if let SwarmEvent::ConnectionEstablished { .. } = event {
+                                return;
+                            }
+                        }
+                        _ = listener.select_next_some() => {}
+                    }
+                }
+            });
+            assert!(established.await.is_ok(), "{muxer:?}");
+        }
+    }
+
+    #[test]
+    fn muxer_flag_is_validated() {
+        use clap::Parser;
+
+        let args = BinArgs::try_parse_from(["difiew", "--muxer", "mplex"]).unwrap();
+        assert_eq!(args.muxer, Muxer::Mplex);
+        assert!(BinArgs::try_parse_from(["difiew", "--muxer", "tls"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn build_swarm_applies_connection_limits() {
+        let args = BinArgs {
+            max_connections: Some(8),
+            max_pending_connections: Some(2),
+            ..Default::default()
+        };
+
+        let mut swarm = build_swarm(identity::Keypair::generate_ed25519(), &args).unwrap();
+        let limits = format!("{:?}", swarm.behaviour_mut().limits.limits_mut());
+        assert!(limits.contains("max_established_total: Some(8)"));
+        assert!(limits.contains("max_pending_incoming: Some(2)"));
+    }
+
+    #[test]
+    fn connection_limits_are_off_by_default() {
+        let limits = format!("{:?}", build_connection_limits(&BinArgs::default()));
+        assert!(!limits.contains("Some("));
+    }
+
+    type DummySwarm = libp2p::Swarm<swarm::dummy::Behaviour>;
+
+    /// Two connected swarms whose behaviour never opens a stream, so only the
+    /// idle timeout decides how long the connection lives
+    async fn connected_idle_swarms(args: &BinArgs) -> (DummySwarm, DummySwarm) {
+        let mut swarms = Vec::new();
+        for _ in 0..2 {
+            let key = identity::Keypair::generate_ed25519();
+            let transport = memory_transport(&key, args.muxer).unwrap();
+            let swarm = SwarmBuilder::with_existing_identity(key)
+                .with_tokio()
+                .with_other_transport(|_| transport)
+                .unwrap()
+                .with_behaviour(|_| swarm::dummy::Behaviour)
+                .unwrap()
+                .with_swarm_config(|config| swarm_config(config, args))
+                .build();
+            swarms.push(swarm);
+        }
+        let (mut dialer, mut listener) = (swarms.remove(0), swarms.remove(0));
+
+        listener.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = listener.select_next_some().await {
+                break address;
+            }
+        };
+        dialer.dial(address).unwrap();
+        (dialer, listener)
+    }
+
+    /// Drives both swarms until the dialer's connection closes or `limit` elapses
+    async fn connection_closes_within(
+        dialer: &mut DummySwarm,
+        listener: &mut DummySwarm,
+        limit: Duration,
+    ) -> bool {
+        let closed = tokio::time::timeout(limit, async {
+            loop {
+                tokio::select! {
+                    event = dialer.select_next_some() => {
+                        if let SwarmEvent::ConnectionClosed { .. } = event {
+                            return;
+                        }
+                    }
+                    _ = listener.select_next_some() => {}
+                }
+            }
+        });
+        closed.await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn idle_connection_closes_after_configured_timeout() {
+        let args = BinArgs {
+            idle_connection_timeout_secs: 1,
+            ..Default::default()
+        };
+        let (mut dialer, mut listener) = connected_idle_swarms(&args).await;
+        assert!(connection_closes_within(&mut dialer, &mut listener, Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test]
+    async fn idle_connection_stays_open_before_timeout() {
+        let args = BinArgs {
+            idle_connection_timeout_secs: 60,
+            ..Default::default()
+        };
+        let (mut dialer, mut listener) = connected_idle_swarms(&args).await;
+        assert!(
+            !connection_closes_within(&mut dialer, &mut listener, Duration::from_secs(2)).await
+        );
+        assert_eq!(dialer.connected_peers().count(), 1);
+    }
+
+    #[test]
+    fn gossipsub_config_reflects_max_transmit_size() {
+        let args = BinArgs {
+            gossipsub_max_transmit_size: 4 * 1024 * 1024,
+            ..Default::default()
+        };
+
+        let config = build_gossipsub_config(&args).unwrap();
+        assert_eq!(config.max_transmit_size(), 4 * 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn build_swarm_applies_peer_scoring() {
+        let args = BinArgs {
+            enable_peer_scoring: true,
+            audit_topic: Some("audit".to_string()),
+            ..Default::default()
+        };
+        assert!(build_gossipsub_config(&args).unwrap().validate_messages());
+        assert_eq!(build_peer_score_params(&args).topics.len(), 2);
+
+        let swarm = build_swarm(identity::Keypair::generate_ed25519(), &args).unwrap();
+        assert_eq!(
+            swarm.behaviour().gossipsub.peer_score(&PeerId::random()),
+            Some(0.0)
+        );
+
+        let swarm = build_swarm(identity::Keypair::generate_ed25519(), &BinArgs::default());
+        assert_eq!(
+            swarm
+                .unwrap()
+                .behaviour()
+                .gossipsub
+                .peer_score(&PeerId::random()),
+            None
+        );
+        assert!(!build_gossipsub_config(&BinArgs::default())
+            .unwrap()
+            .validate_messages());
+    }
+
+    #[tokio::test]
+    async fn build_swarm_rejects_unordered_peer_score_thresholds() {
+        let args = BinArgs {
+            enable_peer_scoring: true,
+            peer_score_graylist_threshold: 0.0,
+            ..Default::default()
+        };
+        assert!(build_swarm(identity::Keypair::generate_ed25519(), &args).is_err());
+    }
+
+    #[tokio::test]
+    async fn build_swarm_accepts_fixint_encoding() {
+        let args = BinArgs {
+            bincode_config: BincodeConfig::Fixint,
+            ..Default::default()
+        };
+        assert!(build_swarm(identity::Keypair::generate_ed25519(), &args).is_ok());
+    }
+
+    #[test]
+    fn gossipsub_config_reflects_duplicate_cache_time() {
+        let args = BinArgs {
+            gossipsub_duplicate_cache_time_secs: 300,
+            ..Default::default()
+        };
+
+        let config = build_gossipsub_config(&args).unwrap();
+        assert_eq!(config.duplicate_cache_time(), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn gossipsub_config_reflects_flood_publish() {
+        let config = build_gossipsub_config(&BinArgs::default()).unwrap();
+        assert!(!config.flood_publish());
+
+        let args = BinArgs {
+            gossipsub_flood_publish: true,
+            ..Default::default()
+        };
+        let config = build_gossipsub_config(&args).unwrap();
+        assert!(config.flood_publish());
+    }
+
+    #[test]
+    fn gossipsub_config_reflects_mesh_outbound_min() {
+        let args = BinArgs {
+            gossipsub_mesh_outbound_min: 3,
+            ..Default::default()
+        };
+        let config = build_gossipsub_config(&args).unwrap();
+        assert_eq!(config.mesh_outbound_min(), 3);
+
+        // more than half of the default mesh of 6 peers
+        let args = BinArgs {
+            gossipsub_mesh_outbound_min: 4,
+            ..Default::default()
+        };
+        assert!(build_gossipsub_config(&args).is_err());
+    }
+
+    #[test]
+    fn gossipsub_config_rejects_max_transmit_size_above_ceiling() {
+        let args = BinArgs {
+            gossipsub_max_transmit_size: MAX_TRANSMIT_SIZE_CEILING + 1,
+            ..Default::default()
+        };
+
+        assert!(build_gossipsub_config(&args).is_err());
+    }
 }
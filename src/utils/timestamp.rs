@@ -1,15 +1,31 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Last value returned by [`timestamp_millis`] in this process
+static LAST_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+/// Milliseconds since the unix epoch, never decreasing within the process:
+/// if the system clock steps back the last returned value is used until it
+/// catches up, so peers never see the timestamps of a node go backwards
 pub fn timestamp_millis() -> Option<u128> {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .ok()
-        .map(|dur| dur.as_millis())
+    monotonic_millis(SystemTime::now(), &LAST_MILLIS)
+}
+
+/// [`timestamp_millis`] reading `now` from any clock and tracking `last`
+fn monotonic_millis(now: SystemTime, last: &AtomicU64) -> Option<u128> {
+    let now = now.duration_since(UNIX_EPOCH).ok()?.as_millis();
+    let now = u64::try_from(now).unwrap_or(u64::MAX);
+    let next = |last: u64| now.max(last);
+    let previous = last
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(next(last)))
+        .expect("the update always succeeds");
+    Some(u128::from(next(previous)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn returns_some_millis() {
@@ -30,4 +46,34 @@ mod tests {
         let ts2 = timestamp_millis().unwrap();
         assert!(ts2 > ts1);
     }
+
+    #[test]
+    fn calls_within_a_millisecond_do_not_move_the_clock() {
+        let last = AtomicU64::new(0);
+        let at = UNIX_EPOCH + Duration::from_millis(7_000);
+        for _ in 0..3 {
+            assert_eq!(monotonic_millis(at, &last), Some(7_000));
+        }
+    }
+
+    #[test]
+    fn clock_going_backwards_is_clamped() {
+        let last = AtomicU64::new(0);
+        let at = |millis: u64| UNIX_EPOCH + Duration::from_millis(millis);
+
+        assert_eq!(monotonic_millis(at(5_000), &last), Some(5_000));
+        // the clock steps back by a second, then catches up
+        assert_eq!(monotonic_millis(at(4_000), &last), Some(5_000));
+        assert_eq!(monotonic_millis(at(4_500), &last), Some(5_000));
+        assert_eq!(monotonic_millis(at(5_002), &last), Some(5_002));
+        assert_eq!(monotonic_millis(at(6_000), &last), Some(6_000));
+    }
+
+    #[test]
+    fn time_before_epoch_is_none() {
+        let last = AtomicU64::new(0);
+        let before = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(monotonic_millis(before, &last), None);
+        assert_eq!(last.load(Ordering::SeqCst), 0);
+    }
 }
@@ -0,0 +1,81 @@
+#![cfg(feature = "networking")]
+
+use std::time::Duration;
+
+use difiew::{
+    client::{Client, ClientError},
+    node::Node,
+    store::{command::StoreCommand, result::ErrorKind},
+    utils::{
+        bin_args::BinArgs,
+        swarm_builder::build_swarm_with_transport,
+    },
+    Component, MyBehaviour,
+};
+use futures::StreamExt;
+use libp2p::{
+    core::transport::MemoryTransport, gossipsub::IdentTopic, identity, swarm::SwarmEvent,
+    Multiaddr, PeerId, Swarm,
+};
+use tokio::task::LocalSet;
+
+const TOPIC: &str = "test-topic";
+
+fn memory_swarm() -> (Swarm<MyBehaviour>, PeerId) {
+    let key = identity::Keypair::generate_ed25519();
+    let peer_id = PeerId::from(key.public());
+    let transport = MemoryTransport::default();
+    let mut swarm = build_swarm_with_transport(key, &BinArgs::default(), transport).unwrap();
+    swarm
+        .behaviour_mut()
+        .gossipsub
+        .subscribe(&IdentTopic::new(TOPIC))
+        .unwrap();
+    (swarm, peer_id)
+}
+
+async fn listen_on_memory(swarm: &mut Swarm<MyBehaviour>) -> Multiaddr {
+    swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+    loop {
+        if let SwarmEvent::NewListenAddr { address, .. } = swarm.select_next_some().await {
+            return address;
+        }
+    }
+}
+
+#[tokio::test]
+async fn client_gets_the_typed_results_of_a_node() {
+    let args = BinArgs::default();
+    let (mut node_swarm, node_id) = memory_swarm();
+    let address = listen_on_memory(&mut node_swarm).await;
+    let mut node = Node::new(node_swarm, node_id, IdentTopic::new(TOPIC), &args);
+
+    let (mut client_swarm, client_id) = memory_swarm();
+    client_swarm.dial(address).unwrap();
+    let mut client = Client::new(client_swarm, client_id, IdentTopic::new(TOPIC), &args)
+        .with_timeout(Duration::from_secs(30));
+
+    let local = LocalSet::new();
+    local
+        .run_until(async move {
+            tokio::task::spawn_local(async move { node.start_event_loop().await });
+
+            assert!(client.set("color", "red").await.unwrap());
+            assert_eq!(client.get("color").await.unwrap().as_deref(), Some("red"));
+            assert_eq!(client.get("size").await.unwrap(), None);
+            assert_eq!(client.keys("col*").await.unwrap(), ["color"]);
+
+            client
+                .execute(StoreCommand::rpush("list", ["a"]))
+                .await
+                .unwrap();
+            assert!(matches!(
+                client.get("list").await,
+                Err(ClientError::Failed(ErrorKind::Conflict, _))
+            ));
+
+            assert_eq!(client.del(&["color", "size"]).await.unwrap(), 1);
+            assert_eq!(client.get("color").await.unwrap(), None);
+        })
+        .await;
+}
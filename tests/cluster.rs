@@ -0,0 +1,178 @@
+#![cfg(feature = "networking")]
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use difiew::{
+    manager::Manager,
+    node::Node,
+    protocol::{decode_message, ComponentMessage, NodeMessage},
+    store::{command::CmdArgs, command::StoreCommand, Store},
+    utils::{
+        bin_args::{BinArgs, BincodeConfig},
+        swarm_builder::build_swarm_with_transport,
+    },
+    Component, MyBehaviour, MyBehaviourEvent,
+};
+use futures::StreamExt;
+use libp2p::{
+    core::transport::MemoryTransport, gossipsub, gossipsub::IdentTopic, identity,
+    swarm::SwarmEvent, Multiaddr, PeerId, Swarm,
+};
+use tokio::task::LocalSet;
+
+const TOPIC: &str = "test-topic";
+const NODES: usize = 3;
+
+fn memory_swarm() -> (Swarm<MyBehaviour>, PeerId) {
+    let key = identity::Keypair::generate_ed25519();
+    let peer_id = PeerId::from(key.public());
+    let transport = MemoryTransport::default();
+    let mut swarm = build_swarm_with_transport(key, &BinArgs::default(), transport).unwrap();
+    swarm
+        .behaviour_mut()
+        .gossipsub
+        .subscribe(&IdentTopic::new(TOPIC))
+        .unwrap();
+    (swarm, peer_id)
+}
+
+async fn listen_on_memory(swarm: &mut Swarm<MyBehaviour>) -> Multiaddr {
+    swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+    loop {
+        if let SwarmEvent::NewListenAddr { address, .. } = swarm.select_next_some().await {
+            return address;
+        }
+    }
+}
+
+fn expected_root() -> Option<[u8; 32]> {
+    let mut store = Store::new();
+    store.execute(StoreCommand::set("color", "red")).unwrap();
+    store.reveal_root()
+}
+
+#[tokio::test]
+#[allow(clippy::await_holding_refcell_ref)]
+async fn nodes_converge_after_manager_set() {
+    let args = BinArgs::default();
+    let (mut manager_swarm, manager_id) = memory_swarm();
+
+    let mut nodes = Vec::new();
+    for _ in 0..NODES {
+        let (mut swarm, peer_id) = memory_swarm();
+        let address = listen_on_memory(&mut swarm).await;
+        manager_swarm.dial(address).unwrap();
+        nodes.push(Node::new(swarm, peer_id, IdentTopic::new(TOPIC), &args));
+    }
+    let mut manager = Manager::new(manager_swarm, manager_id, IdentTopic::new(TOPIC), &args);
+
+    let local = LocalSet::new();
+    local
+        .run_until(async move {
+            for mut node in nodes {
+                tokio::task::spawn_local(async move { node.start_event_loop().await });
+            }
+
+            let deadline = Instant::now() + Duration::from_secs(30);
+            let mut subscribed = HashSet::new();
+            let mut published = false;
+            let mut roots: HashMap<String, Option<[u8; 32]>> = HashMap::new();
+
+            loop {
+                assert!(
+                    Instant::now() < deadline,
+                    "nodes did not converge: {roots:?}"
+                );
+
+                if !published && subscribed.len() == NODES {
+                    let set = CmdArgs {
+                        cmd_type: "set".to_string(),
+                        cmd_arg: "color red".to_string(),
+                    };
+                    manager.execute_user_input(set).unwrap();
+                    published = true;
+                }
+
+                let event = {
+                    let mut swarm = manager.core().swarm.borrow_mut();
+                    tokio::time::timeout(Duration::from_millis(100), swarm.select_next_some()).await
+                };
+                match event {
+                    Ok(SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                        gossipsub::Event::Subscribed { peer_id, .. },
+                    ))) => {
+                        subscribed.insert(peer_id);
+                    }
+                    Ok(SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                        gossipsub::Event::Message { message, .. },
+                    ))) if published => {
+                        if let Ok(Some(ComponentMessage::NodeMessage(
+                            NodeMessage::ShareSignature(params),
+                            _,
+                        ))) = decode_message(&message.data, BincodeConfig::Standard)
+                        {
+                            roots.insert(params.src_id, params.sgn.root);
+                        }
+                    }
+                    _ => {}
+                }
+
+                if roots.len() == NODES && roots.values().all(|root| *root == expected_root()) {
+                    break;
+                }
+            }
+        })
+        .await;
+}
+
+/// Drives the manager swarm until every node subscribed to the topic
+#[allow(clippy::await_holding_refcell_ref)]
+async fn await_subscriptions(manager: &Manager) {
+    let mut subscribed = HashSet::new();
+    let mut swarm = manager.core().swarm.borrow_mut();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while subscribed.len() < NODES {
+            if let SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(
+                gossipsub::Event::Subscribed { peer_id, .. },
+            )) = swarm.select_next_some().await
+            {
+                subscribed.insert(peer_id);
+            }
+        }
+    })
+    .await
+    .expect("nodes did not subscribe");
+}
+
+#[tokio::test]
+async fn manager_detects_convergence_after_a_write() {
+    let args = BinArgs::default();
+    let (mut manager_swarm, manager_id) = memory_swarm();
+
+    let mut nodes = Vec::new();
+    for _ in 0..NODES {
+        let (mut swarm, peer_id) = memory_swarm();
+        let address = listen_on_memory(&mut swarm).await;
+        manager_swarm.dial(address).unwrap();
+        nodes.push(Node::new(swarm, peer_id, IdentTopic::new(TOPIC), &args));
+    }
+    let mut manager = Manager::new(manager_swarm, manager_id, IdentTopic::new(TOPIC), &args);
+
+    let local = LocalSet::new();
+    local
+        .run_until(async move {
+            for mut node in nodes {
+                tokio::task::spawn_local(async move { node.start_event_loop().await });
+            }
+            await_subscriptions(&manager).await;
+
+            let set = CmdArgs {
+                cmd_type: "set".to_string(),
+                cmd_arg: "color red".to_string(),
+            };
+            manager.execute_user_input(set).unwrap();
+            assert!(manager.await_convergence(Duration::from_secs(30)).await);
+        })
+        .await;
+}